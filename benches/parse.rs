@@ -0,0 +1,84 @@
+//! Measures the generated state machines on long inputs, where the time is spent in the match loop.
+//! Run with `cargo bench`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use regex_parse::{re_find, re_for_each, re_parse};
+
+fn csv_line(count: usize) -> String {
+    (0..count)
+        .map(|index| (index * 7919 % 100_000).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn log(lines: usize) -> String {
+    (0..lines)
+        .map(|index| {
+            format!(
+                "[12:00:{:02}] user{index} logged in from 10.0.0.{}\n",
+                index % 60,
+                index % 256
+            )
+        })
+        .collect()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("re_parse");
+    let input = csv_line(10_000);
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("numbers", |b| {
+        b.iter(|| {
+            let numbers: Vec<u32>;
+            re_parse!("{numbers* sep=\",\"}", black_box(&input));
+            numbers
+        })
+    });
+    group.bench_function("text", |b| {
+        b.iter(|| {
+            let first: u32;
+            let rest: String;
+            re_parse!(r"{first:\d+},{rest}", black_box(&input));
+            (first, rest)
+        })
+    });
+    group.finish();
+}
+
+fn bench_for_each(c: &mut Criterion) {
+    let mut group = c.benchmark_group("re_for_each");
+    let input = log(1_000);
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("log", |b| {
+        b.iter(|| {
+            let mut total = 0_usize;
+            re_for_each!(
+                r"\[{time}\] {user} logged in from {address}\n",
+                black_box(&input),
+                {
+                    let (time, user, address): (String, String, String) = (time, user, address);
+                    total += time.len() + user.len() + address.len();
+                }
+            );
+            total
+        })
+    });
+    group.finish();
+}
+
+fn bench_find(c: &mut Criterion) {
+    let mut group = c.benchmark_group("re_find");
+    let mut input = log(1_000);
+    input.push_str("error code 404;");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("late match", |b| {
+        b.iter(|| {
+            let code: u32;
+            let span = re_find!("error code {code};", black_box(&input));
+            (span, code)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_for_each, bench_find);
+criterion_main!(benches);
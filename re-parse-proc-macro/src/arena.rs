@@ -3,7 +3,7 @@ use std::hash::Hash;
 use std::marker::PhantomData;
 use std::ops::{Index, IndexMut};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Arena<T> {
     nodes: Vec<T>,
 }
@@ -15,6 +15,10 @@ impl<T> Arena<T> {
         ArenaIndex::new(index)
     }
 
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = ArenaIndex<T>> + use<'_, T> {
         self.nodes
             .iter()
@@ -64,6 +68,11 @@ impl<T> ArenaIndex<T> {
             _phantom: PhantomData,
         }
     }
+
+    /// The position of the node in the arena. Nodes that are added later have a higher index.
+    pub const fn index(self) -> usize {
+        self.index
+    }
 }
 
 impl<T> Debug for ArenaIndex<T> {
@@ -0,0 +1,230 @@
+//! The classes that are defined with `re_class!` or built in, and referenced with `\k{name}` in patterns.
+//!
+//! The compiler offers no way for proc macros to share state, so `re_class!(hex = "[0-9a-f]")` expands to
+//! a `macro_rules!` macro named `__re_class_hex`, which the compiler resolves like any other macro.
+//! A macro that references a class it does not know yet expands to a call of the macro of the class instead,
+//! which calls the macro again with the definition of the class prepended to its input, like
+//! `re_find! { @classes { hex = "[0-9a-f]", } ... }`. This repeats until every referenced class is known.
+use crate::tokenizer::CaptureType;
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use syn::parse::{ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::{Ident, LitStr, Token};
+
+thread_local! {
+    /// The sources of the classes that were passed to the macro that is being expanded, by their name
+    static DEFINED: RefCell<BTreeMap<String, String>> = const { RefCell::new(BTreeMap::new()) };
+    /// The first class that the macro referenced, but that was not passed to it
+    static MISSING: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Classes that are always available. `re_class!` cannot define classes with these names.
+pub const PRESETS: &[(&str, &str)] = &[
+    (
+        "ipv4",
+        r"(25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)(\.(25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d))#{3}",
+    ),
+    (
+        "ipv6",
+        concat!(
+            r"([[:xdigit:]]#{1,4}:)#{7}[[:xdigit:]]#{1,4}",
+            r"|([[:xdigit:]]#{1,4}:)#{1,7}:",
+            r"|([[:xdigit:]]#{1,4}:)#{1,6}:[[:xdigit:]]#{1,4}",
+            r"|([[:xdigit:]]#{1,4}:)#{1,5}(:[[:xdigit:]]#{1,4})#{1,2}",
+            r"|([[:xdigit:]]#{1,4}:)#{1,4}(:[[:xdigit:]]#{1,4})#{1,3}",
+            r"|([[:xdigit:]]#{1,4}:)#{1,3}(:[[:xdigit:]]#{1,4})#{1,4}",
+            r"|([[:xdigit:]]#{1,4}:)#{1,2}(:[[:xdigit:]]#{1,4})#{1,5}",
+            r"|[[:xdigit:]]#{1,4}:(:[[:xdigit:]]#{1,4})#{1,6}",
+            r"|:((:[[:xdigit:]]#{1,4})#{1,7}|:)",
+        ),
+    ),
+    (
+        "email",
+        r"[a-zA-Z0-9._%+\-]+@[a-zA-Z0-9\-]+(\.[a-zA-Z0-9\-]+)+",
+    ),
+    (
+        "iso_date",
+        r"\d#{4}\-(0[1-9]|1[0-2])\-(0[1-9]|[12]\d|3[01])",
+    ),
+    (
+        "uuid",
+        r"[[:xdigit:]]#{8}\-[[:xdigit:]]#{4}\-[[:xdigit:]]#{4}\-[[:xdigit:]]#{4}\-[[:xdigit:]]#{12}",
+    ),
+    ("float", CaptureType::FLOAT),
+];
+
+/// Expands the macro `macro_name` with the classes that were prepended to its `input`.
+/// If `expand` references a class that is not known, the macro of that class is called instead.
+pub fn expand(
+    macro_name: &str,
+    input: proc_macro::TokenStream,
+    expand: impl FnOnce(proc_macro::TokenStream) -> proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let (definitions, input) = split_definitions(input.into());
+    DEFINED.set(
+        definitions
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.value()))
+            .collect(),
+    );
+    MISSING.set(None);
+    let output = expand(input.clone().into());
+    match MISSING.take() {
+        Some(name) => call_class_macro(macro_name, &name, &definitions, input).into(),
+        None => output,
+    }
+}
+
+/// Stores the source of a class, like passing it to the macro that is being expanded
+#[cfg(test)]
+pub fn define(name: String, source: String) {
+    DEFINED.with_borrow_mut(|classes| classes.insert(name, source));
+}
+
+/// Returns the source of the class with the given name, if it is built in or was passed to the macro.
+/// Otherwise, the class is remembered, so that the macro calls the macro of the class.
+pub fn get(name: &str) -> Option<String> {
+    if let Some(source) = preset(name) {
+        return Some(source.to_string());
+    }
+    if let Some(source) = DEFINED.with_borrow(|classes| classes.get(name).cloned()) {
+        return Some(source);
+    }
+    // A name that is no identifier cannot have been defined with `re_class!`
+    if syn::parse_str::<Ident>(&format!("__re_class_{name}")).is_ok() {
+        MISSING.with_borrow_mut(|missing| {
+            missing.get_or_insert_with(|| name.to_string());
+        });
+    }
+    None
+}
+
+/// Returns the source of a built-in class
+pub fn preset(name: &str) -> Option<&'static str> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, source)| *source)
+}
+
+/// Generates the `macro_rules!` macro of a class that is defined with `re_class!`.
+/// It calls the macro in the brackets with the definition of the class prepended to its input.
+pub fn define_class_macro(name: &Ident, source: &LitStr) -> TokenStream {
+    let class_macro = class_macro_ident(&name.to_string(), name.span());
+    quote! {
+        #[doc(hidden)]
+        #[allow(unused_macros)]
+        macro_rules! #class_macro {
+            ([$($callback:tt)*] { $($classes:tt)* } $($input:tt)*) => {
+                $($callback)*! { @classes { $($classes)* #name = #source, } $($input)* }
+            };
+        }
+    }
+}
+
+/// Calls the macro of the class `name`, which calls the macro `macro_name` again with the class added to `definitions`
+fn call_class_macro(
+    macro_name: &str,
+    name: &str,
+    definitions: &[(Ident, LitStr)],
+    input: TokenStream,
+) -> TokenStream {
+    // An unknown class is reported at the pattern, which is the first literal of every macro
+    let span = input
+        .clone()
+        .into_iter()
+        .find_map(|tree| match tree {
+            TokenTree::Literal(literal) => Some(literal.span()),
+            _ => None,
+        })
+        .map_or_else(Span::call_site, |span| Span::call_site().located_at(span));
+    let class_macro = class_macro_ident(name, span);
+    let callback = format_ident!("{}", macro_name);
+    let names = definitions.iter().map(|(name, _)| name);
+    let sources = definitions.iter().map(|(_, source)| source);
+    quote! {
+        #class_macro! { [::regex_parse::#callback] { #(#names = #sources,)* } #input }
+    }
+}
+
+fn class_macro_ident(name: &str, span: Span) -> Ident {
+    let name = name.strip_prefix("r#").unwrap_or(name);
+    format_ident!("__re_class_{}", name, span = span)
+}
+
+/// Splits the definitions of classes like `@classes { hex = "[0-9a-f]", }` off the start of the input of a macro
+fn split_definitions(input: TokenStream) -> (Vec<(Ident, LitStr)>, TokenStream) {
+    let mut trees = input.clone().into_iter();
+    let group = match (trees.next(), trees.next(), trees.next()) {
+        (
+            Some(TokenTree::Punct(at)),
+            Some(TokenTree::Ident(classes)),
+            Some(TokenTree::Group(group)),
+        ) if at.as_char() == '@'
+            && classes == "classes"
+            && group.delimiter() == Delimiter::Brace =>
+        {
+            group
+        }
+        _ => return (Vec::new(), input),
+    };
+    let parse_definitions = |input: ParseStream| {
+        Punctuated::<_, Token![,]>::parse_terminated_with(input, |input| {
+            let name = input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+            Ok((name, input.parse::<LitStr>()?))
+        })
+    };
+    let definitions = parse_definitions
+        .parse2(group.stream())
+        .expect("The definitions are generated by the macros of the classes");
+    (definitions.into_iter().collect(), trees.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classes::{split_definitions, PRESETS};
+    use crate::dfa::Dfa;
+    use crate::nfa::Nfa;
+    use crate::regex::Regex;
+
+    #[test]
+    fn test_presets() {
+        for (name, source) in PRESETS {
+            let regex = Regex::class(name, source).unwrap();
+            let nfa = Nfa::try_from(regex).unwrap();
+            let dfa = Dfa::try_from(nfa).unwrap();
+            assert!(dfa.iter().count() > 1, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_split_definitions() {
+        let input =
+            quote::quote! { @classes { hex = "[0-9a-f]", octet = "[0-7]", } r"\k{hex}", text };
+        let (definitions, rest) = split_definitions(input);
+        let definitions = definitions
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            [
+                ("hex".to_string(), "[0-9a-f]".to_string()),
+                ("octet".to_string(), "[0-7]".to_string())
+            ]
+        );
+        assert_eq!(
+            rest.to_string(),
+            quote::quote! { r"\k{hex}", text }.to_string()
+        );
+
+        let input = quote::quote! { "{a}", text };
+        let (definitions, rest) = split_definitions(input.clone());
+        assert!(definitions.is_empty());
+        assert_eq!(rest.to_string(), input.to_string());
+    }
+}
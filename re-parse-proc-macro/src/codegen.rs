@@ -1,154 +1,2030 @@
-use crate::dfa::{Dfa, DfaIndex};
-use crate::regex::VariableKind;
+use crate::dfa::{DeferredMatch, Dfa, DfaEdges, DfaIndex, DfaNode, GreedyEnd};
+use crate::regex::{CharKind, CharRange, RegexVariable, Transform, VariableKind};
+use crate::search::Search;
+use crate::time_format::TimeType;
 use crate::{Map, Set};
-use proc_macro2::{Ident, Span, TokenStream};
-use quote::quote;
-use syn::Expr;
+use proc_macro2::{Group, Ident, Literal, Span, TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{Block, Expr, Pat};
 
 pub struct Codegen {
     pub dfa: Dfa,
     pub expression: Expr,
+    pub mode: Mode,
+    /// Whether the state machine prints every character, state and capture to stderr (the `debug` option)
+    pub trace: bool,
+    /// Whether the transitions are looked up in static tables instead of a `match` for every state (the `compact` option)
+    pub compact: bool,
+    /// The span of the pattern literal, resolved at the call site of the macro. The captured variables get this span,
+    /// so the compiler reports unknown variables at the pattern and marks the error as coming from the macro.
+    pub pattern_span: Span,
+    /// The search for the leftmost match, which `re_find!` and `re_for_each!` need
+    pub search: Option<Search>,
+}
+
+/// Selects which macro the code is generated for
+pub enum Mode {
+    /// The whole input has to match and the captures are assigned to existing variables (`re_parse!`).
+    /// If there is a `fallback`, it runs instead of panicking when the input does not match.
+    Parse { fallback: Option<Fallback> },
+    /// `body` runs once for every match in the input, with the captures declared as new variables (`re_for_each!`)
+    ForEach { body: Expr },
+    /// The whole input has to match and the captures are assigned to the fields of `target`,
+    /// which is a mutable reference to a struct (`re_parse_into!`)
+    Into { target: Expr },
+    /// A prefix of the input starting at the byte offset `cursor` has to match.
+    /// The cursor is advanced to the end of the match (`re_parse_at!`).
+    ParseAt { cursor: Expr },
+    /// A prefix of the input has to match and the code evaluates to the unmatched remainder (`re_parse_prefix!`)
+    Prefix,
+    /// The first match anywhere in the input is used and the code evaluates to its byte range (`re_find!`)
+    Find,
+    /// `expression` is a `BufRead` and the code evaluates to an iterator that yields a tuple of the captures for every line.
+    /// Lines that do not match either panic or are skipped (`re_parse_lines!`).
+    Lines { skip_mismatches: bool },
+}
+
+/// The `else` clause of `re_parse!`, like `else |err| { return None }`
+pub struct Fallback {
+    /// The pattern that the error of the mismatch is bound to, if there is one
+    pub error: Option<Pat>,
+    pub body: Block,
+}
+
+impl Mode {
+    /// Whether the captures are declared as new variables, so they cannot be places like `self.x`
+    pub fn declares_variables(&self) -> bool {
+        matches!(self, Mode::ForEach { .. } | Mode::Lines { .. })
+    }
 }
 
 impl Codegen {
-    pub fn generate(self) -> TokenStream {
+    pub fn generate(&self) -> TokenStream {
         let variables = self.collect_variables();
         let variable_idents = variables
             .iter()
             .enumerate()
             .map(|(index, _)| Ident::new(&format!("__var_{index}"), Span::mixed_site()))
             .collect::<Vec<_>>();
-        let variable_map = variables
+        let variable_map = variables
+            .iter()
+            .zip(variable_idents.iter())
+            .map(|(var, ident)| {
+                (
+                    var.key(),
+                    Variable {
+                        ident: ident.clone(),
+                        kind: var.kind.clone(),
+                        name: var.name.clone(),
+                        mutable: var.mutable,
+                        ty: var.ty,
+                    },
+                )
+            })
+            .collect::<Map<_, _>>();
+
+        let expansion = match &self.mode {
+            Mode::Parse { .. } | Mode::Into { .. } => self.generate_parse(&variable_map),
+            Mode::ForEach { body } => self.generate_for_each(&variable_map, body),
+            Mode::ParseAt { cursor } => self.generate_parse_at(&variable_map, cursor),
+            Mode::Prefix => self.generate_prefix(&variable_map),
+            Mode::Find => self.generate_find(&variable_map),
+            Mode::Lines { skip_mismatches } => self.generate_lines(&variable_map, *skip_mismatches),
+        };
+        // With an `else` block, the generated code cannot panic
+        let parse_capture = match &self.mode {
+            Mode::Parse { fallback: Some(_) } => quote_try_parse_capture(),
+            _ => quote_parse_capture(),
+        };
+        let parse_capture_trait = quote_parse_capture_trait(&self.capture_listing());
+        quote! {
+            {
+                #parse_capture_trait
+                #parse_capture
+                #expansion
+            }
+        }
+    }
+
+    fn generate_parse(&self, variable_map: &Map<String, Variable>) -> TokenStream {
+        let fallback = match &self.mode {
+            Mode::Parse { fallback } => fallback.as_ref(),
+            _ => None,
+        };
+        let strategy = Strategy {
+            termination: Termination::EndOfInput,
+            failure: match fallback {
+                Some(_) => Failure::Fallback,
+                None => Failure::Panic,
+            },
+            trace: self.trace,
+            bytes: self.matches_bytes(),
+            backtracks: false,
+        };
+
+        let variable_setups = variable_map
+            .values()
+            .map(|var| self.quote_variable_setup(var));
+        let variable_finalizers = variable_map
+            .iter()
+            .map(|(k, v)| self.quote_variable_finalizer(v, k, Binding::Assign, variable_map));
+
+        let backreference_assertion =
+            self.quote_backreference_assertion(variable_map, strategy.failure);
+
+        let states = self.collect_states();
+        let state_enum = self.quote_state_enum(&states);
+        let state_machine = self.quote_state_machine(&states, variable_map, strategy, None);
+
+        let expr = &self.expression;
+        if let Some(Fallback { error, body }) = fallback {
+            let error = match error {
+                Some(error) => quote! { #error },
+                None => quote! { _ },
+            };
+            let captures = variable_map
+                .iter()
+                .filter_map(|(name, var)| self.quote_fallible_capture(var, name, variable_map))
+                .collect::<Vec<_>>();
+            let parses = captures.iter().map(|capture| &capture.parse);
+            let temporaries = captures
+                .iter()
+                .map(|capture| &capture.temporary)
+                .collect::<Vec<_>>();
+            let assignments = captures.iter().map(|capture| &capture.assign);
+            // The captures are only assigned if the input matched, so they are definitely initialized
+            // after the macro if the `else` block diverges
+            return quote! {
+                {
+                    #(#variable_setups)*
+
+                    #state_enum
+
+                    let __initial_input = #expr;
+                    let __result = '__re_parse: {
+                        #state_machine
+
+                        #backreference_assertion
+                        #(#parses)*
+                        ::core::result::Result::Ok((#(#temporaries,)*))
+                    };
+                    match __result {
+                        ::core::result::Result::Ok((#(#temporaries,)*)) => {
+                            #(#assignments)*
+                        }
+                        ::core::result::Result::Err(#error) => #body
+                    }
+                }
+            };
+        }
+        let target = match &self.mode {
+            Mode::Into { target } => {
+                let target_ident = Ident::new("__target", Span::mixed_site());
+                Some(quote! { let #target_ident: &mut _ = #target; })
+            }
+            _ => None,
+        };
+
+        quote! {
+            {
+                #(#variable_setups)*
+
+                #state_enum
+
+                #target
+                let __initial_input = #expr;
+                #state_machine
+
+                #backreference_assertion
+                #(#variable_finalizers)*
+            }
+        }
+    }
+
+    fn generate_for_each(&self, variable_map: &Map<String, Variable>, body: &Expr) -> TokenStream {
+        let strategy = Strategy {
+            termination: Termination::FirstAccept,
+            failure: Failure::Skip,
+            trace: self.trace,
+            bytes: self.matches_bytes(),
+            backtracks: false,
+        };
+
+        let variable_setups = variable_map
+            .values()
+            .map(|var| self.quote_variable_setup(var));
+        let variable_bindings = variable_map
+            .iter()
+            .map(|(k, v)| self.quote_variable_finalizer(v, k, Binding::Declare, variable_map));
+        // A match whose backreferences differ from their variables is skipped
+        let backreference_check = self
+            .quote_backreferences_match(variable_map)
+            .map(|matches| quote! { if !#matches { continue; } });
+
+        let states = self.collect_states();
+        let state_enum = self.quote_state_enum(&states);
+        let state_machine = self.quote_state_machine(
+            &states,
+            variable_map,
+            strategy,
+            Some(quote! { __haystack[..__match_start].chars().next_back() }),
+        );
+        let search = self.quote_search();
+
+        let expr = &self.expression;
+
+        quote! {
+            {
+                #state_enum
+                #search
+
+                let __haystack = #expr;
+                let mut __offset = 0_usize;
+                while let ::core::option::Option::Some(__match_start) = __match_start(__haystack, __offset) {
+                    let __initial_input = &__haystack[__match_start..];
+                    #(#variable_setups)*
+
+                    let __match_end = { #state_machine };
+                    // Skip a single character if nothing or only the empty string matched
+                    __offset = __match_start + match __match_end {
+                        ::core::option::Option::Some(__match_end) if __match_end > 0 => __match_end,
+                        _ => __initial_input.chars().next().map_or(1, ::core::primitive::char::len_utf8),
+                    };
+                    if __match_end.is_none() {
+                        continue;
+                    }
+                    #backreference_check
+
+                    #(#variable_bindings)*
+                    #body;
+                }
+            }
+        }
+    }
+
+    fn generate_parse_at(
+        &self,
+        variable_map: &Map<String, Variable>,
+        cursor: &Expr,
+    ) -> TokenStream {
+        let strategy = Strategy {
+            termination: Termination::FirstAccept,
+            failure: Failure::Panic,
+            trace: self.trace,
+            bytes: self.matches_bytes(),
+            backtracks: false,
+        };
+
+        let variable_setups = variable_map
+            .values()
+            .map(|var| self.quote_variable_setup(var));
+        let variable_finalizers = variable_map
+            .iter()
+            .map(|(k, v)| self.quote_variable_finalizer(v, k, Binding::Assign, variable_map));
+
+        let states = self.collect_states();
+        let state_enum = self.quote_state_enum(&states);
+        let state_machine = self.quote_state_machine(
+            &states,
+            variable_map,
+            strategy,
+            Some(quote! { __haystack[..*__cursor].chars().next_back() }),
+        );
+        let backreference_assertion =
+            self.quote_backreference_assertion(variable_map, strategy.failure);
+
+        let expr = &self.expression;
+
+        quote! {
+            {
+                #(#variable_setups)*
+
+                #state_enum
+
+                let __cursor: &mut usize = #cursor;
+                let __haystack: &str = &(#expr);
+                let __initial_input = &__haystack[*__cursor..];
+                let __match_end = { #state_machine };
+                #backreference_assertion
+                *__cursor += __match_end;
+
+                #(#variable_finalizers)*
+            }
+        }
+    }
+
+    fn generate_prefix(&self, variable_map: &Map<String, Variable>) -> TokenStream {
+        let strategy = Strategy {
+            termination: Termination::FirstAccept,
+            failure: Failure::Panic,
+            trace: self.trace,
+            bytes: self.matches_bytes(),
+            backtracks: false,
+        };
+
+        let variable_setups = variable_map
+            .values()
+            .map(|var| self.quote_variable_setup(var));
+        let variable_finalizers = variable_map
+            .iter()
+            .map(|(k, v)| self.quote_variable_finalizer(v, k, Binding::Assign, variable_map));
+
+        let states = self.collect_states();
+        let state_enum = self.quote_state_enum(&states);
+        let state_machine = self.quote_state_machine(&states, variable_map, strategy, None);
+        let backreference_assertion =
+            self.quote_backreference_assertion(variable_map, strategy.failure);
+
+        let expr = &self.expression;
+
+        quote! {
+            {
+                #(#variable_setups)*
+
+                #state_enum
+
+                let __initial_input: &str = &#expr;
+                let __match_end = { #state_machine };
+
+                #backreference_assertion
+                #(#variable_finalizers)*
+
+                &__initial_input[__match_end..]
+            }
+        }
+    }
+
+    fn generate_find(&self, variable_map: &Map<String, Variable>) -> TokenStream {
+        let strategy = Strategy {
+            termination: Termination::FirstAccept,
+            failure: Failure::Skip,
+            trace: self.trace,
+            bytes: self.matches_bytes(),
+            backtracks: false,
+        };
+
+        let variable_setups = variable_map
+            .values()
+            .map(|var| self.quote_variable_setup(var));
+        let variable_finalizers = variable_map
+            .iter()
+            .map(|(k, v)| self.quote_variable_finalizer(v, k, Binding::Assign, variable_map));
+
+        let states = self.collect_states();
+        let state_enum = self.quote_state_enum(&states);
+        let state_machine = self.quote_state_machine(
+            &states,
+            variable_map,
+            strategy,
+            Some(quote! { __haystack[..__match_start].chars().next_back() }),
+        );
+        let backreferences_match = self
+            .quote_backreferences_match(variable_map)
+            .map(|matches| quote! { .filter(|_| #matches) });
+
+        let search = self.quote_search();
+
+        let expr = &self.expression;
+
+        quote! {
+            {
+                #state_enum
+                #search
+
+                let __haystack: &str = &#expr;
+                let mut __offset = 0_usize;
+                loop {
+                    let ::core::option::Option::Some(__match_start) = __match_start(__haystack, __offset) else {
+                        panic!("No match found");
+                    };
+                    let __initial_input = &__haystack[__match_start..];
+                    #(#variable_setups)*
+
+                    let __match_end = { #state_machine }#backreferences_match;
+                    if let ::core::option::Option::Some(__match_end) = __match_end {
+                        #(#variable_finalizers)*
+                        break __match_start..__match_start + __match_end;
+                    }
+                    // The search does not compare backreferences and expected values, so it continues after the start
+                    __offset = __match_start + __initial_input.chars().next().map_or(1, ::core::primitive::char::len_utf8);
+                }
+            }
+        }
+    }
+
+    fn generate_lines(
+        &self,
+        variable_map: &Map<String, Variable>,
+        skip_mismatches: bool,
+    ) -> TokenStream {
+        let strategy = Strategy {
+            termination: Termination::EndOfInput,
+            failure: Failure::Skip,
+            trace: self.trace,
+            bytes: self.matches_bytes(),
+            backtracks: false,
+        };
+
+        let variable_setups = variable_map
+            .values()
+            .map(|var| self.quote_variable_setup(var));
+        let variable_bindings = variable_map
+            .iter()
+            .map(|(k, v)| self.quote_variable_finalizer(v, k, Binding::Declare, variable_map));
+        let tuple_fields = self
+            .dfa
+            .variables
+            .iter()
+            .flat_map(|var| match &var.kind {
+                VariableKind::Backreference { .. } | VariableKind::Expected { .. } => vec![],
+                VariableKind::Spanned { span } => vec![&*var.name, span],
+                VariableKind::Zipped { list, index: 0, .. } => vec![&**list],
+                VariableKind::Zipped { .. } => vec![],
+                _ => vec![&*var.name],
+            })
+            .map(|name| binding_ident(name, self.pattern_span));
+        let backreferences_match = self
+            .quote_backreferences_match(variable_map)
+            .map(|matches| quote! { .filter(|_| #matches) });
+
+        let states = self.collect_states();
+        let state_enum = self.quote_state_enum(&states);
+        let state_machine = self.quote_state_machine(&states, variable_map, strategy, None);
+
+        let reader = &self.expression;
+
+        // A line that does not match is skipped, or yields an error with the line
+        let check_match = if skip_mismatches {
+            quote! { __match_end?; }
+        } else {
+            quote! {
+                if __match_end.is_none() {
+                    return ::core::option::Option::Some(::core::result::Result::Err(
+                        ::regex_parse::LineError::Mismatch { line: __line_index + 1, text: __line },
+                    ));
+                }
+            }
+        };
+
+        quote! {
+            {
+                #state_enum
+
+                ::std::io::BufRead::lines(#reader)
+                    .enumerate()
+                    .filter_map(move |(__line_index, __line)| {
+                        let __line = match __line {
+                            ::core::result::Result::Ok(__line) => __line,
+                            ::core::result::Result::Err(error) => {
+                                return ::core::option::Option::Some(::core::result::Result::Err(
+                                    ::regex_parse::LineError::Io { line: __line_index + 1, error },
+                                ));
+                            }
+                        };
+                        let __initial_input: &str = &__line;
+                        #(#variable_setups)*
+
+                        let __match_end = { #state_machine }#backreferences_match;
+                        #check_match
+
+                        #(#variable_bindings)*
+                        ::core::option::Option::Some(::core::result::Result::Ok((#(#tuple_fields,)*)))
+                    })
+            }
+        }
+    }
+
+    /// Generates the function `__match_start(__haystack, __offset)`, which returns where the leftmost match at or after
+    /// the byte offset `__offset` starts, or `None` if there is none. It reads every character at most once, see
+    /// [`Search`]. Like in [`Self::quote_transition_tables`], the transitions are looked up in static tables by the
+    /// state and the class of the character, together with the index of the code that moves the starts of the slots.
+    fn quote_search(&self) -> TokenStream {
+        let search = self
+            .search
+            .as_ref()
+            .expect("The search is built for the macros that search the input");
+
+        // Letters that every state handles alike share a class
+        let mut actions: Map<&[Option<usize>], usize> = Map::default();
+        let mut classes: Map<Vec<(usize, usize)>, usize> = Map::default();
+        let letter_classes = (0..=search.letters.len())
+            .map(|column| {
+                let column = search
+                    .nodes
+                    .iter()
+                    .map(|node| {
+                        let transition = &node.transitions[column];
+                        let next_action = actions.len();
+                        let action = *actions.entry(&transition.sources).or_insert(next_action);
+                        (transition.target, action)
+                    })
+                    .collect();
+                let next_class = classes.len();
+                *classes.entry(column).or_insert(next_class)
+            })
+            .collect::<Vec<_>>();
+
+        let state_type = index_type(search.nodes.len());
+        let class_type = index_type(classes.len());
+        let action_type = index_type(actions.len());
+        let slot_type = index_type(search.slot_count);
+        let (class_tables, class) =
+            quote_char_classes(&search.letters, &letter_classes, &class_type, false);
+        let class_count = classes.len();
+        let state_count = search.nodes.len();
+        let rows = (0..state_count).map(|row| {
+            let entries = classes.keys().map(|column| {
+                let (target, action) = column[row];
+                let target = Literal::usize_unsuffixed(target);
+                let action = Literal::usize_unsuffixed(action);
+                quote! { (#target, #action) }
+            });
+            quote! { [#(#entries),*] }
+        });
+        let ends = search.nodes.iter().map(|node| match node.end {
+            Some(slot) => {
+                let slot = Literal::usize_unsuffixed(slot);
+                quote! { ::core::option::Option::Some(#slot) }
+            }
+            None => quote! { ::core::option::Option::None },
+        });
+
+        // The sources of the slots are in order, so every start is read before it is overwritten
+        let mut actions = actions
+            .into_keys()
+            .map(|sources| {
+                let moves = sources
+                    .iter()
+                    .enumerate()
+                    .filter(|(slot, source)| **source != Some(*slot))
+                    .map(|(slot, source)| {
+                        let start = match source {
+                            Some(source) => {
+                                let source = Literal::usize_unsuffixed(*source);
+                                quote! { __starts[#source] }
+                            }
+                            None => quote! { __offset + __byte_index + __next_char.len_utf8() },
+                        };
+                        let slot = Literal::usize_unsuffixed(slot);
+                        quote! { __starts[#slot] = #start; }
+                    });
+                quote! { { #(#moves)* } }
+            })
+            .collect::<Vec<_>>();
+        let last_action = actions.pop().expect("Every state has a transition");
+        let step = if actions.is_empty() {
+            quote! {
+                let (__target, _) = __SEARCH[__search as usize][(#class) as usize];
+                #last_action
+            }
+        } else {
+            let indices = (0..actions.len()).map(Literal::usize_unsuffixed);
+            quote! {
+                let (__target, __action) = __SEARCH[__search as usize][(#class) as usize];
+                match __action {
+                    #(#indices => #actions,)*
+                    _ => #last_action,
+                }
+            }
+        };
+        let found = search.found.map(|found| {
+            let found = Literal::usize_unsuffixed(found);
+            quote! {
+                if __search == #found {
+                    return ::core::option::Option::Some(__starts[0]);
+                }
+            }
+        });
+
+        let root = Literal::usize_unsuffixed(search.root);
+        let initial_state = if search.inner_roots.is_empty() {
+            quote! { #root }
+        } else {
+            let inner_roots = search.inner_roots.iter().map(|(kind, idx)| {
+                let idx = Literal::usize_unsuffixed(*idx);
+                let pattern = match kind {
+                    CharKind::Newline => quote! { '\n' },
+                    CharKind::Word => quote! { 'a'..='z' | 'A'..='Z' | '0'..='9' | '_' },
+                    CharKind::Other | CharKind::Boundary => quote! { _ },
+                };
+                quote! { ::core::option::Option::Some(#pattern) => #idx, }
+            });
+            quote! {
+                match __haystack[..__offset].chars().next_back() {
+                    ::core::option::Option::None => #root,
+                    #(#inner_roots)*
+                }
+            }
+        };
+        let slot_count = search.slot_count;
+
+        quote! {
+            fn __match_start(__haystack: &str, __offset: usize) -> ::core::option::Option<usize> {
+                #class_tables
+                static __SEARCH: [[(#state_type, #action_type); #class_count]; #state_count] = [#(#rows),*];
+                static __SEARCH_END: [::core::option::Option<#slot_type>; #state_count] = [#(#ends),*];
+
+                let __input = __haystack.get(__offset..)?;
+                let mut __search: #state_type = #initial_state;
+                let mut __starts = [__offset; #slot_count];
+                for (__byte_index, __next_char) in __input.char_indices() {
+                    #step
+                    __search = __target;
+                    #found
+                }
+                __SEARCH_END[__search as usize].map(|__slot| __starts[__slot as usize])
+            }
+        }
+    }
+
+    /// Generates the code that runs the state machine on `__initial_input`.
+    /// What the state machine evaluates to is determined by `strategy`.
+    /// `previous_char` evaluates to the character before `__initial_input`, if the input may not start at the beginning.
+    fn quote_state_machine(
+        &self,
+        states: &Map<DfaIndex, Ident>,
+        variables: &Map<String, Variable>,
+        strategy: Strategy,
+        previous_char: Option<TokenStream>,
+    ) -> TokenStream {
+        let strategy = Strategy {
+            backtracks: self.backtracks(strategy),
+            ..strategy
+        };
+        let root = &states[&self.dfa.root];
+        // A match that starts at the root state skips its literal prefix with a single comparison
+        let literal_prefix = (previous_char.is_none() || self.dfa.inner_roots.is_empty())
+            .then(|| self.literal_prefix(states, variables, strategy))
+            .flatten();
+        let initial_state = match previous_char {
+            Some(previous_char) if !self.dfa.inner_roots.is_empty() => {
+                let inner_roots = self.dfa.inner_roots.iter().map(|(kind, idx)| {
+                    let state = &states[idx];
+                    let pattern = match kind {
+                        CharKind::Newline => quote! { '\n' },
+                        CharKind::Word => quote! { 'a'..='z' | 'A'..='Z' | '0'..='9' | '_' },
+                        CharKind::Other | CharKind::Boundary => quote! { _ },
+                    };
+                    quote! { ::core::option::Option::Some(#pattern) => __State::#state, }
+                });
+                quote! {
+                    match #previous_char {
+                        ::core::option::Option::None => __State::#root,
+                        #(#inner_roots)*
+                    }
+                }
+            }
+            _ => quote! { __State::#root },
+        };
+
+        // Lookaheads that end a match early are only handled by matching on the states
+        let (tables, step) = if self.compact && !self.tracks_deferred_matches(strategy) {
+            let (tables, step) = self.quote_transition_tables(states, variables, strategy);
+            (Some(tables), step)
+        } else {
+            let state_branches = self.collect_state_branches(states, variables, strategy);
+            let step = quote! {
+                match __state {
+                    #(#state_branches),*
+                }
+            };
+            (None, step)
+        };
+        let (termination_tables, termination) =
+            self.quote_terminations(states, variables, strategy);
+        let lookahead_end = self
+            .tracks_deferred_matches(strategy)
+            .then(|| quote! { let mut __lookahead_end = 0_usize; });
+        // The positions where the greedy variable may end
+        let greedy_slot_count = self.greedy_slot_count();
+        let greedy_ends = (greedy_slot_count > 0)
+            .then(|| quote! { let mut __greedy_ends = [0_usize; #greedy_slot_count]; });
+        // Every character is handled by a single match on the dense state enum, which compiles to a jump table.
+        // Rejecting the input only calls a cold function, so that the loop stays small.
+        let reject_fn = (strategy.failure == Failure::Panic).then(quote_reject_fn);
+        let trace_char = strategy.trace.then(|| {
+            let next_char = strategy.quote_next_char();
+            quote! { ::std::eprintln!("[re_parse] {:?}: {:?} at byte {}", __state, #next_char, __byte_index); }
+        });
+        let trace_end = strategy
+            .trace
+            .then(|| quote! { ::std::eprintln!("[re_parse] {:?}: end of input", __state); });
+
+        let (input, next) = if strategy.bytes {
+            (
+                quote! {
+                    let __bytes = __initial_input.as_bytes();
+                    let mut __next_index = 0_usize;
+                },
+                quote! {
+                    let __byte_index = __next_index;
+                    let ::core::option::Option::Some(&__next_byte) = __bytes.get(__byte_index) else {
+                        #trace_end
+                        #termination
+                    };
+                    __next_index += 1;
+                    // A character that is not ASCII takes the default edge as a whole, so its other bytes are skipped
+                    if __next_byte >= 0x80 {
+                        __next_index += __next_byte.leading_ones() as usize - 1;
+                    }
+                },
+            )
+        } else {
+            (
+                quote! { let mut __input = __initial_input.char_indices(); },
+                quote! {
+                    let Some((__byte_index, __next_char)) = __input.next() else {
+                        #trace_end
+                        #termination
+                    };
+                },
+            )
+        };
+
+        let expected_table = self.quote_expected_table(states, strategy);
+        let initial_state = match literal_prefix {
+            Some((prefix, state)) => {
+                let skip = if strategy.bytes {
+                    let len = prefix.len();
+                    quote! { __next_index = #len; }
+                } else {
+                    let skipped = prefix.chars().count() - 1;
+                    quote! { __input.nth(#skipped); }
+                };
+                quote! {
+                    if __initial_input.starts_with(#prefix) {
+                        #skip
+                        __State::#state
+                    } else {
+                        #initial_state
+                    }
+                }
+            }
+            None => initial_state,
+        };
+
+        let run = quote! {
+            #input
+            let mut __variable_start = 0_usize;
+            #lookahead_end
+            #greedy_ends
+        };
+        if !strategy.backtracks {
+            return quote! {
+                #reject_fn
+                #expected_table
+                #termination_tables
+                #tables
+                #run
+
+                let mut __state = #initial_state;
+                loop {
+                    #next
+                    #trace_char
+                    #step
+                }
+            };
+        }
+
+        // The variables are reset before the state machine runs again
+        let resets = variables.values().map(|var| {
+            let ident = &var.ident;
+            let initial_value = Self::quote_initial_value(var);
+            quote! { #ident = #initial_value; }
+        });
+        let trace_replay = strategy.trace.then(|| {
+            quote! { ::std::eprintln!("[re_parse] backtracking to the match that ended at byte {}", __accept_at); }
+        });
+        let replay_accept = self.quote_replay_accept(states, variables, strategy);
+        quote! {
+            #reject_fn
+            #expected_table
+            #termination_tables
+            #tables
+            let mut __accept_at = usize::MAX;
+            '__replay: loop {
+                if __accept_at != usize::MAX {
+                    #trace_replay
+                    #(#resets)*
+                }
+                #run
+                let mut __last_accept = usize::MAX;
+
+                let mut __state = #initial_state;
+                break loop {
+                    #next
+                    if __byte_index == __accept_at {
+                        #replay_accept
+                    }
+                    #trace_char
+                    #step
+                };
+            }
+        }
+    }
+
+    /// Whether the state machine returns to the last position where a match could end if it rejects the input
+    /// after it, like in `ab|abcd` for `abce`. The transitions that continue a match from such a position remember it
+    /// in `__last_accept`. If the input is rejected later, the state machine runs again with the variables reset and
+    /// accepts the match when it reaches `__accept_at`, so that the variables end like in a match that ended there.
+    /// This runs the state machine at most twice, because the second run cannot be rejected before that position.
+    fn backtracks(&self, strategy: Strategy) -> bool {
+        strategy.termination == Termination::FirstAccept
+            && self
+                .dfa
+                .iter()
+                .any(|idx| self.accepts_before_any_edge(idx, strategy))
+    }
+
+    /// Whether a match may end in the state before one of the characters that continue it
+    fn accepts_before_any_edge(&self, dfa_idx: DfaIndex, strategy: Strategy) -> bool {
+        let state = &self.dfa.nodes[dfa_idx];
+        // The matches that ended before a lookahead are accepted right away
+        if self.tracks_deferred_matches(strategy) && state.deferred_match == DeferredMatch::Accepted
+        {
+            return false;
+        }
+        (state.edges.default.is_some() && self.accepts_before(dfa_idx, None))
+            || state
+                .edges
+                .edges
+                .iter()
+                .any(|(range, _)| self.accepts_before(dfa_idx, Some(*range)))
+    }
+
+    /// Accepts the match in the state that remembered `__accept_at`, see [`Self::backtracks`]
+    fn quote_replay_accept(
+        &self,
+        states: &Map<DfaIndex, Ident>,
+        variables: &Map<String, Variable>,
+        strategy: Strategy,
+    ) -> TokenStream {
+        // The states that accept the same way share an arm
+        let mut accepts: Map<String, (Vec<&Ident>, TokenStream)> = Map::default();
+        for (dfa_idx, internal_name) in states {
+            if !self.accepts_before_any_edge(*dfa_idx, strategy) {
+                continue;
+            }
+            let accept = self.accept_transition(*dfa_idx, variables).quote(strategy);
+            accepts
+                .entry(accept.to_string())
+                .or_insert_with(|| (Vec::new(), accept))
+                .0
+                .push(internal_name);
+        }
+        let accepting_states = accepts
+            .values()
+            .map(|(names, _)| names.len())
+            .sum::<usize>();
+        let mut arms = accepts
+            .into_values()
+            .map(|(internal_names, accept)| {
+                quote! {
+                    #(__State::#internal_names)|* => #accept
+                }
+            })
+            .collect::<Vec<_>>();
+        // Only the state that remembered the position can reach it again
+        if accepting_states < states.len() {
+            arms.push(quote! { _ => {} });
+        }
+        quote! {
+            match __state {
+                #(#arms),*
+            }
+        }
+    }
+
+    /// The literal that every match starts with, like `Temperatures: ` in `Temperatures: {temps*}`, and the state
+    /// after it. The characters of the literal are the only characters that their states accept, no match ends
+    /// within the literal, and no variable or counter changes on the way. If the input starts with the literal, the state machine continues after it,
+    /// otherwise it starts at the root state as usual, which reports where the input does not match.
+    /// A single character is not worth the comparison.
+    fn literal_prefix<'a>(
+        &self,
+        states: &'a Map<DfaIndex, Ident>,
+        variables: &Map<String, Variable>,
+        strategy: Strategy,
+    ) -> Option<(String, &'a Ident)> {
+        // The trace shows every character and lookaheads remember where a match started
+        if self.trace || self.tracks_deferred_matches(strategy) {
+            return None;
+        }
+        let mut prefix = String::new();
+        let mut current = self.dfa.root;
+        let mut visited = Set::default();
+        while visited.insert(current) && !self.dfa.nodes[current].may_accept() {
+            let edges = &self.dfa.nodes[current].edges;
+            let [(range, target)] = edges.edges.as_slice() else {
+                break;
+            };
+            if edges.default.is_some()
+                || visited.contains(target)
+                || range.start != range.end
+                || self.make_variable_update(current, *target, variables) != VariableUpdate::None
+                || self.make_greedy_update(current, *target, variables) != GreedyUpdate::None
+                || !self.counter_variables(*target, variables).is_empty()
+            {
+                break;
+            }
+            prefix.push(range.start);
+            current = *target;
+        }
+        (prefix.chars().count() > 1).then(|| (prefix, &states[&current]))
+    }
+
+    /// Whether the state machine can run on the bytes of the input instead of its characters, which is faster.
+    /// This is the case if every character that the states tell apart is ASCII, so that every other character
+    /// takes the default edge. The bytes of such a character after the first one are skipped.
+    fn matches_bytes(&self) -> bool {
+        self.dfa.iter().all(|idx| {
+            let state = &self.dfa.nodes[idx];
+            state
+                .edges
+                .explicit_ranges()
+                .chain(state.accepting_overrides.keys().copied())
+                .all(|range| range.end.is_ascii())
+        })
+    }
+
+    /// The place that a capture is bound to. `re_parse_into!` assigns to the fields of its target instead.
+    fn assigned_place(&self, name: &str) -> TokenStream {
+        let place = binding_place(name, self.pattern_span);
+        match self.mode {
+            Mode::Into { .. } => {
+                let target = Ident::new("__target", Span::mixed_site());
+                quote! { #target.#place }
+            }
+            _ => place,
+        }
+    }
+
+    /// Lists the variables that the pattern binds and whether they bind a single value or every match,
+    /// like "the pattern captures `name` (singular) and `scores` (multiple)"
+    fn capture_listing(&self) -> String {
+        let mut captures = self
+            .dfa
+            .variables
+            .iter()
+            .filter_map(|var| {
+                let multiple = match &var.kind {
+                    VariableKind::Backreference { .. } | VariableKind::Expected { .. } => {
+                        return None
+                    }
+                    VariableKind::Zipped { index: 0, list, .. } => {
+                        return Some(format!("`{list}` (multiple)"))
+                    }
+                    VariableKind::Zipped { .. } => return None,
+                    VariableKind::Multiple { .. }
+                    | VariableKind::Iter { .. }
+                    | VariableKind::Array { .. }
+                    | VariableKind::Nested { .. }
+                    | VariableKind::CsvRecord { .. } => true,
+                    _ => false,
+                };
+                let kind = if multiple { "multiple" } else { "singular" };
+                Some(format!("`{}` ({kind})", var.name))
+            })
+            .collect::<Vec<_>>();
+        let listing = match captures.pop() {
+            None => return "the pattern has no captures".to_string(),
+            Some(last) if captures.is_empty() => last,
+            Some(last) => format!("{} and {last}", captures.join(", ")),
+        };
+        // The note is a format string of `on_unimplemented`
+        format!(
+            "the pattern captures {}",
+            listing.replace('{', "{{").replace('}', "}}")
+        )
+    }
+
+    fn quote_variable_finalizer(
+        &self,
+        var: &Variable,
+        name: &str,
+        binding: Binding,
+        variables: &Map<String, Variable>,
+    ) -> TokenStream {
+        let ident = &var.ident;
+        // Backreferences are not bound, they are only compared to the first occurrence.
+        // Expected values were already compared while matching.
+        if let VariableKind::Backreference { .. } | VariableKind::Expected { .. } = var.kind {
+            return quote! {};
+        }
+        // The variables of a zipped group are bound together as one list by the first variable
+        if let VariableKind::Zipped { list, index, map } = &var.kind {
+            if *index > 0 {
+                return quote! {};
+            }
+            // A declared map has no type annotation, so it needs a concrete type
+            let collection = match (map, binding) {
+                (false, _) => quote! { ::<::std::vec::Vec<_>> },
+                (true, Binding::Assign) => quote! {},
+                (true, Binding::Declare) => quote! { ::<::std::collections::HashMap<_, _>> },
+            };
+            let value = Self::quote_zipped_value(list, variables, collection);
+            return binding.quote_binding(self.assigned_place(list), value);
+        }
+        let place = self.assigned_place(name);
+        let parsed = quote_parse(name, quote! { __initial_input[#ident] });
+        let parsed_span = quote_parse(name, quote! { __initial_input[span] });
+        // A declared list has no type annotation, so it needs a concrete type
+        let collect = match binding {
+            Binding::Assign => quote! { collect_captures() },
+            Binding::Declare => quote! { collect::<::std::vec::Vec<_>>() },
+        };
+        let mut check = quote! {};
+        let value = match &var.kind {
+            VariableKind::Singular | VariableKind::Greedy => parsed,
+            VariableKind::Empty => Self::quote_empty_value(ident, name),
+            VariableKind::Char => {
+                quote! { __initial_input[#ident.start..].chars().next().unwrap() }
+            }
+            VariableKind::Quoted => Self::quote_quoted_value(ident, name),
+            VariableKind::Csv => Self::quote_csv_value(quote! { #ident }, name),
+            VariableKind::CsvRecord { min } => {
+                let value = Self::quote_csv_record_value(ident, name, *min);
+                quote! { #value.#collect }
+            }
+            VariableKind::Time { format, ty } => Self::quote_time_value(ident, name, format, *ty),
+            VariableKind::Radix { radix } => Self::quote_radix_value(ident, name, *radix),
+            VariableKind::Mapped { mapping } => Self::quote_mapped_value(ident, mapping),
+            VariableKind::Converted {
+                transforms,
+                function,
+            } => Self::quote_converted_value(ident, name, transforms, function.as_deref()),
+            VariableKind::Spanned { span } => {
+                let span_place = self.assigned_place(span);
+                let range = self.quote_input_range(ident);
+                check = binding.quote_binding(span_place, range);
+                parsed
+            }
+            VariableKind::Optional { default } => {
+                Self::quote_optional_value(ident, name, default.as_deref())
+            }
+            VariableKind::Iter { min } => {
+                check = Self::quote_minimum_count_check(ident, name, *min);
+                quote! { #ident.into_iter().map(move |span| #parsed_span) }
+            }
+            VariableKind::Multiple { min } => {
+                check = Self::quote_minimum_count_check(ident, name, *min);
+                quote! { #ident.into_iter().map(|span| #parsed_span).#collect }
+            }
+            VariableKind::Array { len } => Self::quote_array_value(ident, name, *len),
+            VariableKind::Count => quote! { #ident },
+            VariableKind::Buffer { min, .. } => {
+                check = Self::quote_buffer_count_check(ident, name, *min);
+                quote! { #ident }
+            }
+            VariableKind::Nested { .. } => {
+                quote! { #ident.into_iter().map(|row| row.into_iter().map(|span| #parsed_span).#collect).#collect }
+            }
+            VariableKind::Backreference { .. }
+            | VariableKind::Expected { .. }
+            | VariableKind::Zipped { .. } => unreachable!(),
+        };
+        // A declared binding is annotated with the type of a typed capture like `{mut count:u32}`,
+        // because the body may not constrain the type enough to infer it.
+        // A capture that only matches in some branches is optional, so its value is not of that type.
+        let place = match binding {
+            Binding::Assign => place,
+            Binding::Declare => {
+                let mutability = var.mutable.then(|| quote! { mut });
+                let ty = var
+                    .ty
+                    .filter(|_| var.kind == VariableKind::Singular)
+                    .map(|ty| {
+                        let ty = Ident::new(ty, self.pattern_span);
+                        quote! { : ::core::primitive::#ty }
+                    });
+                quote! { #mutability #place #ty }
+            }
+        };
+        let binding = binding.quote_binding(place, value);
+        quote! { #check #binding }
+    }
+
+    /// Parses a capture of `re_parse!` with an `else` block without panicking, see [`FallibleCapture`].
+    /// Returns `None` for backreferences and expected values, which are not bound.
+    fn quote_fallible_capture(
+        &self,
+        var: &Variable,
+        name: &str,
+        variables: &Map<String, Variable>,
+    ) -> Option<FallibleCapture> {
+        let ident = &var.ident;
+        let text = quote! { __capture_text(__initial_input, #ident.clone()) };
+        let start = quote! { #ident.start };
+        // The value that is parsed may be made from the text, like the contents of a quoted string
+        let try_parse = |value: TokenStream, text: TokenStream| {
+            quote! { __try_parse(&(#value), #text, #start) }
+        };
+        let reject_at_end =
+            |message: String| Failure::quote_fallback(&message, quote! {__initial_input.len()});
+        if let VariableKind::Backreference { .. } | VariableKind::Expected { .. } = var.kind {
+            return None;
+        }
+        let mut check = quote! {};
+        let mut place = self.assigned_place(name);
+        let mut assign_span = quote! {};
+        let result = match &var.kind {
+            VariableKind::Backreference { .. } | VariableKind::Expected { .. } => unreachable!(),
+            VariableKind::Zipped { index, .. } if *index > 0 => return None,
+            VariableKind::Singular | VariableKind::Greedy => {
+                quote! { __try_parse_capture(__initial_input, #ident.clone()) }
+            }
+            VariableKind::Spanned { span } => {
+                let span_place = self.assigned_place(span);
+                let range = self.quote_input_range(ident);
+                assign_span = quote! { #span_place = #range; };
+                quote! { __try_parse_capture(__initial_input, #ident.clone()) }
+            }
+            VariableKind::Empty => quote! {
+                if #ident.is_empty() {
+                    ::core::result::Result::Ok(::core::default::Default::default())
+                } else {
+                    __try_parse_capture(__initial_input, #ident.clone())
+                }
+            },
+            VariableKind::Char => quote! {
+                #text.and_then(|__text| match __text.chars().next() {
+                    ::core::option::Option::Some(__char) => ::core::result::Result::Ok(__char),
+                    ::core::option::Option::None => __capture_error(#start, __text),
+                })
+            },
+            VariableKind::Quoted => {
+                let parsed = try_parse(quote! { __unescaped }, quote! { __text });
+                quote! {
+                    #text.and_then(|__text| {
+                        let ::core::option::Option::Some(__quoted) = __text
+                            .strip_prefix('"')
+                            .and_then(|__text| __text.strip_suffix('"'))
+                        else {
+                            return __capture_error(#start, __text);
+                        };
+                        let mut __unescaped = ::std::string::String::with_capacity(__quoted.len());
+                        let mut __chars = __quoted.chars();
+                        while let ::core::option::Option::Some(__char) = __chars.next() {
+                            match __char {
+                                '\\' => __unescaped.extend(__chars.next()),
+                                __char => __unescaped.push(__char),
+                            }
+                        }
+                        #parsed
+                    })
+                }
+            }
+            VariableKind::Csv => {
+                let unquoted = try_parse(
+                    quote! { __quoted.replace("\"\"", "\"") },
+                    quote! { __field },
+                );
+                let parsed = try_parse(quote! { __field }, quote! { __field });
+                quote! {
+                    #text.and_then(|__field| {
+                        match __field.strip_prefix('"').map(|__quoted| __quoted.strip_suffix('"')) {
+                            ::core::option::Option::Some(::core::option::Option::Some(__quoted)) => #unquoted,
+                            ::core::option::Option::Some(::core::option::Option::None) => {
+                                __capture_error(#start, __field)
+                            }
+                            ::core::option::Option::None => #parsed,
+                        }
+                    })
+                }
+            }
+            VariableKind::CsvRecord { min } => {
+                let parsed = try_parse(quote! { __field }, quote! { __record });
+                let min = *min as usize;
+                quote! {
+                    #text.and_then(|__record| {
+                        let mut __fields = ::std::vec::Vec::new();
+                        let mut __field = ::std::string::String::new();
+                        let mut __quoted = false;
+                        let mut __chars = __record.chars().peekable();
+                        while let ::core::option::Option::Some(__char) = __chars.next() {
+                            match (__char, __quoted) {
+                                ('"', true) if __chars.next_if_eq(&'"').is_some() => __field.push('"'),
+                                ('"', _) => __quoted = !__quoted,
+                                (',', false) => __fields.push(::core::mem::take(&mut __field)),
+                                (__char, _) => __field.push(__char),
+                            }
+                        }
+                        __fields.push(__field);
+                        if __fields.len() < #min {
+                            return __capture_error(#start, __record);
+                        }
+                        __fields.into_iter().map(|__field| #parsed).collect()
+                    })
+                }
+            }
+            VariableKind::Time { format, ty } => {
+                let path = syn::parse_str::<syn::ExprPath>(ty.path())
+                    .expect("Time type paths should be valid");
+                quote! {
+                    #text.and_then(|__text| {
+                        #path::parse_from_str(__text, #format).or_else(|_| __capture_error(#start, __text))
+                    })
+                }
+            }
+            VariableKind::Radix { radix } => quote! {
+                #text.and_then(|__text| {
+                    ::core::primitive::u128::from_str_radix(__text, #radix)
+                        .ok()
+                        .and_then(|__value| ::core::convert::TryInto::try_into(__value).ok())
+                        .map_or_else(|| __capture_error(#start, __text), ::core::result::Result::Ok)
+                })
+            },
+            VariableKind::Converted {
+                transforms,
+                function,
+            } => {
+                let methods = transforms
+                    .iter()
+                    .map(|transform| Ident::new(transform.method(), Span::call_site()));
+                let converted = quote! { __text #(.#methods())* };
+                let value = match function {
+                    Some(function) => {
+                        let function = syn::parse_str::<syn::ExprPath>(function)
+                            .expect("Functions are validated by the parser");
+                        quote! { #function(&(#converted)).or_else(|_| __capture_error(#start, __text)) }
+                    }
+                    None => try_parse(converted, quote! { __text }),
+                };
+                quote! { #text.and_then(|__text| #value) }
+            }
+            VariableKind::Mapped { mapping } => {
+                let branches = mapping.iter().map(|(text, value)| {
+                    let value = syn::parse_str::<syn::Expr>(value)
+                        .expect("Mapped values are validated by the parser");
+                    quote! { #text => ::core::result::Result::Ok(#value), }
+                });
+                quote! {
+                    #text.and_then(|__text| match __text {
+                        #(#branches)*
+                        _ => __capture_error(#start, __text),
+                    })
+                }
+            }
+            VariableKind::Optional { default } => {
+                let (parsed, default) = match default {
+                    Some(default) => {
+                        let default = syn::parse_str::<syn::Expr>(default)
+                            .expect("Default values are validated by the parser");
+                        (quote! { __parsed }, quote! { #default })
+                    }
+                    None => (
+                        quote! { __parsed.map(::core::option::Option::Some) },
+                        quote! { ::core::option::Option::None },
+                    ),
+                };
+                quote! {
+                    match #ident.clone() {
+                        ::core::option::Option::Some(__span) => {
+                            let __parsed = __try_parse_capture(__initial_input, __span);
+                            #parsed
+                        }
+                        ::core::option::Option::None => ::core::result::Result::Ok(#default),
+                    }
+                }
+            }
+            VariableKind::Multiple { min } | VariableKind::Iter { min } => {
+                if *min > 0 {
+                    let reject =
+                        reject_at_end(format!("Expected at least {min} matches for {name}"));
+                    let min = *min as usize;
+                    check = quote! { if #ident.len() < #min { #reject; } };
+                }
+                let parsed = quote! {
+                    #ident
+                        .iter()
+                        .map(|__span| __try_parse_capture(__initial_input, __span.clone()))
+                };
+                match var.kind {
+                    // The captures are parsed before the iterator is bound, so that a mismatch can still be rejected
+                    VariableKind::Iter { .. } => quote! {
+                        #parsed
+                            .collect::<::core::result::Result<::std::vec::Vec<_>, ::regex_parse::CaptureError<'_>>>()
+                            .map(::core::iter::IntoIterator::into_iter)
+                    },
+                    _ => quote! { #parsed.collect() },
+                }
+            }
+            VariableKind::Array { len } => {
+                let reject = reject_at_end(format!("Expected exactly {len} matches for {name}"));
+                let len = *len as usize;
+                check = quote! { if #ident.len() != #len { #reject; } };
+                quote! {
+                    #ident
+                        .iter()
+                        .map(|__span| __try_parse_capture(__initial_input, __span.clone()))
+                        .collect::<::core::result::Result<::std::vec::Vec<_>, ::regex_parse::CaptureError<'_>>>()
+                        .and_then(|__values| {
+                            <[_; #len] as ::core::convert::TryFrom<_>>::try_from(__values)
+                                .or_else(|_| __capture_error(__initial_input.len(), ""))
+                        })
+                }
+            }
+            VariableKind::Zipped { list, map, .. } => {
+                let elements = zipped_elements(list, variables);
+                let first = &elements[0].2;
+                let reject = reject_at_end(format!(
+                    "Expected every variable of {list} to match once per repetition"
+                ));
+                let lengths = elements.iter().map(|(.., ident)| quote! { #ident.len() });
+                check = quote! { if #(#first.len() != #lengths)||* { #reject; } };
+                let values = elements.iter().map(|(_, _, ident)| {
+                    quote! {
+                        match #ident.get(__index) {
+                            ::core::option::Option::Some(__span) => {
+                                __try_parse_capture(__initial_input, __span.clone())?
+                            }
+                            ::core::option::Option::None => {
+                                return __capture_error(__initial_input.len(), "");
+                            }
+                        }
+                    }
+                });
+                let collection = if *map {
+                    quote! { ::core::result::Result<_, ::regex_parse::CaptureError<'_>> }
+                } else {
+                    quote! { ::core::result::Result<::std::vec::Vec<_>, ::regex_parse::CaptureError<'_>> }
+                };
+                place = self.assigned_place(list);
+                quote! {
+                    (0..#first.len())
+                        .map(|__index| -> ::core::result::Result<_, ::regex_parse::CaptureError<'_>> {
+                            ::core::result::Result::Ok((#(#values,)*))
+                        })
+                        .collect::<#collection>()
+                }
+            }
+            VariableKind::Nested { .. } => quote! {
+                #ident
+                    .iter()
+                    .map(|__row| {
+                        __row
+                            .iter()
+                            .map(|__span| __try_parse_capture(__initial_input, __span.clone()))
+                            .collect()
+                    })
+                    .collect()
+            },
+            VariableKind::Count => {
+                quote! { ::core::result::Result::<_, ::regex_parse::CaptureError<'_>>::Ok(#ident) }
+            }
+            VariableKind::Buffer { min, .. } => {
+                if *min > 0 {
+                    let reject =
+                        reject_at_end(format!("Expected at least {min} matches for {name}"));
+                    let min = *min as usize;
+                    check = quote! { if #ident < #min { #reject; } };
+                }
+                quote! { ::core::result::Result::<_, ::regex_parse::CaptureError<'_>>::Ok(#ident) }
+            }
+        };
+        let temporary = Ident::new(&format!("{ident}_parsed"), Span::mixed_site());
+        let value = quote_or_reject(name, result);
+        Some(FallibleCapture {
+            parse: quote! {
+                #check
+                let #temporary = #value;
+            },
+            assign: quote! {
+                #assign_span
+                #place = #temporary;
+            },
+            temporary,
+        })
+    }
+
+    /// Evaluates to whether every backreference matched the same text as the first occurrence of its variable,
+    /// and every expected value like `{=prefix}` matched its value.
+    /// Returns `None` if the pattern has no backreferences and no expected values.
+    fn quote_backreferences_match(&self, variables: &Map<String, Variable>) -> Option<TokenStream> {
+        let comparisons = self
+            .quote_backreference_comparisons(variables)
+            .into_iter()
+            .chain(
+                self.expected_values(variables)
+                    .map(|(ident, _)| quote! { #ident }),
+            )
+            .collect::<Vec<_>>();
+        if comparisons.is_empty() {
+            return None;
+        }
+        Some(quote! { (#(#comparisons)&&*) })
+    }
+
+    fn quote_backreference_comparisons(
+        &self,
+        variables: &Map<String, Variable>,
+    ) -> Vec<TokenStream> {
+        self.dfa
+            .variables
             .iter()
-            .zip(variable_idents.iter())
-            .map(|(var, ident)| {
-                (
-                    var.ident.to_string(),
-                    Variable {
-                        ident: ident.clone(),
-                        kind: var.kind,
-                    },
-                )
+            .filter(|var| matches!(var.kind, VariableKind::Backreference { .. }))
+            .filter_map(|var| {
+                let first = &variables.get(&var.name)?.ident;
+                let backreference = &variables.get(&var.key())?.ident;
+                Some(quote! { __initial_input.get(#first.clone()) == __initial_input.get(#backreference.clone()) })
             })
-            .collect::<Map<_, _>>();
+            .collect()
+    }
 
-        let variable_setups = variable_map
-            .values()
-            .map(|var| self.quote_variable_setup(var));
-        let variable_finalizers = variable_map
-            .iter()
-            .map(|(k, v)| self.quote_variable_finalizer(v, k));
+    /// The identifiers of the expected values like `{=prefix}`, which are true if the input matched the value,
+    /// together with the expression of the value
+    fn expected_values<'a>(
+        &'a self,
+        variables: &'a Map<String, Variable>,
+    ) -> impl Iterator<Item = (&'a Ident, &'a str)> {
+        self.dfa.variables.iter().filter_map(|var| match &var.kind {
+            VariableKind::Expected { value, .. } => {
+                Some((&variables.get(&var.key())?.ident, value.as_str()))
+            }
+            _ => None,
+        })
+    }
 
-        let states = self.collect_states();
-        let internal_states = states.values();
-        let initial_state = &states[&self.dfa.root];
+    /// Rejects the input if a backreference did not match the same text as the first occurrence of its variable,
+    /// or if an expected value like `{=prefix}` did not match its value
+    fn quote_backreference_assertion(
+        &self,
+        variables: &Map<String, Variable>,
+        failure: Failure,
+    ) -> TokenStream {
+        let comparisons = self.quote_backreference_comparisons(variables);
+        let backreference_assertion = (!comparisons.is_empty()).then(|| {
+            let reject = failure.quote_mismatch(
+                "The repeated variables of the pattern did not match the same text",
+            );
+            quote! {
+                if !(#(#comparisons)&&*) {
+                    #reject;
+                }
+            }
+        });
+        let expected_assertions = self.expected_values(variables).map(|(ident, value)| {
+            let message = format!("The input did not match the expected value `{value}`");
+            let reject = failure.quote_mismatch(&message);
+            quote! {
+                if !#ident {
+                    #reject;
+                }
+            }
+        });
+        quote! {
+            #backreference_assertion
+            #(#expected_assertions)*
+        }
+    }
 
-        let state_branches = self.collect_state_branches(&states, &variable_map);
-        let state_terminations = self.collect_state_terminations(&states, &variable_map);
+    /// Converts the range of a variable in `__initial_input` to a range in the whole input.
+    /// `__initial_input` is a suffix of `__haystack` if the match does not have to start at the beginning of the input.
+    fn quote_input_range(&self, ident: &Ident) -> TokenStream {
+        match self.mode {
+            Mode::ForEach { .. } | Mode::ParseAt { .. } | Mode::Find => quote! {
+                {
+                    let __input_offset = __haystack.len() - __initial_input.len();
+                    #ident.start + __input_offset..#ident.end + __input_offset
+                }
+            },
+            Mode::Parse { .. } | Mode::Into { .. } | Mode::Prefix | Mode::Lines { .. } => {
+                quote! { #ident.clone() }
+            }
+        }
+    }
 
-        let expr = &self.expression;
+    fn quote_time_value(ident: &Ident, name: &str, format: &str, ty: TimeType) -> TokenStream {
+        let path =
+            syn::parse_str::<syn::ExprPath>(ty.path()).expect("Time type paths should be valid");
+        quote! {{
+            let __text = &__initial_input[#ident];
+            __unwrap_capture(#name, __text, #path::parse_from_str(__text, #format))
+        }}
+    }
 
+    /// The value of a possibly empty variable like `{name??}`, which is the default value if nothing was matched
+    fn quote_empty_value(ident: &Ident, name: &str) -> TokenStream {
+        let parsed = quote_parse(name, quote! { __initial_input[#ident] });
         quote! {
-            {
-                #(#variable_setups)*
+            if #ident.is_empty() {
+                ::core::default::Default::default()
+            } else {
+                #parsed
+            }
+        }
+    }
 
-                enum __State {
-                    #(#internal_states),*
+    /// Removes the quotes and backslash escapes of a quoted string like `"say \"hi\""` and parses the contents
+    fn quote_quoted_value(ident: &Ident, name: &str) -> TokenStream {
+        let parsed = quote_parse(name, quote! { __unescaped });
+        quote! {{
+            let __quoted = &__initial_input[#ident.start + 1..#ident.end - 1];
+            let mut __unescaped = ::std::string::String::with_capacity(__quoted.len());
+            let mut __chars = __quoted.chars();
+            while let ::core::option::Option::Some(__char) = __chars.next() {
+                match __char {
+                    '\\' => __unescaped.extend(__chars.next()),
+                    __char => __unescaped.push(__char),
                 }
+            }
+            #parsed
+        }}
+    }
 
-                let __initial_input = #expr;
-                let mut __input = __initial_input.char_indices();
-                let mut __variable_start = 0_usize;
+    /// Removes the quotes of a quoted CSV field like `"a ""b"", c"` and parses the field
+    fn quote_csv_value(span: TokenStream, name: &str) -> TokenStream {
+        let unquoted = quote_parse(
+            name,
+            quote! { __quoted[..__quoted.len() - 1].replace("\"\"", "\"") },
+        );
+        let parsed = quote_parse(name, quote! { __initial_input[#span] });
+        quote! {
+            match __initial_input[#span.clone()].strip_prefix('"') {
+                ::core::option::Option::Some(__quoted) => #unquoted,
+                ::core::option::Option::None => #parsed,
+            }
+        }
+    }
 
-                let mut __state = __State::#initial_state;
-                loop {
-                    let Some((__byte_index, __next_char)) = __input.next() else {
-                        match __state {
-                            #(#state_terminations),*
-                        }
-                    };
-                    match __state {
-                        #(#state_branches),*
-                    }
+    /// Splits a CSV record into its unquoted fields and evaluates to an iterator that parses them.
+    /// The record is split after it was matched, because the pattern can not capture empty fields on its own.
+    fn quote_csv_record_value(ident: &Ident, name: &str, min: u32) -> TokenStream {
+        let parsed = quote_parse(name, quote! { __field });
+        let check = if min > 0 {
+            let message = format!("Expected at least {min} fields for {name}, got {{}}");
+            let min = min as usize;
+            quote! { assert!(__fields.len() >= #min, #message, __fields.len()); }
+        } else {
+            quote! {}
+        };
+        quote! {{
+            let mut __fields = ::std::vec::Vec::new();
+            let mut __field = ::std::string::String::new();
+            let mut __quoted = false;
+            let mut __chars = __initial_input[#ident].chars().peekable();
+            while let ::core::option::Option::Some(__char) = __chars.next() {
+                match (__char, __quoted) {
+                    ('"', true) if __chars.next_if_eq(&'"').is_some() => __field.push('"'),
+                    ('"', _) => __quoted = !__quoted,
+                    (',', false) => __fields.push(::core::mem::take(&mut __field)),
+                    (__char, _) => __field.push(__char),
                 }
+            }
+            __fields.push(__field);
+            #check
+            __fields.into_iter().map(|__field| #parsed)
+        }}
+    }
 
-                #(#variable_finalizers)*
+    /// Parses an integer in another radix. `from_str_radix` is not part of a trait, so the integer is parsed
+    /// as the largest type first and then converted to the type of the variable.
+    /// Both errors are reported with the type of the variable.
+    fn quote_radix_value(ident: &Ident, name: &str, radix: u32) -> TokenStream {
+        quote! {{
+            let __text = &__initial_input[#ident];
+            match ::core::primitive::u128::from_str_radix(__text, #radix) {
+                ::core::result::Result::Ok(__value) => {
+                    __unwrap_capture(#name, __text, ::core::convert::TryInto::try_into(__value))
+                }
+                ::core::result::Result::Err(__err) => {
+                    __unwrap_capture(#name, __text, ::core::result::Result::Err(__err))
+                }
             }
+        }}
+    }
+
+    /// Calls the function of a converted variable with the captured text
+    fn quote_converted_value(
+        ident: &Ident,
+        name: &str,
+        transforms: &[Transform],
+        function: Option<&str>,
+    ) -> TokenStream {
+        let methods = transforms
+            .iter()
+            .map(|transform| Ident::new(transform.method(), Span::call_site()));
+        match function {
+            Some(function) => {
+                let function = syn::parse_str::<syn::ExprPath>(function)
+                    .expect("Functions are validated by the parser");
+                quote! {{
+                    let __text = &__initial_input[#ident];
+                    __unwrap_capture(#name, __text, #function(&(__text #(.#methods())*)))
+                }}
+            }
+            None => quote_parse(name, quote! { __initial_input[#ident] #(.#methods())* }),
         }
     }
 
-    fn quote_variable_finalizer(&self, var: &Variable, name: &str) -> TokenStream {
-        let ident = &var.ident;
-        let original_ident = Ident::new(name, Span::call_site());
-        match var.kind {
-            VariableKind::Singular => {
-                quote! { #original_ident = __initial_input[#ident].parse().unwrap();}
+    /// Evaluates to the value of the branch that matched, without parsing the captured text
+    fn quote_mapped_value(ident: &Ident, mapping: &[(String, String)]) -> TokenStream {
+        let branches = mapping.iter().map(|(text, value)| {
+            let value = syn::parse_str::<syn::Expr>(value)
+                .expect("Mapped values are validated by the parser");
+            quote! { #text => #value, }
+        });
+        quote! {
+            match &__initial_input[#ident] {
+                #(#branches)*
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Parses an optional variable, falling back to the default expression if there is one
+    fn quote_optional_value(ident: &Ident, name: &str, default: Option<&str>) -> TokenStream {
+        let parsed = quote_parse(name, quote! { __initial_input[span] });
+        let value = quote! { #ident.map(|span| #parsed) };
+        match default {
+            Some(default) => {
+                let default = syn::parse_str::<syn::Expr>(default)
+                    .expect("Default values are validated by the parser");
+                quote! { #value.unwrap_or_else(|| #default) }
+            }
+            None => value,
+        }
+    }
+
+    /// Builds the array of an array variable. The pattern guarantees the length, but it is checked again anyway.
+    fn quote_array_value(ident: &Ident, name: &str, len: u32) -> TokenStream {
+        let message = format!("Expected exactly {len} matches for {name}, got {{}}");
+        let parsed = quote_parse(name, quote! { __initial_input[__spans.next().unwrap()] });
+        let len = len as usize;
+        quote! {
+            {
+                assert_eq!(#ident.len(), #len, #message, #ident.len());
+                let mut __spans = #ident.into_iter();
+                ::core::array::from_fn::<_, #len, _>(|_| #parsed)
             }
-            VariableKind::Multiple => {
-                quote! { #original_ident = #ident.into_iter().map(|span| __initial_input[span].parse().unwrap()).collect(); }
+        }
+    }
+
+    /// Builds the list of a zipped group like `({x},{y};)*=pairs`, with one tuple per repetition of the group.
+    /// Every variable of the group matches once per repetition, so the captures of the variables line up.
+    /// The tuples are collected in the order of the repetitions, so for a map, a later key replaces an earlier one.
+    fn quote_zipped_value(
+        list: &str,
+        variables: &Map<String, Variable>,
+        collection: TokenStream,
+    ) -> TokenStream {
+        let elements = zipped_elements(list, variables);
+        let idents = elements
+            .iter()
+            .map(|(.., ident)| *ident)
+            .collect::<Vec<_>>();
+        let values = elements.iter().map(|(_, name, ident)| {
+            quote_parse(name, quote! { __initial_input[#ident[__index].clone()] })
+        });
+        let first = idents[0];
+        let message = format!("Expected every variable of {list} to match once per repetition");
+        quote! {
+            {
+                #(assert_eq!(#first.len(), #idents.len(), #message);)*
+                (0..#first.len())
+                    .map(|__index| (#(#values,)*))
+                    .collect #collection ()
             }
         }
     }
 
+    fn quote_minimum_count_check(ident: &Ident, name: &str, min: u32) -> TokenStream {
+        if min == 0 {
+            return quote! {};
+        }
+        let message = format!("Expected at least {min} matches for {name}, got {{}}");
+        let min = min as usize;
+        quote! { assert!(#ident.len() >= #min, #message, #ident.len()); }
+    }
+
+    fn quote_buffer_count_check(ident: &Ident, name: &str, min: u32) -> TokenStream {
+        if min == 0 {
+            return quote! {};
+        }
+        let message = format!("Expected at least {min} matches for {name}, got {{}}");
+        let min = min as usize;
+        quote! { assert!(#ident >= #min, #message, #ident); }
+    }
+
     fn quote_variable_setup(&self, var: &Variable) -> TokenStream {
         let ident = &var.ident;
+        let initial_value = Self::quote_initial_value(var);
+        // Whether every match of the expected value was equal to it so far
+        if let VariableKind::Expected { value, .. } = &var.kind {
+            let value_ident = expected_value_ident(ident);
+            let owned_ident = Ident::new(&format!("{value_ident}_owned"), Span::mixed_site());
+            let value = respan(
+                value
+                    .parse()
+                    .expect("Expected values are validated by the parser"),
+                self.pattern_span,
+            );
+            return quote! {
+                let mut #ident = #initial_value;
+                let #owned_ident = &(#value);
+                let #value_ident: &str = ::core::convert::AsRef::<str>::as_ref(#owned_ident);
+            };
+        }
+        quote! { let mut #ident = #initial_value; }
+    }
+
+    /// The value of a variable before the state machine runs, see [`Self::quote_variable_setup`]
+    fn quote_initial_value(var: &Variable) -> TokenStream {
         match var.kind {
-            VariableKind::Singular => quote! { let mut #ident = 0_usize..0; },
-            VariableKind::Multiple => quote! { let mut #ident = ::std::vec::Vec::new(); },
+            VariableKind::Singular
+            | VariableKind::Greedy
+            | VariableKind::Empty
+            | VariableKind::Char
+            | VariableKind::Quoted
+            | VariableKind::Csv
+            | VariableKind::CsvRecord { .. }
+            | VariableKind::Time { .. }
+            | VariableKind::Radix { .. }
+            | VariableKind::Converted { .. }
+            | VariableKind::Mapped { .. }
+            | VariableKind::Spanned { .. }
+            | VariableKind::Backreference { .. } => quote! { 0_usize..0 },
+            VariableKind::Optional { .. } => {
+                quote! { ::core::option::Option::<::core::ops::Range<usize>>::None }
+            }
+            VariableKind::Multiple { .. }
+            | VariableKind::Iter { .. }
+            | VariableKind::Array { .. }
+            | VariableKind::Zipped { .. } => quote! { ::std::vec::Vec::new() },
+            VariableKind::Count | VariableKind::Buffer { .. } => quote! { 0_usize },
+            VariableKind::Expected { .. } => quote! { true },
+            // The lists are accessed with `last_mut` before the first push, so the type is needed
+            VariableKind::Nested { .. } => {
+                quote! { ::std::vec::Vec::<::std::vec::Vec<::core::ops::Range<usize>>>::new() }
+            }
         }
     }
 
-    fn collect_state_terminations(
+    /// Ends the state machine at the end of the input. Most states only accept or reject the end of the input and end
+    /// the variable that they capture. They look up whether they accept in `__ACCEPTING` and the index of their variable
+    /// in `__VARIABLES`, which holds the number of variables for states without one, so that only the variables need
+    /// an arm. The states that end a greedy variable or accept a match before a lookahead have an arm of their own.
+    /// Returns the tables and the code that ends the state machine.
+    fn quote_terminations(
         &self,
         states: &Map<DfaIndex, Ident>,
         variables: &Map<String, Variable>,
-    ) -> Vec<TokenStream> {
-        states
-            .iter()
-            .map(|(dfa_idx, internal_name)| {
-                self.collect_state_termination(*dfa_idx, internal_name, variables)
+        strategy: Strategy,
+    ) -> (Option<TokenStream>, TokenStream) {
+        // The states that end the same way share an arm
+        let mut terminations: Map<String, (Vec<&Ident>, TokenStream)> = Map::default();
+        let mut looked_up = false;
+        let mut variable_ends = Map::default();
+        for (dfa_idx, internal_name) in states {
+            if let Some(termination) = self.collect_state_termination(*dfa_idx, variables, strategy)
+            {
+                terminations
+                    .entry(termination.to_string())
+                    .or_insert_with(|| (Vec::new(), termination))
+                    .0
+                    .push(internal_name);
+                continue;
+            }
+            looked_up = true;
+            if let Some(var) = self.ended_variable(*dfa_idx) {
+                variable_ends
+                    .entry(variable_index(variables, var))
+                    .or_insert_with(|| {
+                        self.quote_variable_end(&state_variable(variables, var), strategy)
+                    });
+            }
+        }
+        let mut arms = terminations
+            .into_values()
+            .map(|(internal_names, termination)| {
+                quote! {
+                    #(__State::#internal_names)|* => #termination
+                }
             })
-            .collect()
+            .collect::<Vec<_>>();
+        if !looked_up {
+            return (None, quote! { match __state { #(#arms),* } });
+        }
+
+        let state_count = states.len();
+        let accepting = states.keys().map(|idx| self.dfa.nodes[*idx].is_accepting);
+        let mut tables = quote! {
+            static __ACCEPTING: [bool; #state_count] = [#(#accepting),*];
+        };
+        let variable_end = (!variable_ends.is_empty()).then(|| {
+            let index_type = index_type(variables.len() + 1);
+            let indices = states.keys().map(|idx| {
+                let index = self
+                    .ended_variable(*idx)
+                    .map_or(variables.len(), |var| variable_index(variables, var));
+                Literal::usize_unsuffixed(index)
+            });
+            tables.extend(quote! {
+                static __VARIABLES: [#index_type; #state_count] = [#(#indices),*];
+            });
+            let (indices, ends): (Vec<_>, Vec<_>) = variable_ends
+                .into_iter()
+                .map(|(index, end)| (Literal::usize_unsuffixed(index), end))
+                .unzip();
+            quote! {
+                match __VARIABLES.get(__state as usize) {
+                    #(::core::option::Option::Some(&#indices) => #ends)*
+                    _ => {}
+                }
+            }
+        });
+        let reject = self.quote_end_of_input_reject(strategy);
+        let accept = strategy.quote_accept(quote! {__initial_input.len()});
+        let looked_up = quote! {{
+            if !__ACCEPTING.get(__state as usize).copied().unwrap_or_default() {
+                #reject
+            }
+            #variable_end
+            #accept
+        }};
+        let termination = if arms.is_empty() {
+            looked_up
+        } else {
+            arms.push(quote! { _ => #looked_up });
+            quote! { match __state { #(#arms),* } }
+        };
+        (Some(tables), termination)
+    }
+
+    /// The variable that a state ends if the input ends in it
+    fn ended_variable(&self, dfa_idx: DfaIndex) -> Option<&RegexVariable> {
+        let state = &self.dfa.nodes[dfa_idx];
+        state.variable.as_ref().filter(|_| state.is_accepting)
+    }
+
+    /// Ends the variable at the end of the input
+    fn quote_variable_end(&self, var: &Variable, strategy: Strategy) -> TokenStream {
+        let trace = strategy
+            .trace
+            .then(|| VariableUpdate::End(var.clone()).quote_trace(quote! {__initial_input.len()}));
+        let update =
+            self.quote_update_variable(var, quote! {__initial_input.len()}, strategy.failure);
+        quote! {{
+            #trace
+            #update;
+        }}
     }
 
+    /// The termination of a state that cannot be looked up in the tables of [`Self::quote_terminations`]
     fn collect_state_termination(
         &self,
         dfa_idx: DfaIndex,
-        internal_name: &Ident,
         variables: &Map<String, Variable>,
-    ) -> TokenStream {
+        strategy: Strategy,
+    ) -> Option<TokenStream> {
         let state = &self.dfa.nodes[dfa_idx];
 
-        let panic_message = format!("Unexpected end of input ({internal_name})");
+        if self.tracks_deferred_matches(strategy) && state.deferred_match == DeferredMatch::Accepted
+        {
+            return Some(self.quote_deferred_accept(dfa_idx, variables, strategy));
+        }
 
-        let termination = match (state.is_accepting, &state.variable) {
-            (true, Some(var)) => {
-                let internal_var = &variables[&var.name];
-                let update =
-                    self.quote_update_variable(internal_var, quote! {__initial_input.len()});
-                quote! {
-                    {
-                        #update;
-                        break;
-                    }
-                }
+        // A match that ended before a negative lookahead is accepted, because the lookahead can not match anymore
+        if self.tracks_deferred_matches(strategy)
+            && !state.is_accepting
+            && state.deferred_match == DeferredMatch::AcceptedAtEnd
+        {
+            return Some(self.quote_deferred_accept(dfa_idx, variables, strategy));
+        }
+
+        let greedy_end = self
+            .greedy_accept_update(state, variables)
+            .quote(quote! {__initial_input.len()});
+        if greedy_end.is_empty() {
+            return None;
+        }
+        if !state.is_accepting {
+            return Some(self.quote_end_of_input_reject(strategy));
+        }
+        let variable_end = self
+            .ended_variable(dfa_idx)
+            .map(|var| self.quote_variable_end(&state_variable(variables, var), strategy));
+        let accept = strategy.quote_accept(quote! {__initial_input.len()});
+        Some(quote! {
+            {
+                #greedy_end
+                #variable_end
+                #accept;
             }
-            (true, None) => quote! { break },
-            (false, _) => quote! {panic!(#panic_message)},
-        };
+        })
+    }
 
-        quote! {
-            __State::#internal_name => #termination
+    /// Rejects the end of the input
+    fn quote_end_of_input_reject(&self, strategy: Strategy) -> TokenStream {
+        let backtrack = strategy.quote_backtrack();
+        let reject = strategy.quote_reject(
+            "Unexpected end of input after {__input_len} {__bytes}. {__expected}",
+            "Unexpected end of input",
+            quote! { ::regex_parse::Unexpected::EndOfInput },
+            quote! {__initial_input.len()},
+        );
+        match strategy.failure {
+            Failure::Panic => quote! {
+                {
+                    #backtrack
+                    let __input_len = __initial_input.len();
+                    let __bytes = if __input_len == 1 { "byte" } else { "bytes" };
+                    let (_, __expected) = __EXPECTED.get(__state as usize).copied().unwrap_or_default();
+                    #reject
+                }
+            },
+            Failure::Fallback => quote! {
+                {
+                    #backtrack
+                    let (_, __expected_chars) = __EXPECTED.get(__state as usize).copied().unwrap_or_default();
+                    #reject
+                }
+            },
+            Failure::Skip if strategy.backtracks => quote! {
+                {
+                    #backtrack
+                    #reject
+                }
+            },
+            Failure::Skip => reject,
         }
     }
 
-    fn quote_update_variable(&self, variable: &Variable, variable_end: TokenStream) -> TokenStream {
+    /// The characters that every state expects, as the separator and the summary of an error message.
+    /// The messages are looked up by the state, so that the code which rejects the input is the same for every state.
+    /// The error of a fallback has the summary and the expected characters instead, which is a `regex_parse::Error`.
+    /// The table is only needed if the error has a message.
+    fn quote_expected_table(
+        &self,
+        states: &Map<DfaIndex, Ident>,
+        strategy: Strategy,
+    ) -> Option<TokenStream> {
+        if strategy.failure == Failure::Skip {
+            return None;
+        }
+        let state_count = states.len();
+        let entries = states.keys().map(|idx| {
+            let expected = expected_chars(&self.dfa.nodes[*idx]);
+            let (separator, summary) = expected_summary(&expected);
+            match strategy.failure {
+                Failure::Fallback => quote! { (#summary, &[#(#expected),*]) },
+                Failure::Panic | Failure::Skip => quote! { (#separator, #summary) },
+            }
+        });
+        let entry = match strategy.failure {
+            Failure::Fallback => quote! { (&str, &[&str]) },
+            Failure::Panic | Failure::Skip => quote! { (&str, &str) },
+        };
+        Some(quote! {
+            static __EXPECTED: [#entry; #state_count] = [#(#entries),*];
+        })
+    }
+
+    fn quote_update_variable(
+        &self,
+        variable: &Variable,
+        variable_end: TokenStream,
+        failure: Failure,
+    ) -> TokenStream {
         let ident = &variable.ident;
-        match variable.kind {
-            VariableKind::Singular => quote! { #ident = __variable_start..#variable_end; },
-            VariableKind::Multiple => {
+        match &variable.kind {
+            VariableKind::Singular
+            | VariableKind::Greedy
+            | VariableKind::Empty
+            | VariableKind::Char
+            | VariableKind::Quoted
+            | VariableKind::Csv
+            | VariableKind::CsvRecord { .. }
+            | VariableKind::Time { .. }
+            | VariableKind::Radix { .. }
+            | VariableKind::Converted { .. }
+            | VariableKind::Mapped { .. }
+            | VariableKind::Spanned { .. }
+            | VariableKind::Backreference { .. } => {
+                quote! { #ident = __variable_start..#variable_end; }
+            }
+            VariableKind::Optional { .. } => {
+                quote! { #ident = ::core::option::Option::Some(__variable_start..#variable_end); }
+            }
+            VariableKind::Multiple { .. }
+            | VariableKind::Iter { .. }
+            | VariableKind::Array { .. }
+            | VariableKind::Zipped { .. } => {
                 quote! { #ident.push(__variable_start..#variable_end); }
             }
+            VariableKind::Nested { .. } => {
+                // A row was started by the repetition of the outer group
+                quote! {
+                    if let ::core::option::Option::Some(__row) = #ident.last_mut() {
+                        __row.push(__variable_start..#variable_end);
+                    }
+                }
+            }
+            VariableKind::Count => unreachable!("Counters do not capture text"),
+            VariableKind::Buffer { buffer, .. } => {
+                quote_buffer_write(ident, &variable.name, buffer, variable_end, failure)
+            }
+            VariableKind::Expected { .. } => quote_expected_check(ident, variable_end),
         }
     }
 
@@ -156,6 +2032,7 @@ impl Codegen {
         &self,
         states: &Map<DfaIndex, Ident>,
         variables: &Map<String, Variable>,
+        strategy: Strategy,
     ) -> Vec<TokenStream> {
         // Let's sort the states first to make it easier to read the macro expansion
         let mut sorted_states = states.iter().collect::<Vec<_>>();
@@ -164,7 +2041,7 @@ impl Codegen {
         sorted_states
             .iter()
             .map(|(dfa_idx, internal_name)| {
-                self.collect_state_branch(**dfa_idx, internal_name, states, variables)
+                self.collect_state_branch(**dfa_idx, internal_name, states, variables, strategy)
             })
             .collect()
     }
@@ -175,65 +2052,301 @@ impl Codegen {
         internal_name: &Ident,
         states: &Map<DfaIndex, Ident>,
         variables: &Map<String, Variable>,
+        strategy: Strategy,
     ) -> TokenStream {
         let state = &self.dfa.nodes[dfa_idx];
 
+        let tracks_deferred_matches = self.tracks_deferred_matches(strategy);
+        if tracks_deferred_matches && state.deferred_match == DeferredMatch::Accepted {
+            let accept = self.quote_deferred_accept(dfa_idx, variables, strategy);
+            return quote! {
+                __State::#internal_name => #accept
+            };
+        }
+        // The match might end before the current character, which is only known once the lookaheads matched
+        let mark_lookahead_end = (tracks_deferred_matches
+            && state.deferred_match == DeferredMatch::Started)
+            .then(|| quote! { __lookahead_end = __byte_index; });
+        let next = match strategy.bytes {
+            true => quote! { __next_byte },
+            false => quote! { __next_char },
+        };
+
+        let transitions = self.collect_state_transitions(dfa_idx, states, variables, strategy);
+        let simplified_patterns = self.simplify_match(transitions.into_iter(), strategy);
+
+        quote! {
+            __State::#internal_name => {
+                #mark_lookahead_end
+                match #next {
+                    #(#simplified_patterns)*
+                }
+            }
+        }
+    }
+
+    /// The transitions of a state for its character ranges, followed by the transition for any other character
+    fn collect_state_transitions(
+        &self,
+        dfa_idx: DfaIndex,
+        states: &Map<DfaIndex, Ident>,
+        variables: &Map<String, Variable>,
+        strategy: Strategy,
+    ) -> Vec<(Option<CharRange>, StateTransition)> {
+        let state = &self.dfa.nodes[dfa_idx];
+
+        // An accepting state stops the match at the first character it cannot consume
+        let accept_before = |range: Option<CharRange>| {
+            (strategy.termination == Termination::FirstAccept
+                && self.accepts_before(dfa_idx, range))
+            .then(|| self.accept_transition(dfa_idx, variables))
+        };
+        let accept = accept_before(None);
+        // A character that continues the match after a position where it could end remembers that position,
+        // so that the state machine can return to it, see [`Codegen::backtracks`]
+        let records_accept =
+            |range: Option<CharRange>| strategy.backtracks && self.accepts_before(dfa_idx, range);
+
         let default_edge = match state.edges.default {
             Some(target) => (
                 None,
                 StateTransition::Valid {
-                    target: states[&target].clone(),
+                    target: Some(states[&target].clone()),
                     variable_update: self.make_variable_update(dfa_idx, target, variables),
+                    greedy_update: self.make_greedy_update(dfa_idx, target, variables),
+                    counters: self.counter_variables(target, variables),
+                    records_accept: records_accept(None),
                 },
             ),
-            None => {
-                let expected = if state.edges.edges.is_empty() {
-                    vec!["End of input".to_string()]
-                } else {
-                    let mut expected_chars = state
-                        .edges
-                        .edges
-                        .keys()
-                        .copied()
-                        .map(Into::into)
-                        .collect::<Vec<_>>();
-                    expected_chars.sort_unstable();
-                    expected_chars
-                };
-                (None, StateTransition::Invalid { expected })
-            }
+            None => match &accept {
+                Some(accept) => (None, accept.clone()),
+                None => (None, StateTransition::Invalid),
+            },
         };
-        let initial_patterns = state
+        state
             .edges
             .edges
             .iter()
-            .map(|(char, idx)| {
+            .map(|(range, idx)| {
                 (
-                    Some(*char),
+                    Some(*range),
                     StateTransition::Valid {
-                        target: states[idx].clone(),
+                        target: Some(states[idx].clone()),
                         variable_update: self.make_variable_update(dfa_idx, *idx, variables),
+                        greedy_update: self.make_greedy_update(dfa_idx, *idx, variables),
+                        counters: self.counter_variables(*idx, variables),
+                        records_accept: records_accept(Some(*range)),
                     },
                 )
             })
-            .chain(std::iter::once(default_edge));
-
-        let simplified_patterns = self.simplify_match(initial_patterns);
+            .chain(state.edges.rejected.iter().map(|range| {
+                let transition = accept_before(Some(*range)).unwrap_or(StateTransition::Invalid);
+                (Some(*range), transition)
+            }))
+            .chain(std::iter::once(default_edge))
+            .collect()
+    }
+
+    /// Whether a match may end in the state before a character in `range`, see [`DfaNode::accepts_before`]
+    fn accepts_before(&self, dfa_idx: DfaIndex, range: Option<CharRange>) -> bool {
+        self.dfa.nodes[dfa_idx].accepts_before(range)
+    }
+
+    /// Ends the match before the current character, which ends the variable of the state
+    fn accept_transition(
+        &self,
+        dfa_idx: DfaIndex,
+        variables: &Map<String, Variable>,
+    ) -> StateTransition {
+        let state = &self.dfa.nodes[dfa_idx];
+        let variable_update = match &state.variable {
+            Some(var) => VariableUpdate::End(state_variable(variables, var)),
+            None => VariableUpdate::None,
+        };
+        StateTransition::Accept {
+            variable_update,
+            greedy_update: self.greedy_accept_update(state, variables),
+        }
+    }
+
+    /// Looks up the transitions in static tables instead of matching on the characters in every state, which keeps
+    /// the code of large state machines small (the `compact` option). Returns the tables and the code for one step.
+    ///
+    /// The characters are divided into classes that every state handles alike, where class 0 contains the characters
+    /// that no state names. `__CLASSES` holds the class of every character below 256 and `__CHAR_CLASSES` the sorted
+    /// ranges of the other characters. `__TRANSITIONS` maps every state and class to the next state and to the index
+    /// of the code that runs for the transition, so that code which many transitions share is generated only once.
+    fn quote_transition_tables(
+        &self,
+        states: &Map<DfaIndex, Ident>,
+        variables: &Map<String, Variable>,
+        strategy: Strategy,
+    ) -> (TokenStream, TokenStream) {
+        let ranges = states
+            .keys()
+            .flat_map(|idx| {
+                let state = &self.dfa.nodes[*idx];
+                state
+                    .edges
+                    .explicit_ranges()
+                    .chain(state.accepting_overrides.keys().copied())
+            })
+            .map(|range| (range, ()))
+            .collect::<Vec<_>>();
+        let letters = CharRange::partition(&ranges, &[])
+            .into_iter()
+            .map(|(range, _)| range)
+            .collect::<Vec<_>>();
+
+        // For every state the next state and the action for any other character, followed by every letter
+        let mut actions: Map<StateTransition, usize> = Map::default();
+        let rows = states
+            .iter()
+            .map(|(idx, internal_name)| {
+                let transitions = self.collect_state_transitions(*idx, states, variables, strategy);
+                let lookup = |char: Option<char>| {
+                    let (_, transition) = transitions
+                        .iter()
+                        .find(|(range, _)| {
+                            range.is_none_or(|range| char.is_some_and(|char| range.contains(char)))
+                        })
+                        .expect("Every state has a transition for any other character");
+                    let (target, action) = match transition.clone() {
+                        StateTransition::Valid {
+                            target,
+                            variable_update,
+                            greedy_update,
+                            counters,
+                            records_accept,
+                        } => (
+                            target,
+                            StateTransition::Valid {
+                                target: None,
+                                variable_update,
+                                greedy_update,
+                                counters,
+                                records_accept,
+                            },
+                        ),
+                        other => (None, other),
+                    };
+                    let next_action = actions.len();
+                    let action = *actions.entry(action).or_insert(next_action);
+                    // A transition that leaves the state machine keeps the current state
+                    (target.unwrap_or_else(|| internal_name.clone()), action)
+                };
+                std::iter::once(None)
+                    .chain(letters.iter().map(|letter| Some(letter.start)))
+                    .map(lookup)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Letters that every state handles alike share a class
+        let mut classes: Map<Vec<(Ident, usize)>, usize> = Map::default();
+        let letter_classes = (0..=letters.len())
+            .map(|column| {
+                let column = rows.iter().map(|row| row[column].clone()).collect();
+                let next_class = classes.len();
+                *classes.entry(column).or_insert(next_class)
+            })
+            .collect::<Vec<_>>();
+        let class_type = index_type(classes.len());
+        let action_type = index_type(actions.len());
+        let (class_tables, class) =
+            quote_char_classes(&letters, &letter_classes, &class_type, strategy.bytes);
+        let class_count = classes.len();
+        let state_count = states.len();
+        let rows = (0..state_count).map(|row| {
+            let entries = classes.keys().map(|column| {
+                let (target, action) = &column[row];
+                let action = Literal::usize_unsuffixed(*action);
+                quote! { (__State::#target, #action) }
+            });
+            quote! { [#(#entries),*] }
+        });
+        let tables = quote! {
+            #class_tables
+            static __TRANSITIONS: [[(__State, #action_type); #class_count]; #state_count] = [#(#rows),*];
+        };
+
+        let mut actions = actions
+            .into_keys()
+            .map(|action| action.quote(strategy))
+            .collect::<Vec<_>>();
+        let last_action = actions.pop().expect("Every state has a transition");
+        // The state and the class are always in bounds, but the lookup must not panic with an `else` block
+        let invalid = StateTransition::Invalid.quote(strategy);
+        let transition = quote! {
+            match __TRANSITIONS
+                .get(__state as usize)
+                .and_then(|__row| __row.get((#class) as usize))
+            {
+                ::core::option::Option::Some(&__transition) => __transition,
+                ::core::option::Option::None => #invalid,
+            }
+        };
+        let step = if actions.is_empty() {
+            quote! {
+                let (__target, _) = #transition;
+                #last_action
+                __state = __target;
+            }
+        } else {
+            let indices = (0..actions.len()).map(Literal::usize_unsuffixed);
+            quote! {
+                let (__target, __action) = #transition;
+                match __action {
+                    #(#indices => #actions,)*
+                    _ => #last_action,
+                }
+                __state = __target;
+            }
+        };
+        (tables, step)
+    }
+
+    /// Whether the state machine has to remember where a match ended before a lookahead.
+    /// This is only relevant if the match does not have to end at the end of the input.
+    fn tracks_deferred_matches(&self, strategy: Strategy) -> bool {
+        strategy.termination == Termination::FirstAccept
+            && self.dfa.iter().any(|idx| {
+                matches!(
+                    self.dfa.nodes[idx].deferred_match,
+                    DeferredMatch::Accepted | DeferredMatch::AcceptedAtEnd
+                )
+            })
+    }
 
+    /// Accepts the match that ended before the lookaheads that just matched
+    fn quote_deferred_accept(
+        &self,
+        dfa_idx: DfaIndex,
+        variables: &Map<String, Variable>,
+        strategy: Strategy,
+    ) -> TokenStream {
+        let update = self.dfa.nodes[dfa_idx].variable.as_ref().map(|var| {
+            self.quote_update_variable(
+                &state_variable(variables, var),
+                quote! {__lookahead_end},
+                strategy.failure,
+            )
+        });
+        let accept = strategy.quote_accept(quote! {__lookahead_end});
         quote! {
-            __State::#internal_name => {
-                match __next_char {
-                    #(#simplified_patterns)*
-                }
+            {
+                #update
+                #accept;
             }
         }
     }
 
     fn simplify_match(
         &self,
-        patterns_and_transitions: impl Iterator<Item = (Option<char>, StateTransition)>,
+        patterns_and_transitions: impl Iterator<Item = (Option<CharRange>, StateTransition)>,
+        strategy: Strategy,
     ) -> Vec<TokenStream> {
-        let mut simplified: Map<StateTransition, Vec<Option<char>>> = Map::default();
+        let mut simplified: Map<StateTransition, Vec<Option<CharRange>>> = Map::default();
 
         for (pattern, transition) in patterns_and_transitions {
             simplified
@@ -244,18 +2357,32 @@ impl Codegen {
 
         // Sort the patterns and transitions, so that the default pattern is always at the end
         let mut simplified: Vec<_> = simplified.into_iter().collect();
-        simplified.sort_unstable_by_key(|(_, patterns)| patterns.iter().any(|it| it.is_none()));
+        simplified.sort_by_key(|(_, patterns)| patterns.iter().any(|it| it.is_none()));
 
         simplified
             .into_iter()
             .map(|(transition, patterns)| {
-                let transition = transition.quote();
+                let transition = transition.quote(strategy);
                 if patterns.iter().any(|it| it.is_none()) {
                     quote! {_ => #transition,}
                 } else {
-                    let mut chars = patterns.iter().map(|it| it.unwrap()).collect::<Vec<_>>();
-                    chars.sort_unstable();
-                    quote! {#(#chars)|* => #transition,}
+                    let mut ranges = patterns.iter().map(|it| it.unwrap()).collect::<Vec<_>>();
+                    CharRange::merge_adjacent(&mut ranges);
+                    // The state machine only runs on bytes if the ranges contain nothing but ASCII characters
+                    let literal = |char: char| match strategy.bytes {
+                        true => Literal::byte_character(char as u8),
+                        false => Literal::character(char),
+                    };
+                    let patterns = ranges.iter().map(|CharRange { start, end }| {
+                        let start_literal = literal(*start);
+                        if start == end {
+                            quote! {#start_literal}
+                        } else {
+                            let end_literal = literal(*end);
+                            quote! {#start_literal..=#end_literal}
+                        }
+                    });
+                    quote! {#(#patterns)|* => #transition,}
                 }
             })
             .collect()
@@ -270,29 +2397,135 @@ impl Codegen {
         let current_state = &self.dfa.nodes[current_idx];
         let target_state = &self.dfa.nodes[target_idx];
 
+        // A variable after the greedy variable is abandoned if no thread that left the greedy variable
+        // at the same position is left. Only one position is possible in a state with a variable.
+        let abandoned = match (
+            &current_state.variable,
+            current_state.greedy.slots.as_slice(),
+        ) {
+            (Some(_), [slot]) => !target_state.greedy.slots.contains(slot),
+            _ => false,
+        };
+        if abandoned {
+            return match target_state.variable {
+                Some(_) => VariableUpdate::Start,
+                None => VariableUpdate::None,
+            };
+        }
+        // While the characters do not tell the variables apart, the one that continues started with the others
+        let undecided = !current_state.undecided_variables.is_empty()
+            || !target_state.undecided_variables.is_empty();
+        if undecided
+            && target_state.candidate_variables().any(|var| {
+                current_state
+                    .candidate_variables()
+                    .any(|other| other == var)
+            })
+        {
+            return VariableUpdate::None;
+        }
         match (&current_state.variable, &target_state.variable) {
             (None, Some(_)) => VariableUpdate::Start,
-            (Some(var), None) => VariableUpdate::End(variables[&var.name].clone()),
+            (Some(var), None) => VariableUpdate::End(state_variable(variables, var)),
+            (Some(var), Some(next)) if var != next => {
+                VariableUpdate::EndAndStart(state_variable(variables, var))
+            }
             _ => VariableUpdate::None,
         }
     }
 
-    fn collect_variables(&self) -> Vec<Variable> {
+    fn make_greedy_update(
+        &self,
+        current_idx: DfaIndex,
+        target_idx: DfaIndex,
+        variables: &Map<String, Variable>,
+    ) -> GreedyUpdate {
+        let current = &self.dfa.nodes[current_idx].greedy;
+        let target = &self.dfa.nodes[target_idx].greedy;
+
+        match (&current.variable, &target.variable, target.fresh_slot) {
+            (_, _, Some(slot)) => GreedyUpdate::Left(slot),
+            (None, Some(var), None) => GreedyUpdate::Start(state_variable(variables, var).ident),
+            _ => GreedyUpdate::None,
+        }
+    }
+
+    /// Sets the end of the greedy variable when a match ends in `state`
+    fn greedy_accept_update(
+        &self,
+        state: &DfaNode,
+        variables: &Map<String, Variable>,
+    ) -> GreedyUpdate {
+        let Some(end) = state.greedy.accepted_end else {
+            return GreedyUpdate::None;
+        };
+        let var = self
+            .dfa
+            .variables
+            .iter()
+            .find(|var| var.kind == VariableKind::Greedy)
+            .expect("Only greedy variables have an end");
+        GreedyUpdate::End(state_variable(variables, var).ident, end)
+    }
+
+    /// The number of positions where the greedy variable may end that have to be stored at the same time
+    fn greedy_slot_count(&self) -> usize {
+        self.dfa
+            .iter()
+            .flat_map(|idx| self.dfa.nodes[idx].greedy.slots.iter())
+            .map(|slot| *slot as usize + 1)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// The repetition counts that are incremented when the state is entered, and the nested variables that start a new list
+    fn counter_variables(
+        &self,
+        target_idx: DfaIndex,
+        variables: &Map<String, Variable>,
+    ) -> Vec<Variable> {
+        self.dfa.nodes[target_idx]
+            .counters
+            .iter()
+            .map(|var| state_variable(variables, var))
+            .collect()
+    }
+
+    fn collect_variables(&self) -> Vec<RegexVariable> {
         let mut variables = Set::default();
         for node_idx in self.dfa.iter() {
             let node = &self.dfa.nodes[node_idx];
-            if let Some(variable) = &node.variable {
-                let ident = Ident::new(&variable.name, Span::call_site());
-                variables.insert(Variable {
-                    ident,
-                    kind: variable.kind,
-                });
-            }
+            // Both kinds of a nested variable are captured by the same list of lists
+            let captured = |variable: &RegexVariable| match variable.kind {
+                VariableKind::Nested { .. } => RegexVariable {
+                    kind: VariableKind::Nested { row_start: false },
+                    ..variable.clone()
+                },
+                _ => variable.clone(),
+            };
+            variables.extend(node.variable.iter().map(captured));
+            variables.extend(node.counters.iter().map(captured));
+            variables.extend(node.greedy.variable.iter().cloned());
         }
 
         variables.into_iter().collect()
     }
 
+    /// Declares the enum of the states. The states are printed by name if the state machine is traced.
+    fn quote_state_enum(&self, states: &Map<DfaIndex, Ident>) -> TokenStream {
+        let internal_states = states.values();
+        let debug = self.trace.then(|| quote! { Debug, });
+        // The states index the static tables, like the expected characters in `__EXPECTED`
+        let repr = index_type(states.len());
+        quote! {
+            #[derive(#debug Clone, Copy)]
+            #[repr(#repr)]
+            enum __State {
+                #(#internal_states),*
+            }
+        }
+    }
+
     fn collect_states(&self) -> Map<DfaIndex, Ident> {
         self.dfa
             .iter()
@@ -307,50 +2540,635 @@ impl Codegen {
     }
 }
 
+/// Determines when the state machine stops and what it evaluates to
+#[derive(Debug, Clone, Copy)]
+struct Strategy {
+    termination: Termination,
+    failure: Failure,
+    /// Whether every step is printed, see [`Codegen::trace`]
+    trace: bool,
+    /// Whether the state machine runs on the bytes of the input, see [`Codegen::matches_bytes`]
+    bytes: bool,
+    /// Whether a rejected match returns to the last position where it could end, see [`Codegen::backtracks`].
+    /// This is determined by [`Codegen::quote_state_machine`].
+    backtracks: bool,
+}
+
+impl Strategy {
+    /// The current character, which has to be decoded from the input if the state machine runs on bytes
+    fn quote_next_char(self) -> TokenStream {
+        if self.bytes {
+            quote! {
+                __initial_input
+                    .get(__byte_index..)
+                    .and_then(|__rest| __rest.chars().next())
+                    .unwrap_or_default()
+            }
+        } else {
+            quote! { __next_char }
+        }
+    }
+
+    fn quote_accept(self, match_end: TokenStream) -> TokenStream {
+        match (self.termination, self.failure) {
+            (Termination::EndOfInput, Failure::Panic | Failure::Fallback) => quote! { break },
+            (Termination::FirstAccept, Failure::Panic | Failure::Fallback) => {
+                quote! { break #match_end }
+            }
+            (_, Failure::Skip) => quote! { break ::core::option::Option::Some(#match_end) },
+        }
+    }
+
+    /// Runs the state machine again up to the last position where the match could end and accepts it there,
+    /// instead of rejecting the input, see [`Codegen::backtracks`]
+    fn quote_backtrack(self) -> Option<TokenStream> {
+        self.backtracks.then(|| {
+            quote! {
+                if __last_accept != usize::MAX {
+                    __accept_at = __last_accept;
+                    continue '__replay;
+                }
+            }
+        })
+    }
+
+    /// Rejects the input at the byte index `position`. A panic shows the input around that position.
+    /// `message` is a format string for the panic. The error of a fallback contains the static `summary`,
+    /// the `unexpected` character or end of input and the characters in `__expected_chars`.
+    fn quote_reject(
+        self,
+        message: &str,
+        summary: impl ToTokens,
+        unexpected: TokenStream,
+        position: TokenStream,
+    ) -> TokenStream {
+        match self.failure {
+            Failure::Panic => quote! {
+                __reject(::core::format_args!(#message), &__initial_input[..], #position)
+            },
+            Failure::Skip => quote! { break ::core::option::Option::None },
+            Failure::Fallback => quote! {
+                break '__re_parse <::regex_parse::Error>::reject_input(#position, #summary, #unexpected, __expected_chars)
+            },
+        }
+    }
+}
+
+impl Failure {
+    /// Rejects an input that matched the pattern, but failed a check afterwards like a backreference
+    fn quote_mismatch(self, message: &str) -> TokenStream {
+        match self {
+            Failure::Panic => quote! { panic!("{}", #message) },
+            Failure::Fallback => Failure::quote_fallback(message, quote! {__initial_input.len()}),
+            Failure::Skip => unreachable!("Skipped matches are filtered by their backreferences"),
+        }
+    }
+
+    /// Leaves the matching block of a fallback with a `regex_parse::Error`
+    fn quote_fallback(message: impl ToTokens, position: TokenStream) -> TokenStream {
+        quote! { break '__re_parse <::regex_parse::Error>::reject(#position, #message) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Termination {
+    /// The whole input has to be consumed
+    EndOfInput,
+    /// The state machine stops as soon as an accepting state cannot consume the next character, or returns to the
+    /// last accepting position if the input is rejected later, see [`Codegen::backtracks`].
+    /// It then evaluates to the byte index where the match ended.
+    FirstAccept,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Failure {
+    /// Panic with a message describing the unexpected input
+    Panic,
+    /// Evaluate to `None`, a successful match evaluates to `Some(match_end)`
+    Skip,
+    /// Break out of the matching block with an error, which runs the `else` block of `re_parse!`
+    Fallback,
+}
+
+/// The characters that a state expects when it rejects a character. The state also rejects the end of the input
+/// unless it is accepting. A state whose edges were all rejected because no match can follow them
+/// still expects their characters, see [`DfaNode::pruned_edges`].
+fn expected_chars(state: &DfaNode) -> Vec<String> {
+    let edges = state.pruned_edges.as_ref().unwrap_or(&state.edges);
+    match edges.default {
+        Some(_) => vec![format!(
+            "Any character except {}",
+            edges
+                .rejected
+                .iter()
+                .map(|range| {
+                    if range.start == range.end {
+                        range.start.escape_debug().to_string()
+                    } else {
+                        format!(
+                            "{}-{}",
+                            range.start.escape_debug(),
+                            range.end.escape_debug()
+                        )
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        )],
+        None => expected_edges(state, edges),
+    }
+}
+
+/// The characters of the explicit edges of a state. A state without edges only expects the end of the input
+/// if it is accepting, otherwise it expects nothing at all.
+fn expected_edges(state: &DfaNode, edges: &DfaEdges) -> Vec<String> {
+    if edges.edges.is_empty() {
+        if state.is_accepting {
+            vec!["End of input".to_string()]
+        } else {
+            vec![]
+        }
+    } else {
+        let mut expected_chars = edges
+            .edges
+            .iter()
+            .map(|(range, _)| range.to_string())
+            .collect::<Vec<_>>();
+        expected_chars.sort_unstable();
+        expected_chars
+    }
+}
+
+/// Describes the expected characters for an error message like `Expected one of: 'a', 'b'`.
+/// Also returns the separator between `Unexpected character` and the character, which is only needed for a list.
+fn expected_summary(expected: &[String]) -> (&'static str, String) {
+    match expected {
+        [] => ("", "No further input can match".to_string()),
+        [single] => ("", format!("Expected '{single}'")),
+        _ => (
+            ":",
+            format!(
+                "Expected one of: {}",
+                expected
+                    .iter()
+                    .map(|it| format!("'{it}'"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ),
+    }
+}
+
+/// The smallest unsigned integer type that can hold the indices of `count` elements
+/// The tables that map every character to its class, and the code that looks up the class of `__next_char`, or of
+/// `__next_byte` if the state machine runs on bytes. `letter_classes` holds the class of the characters in no letter,
+/// which is 0, followed by the class of every letter. `__CLASSES` holds the class of every character below 256
+/// and `__CHAR_CLASSES` the sorted ranges of the other characters, see [`Codegen::quote_transition_tables`].
+fn quote_char_classes(
+    letters: &[CharRange],
+    letter_classes: &[usize],
+    class_type: &TokenStream,
+    bytes: bool,
+) -> (TokenStream, TokenStream) {
+    let class_of = |char: char| {
+        let index = letters.partition_point(|letter| letter.end < char);
+        match letters.get(index) {
+            Some(letter) if letter.start <= char => letter_classes[index + 1],
+            _ => 0,
+        }
+    };
+
+    let byte_classes = (0..=u8::MAX).map(|byte| Literal::usize_unsuffixed(class_of(byte as char)));
+    let mut char_classes: Vec<(char, char, usize)> = Vec::new();
+    for (letter, class) in letters.iter().zip(&letter_classes[1..]) {
+        if letter.end < '\u{100}' || *class == 0 {
+            continue;
+        }
+        let start = letter.start.max('\u{100}');
+        match char_classes.last_mut() {
+            Some((_, end, last_class))
+                if *last_class == *class && *end as u32 + 1 == start as u32 =>
+            {
+                *end = letter.end;
+            }
+            _ => char_classes.push((start, letter.end, *class)),
+        }
+    }
+    let char_table = (!bytes && !char_classes.is_empty()).then(|| {
+        let char_class_count = char_classes.len();
+        let entries = char_classes.iter().map(|(start, end, class)| {
+            let class = Literal::usize_unsuffixed(*class);
+            quote! { (#start, #end, #class) }
+        });
+        quote! {
+            static __CHAR_CLASSES: [(char, char, #class_type); #char_class_count] = [#(#entries),*];
+        }
+    });
+    let tables = quote! {
+        static __CLASSES: [#class_type; 256] = [#(#byte_classes),*];
+        #char_table
+    };
+
+    let class = match (bytes, char_table.is_some()) {
+        (true, _) => quote! {
+            match __CLASSES.get(__next_byte as usize) {
+                ::core::option::Option::Some(&__class) => __class,
+                ::core::option::Option::None => 0,
+            }
+        },
+        (false, false) => quote! {
+            match __CLASSES.get(__next_char as usize) {
+                ::core::option::Option::Some(&__class) => __class,
+                ::core::option::Option::None => 0,
+            }
+        },
+        (false, true) => quote! {
+            match __CLASSES.get(__next_char as usize) {
+                ::core::option::Option::Some(&__class) => __class,
+                ::core::option::Option::None => {
+                    let __index = __CHAR_CLASSES.partition_point(|&(_, __end, _)| __end < __next_char);
+                    match __CHAR_CLASSES.get(__index) {
+                        ::core::option::Option::Some(&(__start, _, __class)) if __start <= __next_char => __class,
+                        _ => 0,
+                    }
+                }
+            }
+        },
+    };
+    (tables, class)
+}
+
+fn index_type(count: usize) -> TokenStream {
+    if count <= 1 << 8 {
+        quote! { u8 }
+    } else if count <= 1 << 16 {
+        quote! { u16 }
+    } else {
+        quote! { u32 }
+    }
+}
+
+/// Parses the captured `text` of the variable `name` with `FromStr`, see [`quote_parse_capture`]
+fn quote_parse(name: &str, text: TokenStream) -> TokenStream {
+    quote! { __parse_capture(#name, &#text) }
+}
+
+/// Generates the traits that captures are parsed and collected with. The bounds go through traits with a custom
+/// diagnostic, so that binding a capture to a variable of the wrong kind explains how to capture single or multiple
+/// values instead of only reporting the missing `FromStr` or `FromIterator`. Both list the captures of the pattern.
+fn quote_parse_capture_trait(listing: &str) -> TokenStream {
+    quote! {
+        #[diagnostic::on_unimplemented(
+            message = "`{Self}` cannot be parsed from the text of a capture",
+            label = "captures are parsed with `FromStr`",
+            note = "a capture like `{{name}}` binds a single value. Use a repeated capture like `{{name*}}` to collect every match into a collection like `Vec`",
+            note = #listing
+        )]
+        trait __ParseCapture: ::core::marker::Sized {
+            type Error: ::core::fmt::Debug;
+            fn parse_capture(text: &str) -> ::core::result::Result<Self, Self::Error>;
+        }
+
+        impl<T> __ParseCapture for T
+        where
+            T: ::core::str::FromStr,
+            T::Err: ::core::fmt::Debug,
+        {
+            type Error = T::Err;
+            fn parse_capture(text: &str) -> ::core::result::Result<Self, Self::Error> {
+                text.parse()
+            }
+        }
+
+        #[diagnostic::on_unimplemented(
+            message = "the matches of a repeated capture cannot be collected into `{Self}`",
+            label = "repeated captures are collected with `FromIterator`",
+            note = "a repeated capture like `{{name*}}` binds every match. Use a capture like `{{name}}` to bind a single value",
+            note = #listing
+        )]
+        trait __FromCaptures<T>: ::core::iter::FromIterator<T> {}
+
+        impl<T, C: ::core::iter::FromIterator<T>> __FromCaptures<T> for C {}
+
+        trait __CollectCaptures: ::core::iter::Iterator + ::core::marker::Sized {
+            fn collect_captures<C: __FromCaptures<Self::Item>>(self) -> C {
+                self.collect()
+            }
+        }
+
+        impl<I: ::core::iter::Iterator> __CollectCaptures for I {}
+    }
+}
+
+/// Generates the function that parses captures. If parsing fails, it panics with the name of the variable,
+/// the type and the captured text instead of only the error of `FromStr`. `__unwrap_capture` panics the same way
+/// for captures that are converted by other functions, like `{value:x}` or `{value|parse_hex}`.
+/// The panic is in a cold function that is shared by all types, so that every instantiation stays small.
+fn quote_parse_capture() -> TokenStream {
+    quote! {
+        fn __parse_capture<T: __ParseCapture>(name: &str, text: &str) -> T {
+            __unwrap_capture(name, text, T::parse_capture(text))
+        }
+
+        fn __unwrap_capture<T, E: ::core::fmt::Debug>(
+            name: &str,
+            text: &str,
+            result: ::core::result::Result<T, E>,
+        ) -> T {
+            match result {
+                ::core::result::Result::Ok(value) => value,
+                ::core::result::Result::Err(err) => {
+                    __parse_capture_failed(name, ::core::any::type_name::<T>(), text, &err)
+                }
+            }
+        }
+
+        #[cold]
+        #[inline(never)]
+        fn __parse_capture_failed(name: &str, ty: &str, text: &str, err: &dyn ::core::fmt::Debug) -> ! {
+            panic!("Failed to parse capture `{}` ({}) from {:?}: {:?}", name, ty, text, err)
+        }
+    }
+}
+
+/// Generates the functions that parse captures for `re_parse!` with an `else` block, which cannot panic.
+/// If a capture cannot be parsed, they return a `regex_parse::CaptureError` with the byte index where the capture starts,
+/// the type it was parsed as and its text, see [`quote_or_reject`].
+fn quote_try_parse_capture() -> TokenStream {
+    quote! {
+        #[cold]
+        fn __capture_error<T>(position: usize, text: &str) -> ::core::result::Result<T, ::regex_parse::CaptureError<'_>> {
+            ::core::result::Result::Err(::regex_parse::CaptureError {
+                position,
+                ty: ::core::any::type_name::<T>(),
+                text,
+            })
+        }
+
+        fn __capture_text(
+            input: &str,
+            span: ::core::ops::Range<usize>,
+        ) -> ::core::result::Result<&str, ::regex_parse::CaptureError<'_>> {
+            let start = span.start;
+            match input.get(span) {
+                ::core::option::Option::Some(text) => ::core::result::Result::Ok(text),
+                ::core::option::Option::None => __capture_error(start, ""),
+            }
+        }
+
+        fn __try_parse<'a, T: __ParseCapture>(
+            value: &str,
+            text: &'a str,
+            position: usize,
+        ) -> ::core::result::Result<T, ::regex_parse::CaptureError<'a>> {
+            match T::parse_capture(value) {
+                ::core::result::Result::Ok(value) => ::core::result::Result::Ok(value),
+                ::core::result::Result::Err(_) => __capture_error(position, text),
+            }
+        }
+
+        fn __try_parse_capture<T: __ParseCapture>(
+            input: &str,
+            span: ::core::ops::Range<usize>,
+        ) -> ::core::result::Result<T, ::regex_parse::CaptureError<'_>> {
+            let start = span.start;
+            __capture_text(input, span).and_then(|text| __try_parse(text, text, start))
+        }
+    }
+}
+
+/// Evaluates to the value of a `Result` that [`quote_try_parse_capture`] returned for the capture `name`,
+/// or rejects the input for the `else` block with the capture that could not be parsed
+fn quote_or_reject(name: &str, result: TokenStream) -> TokenStream {
+    let message = format!("Failed to parse capture `{name}`");
+    quote! {
+        match #result {
+            ::core::result::Result::Ok(__value) => __value,
+            ::core::result::Result::Err(__error) => {
+                break '__re_parse <::regex_parse::Error>::reject_capture(__error, #message)
+            }
+        }
+    }
+}
+
+/// Parses the text of the capture `name` in the byte range `span` without panicking, see [`quote_or_reject`]
+fn quote_try_parse(name: &str, span: TokenStream) -> TokenStream {
+    quote_or_reject(name, quote! { __try_parse_capture(__initial_input, #span) })
+}
+
+/// Generates the function that panics when the input is rejected. The panic message is followed by the line of the
+/// input around a byte index, with a `^` under that byte, like:
+/// ```text
+/// name: Alice, age: 4x
+///                    ^
+/// ```
+/// Long lines are cut off with `...`, so that the marked byte stays close to the start of the window.
+///
+/// The function is never inlined and marked as cold, so that the match loop only contains a call for every rejected
+/// character instead of formatting the message.
+fn quote_reject_fn() -> TokenStream {
+    quote! {
+        #[cold]
+        #[inline(never)]
+        fn __reject(message: ::core::fmt::Arguments<'_>, input: &str, index: usize) -> ! {
+            const RADIUS: usize = 30;
+            let line_start = input[..index].rfind('\n').map_or(0, |start| start + 1);
+            let line_end = input[index..].find('\n').map_or(input.len(), |end| index + end);
+            // Control characters like tabs would move the `^`
+            let visible = |char: char| if char.is_control() { ' ' } else { char };
+            let before = input[line_start..index].chars().map(visible).collect::<::std::vec::Vec<_>>();
+            let after = input[index..line_end].chars().map(visible).collect::<::std::vec::Vec<_>>();
+            let skipped = before.len().saturating_sub(RADIUS);
+            let prefix = if skipped > 0 { "..." } else { "" };
+            let suffix = if after.len() > RADIUS { "..." } else { "" };
+            let before = before[skipped..].iter().collect::<::std::string::String>();
+            let after = after.iter().take(RADIUS).collect::<::std::string::String>();
+            let column = prefix.len() + before.chars().count();
+            panic!("{message}\n{prefix}{before}{after}{suffix}\n{}^", " ".repeat(column))
+        }
+    }
+}
+
+/// Creates the identifier that a capture is bound to, which may be a raw identifier like `r#type`
+fn binding_ident(name: &str, span: Span) -> Ident {
+    match name.strip_prefix("r#") {
+        Some(name) => Ident::new_raw(name, span),
+        None => Ident::new(name, span),
+    }
+}
+
+/// Creates the place that a capture is assigned to.
+/// The name is an identifier or a place expression like `self.x`, which are both valid expressions.
+fn binding_place(name: &str, span: Span) -> TokenStream {
+    let tokens = name
+        .parse::<TokenStream>()
+        .expect("Variable names are validated by the parser");
+    respan(tokens, span)
+}
+
+/// Sets the span of every token, including the tokens inside of groups
+fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|tree| match tree {
+            TokenTree::Group(group) => {
+                let mut respanned = Group::new(group.delimiter(), respan(group.stream(), span));
+                respanned.set_span(span);
+                TokenTree::Group(respanned)
+            }
+            mut tree => {
+                tree.set_span(span);
+                tree
+            }
+        })
+        .collect()
+}
+
+/// A capture of `re_parse!` with an `else` block. The generated code must not panic, so every capture is parsed
+/// into a temporary while the input can still be rejected. The captures are only assigned once all of them were parsed,
+/// so that they are definitely initialized after the macro if the `else` block diverges.
+struct FallibleCapture {
+    /// Checks the number of matches and parses the capture into the temporary, or rejects the input
+    parse: TokenStream,
+    temporary: Ident,
+    /// Assigns the temporary to the place of the capture
+    assign: TokenStream,
+}
+
+/// Whether captured values are assigned to existing variables or declared as new ones
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Binding {
+    Assign,
+    Declare,
+}
+
+impl Binding {
+    /// Binds `value` to `place`, which is an existing place for [`Binding::Assign`] and a new variable otherwise
+    fn quote_binding(self, place: TokenStream, value: TokenStream) -> TokenStream {
+        match self {
+            Binding::Assign => quote! { #place = #value; },
+            Binding::Declare => quote! { let #place = #value; },
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 struct Variable {
     kind: VariableKind,
     ident: Ident,
+    /// The name of the capture in the pattern, which is shown if parsing it fails
+    name: String,
+    /// Whether a declared binding is mutable, see [`RegexVariable::mutable`]
+    mutable: bool,
+    /// The type annotation of a declared binding, see [`RegexVariable::ty`]
+    ty: Option<&'static str>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum StateTransition {
-    Invalid {
-        expected: Vec<String>,
-    },
+    /// Rejects the current character. The expected characters of the state are looked up in `__EXPECTED`,
+    /// see [`Codegen::quote_expected_table`]
+    Invalid,
     Valid {
-        target: Ident,
+        /// `None` if the next state is looked up in the transition table, see [`Codegen::quote_transition_tables`]
+        target: Option<Ident>,
+        variable_update: VariableUpdate,
+        greedy_update: GreedyUpdate,
+        counters: Vec<Variable>,
+        /// Whether the match may end before the current character, see [`Codegen::backtracks`]
+        records_accept: bool,
+    },
+    /// Stops the state machine and accepts the input up to (excluding) the current character
+    Accept {
         variable_update: VariableUpdate,
+        greedy_update: GreedyUpdate,
     },
 }
 
 impl StateTransition {
-    fn quote(&self) -> TokenStream {
+    fn quote(&self, strategy: Strategy) -> TokenStream {
         match self {
-            StateTransition::Invalid { expected } => {
-                let message = match expected.as_slice() {
-                    [single] => {
-                        format!("Unexpected character {{__next_char}}. Expected '{single}'")
-                    }
-                    _ => format!(
-                        "Unexpected character: {{__next_char}}. Expected one of: {}",
-                        expected
-                            .iter()
-                            .map(|it| format!("'{it}'"))
-                            .collect::<Vec<_>>()
-                            .join(", ")
-                    ),
-                };
-                quote! {panic!(#message)}
+            StateTransition::Invalid => {
+                let backtrack = strategy.quote_backtrack();
+                let reject = strategy.quote_reject(
+                    "Unexpected character{__separator} {__next_char}. {__expected}",
+                    quote! { __expected },
+                    quote! { ::regex_parse::Unexpected::Char(__next_char) },
+                    quote! {__byte_index},
+                );
+                let next_char = strategy.bytes.then(|| {
+                    let next_char = strategy.quote_next_char();
+                    quote! { let __next_char = #next_char; }
+                });
+                match strategy.failure {
+                    Failure::Panic => quote! {{
+                        #backtrack
+                        let (__separator, __expected) = __EXPECTED.get(__state as usize).copied().unwrap_or_default();
+                        #next_char
+                        #reject
+                    }},
+                    Failure::Fallback => quote! {{
+                        #backtrack
+                        let (__expected, __expected_chars) = __EXPECTED.get(__state as usize).copied().unwrap_or_default();
+                        #next_char
+                        #reject
+                    }},
+                    Failure::Skip if strategy.backtracks => quote! {{
+                        #backtrack
+                        #reject
+                    }},
+                    Failure::Skip => reject,
+                }
             }
             StateTransition::Valid {
                 target,
                 variable_update,
+                greedy_update,
+                counters,
+                records_accept,
+            } => {
+                let record_accept =
+                    records_accept.then(|| quote! { __last_accept = __byte_index; });
+                let trace = strategy
+                    .trace
+                    .then(|| variable_update.quote_trace(quote! {__byte_index}));
+                let variable_update = variable_update.quote(strategy.failure);
+                let greedy_update = greedy_update.quote(quote! {__byte_index});
+                let target = target
+                    .as_ref()
+                    .map(|target| quote! { __state = __State::#target; });
+                let counters = counters
+                    .iter()
+                    .map(|Variable { kind, ident, .. }| match kind {
+                        VariableKind::Nested { .. } => {
+                            quote! { #ident.push(::std::vec::Vec::new()); }
+                        }
+                        _ => quote! { #ident += 1; },
+                    });
+                quote! {{
+                    #trace
+                    #record_accept
+                    #greedy_update
+                    #variable_update
+                    #(#counters)*
+                    #target
+                }}
+            }
+            StateTransition::Accept {
+                variable_update,
+                greedy_update,
             } => {
-                let variable_update = variable_update.quote();
+                let trace = strategy
+                    .trace
+                    .then(|| variable_update.quote_trace(quote! {__byte_index}));
+                let variable_update = variable_update.quote(strategy.failure);
+                let greedy_update = greedy_update.quote(quote! {__byte_index});
+                let accept = strategy.quote_accept(quote! {__byte_index});
                 quote! {{
+                    #trace
+                    #greedy_update
                     #variable_update
-                    __state = __State::#target;
+                    #accept;
                 }}
             }
         }
@@ -362,21 +3180,245 @@ enum VariableUpdate {
     None,
     Start,
     End(Variable),
+    /// Ends the variable and starts the next one at the same character, e.g. in `(?P<a>[a-z]+)(?P<b>\d+)`
+    EndAndStart(Variable),
+}
+
+/// Updates the range of the greedy variable like `{path!}`. It is not tracked with `__variable_start`,
+/// because the variables after it start while it may still be extended.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum GreedyUpdate {
+    None,
+    Start(Ident),
+    /// Stores the current character in a slot, because the greedy variable may end there
+    Left(u8),
+    /// Sets the end of the variable when the match ends
+    End(Ident, GreedyEnd),
+}
+
+impl GreedyUpdate {
+    fn quote(&self, match_end: TokenStream) -> TokenStream {
+        match self {
+            GreedyUpdate::None => quote! {},
+            GreedyUpdate::Start(ident) => quote! { #ident = __byte_index..__byte_index; },
+            GreedyUpdate::Left(slot) => {
+                let slot = *slot as usize;
+                quote! { __greedy_ends[#slot] = __byte_index; }
+            }
+            GreedyUpdate::End(ident, GreedyEnd::Match) => quote! { #ident.end = #match_end; },
+            GreedyUpdate::End(ident, GreedyEnd::Slot(slot)) => {
+                let slot = *slot as usize;
+                quote! { #ident.end = __greedy_ends[#slot]; }
+            }
+        }
+    }
+}
+
+/// Parses a match of a buffer variable like `{len* => buf}` and writes it to the next element of the buffer
+/// Without panicking, a full buffer or a match that cannot be parsed rejects the input instead.
+fn quote_buffer_write(
+    ident: &Ident,
+    name: &str,
+    buffer: &str,
+    variable_end: TokenStream,
+    failure: Failure,
+) -> TokenStream {
+    let message = format!("Expected at most {{}} matches for the buffer {buffer}");
+    let full_message = format!("Too many matches for the buffer {buffer}");
+    let buffer = syn::parse_str::<syn::Expr>(buffer).expect("Buffers are validated by the parser");
+    if failure == Failure::Fallback {
+        let reject_full = Failure::quote_fallback(&full_message, quote! {__variable_start});
+        let parsed = quote_try_parse(name, quote! { __variable_start..#variable_end });
+        return quote! {
+            let ::core::option::Option::Some(__slot) = #buffer.get_mut(#ident) else {
+                #reject_full;
+            };
+            *__slot = #parsed;
+            #ident += 1;
+        };
+    }
+    let parsed = quote_parse(
+        name,
+        quote! { __initial_input[__variable_start..#variable_end] },
+    );
+    quote! {
+        assert!(#ident < #buffer.len(), #message, #buffer.len());
+        #buffer[#ident] = #parsed;
+        #ident += 1;
+    }
+}
+
+/// The index, name and identifier of every variable of the zipped group `list`, in the order of the tuples
+fn zipped_elements<'a>(
+    list: &str,
+    variables: &'a Map<String, Variable>,
+) -> Vec<(u32, &'a String, &'a Ident)> {
+    let mut elements = variables
+        .values()
+        .filter_map(|var| match &var.kind {
+            VariableKind::Zipped {
+                list: other, index, ..
+            } if other == list => Some((*index, &var.name, &var.ident)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    elements.sort_unstable_by_key(|(index, ..)| *index);
+    elements
+}
+
+/// The identifier of the `&str` that an expected value like `{=prefix}` evaluated to
+fn expected_value_ident(ident: &Ident) -> Ident {
+    Ident::new(&format!("{ident}_value"), Span::mixed_site())
+}
+
+/// Compares a match of an expected value like `{=prefix}` to the value
+fn quote_expected_check(ident: &Ident, variable_end: TokenStream) -> TokenStream {
+    let value_ident = expected_value_ident(ident);
+    quote! { #ident &= __initial_input.get(__variable_start..#variable_end) == ::core::option::Option::Some(#value_ident); }
+}
+
+/// The index of a variable in the variables of the pattern
+fn variable_index(variables: &Map<String, Variable>, var: &RegexVariable) -> usize {
+    variables
+        .get_index_of(&var.key())
+        .expect("Every variable of a state is collected")
+}
+
+/// The variable that a state captures, with the kind of the state.
+/// The kinds only differ for nested variables, which start a new list in the first iteration of their repetition.
+fn state_variable(variables: &Map<String, Variable>, var: &RegexVariable) -> Variable {
+    Variable {
+        kind: var.kind.clone(),
+        ..variables[&var.key()].clone()
+    }
 }
 
 impl VariableUpdate {
-    fn quote(&self) -> TokenStream {
+    /// Prints the start or the text of the variable before the update, for the `debug` option
+    fn quote_trace(&self, variable_end: TokenStream) -> TokenStream {
+        let start =
+            quote! { ::std::eprintln!("[re_parse]   a variable starts at byte {}", __byte_index); };
+        let end = |var: &Variable| {
+            let name = &var.name;
+            quote! {
+                ::std::eprintln!("[re_parse]   {} = {:?}", #name, &__initial_input[__variable_start..#variable_end]);
+            }
+        };
+        match self {
+            VariableUpdate::None => quote! {},
+            VariableUpdate::Start => start,
+            VariableUpdate::End(var) => end(var),
+            VariableUpdate::EndAndStart(var) => {
+                let end = end(var);
+                quote! { #end #start }
+            }
+        }
+    }
+
+    fn quote(&self, failure: Failure) -> TokenStream {
         match self {
             VariableUpdate::None => quote! {},
             VariableUpdate::Start => quote! {__variable_start = __byte_index;},
             VariableUpdate::End(Variable {
-                kind: VariableKind::Singular,
+                kind:
+                    VariableKind::Singular
+                    | VariableKind::Greedy
+                    | VariableKind::Empty
+                    | VariableKind::Char
+                    | VariableKind::Quoted
+                    | VariableKind::Csv
+                    | VariableKind::CsvRecord { .. }
+                    | VariableKind::Time { .. }
+                    | VariableKind::Radix { .. }
+                    | VariableKind::Converted { .. }
+                    | VariableKind::Mapped { .. }
+                    | VariableKind::Spanned { .. }
+                    | VariableKind::Backreference { .. },
                 ident,
+                ..
             }) => quote! {#ident = __variable_start..__byte_index;},
             VariableUpdate::End(Variable {
-                kind: VariableKind::Multiple,
+                kind: VariableKind::Optional { .. },
                 ident,
+                ..
+            }) => quote! {#ident = ::core::option::Option::Some(__variable_start..__byte_index);},
+            VariableUpdate::End(Variable {
+                kind:
+                    VariableKind::Multiple { .. }
+                    | VariableKind::Iter { .. }
+                    | VariableKind::Array { .. }
+                    | VariableKind::Zipped { .. },
+                ident,
+                ..
             }) => quote! {#ident.push(__variable_start..__byte_index);},
+            VariableUpdate::End(Variable {
+                kind: VariableKind::Nested { .. },
+                ident,
+                ..
+            }) => quote! {
+                if let ::core::option::Option::Some(__row) = #ident.last_mut() {
+                    __row.push(__variable_start..__byte_index);
+                }
+            },
+            VariableUpdate::End(Variable {
+                kind: VariableKind::Count,
+                ..
+            }) => unreachable!("Counters do not capture text"),
+            VariableUpdate::End(Variable {
+                kind: VariableKind::Buffer { buffer, .. },
+                ident,
+                name,
+                ..
+            }) => quote_buffer_write(ident, name, buffer, quote! {__byte_index}, failure),
+            VariableUpdate::End(Variable {
+                kind: VariableKind::Expected { .. },
+                ident,
+                ..
+            }) => quote_expected_check(ident, quote! {__byte_index}),
+            VariableUpdate::EndAndStart(var) => {
+                let end = VariableUpdate::End(var.clone()).quote(failure);
+                let start = VariableUpdate::Start.quote(failure);
+                quote! {#end #start}
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::{expected_chars, expected_summary};
+    use crate::dfa::Dfa;
+    use crate::nfa::Nfa;
+    use crate::regex::Regex;
+
+    fn expected(pattern: &str) -> Vec<Vec<String>> {
+        let nfa = Nfa::try_from(Regex::from_str(pattern).unwrap()).unwrap();
+        let dfa = Dfa::try_from(nfa).unwrap();
+        dfa.iter()
+            .map(|idx| expected_chars(&dfa.nodes[idx]))
+            .collect()
+    }
+
+    #[test]
+    fn test_expected_edges() {
+        assert_eq!(expected("ab"), [vec!["a"], vec!["b"], vec!["End of input"]]);
+        // After the `a`, the lookahead needs a `b` that the match cannot consume, so nothing is expected
+        assert!(expected(r"a(?=b)").contains(&vec![]));
+    }
+
+    #[test]
+    fn test_expected_summary() {
+        assert_eq!(
+            expected_summary(&[]),
+            ("", "No further input can match".to_string())
+        );
+        assert_eq!(
+            expected_summary(&["a".to_string()]),
+            ("", "Expected 'a'".to_string())
+        );
+        assert_eq!(
+            expected_summary(&["a".to_string(), "b".to_string()]),
+            (":", "Expected one of: 'a', 'b'".to_string())
+        );
+    }
+}
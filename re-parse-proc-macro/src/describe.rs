@@ -0,0 +1,205 @@
+//! Describes the state machine of a pattern for people, as a graph in the DOT language of Graphviz for `re_debug_dot!`
+//! or as text for `re_explain!`.
+//!
+//! The states are named like the states of the generated code, so the descriptions can be compared to a macro expansion
+//! or to the output of the `debug` option.
+use crate::dfa::{Dfa, DfaIndex, DfaNode};
+use crate::regex::CharRange;
+use crate::Map;
+use std::fmt::Write;
+
+pub fn to_dot(dfa: &Dfa) -> String {
+    let names = state_names(dfa);
+
+    let mut dot = String::from("digraph dfa {\n    rankdir=LR;\n    node [shape=circle];\n");
+    dot.push_str("    start [shape=point];\n");
+    writeln!(dot, "    start -> {};", names[&dfa.root]).unwrap();
+    // A match that does not start at the beginning of the input starts in a state for the previous character
+    for (index, (kind, idx)) in dfa.inner_roots.iter().enumerate() {
+        writeln!(dot, "    start_{index} [shape=point];").unwrap();
+        let label = escape(&format!("after {kind:?}"));
+        writeln!(
+            dot,
+            "    start_{index} -> {} [label=\"{label}\", style=dashed];",
+            names[idx]
+        )
+        .unwrap();
+    }
+
+    for idx in dfa.iter() {
+        let node = &dfa.nodes[idx];
+        let name = &names[&idx];
+        let label = std::iter::once(name.clone())
+            .chain(variable_notes(node))
+            .map(|line| escape(&line))
+            .collect::<Vec<_>>()
+            .join("\\n");
+        let shape = if node.is_accepting {
+            ", shape=doublecircle"
+        } else {
+            ""
+        };
+        writeln!(dot, "    {name} [label=\"{label}\"{shape}];").unwrap();
+
+        for (target, label) in edges(node, &names) {
+            writeln!(
+                dot,
+                "    {name} -> {target} [label=\"{}\"];",
+                escape(&label)
+            )
+            .unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Lists every state with what it captures, followed by its transitions, like:
+/// ```text
+/// State_1: capture key
+///     '=' -> State_2
+///     any other except '\n' -> State_1
+/// ```
+pub fn to_text(dfa: &Dfa) -> String {
+    let names = state_names(dfa);
+
+    let mut text = format!("start: {}\n", names[&dfa.root]);
+    for (kind, idx) in &dfa.inner_roots {
+        writeln!(text, "start after {kind:?}: {}", names[idx]).unwrap();
+    }
+    for idx in dfa.iter() {
+        let node = &dfa.nodes[idx];
+        let notes = node
+            .is_accepting
+            .then(|| "accepting".to_string())
+            .into_iter()
+            .chain(variable_notes(node))
+            .collect::<Vec<_>>();
+        text.push_str(&names[&idx]);
+        if !notes.is_empty() {
+            write!(text, ": {}", notes.join(", ")).unwrap();
+        }
+        text.push('\n');
+        for (target, label) in edges(node, &names) {
+            writeln!(text, "    {label} -> {target}").unwrap();
+        }
+    }
+    text
+}
+
+/// The names of the states, in the same order as in the generated code
+fn state_names(dfa: &Dfa) -> Map<DfaIndex, String> {
+    dfa.iter()
+        .enumerate()
+        .map(|(index, idx)| (idx, format!("State_{index}")))
+        .collect()
+}
+
+/// Describes the variables that a state captures
+fn variable_notes(node: &DfaNode) -> Vec<String> {
+    let mut notes = Vec::new();
+    if let Some(var) = &node.variable {
+        notes.push(format!("capture {}", var.name));
+    }
+    notes.extend(
+        node.undecided_variables
+            .iter()
+            .map(|var| format!("or {}", var.name)),
+    );
+    if let Some(var) = &node.greedy.variable {
+        notes.push(format!("greedy {}", var.name));
+    }
+    notes.extend(
+        node.counters
+            .iter()
+            .map(|var| format!("count {}", var.name)),
+    );
+    notes
+}
+
+/// One edge per target, labeled with all of its characters, followed by the default edge
+fn edges(node: &DfaNode, names: &Map<DfaIndex, String>) -> Vec<(String, String)> {
+    let mut targets: Map<DfaIndex, Vec<CharRange>> = Map::default();
+    for (range, target) in &node.edges.edges {
+        targets.entry(*target).or_default().push(*range);
+    }
+    let mut edges = targets
+        .into_iter()
+        .map(|(target, mut ranges)| (names[&target].clone(), list_ranges(&mut ranges)))
+        .collect::<Vec<_>>();
+    edges.sort_unstable();
+    if let Some(default) = node.edges.default {
+        let label = match list_ranges(&mut node.edges.rejected.clone()) {
+            rejected if rejected.is_empty() => "any other".to_string(),
+            rejected => format!("any other except {rejected}"),
+        };
+        edges.push((names[&default].clone(), label));
+    }
+    edges
+}
+
+fn list_ranges(ranges: &mut Vec<CharRange>) -> String {
+    CharRange::merge_adjacent(ranges);
+    ranges
+        .iter()
+        .map(|range| format!("{range:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Escapes a label for a double-quoted DOT string
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::describe::{to_dot, to_text};
+    use crate::dfa::Dfa;
+    use crate::nfa::Nfa;
+    use crate::regex::Regex;
+
+    fn dfa(pattern: &str) -> Dfa {
+        let nfa = Nfa::try_from(Regex::from_str(pattern).unwrap()).unwrap();
+        Dfa::try_from(nfa).unwrap()
+    }
+
+    #[test]
+    fn test_to_dot() {
+        insta::assert_snapshot!(to_dot(&dfa("{name}: (a|b)+\"?")));
+        insta::assert_snapshot!(to_dot(&dfa(r"\b{word:\w+}.")));
+    }
+
+    #[test]
+    fn test_to_text() {
+        insta::assert_snapshot!(to_text(&dfa("{name}: (a|b)+\"?")));
+        insta::assert_snapshot!(to_text(&dfa(r"\b{word:\w+}.")));
+        insta::assert_snapshot!(to_text(&dfa(r"({num:x\d+}|{word:x[a-z]+})")));
+    }
+
+    #[test]
+    fn test_states_are_numbered_in_visiting_order() {
+        // Every state is reached from a state with a lower number, so the numbering does not depend on hashing
+        let text = to_text(&dfa(r"{key:\w+}=({value:\d+}|\[{list*}\]|\{{map}\});"));
+        let number = |name: &str| name.trim_start_matches("State_").parse::<usize>().unwrap();
+        let mut targets: Vec<Vec<usize>> = Vec::new();
+        for line in text.lines().skip(1) {
+            match line.strip_prefix("    ") {
+                Some(edge) => targets
+                    .last_mut()
+                    .unwrap()
+                    .push(number(edge.rsplit(" -> ").next().unwrap())),
+                None => {
+                    assert_eq!(number(line.split(':').next().unwrap()), targets.len());
+                    targets.push(Vec::new());
+                }
+            }
+        }
+        for state in 1..targets.len() {
+            assert!(
+                targets[..state].iter().any(|it| it.contains(&state)),
+                "{text}"
+            );
+        }
+    }
+}
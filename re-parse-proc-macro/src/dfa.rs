@@ -1,53 +1,190 @@
 use crate::arena::{Arena, ArenaIndex};
 use crate::nfa::{Nfa, NfaEdge, NfaIndex, NfaNodeKind};
-use crate::regex::{RegexPattern, RegexVariable};
+use crate::regex::{
+    CharKind, CharRange, PatternError, PatternPart, Position, RegexPattern, RegexVariable,
+    VariableKind,
+};
 use crate::util::FloodFill;
 use crate::{Map, Set};
-use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::ops::Range;
 use thiserror::Error;
 
 pub type DfaArena = Arena<DfaNode>;
 pub type DfaIndex = ArenaIndex<DfaNode>;
 
+pub type DfaError = PatternError<DfaErrorKind>;
+
+/// How many states a pattern may need, unless the macro raises the limit with the `max_states` option.
+/// Every state adds code to the expansion, so this keeps patterns from slowing down compilation too much.
+pub const DEFAULT_MAX_STATES: usize = 10_000;
+
 #[derive(Debug, Error)]
-pub enum DfaError {
-    #[error("Ambiguous variables: {} collides with {}. Make sure that variables are always separated by a character, so it is possible to tell them apart.", first, second)]
-    AmbiguousVariables { first: String, second: String },
+pub enum DfaErrorKind {
+    #[error("Ambiguous variables: {} collides with {}. Make sure that variables are always separated by a character, so it is possible to tell them apart. For example, it is not clear which of them the end of the input {:?} belongs to.", first, second, witness)]
+    AmbiguousVariables {
+        first: String,
+        second: String,
+        /// An input after which both variables are possible
+        witness: String,
+    },
+    #[error("Ambiguous repetition count: It is not possible to tell whether a character starts a new repetition of the group of {}", name)]
+    AmbiguousCounter { name: String },
+    #[error("Ambiguous greedy variable: It is not possible to tell where {} ends, because the rest of the pattern could start at more than one position at the same time", name)]
+    AmbiguousGreedyVariable { name: String },
+    #[error("The greedy variable {} cannot be followed by {}, because {} is collected before it is known where {} ends", name, other, other, name)]
+    GreedyFollowedByCollection { name: String, other: String },
+    #[error("The pattern needs more than {} states, most of them for `{}`. Simplify this part of the pattern, or raise the limit with the option `max_states = ...`", max_states, part.text)]
+    TooManyStates {
+        max_states: usize,
+        /// The part of the pattern that most of the states are in
+        part: PatternPart,
+    },
+}
+
+impl DfaErrorKind {
+    /// An ambiguity between two variables. The input that shows it is added by [`DfaBuilder::with_witness`].
+    fn ambiguous(first: &RegexVariable, second: &RegexVariable) -> Self {
+        Self::AmbiguousVariables {
+            first: first.name.clone(),
+            second: second.name.clone(),
+            witness: String::new(),
+        }
+    }
+
+    /// The position at which the error is reported, which is the variable or the part of the pattern it is about
+    fn position(&self, nfa: &Nfa) -> Position {
+        let name = match self {
+            Self::AmbiguousVariables { second: name, .. }
+            | Self::AmbiguousCounter { name }
+            | Self::AmbiguousGreedyVariable { name }
+            | Self::GreedyFollowedByCollection { other: name, .. } => name,
+            Self::TooManyStates { part, .. } => return part.position.clone(),
+        };
+        nfa.positions.get(name).unwrap_or(&nfa.source).clone()
+    }
 }
 
 #[derive(Debug)]
 pub struct Dfa {
     pub root: DfaIndex,
+    /// The initial states for a match that does not start at the beginning of the input, by the kind of the
+    /// previous character. This is empty if the pattern does not start with an assertion, because then the previous
+    /// character does not matter.
+    pub inner_roots: Vec<(CharKind, DfaIndex)>,
     pub nodes: DfaArena,
+    /// The variables in the order in which they appear in the pattern
+    pub variables: Vec<RegexVariable>,
 }
 
 impl Dfa {
     pub fn iter(&self) -> impl Iterator<Item = DfaIndex> + use<'_> {
-        <Self as FloodFill>::iter(self, self.root)
+        let mut visited = Set::default();
+        std::iter::once(self.root)
+            .chain(self.inner_roots.iter().map(|(_, idx)| *idx))
+            .flat_map(|root| <Self as FloodFill>::iter(self, root))
+            .filter(move |idx| visited.insert(*idx))
     }
-}
 
-impl TryFrom<Nfa> for Dfa {
-    type Error = DfaError;
-    fn try_from(nfa: Nfa) -> Result<Self, DfaError> {
-        let mut builder = DfaBuilder::default();
-        let root_group = expand_group(&nfa, &[nfa.root]);
-        builder.pending_nodes.insert(root_group.clone());
+    /// Runs the states on the whole `input` like `re_parse!` does, without comparing backreferences and expected values.
+    /// Returns the byte index of the first character that does not match, or the length of the input if it ends too early.
+    pub fn mismatch(&self, input: &str) -> Option<usize> {
+        let mut state = self.root;
+        for (index, char) in input.char_indices() {
+            state = match self.nodes[state].edges.target(char) {
+                Some(target) => target,
+                None => return Some(index),
+            };
+        }
+        (!self.nodes[state].is_accepting).then_some(input.len())
+    }
 
-        while let Some(group) = builder.pending_nodes.iter().next() {
-            let group = group.clone();
-            builder.pending_nodes.remove(&group);
+    /// Builds the states for the nfa, but fails if more than `max_states` states are needed
+    pub fn new(nfa: &Nfa, max_states: usize) -> Result<Self, DfaError> {
+        Self::build(nfa, max_states).map_err(|kind| PatternError {
+            position: kind.position(nfa),
+            kind,
+        })
+    }
 
-            builder.compute_group(&nfa, group)?;
+    /// Builds the states for the nfa. The errors are positioned by [`Dfa::new`]
+    fn build(nfa: &Nfa, max_states: usize) -> Result<Self, DfaErrorKind> {
+        let mut builder = DfaBuilder::default();
+        let root_group = thread_closure(nfa, Thread::new(nfa.root));
+        let root = builder.entry(DfaKey::new(nfa, root_group.clone(), CharKind::Boundary));
+        let inner_roots = if has_pending_assertions(nfa, &root_group) {
+            [CharKind::Newline, CharKind::Word, CharKind::Other]
+                .into_iter()
+                .map(|kind| {
+                    let key = DfaKey::new(nfa, root_group.clone(), kind);
+                    (kind, builder.entry(key))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        while let Some(key) = builder.pending_nodes.shift_remove_index(0) {
+            let state = builder.nfa_to_dfa[&key];
+            builder
+                .compute_group(nfa, key)
+                .map_err(|err| builder.with_witness(err, state))?;
+            if builder.nodes.len() > max_states {
+                return Err(DfaErrorKind::TooManyStates {
+                    max_states,
+                    part: builder.largest_part(nfa),
+                });
+            }
         }
+        builder.check_undecided_variables()?;
 
-        builder.dedup();
+        builder.reject_dead_states();
+        let replacements = builder.minimize();
+        let resolve = |idx: DfaIndex| replacements.get(&idx).copied().unwrap_or(idx);
 
-        let root = builder.nfa_to_dfa[&root_group];
-        Ok(Dfa {
-            root,
+        let mut dfa = Dfa {
+            root: resolve(root),
+            inner_roots: inner_roots
+                .into_iter()
+                .map(|(kind, idx)| (kind, resolve(idx)))
+                .collect(),
             nodes: builder.nodes,
-        })
+            variables: nfa.variables(),
+        };
+        dfa.remove_unreachable_states();
+        Ok(dfa)
+    }
+
+    /// Moves the states that are reachable from the roots into a new arena, keeping their order
+    fn remove_unreachable_states(&mut self) {
+        let reachable = self.iter().collect::<Set<_>>();
+        let old_indices = self
+            .nodes
+            .iter()
+            .filter(|idx| reachable.contains(idx))
+            .collect::<Vec<_>>();
+        let mut nodes = DfaArena::default();
+        let new_indices = old_indices
+            .into_iter()
+            .map(|idx| (idx, nodes.add(std::mem::take(&mut self.nodes[idx]))))
+            .collect::<Map<_, _>>();
+        for (_, node) in nodes.iter_mut() {
+            node.edges.replace(&new_indices);
+        }
+
+        self.root = new_indices[&self.root];
+        for (_, idx) in &mut self.inner_roots {
+            *idx = new_indices[&*idx];
+        }
+        self.nodes = nodes;
+    }
+}
+
+impl TryFrom<Nfa> for Dfa {
+    type Error = DfaError;
+    fn try_from(nfa: Nfa) -> Result<Self, DfaError> {
+        Self::new(&nfa, DEFAULT_MAX_STATES)
     }
 }
 
@@ -60,54 +197,358 @@ impl FloodFill for Dfa {
             .default
             .iter()
             .copied()
-            .chain(edges.edges.values().copied())
+            .chain(edges.edges.iter().map(|(_, target)| *target))
+    }
+}
+
+/// A path through the nfa that a dfa state follows
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+struct Thread {
+    node: NfaIndex,
+    /// The lookaheads that were entered on this path, but are not decided yet
+    lookaheads: Vec<Lookahead>,
+    deferred: Deferred,
+    /// The counters that were passed since the last character was consumed
+    passed: Vec<NfaIndex>,
+    /// The counters that were passed before the last character was consumed, so that character counts for them
+    counted: Vec<NfaIndex>,
+    greedy_exit: GreedyExit,
+}
+
+/// A lookahead that is tracked by the set of its active nfa nodes
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+struct Lookahead {
+    /// A negative lookahead must not match
+    negative: bool,
+    nodes: Vec<NfaIndex>,
+}
+
+impl Lookahead {
+    /// Returns `Some(true)` if the lookahead is decided to hold, `Some(false)` if it is decided to fail
+    /// and `None` if more characters are needed.
+    fn decision(&self, nfa: &Nfa) -> Option<bool> {
+        if is_lookahead_matched(nfa, &self.nodes) {
+            Some(!self.negative)
+        } else if self.nodes.is_empty() {
+            Some(self.negative)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether a thread reached the end of the pattern before its lookaheads matched
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+enum Deferred {
+    No,
+    /// The end of the pattern was reached right before the next character
+    Fresh,
+    /// The end of the pattern was reached earlier. Only the lookaheads of this thread are still advanced.
+    Pending,
+}
+
+/// Where a thread left the greedy variable like `{path!}`.
+/// The greedy variable may end before every character that the rest of the pattern can start with.
+/// Each of these positions is stored in a slot, and the thread that matches in the end decides which slot is used.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+enum GreedyExit {
+    No,
+    /// The greedy variable was left, but no character was consumed since
+    Reached,
+    /// The greedy variable was left right before the last character, but no slot was assigned yet
+    Leaving,
+    /// The greedy variable was left right before the last character, which is stored in the slot
+    Fresh(u8),
+    /// The greedy variable was left before an earlier character, which is stored in the slot
+    Left(u8),
+}
+
+impl GreedyExit {
+    /// Returns the exit after a character was consumed. `leaves` is true if that character follows the greedy variable.
+    fn advance(self, leaves: bool) -> Self {
+        match self {
+            GreedyExit::No if leaves => GreedyExit::Leaving,
+            GreedyExit::No => GreedyExit::No,
+            GreedyExit::Reached | GreedyExit::Leaving => GreedyExit::Leaving,
+            GreedyExit::Fresh(slot) | GreedyExit::Left(slot) => GreedyExit::Left(slot),
+        }
+    }
+
+    fn slot(self) -> Option<u8> {
+        match self {
+            GreedyExit::Fresh(slot) | GreedyExit::Left(slot) => Some(slot),
+            _ => None,
+        }
+    }
+}
+
+impl Thread {
+    fn new(node: NfaIndex) -> Self {
+        Self {
+            node,
+            lookaheads: Vec::new(),
+            deferred: Deferred::No,
+            passed: Vec::new(),
+            counted: Vec::new(),
+            greedy_exit: GreedyExit::No,
+        }
+    }
+
+    fn at(&self, node: NfaIndex) -> Self {
+        Self {
+            node,
+            ..self.clone()
+        }
+    }
+
+    /// Whether a match may end on this thread at the end of the input.
+    /// Negative lookaheads can not match there anymore.
+    fn accepts_at_end(&self, nfa: &Nfa) -> bool {
+        self.deferred == Deferred::No
+            && nfa.nodes[self.node].is_accepting
+            && self.lookaheads.iter().all(|lookahead| lookahead.negative)
+    }
+
+    /// Whether a match may end on this thread right before `next`,
+    /// or before any character without an explicit edge in the lookaheads if `next` is `None`.
+    fn accepts_before(&self, nfa: &Nfa, next: Option<CharRange>) -> bool {
+        self.deferred == Deferred::No
+            && nfa.nodes[self.node].is_accepting
+            && self
+                .advance_lookaheads(nfa, next)
+                .is_some_and(|lookaheads| lookaheads.is_empty())
+    }
+
+    /// Moves the thread to the lookahead node `node` and starts to track the lookahead that begins at `start`.
+    /// Returns `None` if the lookahead fails right away.
+    fn enter_lookahead(
+        &self,
+        nfa: &Nfa,
+        node: NfaIndex,
+        start: NfaIndex,
+        negative: bool,
+    ) -> Option<Self> {
+        let mut thread = self.at(node);
+        let lookahead = Lookahead {
+            negative,
+            nodes: expand_group(nfa, &[start]),
+        };
+        match lookahead.decision(nfa) {
+            Some(holds) => holds.then_some(thread),
+            None => {
+                thread.lookaheads.push(lookahead);
+                thread.lookaheads.sort();
+                thread.lookaheads.dedup();
+                Some(thread)
+            }
+        }
+    }
+
+    /// Advances all lookaheads by a character of `range`, or by any character without an explicit edge
+    /// if `range` is `None`. Returns `None` if one of the lookaheads fails.
+    fn advance_lookaheads(&self, nfa: &Nfa, range: Option<CharRange>) -> Option<Vec<Lookahead>> {
+        let mut lookaheads = Vec::new();
+        for lookahead in &self.lookaheads {
+            let edges = NfaEdges::new(nfa, &lookahead.nodes, &[]);
+            let targets = match range {
+                Some(range) => edges.targets(range).unwrap_or_default(),
+                None => &edges.default,
+            };
+            let lookahead = Lookahead {
+                negative: lookahead.negative,
+                nodes: expand_group(nfa, targets),
+            };
+            match lookahead.decision(nfa) {
+                Some(true) => {}
+                Some(false) => return None,
+                None => lookaheads.push(lookahead),
+            }
+        }
+        lookaheads.sort();
+        lookaheads.dedup();
+        Some(lookaheads)
+    }
+}
+
+/// Identifies a dfa state by the threads it consists of and by the kind of the previous character.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct DfaKey {
+    group: Vec<Thread>,
+    /// Only relevant if the group has assertions, otherwise this is always [`CharKind::Other`],
+    /// so that no unnecessary states are created.
+    previous: CharKind,
+}
+
+impl DfaKey {
+    fn new(nfa: &Nfa, mut group: Vec<Thread>, previous: CharKind) -> Self {
+        // Threads that can only continue through epsilon edges are already represented by the threads that
+        // these edges lead to. Removing them allows groups that only differ in such threads to share a state,
+        // which matters for large alternations like `\p{L}`.
+        group.retain(|thread| thread.deferred != Deferred::No || !is_transparent(nfa, thread.node));
+        assign_greedy_slot(&mut group);
+        group.sort();
+        group.dedup();
+        // Only the earliest end of a deferred match is tracked
+        if group
+            .iter()
+            .any(|thread| thread.deferred == Deferred::Pending)
+        {
+            group.retain(|thread| thread.deferred != Deferred::Fresh);
+        }
+
+        let previous = if has_pending_assertions(nfa, &group) {
+            previous
+        } else {
+            CharKind::Other
+        };
+        Self { group, previous }
     }
 }
 
 #[derive(Debug, Default)]
 pub struct DfaBuilder {
     nodes: DfaArena,
-    nfa_to_dfa: Map<Vec<NfaIndex>, DfaIndex>,
-    pending_nodes: Set<Vec<NfaIndex>>,
+    nfa_to_dfa: Map<DfaKey, DfaIndex>,
+    pending_nodes: Set<DfaKey>,
 }
 
 impl DfaBuilder {
-    fn dedup(&mut self) {
-        let mut fixed_duplicates = HashSet::new();
-        loop {
-            let mut visited_nodes: Vec<DfaIndex> = Vec::new();
-            let mut duplicates: Vec<(DfaIndex, DfaIndex)> = Vec::new();
-
-            let valid_nodes = self
-                .nodes
+    /// Rejects the characters that lead to dead states, from which no match can be reached. A dead state would only
+    /// reject the input later, like after the `a` in `xa\bb|y`, where the assertion can never hold.
+    fn reject_dead_states(&mut self) {
+        let mut predecessors: Map<DfaIndex, Vec<DfaIndex>> = Map::default();
+        for idx in self.nodes.iter() {
+            let edges = &self.nodes[idx].edges;
+            for target in edges
+                .default
                 .iter()
-                .filter(|idx| !fixed_duplicates.contains(idx));
-            for node_idx in valid_nodes {
-                let node = &self.nodes[node_idx];
-                let other_node_index = visited_nodes
-                    .iter()
-                    .find(|other| &self.nodes[**other] == node);
-                if let Some(idx) = other_node_index {
-                    duplicates.push((node_idx, *idx));
-                } else {
-                    visited_nodes.push(node_idx);
-                }
+                .chain(edges.edges.iter().map(|(_, target)| target))
+            {
+                predecessors.entry(*target).or_default().push(idx);
+            }
+        }
+
+        let mut live = Set::default();
+        let mut pending = self
+            .nodes
+            .iter()
+            .filter(|idx| self.nodes[*idx].may_accept())
+            .collect::<Vec<_>>();
+        while let Some(idx) = pending.pop() {
+            if live.insert(idx) {
+                pending.extend(predecessors.get(&idx).into_iter().flatten());
             }
+        }
 
-            if duplicates.is_empty() {
+        for (_, node) in self.nodes.iter_mut() {
+            let edges = node.edges.clone();
+            node.edges.reject_targets(|target| !live.contains(&target));
+            if node.edges.default.is_none()
+                && node.edges.edges.is_empty()
+                && (edges.default.is_some() || !edges.edges.is_empty())
+            {
+                node.pruned_edges = Some(edges);
+            }
+        }
+    }
+
+    /// Merges states that behave the same on every input with Hopcroft's algorithm, which takes `O(n log n)` steps
+    /// for `n` states and every kind of character. The states start in blocks of states that are equal apart from
+    /// their edges, and a block is split whenever only some of its states lead into another block for some character.
+    /// Returns which states were replaced by which other state. The first state of a block in the arena is kept.
+    fn minimize(&mut self) -> Map<DfaIndex, DfaIndex> {
+        // Every state treats all characters of a letter the same, because no range of an edge starts inside of it.
+        // The letters are represented by their first character.
+        let ranges = self
+            .nodes
+            .iter()
+            .flat_map(|idx| self.nodes[idx].edges.explicit_ranges())
+            .map(|range| (range, ()))
+            .collect::<Vec<_>>();
+        let mut letters = CharRange::partition(&ranges, &[])
+            .into_iter()
+            .map(|(range, _)| range)
+            .collect::<Vec<_>>();
+        // The characters without an explicit range are one more letter, unless there are none
+        let mut other = Some('\0');
+        for letter in &letters {
+            if other.is_some_and(|other| other < letter.start) {
                 break;
             }
+            other = (letter.end..=char::MAX).nth(1);
+        }
+        letters.extend(other.map(CharRange::single));
+
+        // A rejected character leads to an extra state that rejects everything
+        let rejected = self.nodes.len();
+        let target = |idx: DfaIndex, letter: CharRange| {
+            self.nodes[idx]
+                .edges
+                .target(letter.start)
+                .map_or(rejected, ArenaIndex::index)
+        };
+        let predecessors = letters
+            .iter()
+            .map(|letter| {
+                let mut predecessors = vec![Vec::new(); rejected + 1];
+                for idx in self.nodes.iter() {
+                    predecessors[target(idx, *letter)].push(idx.index());
+                }
+                predecessors
+            })
+            .collect::<Vec<_>>();
+
+        let mut partition = {
+            let mut labels: Map<_, usize> = Map::default();
+            let mut blocks = Vec::with_capacity(rejected + 1);
+            for idx in self.nodes.iter() {
+                let next = labels.len();
+                blocks.push(*labels.entry(self.nodes[idx].labels()).or_insert(next));
+            }
+            blocks.push(labels.len());
+            Partition::new(&blocks)
+        };
 
-            for (previous, new) in duplicates {
-                fixed_duplicates.insert(previous);
-                for (_, node) in self.nodes.iter_mut() {
-                    node.edges.replace(previous, new);
+        let mut pending = (0..partition.len())
+            .flat_map(|block| (0..letters.len()).map(move |letter| (block, letter)))
+            .collect::<Set<_>>();
+        while let Some((splitter, letter)) = pending.pop() {
+            let states = partition.states(splitter).to_vec();
+            for state in states {
+                for predecessor in &predecessors[letter][state] {
+                    partition.mark(*predecessor);
+                }
+            }
+            for (block, new_block) in partition.split_marked() {
+                for letter in 0..letters.len() {
+                    if pending.contains(&(block, letter))
+                        || partition.size(new_block) <= partition.size(block)
+                    {
+                        pending.insert((new_block, letter));
+                    } else {
+                        pending.insert((block, letter));
+                    }
                 }
             }
         }
+
+        let mut representatives: Map<usize, DfaIndex> = Map::default();
+        let mut replacements = Map::default();
+        for idx in self.nodes.iter() {
+            let representative = *representatives
+                .entry(partition.block(idx.index()))
+                .or_insert(idx);
+            if representative != idx {
+                replacements.insert(idx, representative);
+            }
+        }
+        for (_, node) in self.nodes.iter_mut() {
+            node.edges.replace(&replacements);
+        }
+        replacements
     }
 
-    fn insert(&mut self, key: Vec<NfaIndex>, node: DfaNode) -> DfaIndex {
+    fn insert(&mut self, key: DfaKey, node: DfaNode) -> DfaIndex {
         if let Some(idx) = self.nfa_to_dfa.get(&key) {
             self.nodes[*idx] = node;
             return *idx;
@@ -118,7 +559,84 @@ impl DfaBuilder {
         idx
     }
 
-    fn entry(&mut self, key: Vec<NfaIndex>) -> DfaIndex {
+    /// Finds the part of the pattern that most of the states so far are in. A state counts for every thread in it,
+    /// and the smallest part with at least half of all threads is chosen. Without such a part, the part
+    /// with the most threads is chosen.
+    fn largest_part(&self, nfa: &Nfa) -> PatternPart {
+        let mut threads = vec![0_usize; nfa.nodes.len()];
+        for key in self.nfa_to_dfa.keys() {
+            for thread in &key.group {
+                threads[thread.node.index()] += 1;
+            }
+        }
+        let total = threads.iter().sum::<usize>();
+        let parts = nfa.parts.iter().map(|(part, nodes)| {
+            (
+                part,
+                nodes.len(),
+                threads[nodes.clone()].iter().sum::<usize>(),
+            )
+        });
+        parts
+            .clone()
+            .filter(|(.., count)| count * 2 >= total)
+            .min_by_key(|(_, len, _)| *len)
+            .or_else(|| parts.max_by_key(|(.., count)| *count))
+            .map(|(part, ..)| part.clone())
+            .expect("A pattern with states has parts")
+    }
+
+    /// Adds an input that leads to `state` to an error about ambiguous variables, so that the error can show
+    /// an example of the ambiguity
+    fn with_witness(&self, err: DfaErrorKind, state: DfaIndex) -> DfaErrorKind {
+        match err {
+            DfaErrorKind::AmbiguousVariables { first, second, .. } => {
+                DfaErrorKind::AmbiguousVariables {
+                    first,
+                    second,
+                    witness: self.shortest_input(state),
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Finds one of the shortest inputs that lead from the root to `state`. The root is the first state that is added.
+    fn shortest_input(&self, state: DfaIndex) -> String {
+        let Some(root) = self.nodes.iter().next() else {
+            return String::new();
+        };
+        let mut inputs = Map::default();
+        inputs.insert(root, String::new());
+        let mut queue = VecDeque::from([root]);
+        while let Some(idx) = queue.pop_front() {
+            if idx == state {
+                break;
+            }
+            let edges = &self.nodes[idx].edges;
+            let mut targets = edges
+                .edges
+                .iter()
+                .map(|(range, target)| (range.start, *target))
+                .collect::<Vec<_>>();
+            targets.extend(
+                edges
+                    .default
+                    .zip(edges.default_char())
+                    .map(|(target, char)| (char, target)),
+            );
+            for (char, target) in targets {
+                if !inputs.contains_key(&target) {
+                    let input = format!("{}{char}", inputs[&idx]);
+                    inputs.insert(target, input);
+                    queue.push_back(target);
+                }
+            }
+        }
+        inputs.swap_remove(&state).unwrap_or_default()
+    }
+
+    fn entry(&mut self, key: DfaKey) -> DfaIndex {
         if let Some(idx) = self.nfa_to_dfa.get(&key) {
             return *idx;
         }
@@ -128,52 +646,147 @@ impl DfaBuilder {
         self.insert(key, node)
     }
 
-    fn compute_group(&mut self, nfa: &Nfa, group: Vec<NfaIndex>) -> Result<(), DfaError> {
-        let edges = DfaEdges::from_nfa_group(self, nfa, &group);
-        let is_accepting = group
+    fn compute_group(&mut self, nfa: &Nfa, key: DfaKey) -> Result<(), DfaErrorKind> {
+        let (edges, accepting_overrides) = DfaEdges::from_nfa_group(self, nfa, &key);
+        let is_accepting = resolve_assertions(nfa, &key.group, key.previous, CharKind::Boundary)
             .iter()
-            .copied()
-            .any(|nfa_idx| nfa.nodes[nfa_idx].is_accepting);
-        let variable = self.compute_group_variable(nfa, &group)?;
+            .any(|thread| thread.accepts_at_end(nfa));
+        let accepting_before = [CharKind::Newline, CharKind::Word, CharKind::Other]
+            .into_iter()
+            .filter(|kind| ResolvedGroup::new(nfa, &key, *kind).accepts_before(nfa, None))
+            .collect();
+        let deferred_match = DeferredMatch::of(&key.group);
+        let greedy = GreedyState::of(nfa, &key.group)?;
+        let mut undecided_variables = self.compute_group_variables(nfa, &key.group);
+        let variable = match undecided_variables.is_empty() {
+            true => None,
+            false => Some(undecided_variables.remove(0)),
+        };
+        if let Some(var) = &variable {
+            greedy.check_variable(var)?;
+        }
+        // The end of the greedy variable is tracked by the variable after it, so that variable must be known
+        if let (Some(first), Some(second)) = (&variable, undecided_variables.first()) {
+            if greedy.variable.is_some() || !greedy.slots.is_empty() {
+                return Err(DfaErrorKind::ambiguous(first, second));
+            }
+        }
+        let counters = compute_group_counters(nfa, &key.group)?;
 
         self.insert(
-            group,
+            key,
             DfaNode {
                 is_accepting,
+                accepting_before,
+                accepting_overrides,
+                deferred_match,
                 variable,
+                undecided_variables,
+                greedy,
+                counters,
                 edges,
+                pruned_edges: None,
             },
         );
         Ok(())
     }
 
-    fn compute_group_variable(
-        &self,
-        nfa: &Nfa,
-        group: &[NfaIndex],
-    ) -> Result<Option<RegexVariable>, DfaError> {
-        let mut variable = None;
+    /// Returns the variables that the threads of the group capture.
+    /// More than one variable is only valid if the next characters tell them apart, see [`Self::check_undecided_variables`].
+    fn compute_group_variables(&self, nfa: &Nfa, group: &[Thread]) -> Vec<RegexVariable> {
+        let mut variables: Vec<RegexVariable> = Vec::new();
 
-        for nfa_idx in group.iter().copied() {
+        // The greedy variable is tracked separately, see `compute_group_greedy`
+        let nodes = group
+            .iter()
+            .filter(|thread| thread.deferred == Deferred::No)
+            .filter(|thread| !is_greedy(nfa, thread.node))
+            .map(|thread| thread.node);
+        for nfa_idx in nodes {
             let NfaNodeKind::Variable(var) = &nfa.nodes[nfa_idx].kind else {
                 continue;
             };
+            // The same variable can be reached by multiple threads with different lookaheads
+            if !variables.contains(var) {
+                variables.push(var.clone());
+            }
+        }
 
-            match variable {
-                None => variable = Some(var.clone()),
-                Some(RegexVariable {
-                    name: other_var, ..
-                }) => {
-                    return Err(DfaError::AmbiguousVariables {
-                        first: other_var,
-                        second: var.name.clone(),
-                    })
+        variables
+    }
+
+    /// Makes sure that states with undecided variables, like the state after the `x` in `({num:x\d+}|{word:x[a-z]+})`,
+    /// can be resolved: Every character after such a state must continue exactly one of its variables, so the
+    /// variables end up with disjoint sets of characters. All of the variables also have to start at the same character.
+    fn check_undecided_variables(&self) -> Result<(), DfaErrorKind> {
+        let ambiguous =
+            |first, second, state| self.with_witness(DfaErrorKind::ambiguous(first, second), state);
+        for node_idx in self.nodes.iter() {
+            let node = &self.nodes[node_idx];
+            let candidates = node.candidate_variables().collect::<Vec<_>>();
+            let targets = node
+                .edges
+                .default
+                .iter()
+                .chain(node.edges.edges.iter().map(|(_, target)| target));
+            for target_idx in targets {
+                let target = &self.nodes[*target_idx];
+                let target_candidates = target.candidate_variables().collect::<Vec<_>>();
+                // The next character has to continue one of the variables
+                if !node.undecided_variables.is_empty() && target.variable.is_none() {
+                    return Err(ambiguous(candidates[0], candidates[1], node_idx));
+                }
+                // A variable that continues cannot be undecided with a variable that just started
+                let continues = target_candidates.iter().any(|var| candidates.contains(var));
+                if !node.undecided_variables.is_empty()
+                    || (continues && !target.undecided_variables.is_empty())
+                {
+                    let other = target_candidates
+                        .iter()
+                        .find(|var| !candidates.contains(var));
+                    if let Some(other) = other {
+                        return Err(ambiguous(candidates[0], other, *target_idx));
+                    }
                 }
             }
+
+            // A match cannot end before it is known which variable was captured
+            let accepts = node.is_accepting
+                || !node.accepting_before.is_empty()
+                || node.accepting_overrides.values().any(|accepts| *accepts)
+                || node.deferred_match != DeferredMatch::None;
+            if accepts && !node.undecided_variables.is_empty() {
+                return Err(ambiguous(candidates[0], candidates[1], node_idx));
+            }
         }
+        Ok(())
+    }
+}
 
-        Ok(variable)
+/// Returns the counters that are incremented when this state is entered.
+/// Every thread must have consumed the last character after the same counters, otherwise the count is ambiguous.
+fn compute_group_counters(nfa: &Nfa, group: &[Thread]) -> Result<Vec<RegexVariable>, DfaErrorKind> {
+    let mut threads = group
+        .iter()
+        .filter(|thread| thread.deferred == Deferred::No);
+    let Some(first) = threads.next() else {
+        return Ok(Vec::new());
+    };
+    let counter = |idx: &NfaIndex| match &nfa.nodes[*idx].kind {
+        NfaNodeKind::Counter(var) => var.clone(),
+        _ => unreachable!("Only counters are passed"),
+    };
+    if let Some(other) = threads.find(|thread| thread.counted != first.counted) {
+        let name = first
+            .counted
+            .iter()
+            .chain(&other.counted)
+            .find(|idx| !first.counted.contains(idx) || !other.counted.contains(idx))
+            .map(|idx| counter(idx).name)
+            .expect("The counters differ");
+        return Err(DfaErrorKind::AmbiguousCounter { name });
     }
+    Ok(first.counted.iter().map(counter).collect())
 }
 
 fn get_non_epsilon_edges(nfa: &Nfa, group: &[NfaIndex]) -> Vec<(RegexPattern, NfaIndex)> {
@@ -190,6 +803,155 @@ fn get_non_epsilon_edges(nfa: &Nfa, group: &[NfaIndex]) -> Vec<(RegexPattern, Nf
     edges
 }
 
+/// Assigns the first free slot to the threads that just left the greedy variable.
+/// Threads that left it earlier are dropped if they are at the same position as one of them,
+/// because they can only match the same way, and the later end makes the greedy variable longer.
+fn assign_greedy_slot(group: &mut Vec<Thread>) {
+    let without_exit = |thread: &Thread| Thread {
+        greedy_exit: GreedyExit::No,
+        ..thread.clone()
+    };
+    let leaving = group
+        .iter()
+        .filter(|thread| thread.greedy_exit == GreedyExit::Leaving)
+        .map(without_exit)
+        .collect::<Vec<_>>();
+    if leaving.is_empty() {
+        return;
+    }
+    group.retain(|thread| {
+        thread.greedy_exit.slot().is_none() || !leaving.contains(&without_exit(thread))
+    });
+    let used = group
+        .iter()
+        .filter_map(|thread| thread.greedy_exit.slot())
+        .collect::<Vec<_>>();
+    let slot = (0..=u8::MAX)
+        .find(|slot| !used.contains(slot))
+        .expect("There are fewer threads than slots");
+    for thread in group.iter_mut() {
+        if thread.greedy_exit == GreedyExit::Leaving {
+            thread.greedy_exit = GreedyExit::Fresh(slot);
+        }
+    }
+}
+
+/// Whether `node` consumes the characters of a greedy variable like `{path!}`
+fn is_greedy(nfa: &Nfa, node: NfaIndex) -> bool {
+    matches!(&nfa.nodes[node].kind, NfaNodeKind::Variable(var) if var.kind == VariableKind::Greedy)
+}
+
+/// Whether a thread at `node` behaves exactly like the threads in its closure
+fn is_transparent(nfa: &Nfa, node: NfaIndex) -> bool {
+    let node = &nfa.nodes[node];
+    matches!(node.kind, NfaNodeKind::Simple)
+        && !node.is_accepting
+        && !node.edge_kind.is_assertion()
+        && node.edges.iter().all(|edge| {
+            matches!(
+                nfa.nodes[*edge].edge_kind,
+                NfaEdge::Epsilon | NfaEdge::Lookahead(_) | NfaEdge::NegativeLookahead(_)
+            )
+        })
+}
+
+/// Whether the group contains assertion nodes, which are not entered yet because they depend on the next character
+fn has_pending_assertions(nfa: &Nfa, group: &[Thread]) -> bool {
+    group.iter().any(|thread| {
+        thread.deferred == Deferred::No && nfa.nodes[thread.node].edge_kind.is_assertion()
+    })
+}
+
+/// Returns the threads of the group that are active if the previous and next character are of the given kinds.
+/// These are all threads except for assertions that do not hold, plus everything that is reachable from assertions that hold.
+fn resolve_assertions(
+    nfa: &Nfa,
+    group: &[Thread],
+    previous: CharKind,
+    next: CharKind,
+) -> Vec<Thread> {
+    let mut resolved = Set::default();
+    let mut visited = Set::default();
+    let mut pending = group.to_vec();
+    while let Some(thread) = pending.pop() {
+        if !visited.insert(thread.clone()) {
+            continue;
+        }
+        match &nfa.nodes[thread.node].edge_kind {
+            NfaEdge::Assertion(assertion) if thread.deferred == Deferred::No => {
+                if assertion.holds(previous, next) {
+                    pending.extend(thread_closure(nfa, thread.clone()));
+                    resolved.insert(thread);
+                }
+            }
+            _ => {
+                resolved.insert(thread);
+            }
+        }
+    }
+
+    let mut result = resolved.into_iter().collect::<Vec<_>>();
+    result.sort();
+    result
+}
+
+/// Returns all threads that are reachable from `thread` without consuming a character.
+/// Lookaheads are entered immediately. Assertions are included, but not followed (unless `thread` is the assertion),
+/// because they can only be resolved once the next character is known.
+fn thread_closure(nfa: &Nfa, thread: Thread) -> Vec<Thread> {
+    let mut threads: Set<Thread> = Set::default();
+    let mut pending = vec![thread.clone()];
+    while let Some(current) = pending.pop() {
+        if !threads.insert(current.clone()) {
+            continue;
+        }
+        let node = &nfa.nodes[current.node];
+        if current.node != thread.node && node.edge_kind.is_assertion() {
+            continue;
+        }
+
+        // The end of the pattern was reached, but the match can only end here once the lookaheads matched
+        if node.is_accepting && !current.lookaheads.is_empty() {
+            threads.insert(Thread {
+                deferred: Deferred::Fresh,
+                ..current.clone()
+            });
+        }
+
+        for edge in node.edges.iter().copied() {
+            match &nfa.nodes[edge].edge_kind {
+                NfaEdge::Epsilon | NfaEdge::Assertion(_) => {
+                    let mut next = current.at(edge);
+                    if is_greedy(nfa, current.node) {
+                        next.greedy_exit = GreedyExit::Reached;
+                    }
+                    if let NfaNodeKind::Counter(_) = nfa.nodes[edge].kind {
+                        next.passed.push(edge);
+                        next.passed.sort();
+                        next.passed.dedup();
+                    }
+                    pending.push(next)
+                }
+                NfaEdge::Lookahead(start) => {
+                    pending.extend(current.enter_lookahead(nfa, edge, *start, false))
+                }
+                NfaEdge::NegativeLookahead(start) => {
+                    pending.extend(current.enter_lookahead(nfa, edge, *start, true))
+                }
+                NfaEdge::Pattern(_) => {}
+            }
+        }
+    }
+
+    threads.into_iter().collect()
+}
+
+fn is_lookahead_matched(nfa: &Nfa, lookahead: &[NfaIndex]) -> bool {
+    lookahead
+        .iter()
+        .any(|idx| matches!(nfa.nodes[*idx].kind, NfaNodeKind::LookaheadEnd))
+}
+
 fn expand_group(nfa: &Nfa, group: &[NfaIndex]) -> Vec<NfaIndex> {
     let mut nodes = Set::default();
     for idx in group.iter().copied() {
@@ -201,13 +963,13 @@ fn expand_group(nfa: &Nfa, group: &[NfaIndex]) -> Vec<NfaIndex> {
     result
 }
 
+/// Returns all nodes that are reachable from `idx` via epsilon edges.
 fn get_connected_nodes(nfa: &Nfa, idx: NfaIndex) -> Vec<NfaIndex> {
     let mut nodes: Set<NfaIndex> = Set::default();
     let mut pending_nodes: Set<NfaIndex> = Set::default();
 
     pending_nodes.insert(idx);
-    while let Some(node) = pending_nodes.iter().copied().next() {
-        pending_nodes.remove(&node);
+    while let Some(node) = pending_nodes.pop() {
         nodes.insert(node);
 
         pending_nodes.extend(
@@ -215,7 +977,7 @@ fn get_connected_nodes(nfa: &Nfa, idx: NfaIndex) -> Vec<NfaIndex> {
                 .edges
                 .iter()
                 .copied()
-                .filter(|edge| nfa.nodes[*edge].edge_kind.is_epsilon()),
+                .filter(|edge| !nodes.contains(edge) && nfa.nodes[*edge].edge_kind.is_epsilon()),
         )
     }
 
@@ -226,92 +988,769 @@ fn get_connected_nodes(nfa: &Nfa, idx: NfaIndex) -> Vec<NfaIndex> {
 
 #[derive(Debug, Default, Eq, PartialEq)]
 pub struct DfaNode {
+    /// Whether a match may end in this state at the end of the input
     pub is_accepting: bool,
+    /// The kinds of characters before which a match may end in this state.
+    /// Without assertions, this contains either every kind or none.
+    pub accepting_before: Vec<CharKind>,
+    /// Characters before which a match may end or may not end, regardless of their kind.
+    /// This is decided by lookaheads of a single character, like in `a(?!b)`.
+    pub accepting_overrides: Map<CharRange, bool>,
+    pub deferred_match: DeferredMatch,
     pub variable: Option<RegexVariable>,
+    /// Other variables that the state may capture instead of [`DfaNode::variable`], because the characters so far
+    /// do not tell them apart, like after the `x` in `({num:x\d+}|{word:x[a-z]+})`. The next character decides.
+    pub undecided_variables: Vec<RegexVariable>,
+    pub greedy: GreedyState,
+    /// The repetition counts that are incremented whenever this state is entered
+    pub counters: Vec<RegexVariable>,
     pub edges: DfaEdges,
+    /// The edges of a state before [`DfaBuilder::reject_dead_states`] rejected all of them, because none leads to a match.
+    /// Only their characters are used, so that the error message still lists the characters that the pattern expects.
+    /// Such a state is dead itself, so it is only reached if it is the root and the pattern can never match.
+    pub pruned_edges: Option<DfaEdges>,
 }
 
-#[derive(Debug, Default, Eq, PartialEq)]
+impl DfaNode {
+    /// Whether a match may end in this state, at the end of the input or before some character
+    pub fn may_accept(&self) -> bool {
+        self.is_accepting
+            || !self.accepting_before.is_empty()
+            || self.accepting_overrides.values().any(|accepts| *accepts)
+            || matches!(
+                self.deferred_match,
+                DeferredMatch::Accepted | DeferredMatch::AcceptedAtEnd
+            )
+    }
+
+    /// Whether a match may end in this state before a character in `range`, which is a range of
+    /// [`DfaEdges::explicit_ranges`], or before any other character if it is `None`.
+    /// Lookarounds like `\b` decide this by the kind of the character.
+    pub fn accepts_before(&self, range: Option<CharRange>) -> bool {
+        let kind = range.map_or(CharKind::Other, CharRange::kind);
+        range
+            .and_then(|range| self.accepting_overrides.get(&range).copied())
+            .unwrap_or_else(|| self.accepting_before.contains(&kind))
+    }
+
+    /// Everything about the state apart from its edges, so that states with equal labels can be found by hashing
+    fn labels(&self) -> impl Hash + Eq + use<'_> {
+        let DfaNode {
+            is_accepting,
+            accepting_before,
+            accepting_overrides,
+            deferred_match,
+            variable,
+            undecided_variables,
+            greedy,
+            counters,
+            edges: _,
+            pruned_edges,
+        } = self;
+        let mut accepting_overrides = accepting_overrides
+            .iter()
+            .map(|(range, accepts)| (*range, *accepts))
+            .collect::<Vec<_>>();
+        accepting_overrides.sort_unstable();
+        let pruned_edges = pruned_edges.as_ref().map(|edges| {
+            (
+                edges.default.is_some(),
+                edges.explicit_ranges().collect::<Vec<_>>(),
+            )
+        });
+        (
+            is_accepting,
+            accepting_before,
+            accepting_overrides,
+            deferred_match,
+            variable,
+            undecided_variables,
+            greedy,
+            counters,
+            pruned_edges,
+        )
+    }
+
+    /// The variable of the state and the undecided variables
+    pub fn candidate_variables(&self) -> impl Iterator<Item = &RegexVariable> {
+        self.variable.iter().chain(&self.undecided_variables)
+    }
+}
+
+/// Describes how a state relates to the greedy variable like `{path!}`.
+/// While a state may still extend the greedy variable, [`DfaNode::variable`] is the variable of the threads
+/// that already left it.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct GreedyState {
+    /// The greedy variable, if this state may still extend it
+    pub variable: Option<RegexVariable>,
+    /// The slot that stores the end of the greedy variable if it was left right before the character that led to this state
+    pub fresh_slot: Option<u8>,
+    /// The slots of the ends of the greedy variable that the threads of this state started after
+    pub slots: Vec<u8>,
+    /// Where the greedy variable ends if a match ends in this state
+    pub accepted_end: Option<GreedyEnd>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum GreedyEnd {
+    /// The greedy variable ends together with the match
+    Match,
+    Slot(u8),
+}
+
+impl GreedyState {
+    fn of(nfa: &Nfa, group: &[Thread]) -> Result<Self, DfaErrorKind> {
+        let threads = group
+            .iter()
+            .filter(|thread| thread.deferred == Deferred::No);
+        let mut state = GreedyState::default();
+        let mut accepted_ends = Vec::new();
+        for thread in threads.clone() {
+            let is_greedy = is_greedy(nfa, thread.node);
+            match (thread.greedy_exit, &nfa.nodes[thread.node].kind) {
+                (GreedyExit::No, NfaNodeKind::Variable(var)) if is_greedy => {
+                    state.variable = Some(var.clone())
+                }
+                // The greedy variable is in a repetition and was entered again
+                (_, NfaNodeKind::Variable(var)) if is_greedy => {
+                    return Err(DfaErrorKind::AmbiguousGreedyVariable {
+                        name: var.name.clone(),
+                    })
+                }
+                (GreedyExit::Fresh(slot), _) => state.fresh_slot = Some(slot),
+                _ => {}
+            }
+            state.slots.extend(thread.greedy_exit.slot());
+            if nfa.nodes[thread.node].is_accepting {
+                let end = match thread.greedy_exit {
+                    GreedyExit::No if is_greedy => Some(GreedyEnd::Match),
+                    GreedyExit::Reached => Some(GreedyEnd::Match),
+                    exit => exit.slot().map(GreedyEnd::Slot),
+                };
+                if let Some(end) = end.filter(|end| !accepted_ends.contains(end)) {
+                    accepted_ends.push(end);
+                }
+            }
+        }
+        state.slots.sort_unstable();
+        state.slots.dedup();
+        state.accepted_end = accepted_ends.first().copied();
+
+        // Threads that left at different positions must be told apart until at most one of them can match
+        let converged = threads.clone().any(|thread| {
+            threads.clone().any(|other| {
+                thread.greedy_exit != other.greedy_exit
+                    && thread.greedy_exit.slot().is_some()
+                    && other.greedy_exit.slot().is_some()
+                    && Thread {
+                        greedy_exit: other.greedy_exit,
+                        ..thread.clone()
+                    } == *other
+            })
+        });
+        // A variable after the greedy variable is only captured after a single known end
+        let has_variable = threads.clone().any(|thread| {
+            !is_greedy(nfa, thread.node)
+                && matches!(nfa.nodes[thread.node].kind, NfaNodeKind::Variable(_))
+        });
+        if converged || accepted_ends.len() > 1 || (has_variable && state.slots.len() > 1) {
+            return Err(DfaErrorKind::AmbiguousGreedyVariable {
+                name: greedy_variable(nfa).name,
+            });
+        }
+
+        // Other variables can only be told apart from the greedy variable after it was left
+        if let Some(greedy) = &state.variable {
+            for thread in threads.filter(|thread| thread.greedy_exit == GreedyExit::No) {
+                if let NfaNodeKind::Variable(var) = &nfa.nodes[thread.node].kind {
+                    if var != greedy {
+                        return Err(DfaErrorKind::ambiguous(greedy, var));
+                    }
+                }
+            }
+        }
+        Ok(state)
+    }
+
+    /// While the greedy variable may still be extended, `var` may be captured again after a later end of it,
+    /// so only variables that are assigned can be captured then
+    fn check_variable(&self, var: &RegexVariable) -> Result<(), DfaErrorKind> {
+        match &self.variable {
+            Some(greedy) if !var.kind.is_assigned() => {
+                Err(DfaErrorKind::GreedyFollowedByCollection {
+                    name: greedy.name.clone(),
+                    other: var.name.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The greedy variable of a pattern. The parser makes sure that there is at most one.
+fn greedy_variable(nfa: &Nfa) -> RegexVariable {
+    nfa.variables()
+        .into_iter()
+        .find(|var| var.kind == VariableKind::Greedy)
+        .expect("The pattern has a greedy variable")
+}
+
+/// Describes how a state relates to a match whose end depends on a lookahead, like in `a(?=b)`
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DeferredMatch {
+    #[default]
+    None,
+    /// A match may end right before the next character, if the lookaheads after it match
+    Started,
+    /// Only negative lookaheads are left, so the match that ended earlier is accepted at the end of the input
+    AcceptedAtEnd,
+    /// The lookaheads matched, so the match ends where it was started
+    Accepted,
+}
+
+impl DeferredMatch {
+    fn of(group: &[Thread]) -> Self {
+        let mut deferred_threads = group
+            .iter()
+            .filter(|thread| thread.deferred != Deferred::No);
+        if deferred_threads
+            .clone()
+            .any(|thread| thread.lookaheads.is_empty())
+        {
+            DeferredMatch::Accepted
+        } else if deferred_threads
+            .clone()
+            .any(|thread| thread.deferred == Deferred::Fresh)
+        {
+            DeferredMatch::Started
+        } else if deferred_threads
+            .any(|thread| thread.lookaheads.iter().all(|lookahead| lookahead.negative))
+        {
+            DeferredMatch::AcceptedAtEnd
+        } else {
+            DeferredMatch::None
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub struct DfaEdges {
     pub default: Option<DfaIndex>,
-    pub edges: Map<char, DfaIndex>,
+    /// The edges for disjoint ranges of characters, sorted by their start
+    pub edges: Vec<(CharRange, DfaIndex)>,
+    /// Characters that are not matched by the default edge and have no edge on their own, like `\n` for `.`
+    pub rejected: Vec<CharRange>,
 }
 
 impl DfaEdges {
-    fn replace(&mut self, old_target: DfaIndex, new_target: DfaIndex) {
-        let DfaEdges { default, edges } = self;
-        if *default == Some(old_target) {
-            *default = Some(new_target);
+    /// The state that `char` leads to, or `None` if the character is rejected
+    pub fn target(&self, char: char) -> Option<DfaIndex> {
+        let index = self.edges.partition_point(|(range, _)| range.end < char);
+        if let Some((range, target)) = self.edges.get(index) {
+            if range.contains(char) {
+                return Some(*target);
+            }
+        }
+        if self.rejected.iter().any(|range| range.contains(char)) {
+            return None;
+        }
+        self.default
+    }
+
+    /// The range of the edge or of the rejected characters that `char` is in, or `None` if it takes the default edge
+    pub fn range_of(&self, char: char) -> Option<CharRange> {
+        self.explicit_ranges().find(|range| range.contains(char))
+    }
+
+    /// A character that takes the default edge, preferring letters and digits so that examples are readable
+    fn default_char(&self) -> Option<char> {
+        let is_explicit = |char: char| {
+            self.edges
+                .iter()
+                .map(|(range, _)| range)
+                .chain(&self.rejected)
+                .any(|range| range.contains(char))
+        };
+        ('a'..='z')
+            .chain('0'..='9')
+            .chain(' '..=char::MAX)
+            .find(|char| !is_explicit(*char))
+    }
+
+    /// Removes the edges to the targets for which `is_dead` is true, so that their characters are rejected
+    fn reject_targets(&mut self, is_dead: impl Fn(DfaIndex) -> bool) {
+        if self.default.is_some_and(&is_dead) {
+            self.default = None;
+        }
+        let (dead, live) = std::mem::take(&mut self.edges)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, target)| is_dead(*target));
+        self.edges = live;
+        if !dead.is_empty() {
+            self.rejected
+                .extend(dead.into_iter().map(|(range, _)| range));
+            self.rejected.sort_unstable();
+        }
+    }
+
+    /// The ranges of the explicit edges and the rejected characters
+    pub fn explicit_ranges(&self) -> impl Iterator<Item = CharRange> + use<'_> {
+        self.edges
+            .iter()
+            .map(|(range, _)| *range)
+            .chain(self.rejected.iter().copied())
+    }
+
+    /// Lets the edges lead to the replacements of their targets
+    fn replace(&mut self, replacements: &Map<DfaIndex, DfaIndex>) {
+        let DfaEdges { default, edges, .. } = self;
+        for target in default
+            .iter_mut()
+            .chain(edges.iter_mut().map(|(_, target)| target))
+        {
+            if let Some(replacement) = replacements.get(target) {
+                *target = *replacement;
+            }
         }
+    }
 
-        for edge in edges.values_mut() {
-            if *edge == old_target {
-                *edge = new_target;
+    /// Computes the edges of the state of `key`, together with its accepting overrides
+    fn from_nfa_group(
+        dfa: &mut DfaBuilder,
+        nfa: &Nfa,
+        key: &DfaKey,
+    ) -> (Self, Map<CharRange, bool>) {
+        let other = ResolvedGroup::new(nfa, key, CharKind::Other);
+        // If the nfa has assertions, the kind of the next character matters
+        let special = nfa.has_assertions().then(|| {
+            [CharKind::Newline, CharKind::Word].map(|kind| ResolvedGroup::new(nfa, key, kind))
+        });
+        let kind_of = |range: CharRange| match special {
+            Some(_) => range.kind(),
+            None => CharKind::Other,
+        };
+        let resolved_for = |range: CharRange| match (&special, kind_of(range)) {
+            (Some([newline, _]), CharKind::Newline) => newline,
+            (Some([_, word]), CharKind::Word) => word,
+            _ => &other,
+        };
+
+        // Each group only decides about the characters of its own kind.
+        // To know the kind of the previous character in every state, all characters that are not
+        // `CharKind::Other` get an explicit edge if the nfa has assertions.
+        let mut ranges = other
+            .explicit_ranges()
+            .map(|range| (range, CharKind::Other))
+            .collect::<Vec<_>>();
+        if let Some([newline, word]) = &special {
+            ranges.extend(
+                newline
+                    .explicit_ranges()
+                    .map(|range| (range, CharKind::Newline)),
+            );
+            ranges.extend(word.explicit_ranges().map(|range| (range, CharKind::Word)));
+            ranges.extend(CharKind::special_ranges().map(|range| (range, range.kind())));
+        }
+        let ranges = CharRange::partition(&ranges, &[])
+            .into_iter()
+            .filter(|(range, kinds)| kinds.contains(&kind_of(*range)))
+            .map(|(range, _)| range);
+
+        let default_threads = other.advance(nfa, None);
+        let default = if default_threads.is_empty() {
+            None
+        } else {
+            Some(dfa.entry(DfaKey::new(nfa, default_threads, CharKind::Other)))
+        };
+
+        // The partition is sorted, so the edges are too
+        let mut edges = Vec::new();
+        let mut rejected = Vec::new();
+        let mut accepting_overrides = Map::default();
+        let other_accepts = other.accepts_before(nfa, None);
+        for range in ranges {
+            let resolved = resolved_for(range);
+            let accepts = resolved.accepts_before(nfa, Some(range));
+            if accepts != resolved.accepts_before(nfa, None) {
+                accepting_overrides.insert(range, accepts);
+            }
+
+            let threads = resolved.advance(nfa, Some(range));
+            if !threads.is_empty() {
+                let key = DfaKey::new(nfa, threads, range.kind());
+                edges.push((range, dfa.entry(key)));
+            } else if default.is_some() || accepts != other_accepts {
+                // Without an edge, the characters must still be told apart from the default edge,
+                // or from other characters if they change whether the state accepts
+                rejected.push(range);
             }
         }
+
+        let edges = DfaEdges {
+            default,
+            edges,
+            rejected,
+        };
+        (edges, accepting_overrides)
     }
+}
+
+/// The threads of a dfa state after its assertions were resolved for some kind of next character
+struct ResolvedGroup {
+    threads: Vec<Thread>,
+    edges: NfaEdges,
+    /// The characters that the lookaheads of the threads match explicitly
+    lookahead_chars: Vec<CharRange>,
+}
 
-    fn from_nfa_group(dfa: &mut DfaBuilder, nfa: &Nfa, group: &[NfaIndex]) -> Self {
+impl ResolvedGroup {
+    fn new(nfa: &Nfa, key: &DfaKey, next: CharKind) -> Self {
+        let threads = resolve_assertions(nfa, &key.group, key.previous, next);
+        let nodes = threads
+            .iter()
+            .filter(|thread| thread.deferred == Deferred::No)
+            .map(|thread| thread.node)
+            .collect::<Vec<_>>();
+
+        // The characters that the lookaheads depend on need explicit edges.
+        // If the pattern already ended before the lookahead, its characters also end lazy variables,
+        // just like the characters of a literal after the variable would.
+        let mut lookahead_chars = Vec::new();
+        let mut deferred_chars = Vec::new();
+        for thread in &threads {
+            for lookahead in &thread.lookaheads {
+                let edges = NfaEdges::new(nfa, &lookahead.nodes, &[]);
+                let chars = edges.explicit_ranges().collect::<Vec<_>>();
+                if thread.deferred == Deferred::No {
+                    lookahead_chars.extend(chars);
+                } else {
+                    deferred_chars.extend(chars);
+                }
+            }
+        }
+
+        let edges = NfaEdges::new(nfa, &nodes, &deferred_chars);
+        lookahead_chars.extend(deferred_chars);
+        Self {
+            threads,
+            edges,
+            lookahead_chars,
+        }
+    }
+
+    /// The ranges of characters that need to be told apart from the default edge
+    fn explicit_ranges(&self) -> impl Iterator<Item = CharRange> + use<'_> {
+        self.edges
+            .explicit_ranges()
+            .chain(self.lookahead_chars.iter().copied())
+    }
+
+    /// Whether a match may end before a character of `range`,
+    /// or before any character without an explicit edge if `range` is `None`
+    fn accepts_before(&self, nfa: &Nfa, range: Option<CharRange>) -> bool {
+        self.threads
+            .iter()
+            .any(|thread| thread.accepts_before(nfa, range))
+    }
+
+    /// Returns the threads after consuming a character of `range`,
+    /// or any character without an explicit edge if `range` is `None`
+    fn advance(&self, nfa: &Nfa, range: Option<CharRange>) -> Vec<Thread> {
+        let targets = match range {
+            Some(range) => self.edges.targets(range).unwrap_or_default(),
+            None => &self.edges.default,
+        };
+
+        let mut threads = Vec::new();
+        for thread in &self.threads {
+            match thread.deferred {
+                Deferred::No => {
+                    let edges = nfa.nodes[thread.node]
+                        .edges
+                        .iter()
+                        .filter(|edge| targets.binary_search(edge).is_ok());
+                    for edge in edges {
+                        let Some(lookaheads) = thread.advance_lookaheads(nfa, range) else {
+                            continue;
+                        };
+                        let next = Thread {
+                            node: *edge,
+                            lookaheads,
+                            deferred: Deferred::No,
+                            passed: Vec::new(),
+                            counted: thread.passed.clone(),
+                            greedy_exit: thread
+                                .greedy_exit
+                                .advance(is_greedy(nfa, thread.node) && !is_greedy(nfa, *edge)),
+                        };
+                        threads.extend(thread_closure(nfa, next));
+                    }
+                }
+                // This thread already matched
+                _ if thread.lookaheads.is_empty() => {}
+                _ => {
+                    if let Some(lookaheads) = thread.advance_lookaheads(nfa, range) {
+                        // Lookaheads that are decided by the first character are handled by the accepting overrides
+                        if thread.deferred == Deferred::Fresh && lookaheads.is_empty() {
+                            continue;
+                        }
+                        threads.push(Thread {
+                            lookaheads,
+                            deferred: Deferred::Pending,
+                            passed: Vec::new(),
+                            counted: Vec::new(),
+                            ..thread.clone()
+                        });
+                    }
+                }
+            }
+        }
+        threads
+    }
+}
+
+/// The outgoing edges of a group of nfa nodes
+struct NfaEdges {
+    /// The targets for disjoint ranges of characters, sorted by their start
+    edges: Vec<(CharRange, Vec<NfaIndex>)>,
+    default: Vec<NfaIndex>,
+    rejected: Vec<CharRange>,
+}
+
+impl NfaEdges {
+    /// Computes the edges of `group`. The characters in `extra_ranges` get an explicit edge,
+    /// even if no node of the group matches them explicitly.
+    fn new(nfa: &Nfa, group: &[NfaIndex], extra_ranges: &[CharRange]) -> Self {
         let edges = get_non_epsilon_edges(nfa, group);
 
-        let mut default_edges: Vec<NfaIndex> = Vec::new();
-        let mut lazy_default_edges: Vec<NfaIndex> = Vec::new();
+        // The default edges, together with whether they also match a newline
+        let mut default_edges: Vec<(NfaIndex, bool)> = Vec::new();
+        let mut lazy_default_edges: Vec<(NfaIndex, bool)> = Vec::new();
+        // Greedy variables continue on every character, but unlike other default edges, they keep lazy variables alive
+        let mut greedy_default_edges: Vec<(NfaIndex, bool)> = Vec::new();
 
-        let mut edge_map: Map<char, Vec<NfaIndex>> = Map::default();
+        let mut explicit_ranges: Vec<(CharRange, Option<NfaIndex>)> =
+            extra_ranges.iter().map(|range| (*range, None)).collect();
         for (edge_pattern, target_idx) in edges.iter().copied() {
             match edge_pattern {
-                RegexPattern::Char(char) => edge_map.entry(char).or_default().push(target_idx),
+                RegexPattern::Char(char) => {
+                    explicit_ranges.push((CharRange::single(char), Some(target_idx)))
+                }
                 RegexPattern::Range(start, end) => {
-                    for char in start..=end {
-                        edge_map.entry(char).or_default().push(target_idx);
-                    }
+                    explicit_ranges.push((CharRange::new(start, end), Some(target_idx)))
+                }
+                RegexPattern::AnyChar { newline } if is_greedy(nfa, target_idx) => {
+                    greedy_default_edges.push((target_idx, newline))
+                }
+                RegexPattern::AnyChar { newline } => default_edges.push((target_idx, newline)),
+                RegexPattern::AnyCharLazy { newline } => {
+                    lazy_default_edges.push((target_idx, newline))
                 }
-                RegexPattern::AnyChar => default_edges.push(target_idx),
-                RegexPattern::AnyCharLazy => lazy_default_edges.push(target_idx),
             }
         }
 
-        // Since a default edge can be any char, it also has to be added to each value in the edge map now.
-        for targets in edge_map.values_mut() {
-            targets.extend(default_edges.iter().copied());
-            targets.sort_unstable();
-            targets.dedup();
-        }
+        // A newline always gets a range of its own, because not every default edge matches it.
+        // Since a default edge can be any char, it also has to be added to each explicit range now.
+        let mut edge_map = CharRange::partition(&explicit_ranges, &['\n'])
+            .into_iter()
+            .map(|(range, targets)| {
+                let mut targets = targets
+                    .into_iter()
+                    .flatten()
+                    .chain(
+                        default_edges
+                            .iter()
+                            .chain(&greedy_default_edges)
+                            .filter(|(_, newline)| !range.contains('\n') || *newline)
+                            .map(|(idx, _)| *idx),
+                    )
+                    .collect::<Vec<_>>();
+                targets.sort_unstable();
+                targets.dedup();
+                (range, targets)
+            })
+            .collect::<Vec<_>>();
 
         // If there is a default_edge, it will overwrite the lazy-default edge completely.
         if default_edges.is_empty() {
             default_edges = lazy_default_edges;
         }
+        default_edges.extend(greedy_default_edges);
+
+        // A newline needs its own edge if some default edges do not match it
+        let mut rejected = Vec::new();
+        let has_newline_edge = edge_map.iter().any(|(range, _)| range.contains('\n'));
+        if default_edges.iter().any(|(_, newline)| !newline) && !has_newline_edge {
+            let newline_edges = default_edges
+                .iter()
+                .filter(|(_, newline)| *newline)
+                .map(|(idx, _)| *idx)
+                .collect::<Vec<_>>();
+            if newline_edges.is_empty() {
+                rejected.push(CharRange::single('\n'));
+            } else {
+                edge_map.push((CharRange::single('\n'), newline_edges));
+                edge_map.sort_unstable();
+            }
+        }
+
+        let mut default_edges = default_edges
+            .into_iter()
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
         default_edges.sort_unstable();
         default_edges.dedup();
 
-        let default_edge_idx = if default_edges.is_empty() {
-            None
-        } else {
-            Some(dfa.entry(expand_group(nfa, &default_edges)))
-        };
-        let edge_indices = edge_map
-            .into_iter()
-            .map(|(key, value)| (key, dfa.entry(expand_group(nfa, &value))))
+        NfaEdges {
+            edges: edge_map,
+            default: default_edges,
+            rejected,
+        }
+    }
+
+    /// The ranges of characters that need to be told apart from the default edge
+    fn explicit_ranges(&self) -> impl Iterator<Item = CharRange> + use<'_> {
+        self.edges
+            .iter()
+            .map(|(range, _)| *range)
+            .chain(self.rejected.iter().copied())
+    }
+
+    /// Returns the targets for the characters of `range`.
+    /// The range must not overlap with more than one explicit range.
+    fn targets(&self, range: CharRange) -> Option<&[NfaIndex]> {
+        let index = self
+            .edges
+            .partition_point(|(other, _)| other.end < range.start);
+        if let Some((other, targets)) = self.edges.get(index) {
+            if other.contains(range.start) {
+                return Some(targets);
+            }
+        }
+        let is_rejected = self
+            .rejected
+            .iter()
+            .any(|rejected| rejected.contains(range.start));
+        if is_rejected || self.default.is_empty() {
+            return None;
+        }
+        Some(&self.default)
+    }
+}
+
+/// A partition of the states `0..n` into blocks, which can be refined by marking some states and splitting them off
+/// from the other states of their block
+struct Partition {
+    /// The states, so that the states of every block are next to each other
+    states: Vec<usize>,
+    /// Where each state is in `states`
+    positions: Vec<usize>,
+    block_of: Vec<usize>,
+    /// The range of every block in `states`. The marked states of a block are at its start.
+    ranges: Vec<Range<usize>>,
+    marked: Vec<usize>,
+    touched: Vec<usize>,
+}
+
+impl Partition {
+    /// Creates the partition where `blocks[state]` is the block of each state. The blocks must be numbered from 0.
+    fn new(blocks: &[usize]) -> Self {
+        let block_count = blocks.iter().max().map_or(0, |max| max + 1);
+        let mut states = (0..blocks.len()).collect::<Vec<_>>();
+        states.sort_by_key(|state| blocks[*state]);
+        let mut positions = vec![0; blocks.len()];
+        for (position, state) in states.iter().enumerate() {
+            positions[*state] = position;
+        }
+        let ranges = (0..block_count)
+            .map(|block| {
+                let start = states.partition_point(|state| blocks[*state] < block);
+                let end = states.partition_point(|state| blocks[*state] <= block);
+                start..end
+            })
             .collect();
-        DfaEdges {
-            default: default_edge_idx,
-            edges: edge_indices,
+        Self {
+            states,
+            positions,
+            block_of: blocks.to_vec(),
+            ranges,
+            marked: vec![0; block_count],
+            touched: Vec::new(),
         }
     }
+
+    fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    fn size(&self, block: usize) -> usize {
+        self.ranges[block].len()
+    }
+
+    fn block(&self, state: usize) -> usize {
+        self.block_of[state]
+    }
+
+    fn states(&self, block: usize) -> &[usize] {
+        &self.states[self.ranges[block].clone()]
+    }
+
+    fn mark(&mut self, state: usize) {
+        let block = self.block_of[state];
+        let first_unmarked = self.ranges[block].start + self.marked[block];
+        let position = self.positions[state];
+        if position < first_unmarked {
+            return;
+        }
+        if self.marked[block] == 0 {
+            self.touched.push(block);
+        }
+        let other = self.states[first_unmarked];
+        self.states.swap(position, first_unmarked);
+        self.positions[other] = position;
+        self.positions[state] = first_unmarked;
+        self.marked[block] += 1;
+    }
+
+    /// Moves the marked states of every block that also has unmarked states into a new block.
+    /// Returns the blocks that were split, together with their new block.
+    fn split_marked(&mut self) -> Vec<(usize, usize)> {
+        let mut splits = Vec::new();
+        for block in std::mem::take(&mut self.touched) {
+            let marked = std::mem::take(&mut self.marked[block]);
+            if marked == self.size(block) {
+                continue;
+            }
+            let Range { start, end } = self.ranges[block];
+            let new_block = self.ranges.len();
+            self.ranges[block] = start + marked..end;
+            self.ranges.push(start..start + marked);
+            self.marked.push(0);
+            for state in &self.states[start..start + marked] {
+                self.block_of[*state] = new_block;
+            }
+            splits.push((block, new_block));
+        }
+        splits
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::dfa::Dfa;
+    use crate::dfa::{Dfa, DEFAULT_MAX_STATES};
     use crate::nfa::Nfa;
     use crate::regex::Regex;
     use crate::ProcMacroErrorKind;
 
     fn parse(input: &str) -> Result<Dfa, ProcMacroErrorKind> {
+        parse_limited(input, DEFAULT_MAX_STATES)
+    }
+
+    fn parse_limited(input: &str, max_states: usize) -> Result<Dfa, ProcMacroErrorKind> {
         let regex = Regex::from_str(input)?;
         let nfa = Nfa::try_from(regex)?;
-        let dfa = Dfa::try_from(nfa)?;
+        let dfa = Dfa::new(&nfa, max_states)?;
         Ok(dfa)
     }
 
@@ -340,8 +1779,88 @@ mod tests {
         insta::assert_debug_snapshot!(parse("([abc]\\s*)*"));
     }
 
+    #[test]
+    fn test_minimize() {
+        // The repetitions after `x` and `y` only refer to themselves, so they are merged although they are not equal
+        let dfa = parse("(x(ab)*|y(ab)*);").unwrap();
+        assert_eq!(dfa.iter().count(), 4);
+        // The merged states are removed from the arena
+        assert_eq!(dfa.nodes.len(), 4);
+        let dfa = parse("((a|b)(a|b))*").unwrap();
+        assert_eq!(dfa.iter().count(), 2);
+        // States that capture different variables stay apart
+        let dfa = parse("(x{a}|y{b});").unwrap();
+        assert_eq!(dfa.iter().count(), 6);
+        // The states after `x` can not reach a match, so `x` is rejected right away
+        let dfa = parse(r"xa\bb|y").unwrap();
+        assert_eq!(dfa.iter().count(), 2);
+        assert_eq!(dfa.nodes.len(), 2);
+        assert_eq!(dfa.mismatch("xab"), Some(0));
+        // Every state of a long literal is told apart by the distance to its end
+        let dfa = parse_limited(&"ab".repeat(2000), 5000).unwrap();
+        assert_eq!(dfa.iter().count(), 4001);
+    }
+
+    #[test]
+    fn test_newline() {
+        insta::assert_debug_snapshot!(parse("a.b"));
+        insta::assert_debug_snapshot!(parse("(?s)a.b"));
+        insta::assert_debug_snapshot!(parse("{a}\n{b}"));
+    }
+
+    #[test]
+    fn test_lookahead() {
+        insta::assert_debug_snapshot!(parse("a(?=b)"));
+    }
+
+    #[test]
+    fn test_negative_lookahead() {
+        insta::assert_debug_snapshot!(parse("a(?!bc)"));
+    }
+
     #[test]
     fn test_nfa_to_dfa_ambiguous_variable() {
         insta::assert_debug_snapshot!(parse("A{foo}B?{bar}"));
     }
+
+    #[test]
+    fn test_undecided_variables() {
+        insta::assert_debug_snapshot!(parse(r"({a:x\d}|{b:x[a-z]})"));
+        insta::assert_debug_snapshot!(parse(r"({a:y}|{b:y[a-z]})"));
+        insta::assert_debug_snapshot!(parse(r"({a:x[a-z]}|{b:x[a-z0-9]})"));
+    }
+
+    #[test]
+    fn test_too_many_states() {
+        let pattern = "{name}: (a|b)*a(a|b)#{5}!";
+        assert!(parse_limited(pattern, 100).is_ok());
+        insta::assert_debug_snapshot!(parse_limited(pattern, 50).unwrap_err());
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let dfa = parse(r"{a}:\d+(ms|s)").unwrap();
+        assert_eq!(dfa.mismatch("x:12ms"), None);
+        assert_eq!(dfa.mismatch("x:12s"), None);
+        assert_eq!(dfa.mismatch("x:1a"), Some(3));
+        assert_eq!(dfa.mismatch("x:12m"), Some(5));
+        assert_eq!(dfa.mismatch("x:12"), Some(4));
+
+        let dfa = parse(".").unwrap();
+        assert_eq!(dfa.mismatch("é"), None);
+        assert_eq!(dfa.mismatch("\n"), Some(0));
+        assert_eq!(dfa.mismatch(""), Some(0));
+    }
+
+    #[test]
+    fn test_sorted_edges() {
+        let dfa = parse("[a-cx-z\u{4e00}-\u{9fff}0-4]+;").unwrap();
+        for idx in dfa.iter() {
+            let edges = &dfa.nodes[idx].edges.edges;
+            assert!(edges.windows(2).all(|pair| pair[0].0.end < pair[1].0.start));
+        }
+        assert_eq!(dfa.mismatch("a3z漢;"), None);
+        assert_eq!(dfa.mismatch("a5;"), Some(1));
+        assert_eq!(dfa.mismatch("yz\u{a000};"), Some(2));
+    }
 }
@@ -1,26 +1,37 @@
 mod arena;
+mod classes;
 mod codegen;
+mod describe;
 mod dfa;
+mod literal;
 mod nfa;
+mod overlap;
 mod parser;
 mod regex;
+mod search;
+mod stats;
+mod time_format;
 mod tokenizer;
 mod util;
 
-use crate::codegen::Codegen;
-use crate::dfa::{Dfa, DfaError};
+use crate::codegen::{Codegen, Fallback, Mode};
+use crate::dfa::{Dfa, DfaError, DfaErrorKind, DEFAULT_MAX_STATES};
 use crate::nfa::{Nfa, NfaError};
-use crate::regex::Regex;
+use crate::regex::{Regex, VariableKind};
+use crate::search::Search;
+use crate::stats::Stats;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
+use quote::quote;
 use syn::parse::{Parse, ParseStream};
-use syn::{parse_macro_input, Expr, LitStr};
+use syn::{parse_macro_input, Expr, Lit, LitStr};
 use thiserror::Error;
 
-// Use non-std map and set implementations to make snapshot testing possible.
-// std map and set implementations are not deterministic, which is required for that.
-pub(crate) type Map<K, V> = fxhash::FxHashMap<K, V>;
-pub(crate) type Set<K> = fxhash::FxHashSet<K>;
+// Use maps and sets that iterate in insertion order, so the generated code for a pattern is the same on every build
+// and platform. The iteration order of hash based maps depends on the hasher, which differs between the std random
+// state and between 32 and 64 bit targets for fxhash.
+pub(crate) type Map<K, V> = indexmap::IndexMap<K, V, fxhash::FxBuildHasher>;
+pub(crate) type Set<K> = indexmap::IndexSet<K, fxhash::FxBuildHasher>;
 
 struct ReParseInput {
     regex: LitStr,
@@ -36,25 +47,361 @@ impl Parse for ReParseInput {
     }
 }
 
+/// The arguments of `re_parse!`, which may end with an `else` clause like `else |err| { ... }`
+struct ReParseFallbackInput {
+    regex: LitStr,
+    expression: Expr,
+    fallback: Option<Fallback>,
+}
+
+impl Parse for ReParseFallbackInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ReParseInput { regex, expression } = input.parse()?;
+        let fallback = if input.peek(syn::Token![,]) && input.peek2(syn::Token![else]) {
+            input.parse::<syn::Token![,]>()?;
+            input.parse::<syn::Token![else]>()?;
+            let error = if input.peek(syn::Token![|]) {
+                input.parse::<syn::Token![|]>()?;
+                let error = syn::Pat::parse_single(input)?;
+                input.parse::<syn::Token![|]>()?;
+                Some(error)
+            } else {
+                None
+            };
+            let body = input.parse()?;
+            Some(Fallback { error, body })
+        } else {
+            None
+        };
+        Ok(Self {
+            regex,
+            expression,
+            fallback,
+        })
+    }
+}
+
+/// A `name = value` argument that configures the behavior of a macro
+struct MacroOption {
+    name: syn::Ident,
+    value: Lit,
+}
+
+impl Parse for MacroOption {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<syn::Token![=]>()?;
+        let value = input.parse()?;
+        Ok(Self { name, value })
+    }
+}
+
+impl MacroOption {
+    /// Parses the remaining `, name = value` arguments of a macro invocation
+    fn parse_trailing(input: ParseStream) -> syn::Result<Vec<Self>> {
+        let mut options = Vec::new();
+        while !input.is_empty() {
+            input.parse::<syn::Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            options.push(input.parse()?);
+        }
+        Ok(options)
+    }
+
+    fn bool_value(&self) -> syn::Result<bool> {
+        match &self.value {
+            Lit::Bool(value) => Ok(value.value),
+            other => Err(syn::Error::new_spanned(
+                other,
+                format!("Expected `true` or `false` for the option `{}`", self.name),
+            )),
+        }
+    }
+
+    fn usize_value(&self) -> syn::Result<usize> {
+        match &self.value {
+            Lit::Int(value) => value.base10_parse(),
+            other => Err(syn::Error::new_spanned(
+                other,
+                format!("Expected a number for the option `{}`", self.name),
+            )),
+        }
+    }
+
+    fn unknown_option_error(&self) -> syn::Error {
+        syn::Error::new(self.name.span(), format!("Unknown option `{}`", self.name))
+    }
+}
+
+/// The options that every macro accepts after its arguments, like `max_states = 20000`
+#[derive(Clone, Copy)]
+struct Options {
+    /// How many states the state machine of the pattern may have
+    max_states: usize,
+    /// Whether the generated code prints every step of the state machine
+    debug: bool,
+    /// Whether a pattern that matches the empty string is an error if it captures variables
+    strict: bool,
+    /// Whether the state machine looks up its transitions in static tables instead of matching on the characters
+    compact: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_states: DEFAULT_MAX_STATES,
+            debug: false,
+            strict: false,
+            compact: false,
+        }
+    }
+}
+
+impl Options {
+    /// Applies `option` if it is one of the options that every macro accepts. Returns `false` for other options.
+    fn apply(&mut self, option: &MacroOption) -> syn::Result<bool> {
+        match option.name.to_string().as_str() {
+            "max_states" => self.max_states = option.usize_value()?,
+            "debug" => self.debug = option.bool_value()?,
+            "strict" => self.strict = option.bool_value()?,
+            "compact" => self.compact = option.bool_value()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Parses the arguments of a macro, followed by the options that every macro accepts
+fn parse_with_options<T: Parse>(input: ParseStream) -> syn::Result<(T, Options)> {
+    let arguments = input.parse()?;
+    let mut options = Options::default();
+    for option in MacroOption::parse_trailing(input)? {
+        if !options.apply(&option)? {
+            return Err(option.unknown_option_error());
+        }
+    }
+    Ok((arguments, options))
+}
+
+struct ReParseLinesInput {
+    regex: LitStr,
+    reader: Expr,
+    skip_mismatches: bool,
+    options: Options,
+}
+
+impl Parse for ReParseLinesInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ReParseInput {
+            regex,
+            expression: reader,
+        } = input.parse()?;
+
+        let mut skip_mismatches = false;
+        let mut options = Options::default();
+        for option in MacroOption::parse_trailing(input)? {
+            match option.name.to_string().as_str() {
+                "skip_mismatches" => skip_mismatches = option.bool_value()?,
+                _ if options.apply(&option)? => {}
+                _ => return Err(option.unknown_option_error()),
+            }
+        }
+
+        Ok(Self {
+            regex,
+            reader,
+            skip_mismatches,
+            options,
+        })
+    }
+}
+
+struct ReParseAtInput {
+    regex: LitStr,
+    expression: Expr,
+    cursor: Expr,
+}
+
+impl Parse for ReParseAtInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ReParseInput { regex, expression } = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let cursor = input.parse()?;
+        Ok(Self {
+            regex,
+            expression,
+            cursor,
+        })
+    }
+}
+
+struct ReParseIntoInput {
+    regex: LitStr,
+    expression: Expr,
+    target: Expr,
+}
+
+impl Parse for ReParseIntoInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ReParseInput { regex, expression } = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let target = input.parse()?;
+        Ok(Self {
+            regex,
+            expression,
+            target,
+        })
+    }
+}
+
+struct ReForEachInput {
+    regex: LitStr,
+    expression: Expr,
+    body: Expr,
+}
+
+impl Parse for ReForEachInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ReParseInput { regex, expression } = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let body = input.parse()?;
+        Ok(Self {
+            regex,
+            expression,
+            body,
+        })
+    }
+}
+
+struct ReAssertInput {
+    regex: LitStr,
+    example: LitStr,
+}
+
+impl Parse for ReAssertInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let regex = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let example = input.parse()?;
+        Ok(Self { regex, example })
+    }
+}
+
+struct ReClassInput {
+    name: syn::Ident,
+    pattern: LitStr,
+}
+
+impl Parse for ReClassInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<syn::Token![=]>()?;
+        let pattern = input.parse()?;
+        Ok(Self { name, pattern })
+    }
+}
+
 /// The main macro of this crate, which parses strings using regular expressions and can extract variables.
 ///
 /// # Usage
 /// `re_parse!(pattern: StrLiteral, value: &str);`
 ///
+/// `re_parse!(pattern: StrLiteral, value: &str, else |error| { ... });`
+///
 /// Any variables contained in `pattern` will be set after the macro has run.
-/// For now, the macro will panic if the input cannot be parsed (TODO: Return error)
+/// A variable that does not exist is reported at the pattern, together with the compiler's suggestions for similar names.
+/// The macro panics if the input does not match the pattern.
+/// The panic message shows the line of the input with a `^` under the first character that does not match.
+/// If a capture cannot be parsed, the message names the capture, its type and the captured text.
+///
+/// With an `else` clause, the block runs instead of panicking if the input does not match, see [Fallback](#fallback).
 ///
 /// The pattern is a regular expression which can contain variable captures.
+/// The earlier branch of an alternation wins if several branches match, so a branch that matches the same text as an
+/// earlier branch, like `a.c` in `(abc|a.c)`, is rejected. Branches with untyped variables or assertions are not checked,
+/// because the text they match depends on the rest of the pattern.
 ///
 /// ## Variable Captures
-/// - `{var_name}`: Captures a single variable of at least one character
-/// - `{var_name*}`: Captures multiple (or zero) variables
+/// - `{var_name}`: Captures a single variable of at least one character.
+///   Keywords can be captured as raw identifiers, like `{r#type}`.
+/// - `{var_name!}`: Captures a single variable greedily. A variable usually ends where the rest of the pattern can start
+///   first, but a greedy variable ends at the last position where the rest of the pattern still matches,
+///   e.g. `{path!} {line}` captures `my file.rs` from `my file.rs 10`. A pattern can contain one greedy variable.
+///   Patterns where the rest of the pattern can match from two ends of the variable at once, like `{a!} {b} {c}`,
+///   are rejected as ambiguous.
+/// - `{place}`: Instead of a variable name, every capture can also assign to a place like `{self.x}`, `{points[0].y}`,
+///   `{cells[y * width + x]}` or `{*slot}`. Indices may use variables from the surrounding scope.
+///   `re_for_each!` and `re_parse_lines!` declare new variables, so they only accept names.
+/// - `{=expr}`: Matches like a variable, but instead of capturing the text, the text has to equal the value of `expr`,
+///   which is evaluated at runtime and can be a `&str` or `String`, e.g. `\[{=level}\] {msg}`.
+///   If the text differs, `re_parse!` panics and `re_for_each!` skips the match.
+/// - `{mut var_name}`: Declares a mutable binding in the body of `re_for_each!`, e.g. `{mut total: u32}`,
+///   which declares `let mut total: u32`. Later occurrences of the variable do not need to repeat the `mut`.
+/// - `{var_name*}`: Captures multiple (or zero) variables.
+///   Assigned variables can be any `FromIterator`, e.g. a `SmallVec` or `ArrayVec` to avoid the heap.
+///   The type is declared at the binding, so a pattern like `{var_name: Vec<u32>}` is rejected.
+/// - `{var_name* => buf}`: Parses every match directly into the next element of `buf`, a `&mut [T]` or array,
+///   and binds the number of matches. Panics if `buf` is too small.
+/// - `{var_name* : iter}`: Binds an iterator that parses the matches while it is consumed, instead of collecting them into a `Vec`,
+///   e.g. `{xs* sep="," : iter}`. It borrows the input, so `re_parse_lines!` does not support it.
+/// - `{var_name**}`: Captures a `Vec<Vec<T>>` with one list per repetition of an outer group, e.g. `(({n**},)*\n)*`
+///   binds the numbers of every line. A repetition of the outer group in which the variable does not match adds an empty list.
+/// - `{var_name;n}`: Captures an array `[T; n]`. The pattern has to match the variable exactly `n` times, e.g. `({xs;3},)#{3}`
+/// - `{var_name* sep=", "}`, `{var_name;n sep=", "}`: Captures a list of matches with the separator between them,
+///   but not after the last one, e.g. `\[{items* sep=", "}\]`. In the separator, `\"` is a quote.
+/// - `(...)*=list`, `(...)+=list`: Binds a `Vec` with a tuple of the variables of the group for every repetition,
+///   e.g. `({x},{y};)*=pairs` binds `pairs: Vec<(X, Y)>`. Every variable must match exactly once per repetition.
+/// - `(...)*=>map`: Collects the key and value variables of every repetition into a map, or any other `FromIterator<(K, V)>`,
+///   e.g. `({key}: {value}\n)*=>headers`. A later key replaces an earlier one. `re_for_each!` and `re_parse_lines!` bind a `HashMap`.
+///   A pair cannot be written as one capture like `{key => value}`, because `=>` after a variable names a buffer,
+///   see `{var_name* => buffer}`.
+/// - `{#var_name}`: Binds how often the repeated group that contains it matched, as a `usize`, without capturing any text,
+///   e.g. `(\#{#level})+ {title}`. It must not be inside of an alternation or an optional part of the group,
+///   and every character must tell whether it starts a new repetition.
+/// - `{var_name?}`: Captures an `Option`, which is `None` if the part of the pattern with the variable did not match.
+///   A single variable that only appears in some branches of an alternation is optional as well,
+///   e.g. `(cat {lives}|dog {name})` sets `lives` and `name` to `Option`s.
+///   A variable that appears in several branches, like `({n} items|{n} item)`, is set by whichever branch matched.
+/// - `{var_name?=expr}`: Like `{var_name?}`, but the variable is set to the rust expression `expr` instead of `None`, e.g. `{port?=80}`
+/// - `{var_name??}`: Captures a single variable that may also be empty, like a missing field in `{name},{age??},{city}`.
+///   An empty variable is set to `Default::default()` instead of being parsed.
+/// - `{var_name:pattern}`: Captures exactly the text that matches `pattern`, e.g. `{id:[A-Z]{3}\d+}`.
+///   Inside of the pattern, `{n}`, `{n,}` and `{n,m}` are repetitions. Variables in different branches may start
+///   with the same text, like in `({hex:0x[0-9a-f]+}|{oct:0o[0-7]+})`, as long as a later character tells them apart.
+/// - `{var_name:min..=max}`: Captures a variable that spans between `min` and `max` characters, e.g. `{code:3..=6}`.
+///   `{var_name:min..max}` excludes `max` and `{var_name:min..}` has no upper bound. Like `{var_name}`, the variable
+///   ends as early as possible, so fixed-width fields like `{year:4..=4}{month:2..=2}` can follow each other directly.
+/// - `{var_name:(text=>expr|...)}`: Matches one of the literal texts and binds the rust expression of that branch,
+///   e.g. `{enabled:(on=>true|off=>false)}`. The captured text is not parsed.
+///   A branch with the same text as an earlier branch could never be chosen, so it is rejected.
+/// - `{var_name:(text|...)=>Enum}`: Binds the variant of `Enum` that is named like the matched text in UpperCamelCase,
+///   e.g. `{dir:(north|south)=>Direction}` binds `Direction::North` or `Direction::South`
+/// - `{var_name|function}`: Converts the captured `&str` with `function` instead of `FromStr`, e.g. `{addr|parse_hex}`.
+///   The function returns a `Result`, and the macro panics on an error like it does if parsing fails.
+/// - `{var_name | trim | lowercase}`: Transforms the captured text before it is parsed or passed to a function.
+///   The transformations are `trim`, `trim_start`, `trim_end`, `lowercase` and `uppercase`.
+///   The `t` flag adds `trim` to every single variable after it, so `(?t){name},{age}` parses ` Alice , 42`.
+/// - `{var_name@span_name}`: Also binds the byte range of the match in the input, as a `Range<usize>`, to `span_name`
+/// - `{_}`: Matches like a variable, but nothing is captured. `{_:pattern}`, `{_:type}` and `{_:min..=max}` work the same way.
+/// - `{var_name:type}`: Captures only text that can be parsed as a primitive type like `u32`, `i64`, `f64`, `bool` or `char`.
+///   A `char` is taken directly from the input, without parsing it.
+/// - `{var_name:x}`, `{var_name:b}`, `{var_name:o}`: Captures a hexadecimal, binary or octal integer
+/// - `{var_name:quoted}`: Captures a double-quoted string like `"say \"hi\""`, where a backslash escapes the next character.
+///   The quotes are removed and the escapes are resolved before the contents are parsed.
+/// - `{var_name:csv}`: Captures a CSV field as described in RFC 4180. A quoted field like `"a ""b"", c"` may contain
+///   commas, line breaks and doubled quotes, and is unquoted before it is parsed.
+///   `{var_name*:csv}` captures a whole record and binds its fields.
+/// - `{var_name:%Y-%m-%d %H:%M:%S}`: Captures a date or time in the given format as a `chrono` type (requires the `chrono` feature).
+///   The type is `NaiveDate`, `NaiveTime`, `NaiveDateTime` or `DateTime<FixedOffset>`, depending on the specifiers that are used
 ///
 /// ## Character Classes
 /// `re_parse!` currently supports these character classes:
 /// - `\s`: Any Whitespace (equivalent to `[\n\t\r ]`)
 /// - `\d`: Any Digit (equivalent to `[0-9]`)
 /// - `\w`: Any Word (equivalent to `[a-zA-Z0-0_]`)
+/// - `\f`: A number with an optional sign, decimals and exponent, like `-1.5e-3` (the same as `\k{float}`)
 ///
 /// # Example
 ///
@@ -75,43 +422,778 @@ impl Parse for ReParseInput {
 /// assert_eq!(temperatures, vec![10.0, 9.0, 8.5, 8.0]);
 /// ```
 ///
+/// ## Fallback
+/// The `else` block runs if the input does not match the pattern. The captured variables are only assigned
+/// if the input matched, so they can only be used after the macro if the block does not complete, e.g. because it returns.
+/// The block may bind the error, which is a `regex_parse::Error` with the public fields `position` (the byte index
+/// where the input stopped matching) and `message`, and implements `Display` and `Error`. If the input did not match
+/// the pattern, `unexpected` is the rejected character like `Some(regex_parse::Unexpected::Char('x'))`
+/// or `Some(regex_parse::Unexpected::EndOfInput)`, and `expected` lists the characters that were expected instead,
+/// like `["0-9"]`. If a capture could not be parsed, the field `capture` is a `regex_parse::CaptureError`
+/// with its `ty` (the name of the type it was parsed as) and its `text`. The generated code refers to these types
+/// by the path `::regex_parse`, so the macro has to be used through the `regex-parse` crate.
+///
+/// With an `else` block, the generated code cannot panic: it contains no `panic!`, `unwrap` or `assert!`,
+/// and it does not slice the input with an index that could be out of bounds. A capture that cannot be parsed,
+/// a full buffer and a wrong number of matches run the `else` block as well. The position of a capture that cannot
+/// be parsed is its start. This keeps the macro usable where panics are audited, like with `no_panic`.
+/// Only the code that the pattern refers to can still panic, like the `FromStr` implementation of a capture,
+/// a converting function, or a collection that cannot hold all matches. The `debug` option prints to stderr,
+/// which panics if stderr cannot be written to.
+/// ```rust
+/// # use re_parse_proc_macro::re_parse;
+/// fn parse_point(input: &str) -> Result<(i32, i32), String> {
+///     let x: i32;
+///     let y: i32;
+///     re_parse!(r"\({x},{y}\)", input, else |err| {
+///         return Err(err.to_string());
+///     });
+///     Ok((x, y))
+/// }
+/// assert_eq!(parse_point("(1,2)"), Ok((1, 2)));
+/// assert_eq!(parse_point("(1,2"), Err("Unexpected end of input (at byte 4 of the input)".to_string()));
+/// ```
+///
 /// # Efficiency
 /// The macro compiles the pattern into a state-machine which executes in linear time, so it should be very efficient.
+/// `re_find!` and `re_for_each!` do not run the state machine from every position of the input, but find the start
+/// of a match with a second state machine that reads every character once.
+/// States that behave the same on every input are merged, so the state machine is as small as possible.
+/// The state-machine is a loop with a single `match` on the current state, and the code that reports a mismatch
+/// is moved into cold functions, so that the loop stays small. The states are numbered by the smallest integer type
+/// that fits, and the characters that each state expects are looked up in a static table when the input does not match,
+/// so that every state reports a mismatch with the same code. Likewise, whether a state accepts the end of the input
+/// and which variable it ends there are looked up in static tables. If the pattern only tells ASCII characters apart,
+/// the state machine runs on the bytes of the input instead of decoding its characters. Any other character
+/// is handled as a whole by the edge for all remaining characters. If every match starts with a literal, like
+/// `Temperatures: ` in `Temperatures: {temps*}`, the input is compared to the whole literal at once instead of
+/// running one state per character. The benchmarks in `benches/` measure long inputs
+/// and can be run with `cargo bench`.
+///
+/// Some patterns need very many states, like `(a|b)*a(a|b)#{20}`, which has to remember the last 21 characters.
+/// To keep compilation fast, a pattern may have at most 10000 states. The error names the part of the pattern
+/// that most of the states are for. Every macro accepts the option `max_states` after its arguments to raise the limit,
+/// like `re_parse!(pattern, input, max_states = 50000)`.
+/// A bounded repetition like `a#{3}` is expanded into copies of its operand before the states are built, so a count
+/// may be at most 1000, and nested repetitions may expand the pattern to at most 100000 parts.
+///
+/// Large state machines take long to compile, because every state gets its own `match` on the next character.
+/// With the option `compact = true`, the transitions are stored in static tables instead, which are indexed by
+/// the state and by the class of the character, and only the code that runs for a transition is generated.
+/// This makes compilation much faster and the binary smaller, but matching an input may be slower.
+/// A pattern with a lookahead like `\d+(?=px)` keeps its `match` unless the whole input has to match.
+///
+/// If the environment variable `RE_PARSE_STATS` is set, like `RE_PARSE_STATS=1 cargo build`, every macro prints
+/// the number of states and edges of its pattern and the size of the generated code. Cargo does not rebuild
+/// a crate when only the variable changes, so the crate may have to be cleaned first, e.g. with `cargo clean -p my-crate`.
+///
+/// # Debugging
+/// With the option `debug = true`, like `re_parse!(pattern, input, debug = true)`, the generated code prints
+/// every character that it consumes together with the current state, and the text of every variable when it ends, to stderr.
+/// The last lines show where the input stopped matching. Every macro that runs a state machine accepts the option.
+///
+/// A pattern that matches the empty string although it captures variables is usually a mistake in a quantifier,
+/// like `({x*},)*`, where zero repetitions leave `x` empty. With the option `strict = true`, such a pattern is
+/// a compile error. Optional variables like `{x?}` and `{x??}`, counters and backreferences may stay without a match.
 #[proc_macro]
 pub fn re_parse(input: TokenStream) -> TokenStream {
-    let ReParseInput { regex, expression } = parse_macro_input!(input as ReParseInput);
+    classes::expand("re_parse", input, |input| {
+        let (
+            ReParseFallbackInput {
+                regex,
+                expression,
+                fallback,
+            },
+            options,
+        ) = parse_macro_input!(input with parse_with_options::<ReParseFallbackInput>);
 
-    let result = re_parse_impl(regex, expression).unwrap_or_else(|err| err.into_token_stream());
-    result.into()
+        let result = re_parse_impl(regex, expression, Mode::Parse { fallback }, options)
+            .unwrap_or_else(|err| err.into_token_stream());
+        result.into()
+    })
 }
 
-fn re_parse_impl(
-    regex: LitStr,
-    expression: Expr,
-) -> Result<proc_macro2::TokenStream, ProcMacroError> {
-    // TODO: When subspan becomes stable, use that to get a more accurate span of the error
-    let span = regex.span();
+/// Runs a body for every match of a pattern in the input, with the captured variables bound.
+///
+/// # Usage
+/// `re_for_each!(pattern: StrLiteral, value: &str, body: Expr);`
+///
+/// The input is scanned from left to right for non-overlapping matches of `pattern`.
+/// Parts of the input that do not match are skipped.
+/// For every match, the variables contained in `pattern` are declared as new variables and `body` is executed.
+/// Their types are usually inferred from how `body` uses them.
+///
+/// Every match is the longest one that starts at its position.
+/// `break` and `continue` inside of `body` apply to the scanning loop.
+///
+/// Like [re_find!], the start of every match is found in a single pass over the input. Only the state machine
+/// that finds the longest match may read past the end of the match, like in `a|a*b` for `aaa`, where it has to
+/// look for a `b`. The next search starts at the end of the match, so such a part of the input is read again.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_for_each;
+/// let mut points: Vec<(i32, i32)> = Vec::new();
+/// re_for_each!(r"\({x},{y}\)", "(1,2) (3,4), (-5,6)", {
+///     points.push((x, y));
+/// });
+/// assert_eq!(points, vec![(1, 2), (3, 4), (-5, 6)]);
+/// ```
+#[proc_macro]
+pub fn re_for_each(input: TokenStream) -> TokenStream {
+    classes::expand("re_for_each", input, |input| {
+        let (
+            ReForEachInput {
+                regex,
+                expression,
+                body,
+            },
+            options,
+        ) = parse_macro_input!(input with parse_with_options::<ReForEachInput>);
 
-    let regex = Regex::from_str(&regex.value()).map_err(|err| ProcMacroError {
-        kind: err.into(),
-        span,
+        let result = re_parse_impl(regex, expression, Mode::ForEach { body }, options)
+            .unwrap_or_else(|err| err.into_token_stream());
+        result.into()
+    })
+}
+
+/// Parses a prefix of the input starting at a byte offset and advances the offset past the match.
+///
+/// # Usage
+/// `re_parse_at!(pattern: StrLiteral, value: &str, cursor: &mut usize);`
+///
+/// Works like [re_parse!], except that matching starts at `value[*cursor..]` and does not have to reach the end of the input.
+/// The match is the longest one that the pattern allows, so `ab|abcd` matches `ab` of `abce`.
+/// Afterward, `cursor` points to the first byte after the match, so several patterns can be chained over one string.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_parse_at;
+/// let input = "move 10 left";
+/// let mut cursor = 0;
+/// let distance: u32;
+/// let direction: String;
+/// re_parse_at!("move ", input, &mut cursor);
+/// re_parse_at!("{distance} ", input, &mut cursor);
+/// assert_eq!(cursor, 8);
+/// re_parse_at!("{direction}", input, &mut cursor);
+/// assert_eq!((distance, direction.as_str()), (10, "left"));
+/// assert_eq!(cursor, input.len());
+/// ```
+#[proc_macro]
+pub fn re_parse_at(input: TokenStream) -> TokenStream {
+    classes::expand("re_parse_at", input, |input| {
+        let (
+            ReParseAtInput {
+                regex,
+                expression,
+                cursor,
+            },
+            options,
+        ) = parse_macro_input!(input with parse_with_options::<ReParseAtInput>);
+
+        let result = re_parse_impl(regex, expression, Mode::ParseAt { cursor }, options)
+            .unwrap_or_else(|err| err.into_token_stream());
+        result.into()
+    })
+}
+
+/// Parses the input into the fields of an existing struct.
+///
+/// # Usage
+/// `re_parse_into!(pattern: StrLiteral, value: &str, target: &mut T);`
+///
+/// Works like [re_parse!], except that every variable of the pattern is assigned to the field of `target` with the same name.
+/// Fields that do not appear in the pattern keep their value, so a struct can be updated from several inputs.
+/// The variables must be plain names, not places like `{self.x}`.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_parse_into;
+/// struct Config {
+///     host: String,
+///     port: u16,
+///     verbose: bool,
+/// }
+///
+/// let mut config = Config { host: "localhost".to_string(), port: 80, verbose: true };
+/// re_parse_into!("port={port}", "port=8080", &mut config);
+/// assert_eq!((config.host.as_str(), config.port, config.verbose), ("localhost", 8080, true));
+/// ```
+#[proc_macro]
+pub fn re_parse_into(input: TokenStream) -> TokenStream {
+    classes::expand("re_parse_into", input, |input| {
+        let (
+            ReParseIntoInput {
+                regex,
+                expression,
+                target,
+            },
+            options,
+        ) = parse_macro_input!(input with parse_with_options::<ReParseIntoInput>);
+
+        let result = re_parse_impl(regex, expression, Mode::Into { target }, options)
+            .unwrap_or_else(|err| err.into_token_stream());
+        result.into()
+    })
+}
+
+/// Parses a prefix of the input and evaluates to the remainder that was not matched.
+///
+/// # Usage
+/// `re_parse_prefix!(pattern: StrLiteral, value: &str) -> &str`
+///
+/// Works like [re_parse!], except that the pattern does not have to match the whole input.
+/// The match is the longest one that the pattern allows.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_parse_prefix;
+/// let command: String;
+/// let rest = re_parse_prefix!("{command} ", "echo Hello World");
+/// assert_eq!(command, "echo");
+/// assert_eq!(rest, "Hello World");
+/// ```
+#[proc_macro]
+pub fn re_parse_prefix(input: TokenStream) -> TokenStream {
+    classes::expand("re_parse_prefix", input, |input| {
+        let (ReParseInput { regex, expression }, options) =
+            parse_macro_input!(input with parse_with_options::<ReParseInput>);
+
+        let result = re_parse_impl(regex, expression, Mode::Prefix, options)
+            .unwrap_or_else(|err| err.into_token_stream());
+        result.into()
+    })
+}
+
+/// Searches the input for the first match of a pattern and evaluates to the byte range of the match.
+///
+/// # Usage
+/// `re_find!(pattern: StrLiteral, value: &str) -> Range<usize>`
+///
+/// Works like [re_parse!], except that the match may start and end anywhere in the input.
+/// The leftmost match is used, and it is the longest one that starts at its position.
+/// The macro panics if the input contains no match.
+///
+/// Because the match is the longest one, a variable at the end of the pattern like in `"error code {code}"`
+/// extends to the end of the input. To stop it earlier, end the pattern with the text that follows the variable,
+/// or give the variable a type whose pattern stops at the end of the value, like `"error code {code:u32}"`.
+///
+/// The input is searched in a single pass, which follows the matches from every start position at the same time,
+/// so finding where the leftmost match starts takes time proportional to the length of the input.
+/// The state machine of the pattern then runs once from that start to find the longest match.
+/// Backreferences and expected values are only compared afterwards, so if they reject the match,
+/// the search continues after its start.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_find;
+/// let code: u32;
+/// let span = re_find!("error code {code};", "[12:00:01] error code 404; not found");
+/// assert_eq!(code, 404);
+/// assert_eq!(span, 11..26);
+///
+/// let code: u32;
+/// re_find!("error code {code:u32}", "[12:00:01] error code 404 not found");
+/// assert_eq!(code, 404);
+/// ```
+#[proc_macro]
+pub fn re_find(input: TokenStream) -> TokenStream {
+    classes::expand("re_find", input, |input| {
+        let (ReParseInput { regex, expression }, options) =
+            parse_macro_input!(input with parse_with_options::<ReParseInput>);
+
+        let result = re_parse_impl(regex, expression, Mode::Find, options)
+            .unwrap_or_else(|err| err.into_token_stream());
+        result.into()
+    })
+}
+
+/// Parses every line of a [BufRead](std::io::BufRead) and evaluates to an iterator over the captures.
+///
+/// # Usage
+/// `re_parse_lines!(pattern: StrLiteral, reader: impl BufRead, [skip_mismatches = bool], [max_states = usize]) -> impl Iterator<Item = Result<(..), regex_parse::LineError>>`
+///
+/// Every line has to match `pattern` completely.
+/// For each line, the iterator yields a tuple that contains the captured variables in the order
+/// in which they appear in the pattern. The span of a `{var@span}` variable follows right after its value
+/// and is relative to the line.
+/// The element types are inferred from how the tuples are used.
+///
+/// A line that cannot be read yields a `LineError::Io`, and a line that does not match the pattern
+/// yields a `LineError::Mismatch` with the text of the line. Both contain the number of the line, starting at 1.
+/// With `skip_mismatches = true`, the lines that do not match are skipped instead.
+/// A capture that cannot be parsed as its type panics, like in [re_parse!].
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_parse_lines;
+/// let input = "move 1 2\nmove 3 4\n";
+/// let moves: Vec<(i32, i32)> = re_parse_lines!("move {x} {y}", input.as_bytes())
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(moves, vec![(1, 2), (3, 4)]);
+///
+/// let input = "# comment\nmove 5 6";
+/// let moves: Vec<(i32, i32)> = re_parse_lines!("move {x} {y}", input.as_bytes(), skip_mismatches = true)
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(moves, vec![(5, 6)]);
+///
+/// let err = re_parse_lines!("move {x} {y}", "move 1 2\njump".as_bytes())
+///     .map(|line| line.map(|(x, y): (i32, i32)| x + y))
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap_err();
+/// assert_eq!(err.to_string(), r#"Line 2 does not match the pattern: "jump""#);
+/// ```
+#[proc_macro]
+pub fn re_parse_lines(input: TokenStream) -> TokenStream {
+    classes::expand("re_parse_lines", input, |input| {
+        let ReParseLinesInput {
+            regex,
+            reader,
+            skip_mismatches,
+            options,
+        } = parse_macro_input!(input as ReParseLinesInput);
+
+        let result = re_parse_impl(regex, reader, Mode::Lines { skip_mismatches }, options)
+            .unwrap_or_else(|err| err.into_token_stream());
+        result.into()
+    })
+}
+
+/// Checks at compile time that an example matches a pattern.
+///
+/// # Usage
+/// `re_assert_matches!(pattern: StrLiteral, example: StrLiteral);`
+///
+/// The whole example has to match the pattern, like the input of [re_parse!].
+/// Compilation fails at the first character of the example that does not match, so patterns can be tested
+/// without any cost at runtime. The macro expands to nothing and can be used wherever an item or a statement can.
+///
+/// Only the pattern is checked, so a capture that cannot be parsed as the type of its variable is not detected.
+/// Backreferences and expected values like `{=prefix}` compare the input to other text, so they are rejected.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::{re_assert_matches, re_assert_no_match};
+/// re_assert_matches!(r"{year}/{month}/{day}", "2024/01/31");
+/// re_assert_no_match!(r"{year}/{month}/{day}", "2024/01");
+/// ```
+///
+/// ```compile_fail
+/// # use re_parse_proc_macro::re_assert_matches;
+/// re_assert_matches!(r"\d+", "12a");
+/// ```
+#[proc_macro]
+pub fn re_assert_matches(input: TokenStream) -> TokenStream {
+    classes::expand("re_assert_matches", input, |input| re_assert(input, true))
+}
+
+/// Checks at compile time that an example does not match a pattern.
+///
+/// # Usage
+/// `re_assert_no_match!(pattern: StrLiteral, example: StrLiteral);`
+///
+/// The opposite of [re_assert_matches!]: compilation fails if the whole example matches the pattern.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_assert_no_match;
+/// re_assert_no_match!(r"\d+", "12a");
+/// ```
+#[proc_macro]
+pub fn re_assert_no_match(input: TokenStream) -> TokenStream {
+    classes::expand("re_assert_no_match", input, |input| re_assert(input, false))
+}
+
+/// Checks a pattern at compile time, without generating any code.
+///
+/// # Usage
+/// `re_validate!(pattern: StrLiteral);`
+///
+/// The pattern is compiled like [re_parse!] compiles it, so every error of the pattern is reported in the same way,
+/// including ambiguous variables and patterns that need too many states. The option `max_states` raises the limit.
+/// The macro expands to nothing and can be used wherever an item or a statement can, e.g. next to a pattern
+/// that is stored in a constant or shown in documentation.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_validate;
+/// re_validate!(r"{name}: {score}");
+/// ```
+///
+/// ```compile_fail
+/// # use re_parse_proc_macro::re_validate;
+/// re_validate!("Foo{variable}B*{other_variable}C");
+/// ```
+#[proc_macro]
+pub fn re_validate(input: TokenStream) -> TokenStream {
+    classes::expand("re_validate", input, |input| {
+        let (regex, options) = parse_macro_input!(input with parse_with_options::<LitStr>);
+
+        match compile_pattern(&regex, options) {
+            Ok(_) => TokenStream::new(),
+            Err(err) => err.into_syn_error().into_compile_error().into(),
+        }
+    })
+}
+
+/// Renders the state machine of a pattern as a graph, to understand how the macros match it.
+///
+/// # Usage
+/// `re_debug_dot!(pattern: StrLiteral) -> &'static str`
+///
+/// Evaluates to the graph in the DOT language of [Graphviz](https://graphviz.org), which can be printed
+/// or written to a file and rendered with `dot -Tsvg pattern.dot -o pattern.svg`.
+/// The states are named like the states in the output of the `debug` option. Every state shows the variable
+/// that it captures and the variables that the characters so far do not tell apart from it.
+/// Accepting states are drawn with a double circle, and every edge is labeled with the characters that take it.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_debug_dot;
+/// let dot = re_debug_dot!("{key}={value}");
+/// assert!(dot.starts_with("digraph"));
+/// // std::fs::write("pattern.dot", dot).unwrap();
+/// ```
+#[proc_macro]
+pub fn re_debug_dot(input: TokenStream) -> TokenStream {
+    classes::expand("re_debug_dot", input, |input| {
+        let (regex, options) = parse_macro_input!(input with parse_with_options::<LitStr>);
+
+        match compile_pattern(&regex, options) {
+            Ok(dfa) => {
+                let dot = describe::to_dot(&dfa);
+                quote! { #dot }.into()
+            }
+            Err(err) => err.into_token_stream().into(),
+        }
+    })
+}
+
+/// Describes the state machine of a pattern as text, to understand how the macros match it.
+///
+/// # Usage
+/// `re_explain!(pattern: StrLiteral) -> &'static str`
+///
+/// Evaluates to a compact summary with one paragraph per state. A paragraph starts with the name of the state,
+/// whether it is accepting and the variables that it captures, followed by one line per transition with the
+/// characters that take it. `or` lists the variables that the characters so far do not tell apart from the captured
+/// one. The states are named like in [re_debug_dot!] and in the output of the `debug` option.
+///
+/// ```text
+/// start: State_0
+/// State_0
+///     any other except '\n' -> State_1
+/// State_1: capture key
+///     '=' -> State_2
+///     any other except '\n' -> State_1
+/// State_2
+///     any other except '\n' -> State_3
+/// State_3: accepting, capture value
+///     any other except '\n' -> State_3
+/// ```
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::re_explain;
+/// let explanation = re_explain!("{key}={value}");
+/// assert!(explanation.contains("State_1: capture key"));
+/// // println!("{explanation}");
+/// ```
+#[proc_macro]
+pub fn re_explain(input: TokenStream) -> TokenStream {
+    classes::expand("re_explain", input, |input| {
+        let (regex, options) = parse_macro_input!(input with parse_with_options::<LitStr>);
+
+        match compile_pattern(&regex, options) {
+            Ok(dfa) => {
+                let text = describe::to_text(&dfa);
+                quote! { #text }.into()
+            }
+            Err(err) => err.into_token_stream().into(),
+        }
+    })
+}
+
+/// The shared implementation of [re_assert_matches!] and [re_assert_no_match!]
+fn re_assert(input: TokenStream, expect_match: bool) -> TokenStream {
+    let (ReAssertInput { regex, example }, options) =
+        parse_macro_input!(input with parse_with_options::<ReAssertInput>);
+
+    // The macros expand to nothing, so the errors are not wrapped in a block and may be items
+    match re_assert_impl(regex, example, expect_match, options) {
+        Ok(()) => TokenStream::new(),
+        Err(err) => err.into_syn_error().into_compile_error().into(),
+    }
+}
+
+/// Defines a named class that patterns can reference with `\k{name}`.
+///
+/// # Usage
+/// `re_class!(name = pattern: StrLiteral);`
+///
+/// The pattern can be any pattern without variables, so it can be reused in many other patterns.
+/// It is parsed on its own, so flags like `(?i)` of the referencing pattern do not apply to it.
+///
+/// The class is a `macro_rules!` macro named `__re_class_<name>`, which the macros that reference the class call,
+/// so it is in scope like any other `macro_rules!` macro: after the definition in the same module and in the modules
+/// that are declared after it. To use a class in a module that is declared earlier, or in a sibling module,
+/// define it in that module, or declare the module that defines it with `#[macro_use]`.
+/// Like every macro of this crate, the macros are called through the crate `regex_parse`, so it has to be a dependency.
+/// A class that is not in scope is reported as an unknown macro `__re_class_<name>`.
+/// The compiler does not let a class that is defined by a macro shadow another class, so each name is defined once.
+///
+/// These classes are built in: `ipv4`, `ipv6`, `email`, `iso_date`, `uuid` and `float`.
+/// They are always in scope and cannot be defined again.
+///
+/// # Example
+/// ```rust
+/// # use re_parse_proc_macro::{re_class, re_find};
+/// re_class!(hex = "[0-9a-fA-F]");
+///
+/// assert_eq!(re_find!(r"0x\k{hex}+", "value: 0x1F"), 7..11);
+/// ```
+#[proc_macro]
+pub fn re_class(input: TokenStream) -> TokenStream {
+    classes::expand("re_class", input, |input| {
+        let ReClassInput { name, pattern } = parse_macro_input!(input as ReClassInput);
+
+        if classes::preset(&name.to_string()).is_some() {
+            let error = ProcMacroError {
+                span: name.span(),
+                kind: ProcMacroErrorKind::BuiltInClass {
+                    name: name.to_string(),
+                },
+                related: Vec::new(),
+            };
+            return error.into_syn_error().into_compile_error().into();
+        }
+        // Classes that this class references are added before the class is checked, see `classes::expand`
+        if let Err(err) = Regex::class(&name.to_string(), &pattern.value()) {
+            let error = ProcMacroError {
+                span: literal::subspan(&pattern, err.position.range.clone()),
+                kind: err.into(),
+                related: Vec::new(),
+            };
+            return error.into_syn_error().into_compile_error().into();
+        }
+        classes::define_class_macro(&name, &pattern).into()
+    })
+}
+
+/// Compiles the pattern into its state machine. The errors point at the part of the pattern that caused them.
+fn compile_pattern(regex: &LitStr, options: Options) -> Result<Dfa, Box<ProcMacroError>> {
+    let parsed = Regex::parse(&regex.value()).map_err(|errors| {
+        let mut errors = errors.into_iter().map(|err| ProcMacroError {
+            span: literal::subspan(regex, err.position.range.clone()),
+            kind: err.into(),
+            related: Vec::new(),
+        });
+        let first = errors.next().expect("Parsing only fails with an error");
+        ProcMacroError {
+            related: errors.collect(),
+            ..first
+        }
     })?;
-    let nfa = Nfa::try_from(regex).map_err(|err| ProcMacroError {
+    if let Some(overlap) = overlap::find_overlap(&parsed, options.max_states) {
+        let pattern = regex.value();
+        return Err(Box::new(ProcMacroError {
+            span: literal::subspan(regex, overlap.branch.range.clone()),
+            kind: ProcMacroErrorKind::OverlappingBranch {
+                branch: pattern[overlap.branch.range].to_string(),
+                earlier: pattern[overlap.earlier.range].to_string(),
+                text: overlap.text,
+                index: overlap.branch.index,
+            },
+            related: Vec::new(),
+        }));
+    }
+    let nfa = Nfa::try_from(parsed).map_err(|err| ProcMacroError {
+        span: literal::subspan(regex, err.position.range.clone()),
         kind: err.into(),
-        span,
+        related: Vec::new(),
     })?;
-    let dfa = Dfa::try_from(nfa).map_err(|err| ProcMacroError {
-        kind: err.into(),
-        span,
+    let dfa = Dfa::new(&nfa, options.max_states).map_err(|err| {
+        // Ambiguous variables are reported at both variables
+        let related = match &err.kind {
+            DfaErrorKind::AmbiguousVariables { first, second, .. } => nfa
+                .positions
+                .get(first)
+                .map(|position| ProcMacroError {
+                    span: literal::subspan(regex, position.range.clone()),
+                    kind: ProcMacroErrorKind::CollidingVariable {
+                        name: first.clone(),
+                        other: second.clone(),
+                    },
+                    related: Vec::new(),
+                })
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        };
+        ProcMacroError {
+            span: literal::subspan(regex, err.position.range.clone()),
+            kind: err.into(),
+            related,
+        }
     })?;
-    let codegen = Codegen { dfa, expression };
-    Ok(codegen.generate())
+    if options.strict && dfa.nodes[dfa.root].is_accepting {
+        if let Some(var) = dfa.variables.iter().find(|var| !var.kind.is_optional()) {
+            return Err(Box::new(ProcMacroError {
+                span: nfa.positions.get(&var.key()).map_or_else(
+                    || regex.span(),
+                    |position| literal::subspan(regex, position.range.clone()),
+                ),
+                kind: ProcMacroErrorKind::EmptyMatch {
+                    name: var.name.clone(),
+                },
+                related: Vec::new(),
+            }));
+        }
+    }
+    Ok(dfa)
+}
+
+fn re_parse_impl(
+    regex: LitStr,
+    expression: Expr,
+    mode: Mode,
+    options: Options,
+) -> Result<proc_macro2::TokenStream, Box<ProcMacroError>> {
+    let span = regex.span();
+    let dfa = compile_pattern(&regex, options)?;
+    if matches!(mode, Mode::Into { .. }) {
+        if let Some(var) = dfa.variables.iter().find(|var| var.is_place()) {
+            return Err(Box::new(ProcMacroError {
+                kind: ProcMacroErrorKind::FieldPlace {
+                    name: var.name.clone(),
+                },
+                span,
+                related: Vec::new(),
+            }));
+        }
+    }
+    if mode.declares_variables() {
+        if let Some(var) = dfa.variables.iter().find(|var| var.is_place()) {
+            return Err(Box::new(ProcMacroError {
+                kind: ProcMacroErrorKind::DeclaredPlace {
+                    name: var.name.clone(),
+                },
+                span,
+                related: Vec::new(),
+            }));
+        }
+    }
+    if !matches!(mode, Mode::ForEach { .. }) {
+        if let Some(var) = dfa.variables.iter().find(|var| var.mutable) {
+            return Err(Box::new(ProcMacroError {
+                kind: ProcMacroErrorKind::MutableBinding {
+                    name: var.name.clone(),
+                },
+                span,
+                related: Vec::new(),
+            }));
+        }
+    }
+    // The lines are read into a buffer that is reused, so an iterator over a line cannot be returned
+    if matches!(mode, Mode::Lines { .. }) {
+        if let Some(var) = dfa
+            .variables
+            .iter()
+            .find(|var| matches!(var.kind, VariableKind::Iter { .. }))
+        {
+            return Err(Box::new(ProcMacroError {
+                kind: ProcMacroErrorKind::IterInLines {
+                    name: var.name.clone(),
+                },
+                span,
+                related: Vec::new(),
+            }));
+        }
+    }
+    let search = match mode {
+        Mode::Find | Mode::ForEach { .. } => Some(
+            Search::new(&dfa, options.max_states).ok_or_else(|| ProcMacroError {
+                kind: ProcMacroErrorKind::TooManySearchStates {
+                    max_states: options.max_states,
+                },
+                span,
+                related: Vec::new(),
+            })?,
+        ),
+        _ => None,
+    };
+    let codegen = Codegen {
+        dfa,
+        expression,
+        mode,
+        trace: options.debug,
+        compact: options.compact,
+        pattern_span: Span::call_site().located_at(span),
+        search,
+    };
+    let code = codegen.generate();
+    if stats::enabled() {
+        Stats::new(&codegen.dfa, &code).report(&regex);
+    }
+    Ok(code)
+}
+
+/// Checks at compile time whether `example` matches the pattern, and fails if that is not `expect_match`
+fn re_assert_impl(
+    regex: LitStr,
+    example: LitStr,
+    expect_match: bool,
+    options: Options,
+) -> Result<(), Box<ProcMacroError>> {
+    let dfa = compile_pattern(&regex, options)?;
+    // Backreferences and expected values are compared to other text, which is only known when the input is parsed
+    if let Some(var) = dfa.variables.iter().find(|var| {
+        matches!(
+            var.kind,
+            VariableKind::Backreference { .. } | VariableKind::Expected { .. }
+        )
+    }) {
+        return Err(Box::new(ProcMacroError {
+            kind: ProcMacroErrorKind::UncheckedComparison {
+                name: var.name.clone(),
+            },
+            span: regex.span(),
+            related: Vec::new(),
+        }));
+    }
+
+    let value = example.value();
+    let kind = match (dfa.mismatch(&value), expect_match) {
+        (None, true) | (Some(_), false) => return Ok(()),
+        (None, false) => ProcMacroErrorKind::ExampleMatches,
+        (Some(position), true) => match value[position..].chars().next() {
+            Some(char) => ProcMacroErrorKind::UnexpectedExampleChar { char, position },
+            None => ProcMacroErrorKind::ExampleEndsEarly,
+        },
+    };
+    let range = match &kind {
+        ProcMacroErrorKind::UnexpectedExampleChar { char, position } => {
+            *position..position + char.len_utf8()
+        }
+        ProcMacroErrorKind::ExampleEndsEarly => value.len()..value.len(),
+        _ => 0..value.len(),
+    };
+    Err(Box::new(ProcMacroError {
+        kind,
+        span: literal::subspan(&example, range),
+        related: Vec::new(),
+    }))
 }
 
 #[derive(Debug)]
 struct ProcMacroError {
     kind: ProcMacroErrorKind,
     span: Span,
+    /// More errors that were found together with this one, which are reported as well
+    related: Vec<ProcMacroError>,
 }
 
 #[derive(Debug, Error)]
@@ -122,25 +1204,98 @@ enum ProcMacroErrorKind {
     Nfa(#[from] NfaError),
     #[error(transparent)]
     Dfa(#[from] DfaError),
+    #[error("The variable '{}' is a place, but this macro declares new variables. Use an identifier instead", name)]
+    DeclaredPlace { name: String },
+    #[error("The variable '{}' is an iterator over the line, which cannot outlive it. Use `{{{}*}}` to collect the matches instead", name, name)]
+    IterInLines { name: String },
+    #[error("The variable '{}' is a place, but `re_parse_into!` assigns to the fields of its target. Use a field name instead", name)]
+    FieldPlace { name: String },
+    #[error("The variable '{}' is declared with `mut`, but only `re_for_each!` declares bindings for its body", name)]
+    MutableBinding { name: String },
+    #[error(
+        "The variable '{}' is captured here, so it cannot be told apart from {}",
+        name,
+        other
+    )]
+    CollidingVariable { name: String, other: String },
+    #[error(
+        "The example does not match the pattern, because of the character {:?} at byte {}",
+        char,
+        position
+    )]
+    UnexpectedExampleChar { char: char, position: usize },
+    #[error("The example does not match the pattern, because it ends too early")]
+    ExampleEndsEarly,
+    #[error("The example matches the pattern, but it is asserted not to")]
+    ExampleMatches,
+    #[error("The variable '{}' is compared to other text, which can only be checked when the input is parsed", name)]
+    UncheckedComparison { name: String },
+    #[error("The pattern matches the empty string, which leaves the variable '{}' without a match. This is usually a mistake in a quantifier, like `*` instead of `+`. Remove the option `strict` if this is intended", name)]
+    EmptyMatch { name: String },
+    #[error("The class '{}' is built in and cannot be defined again", name)]
+    BuiltInClass { name: String },
+    #[error("Searching the input for the pattern needs more than {} states, because matches that start at different positions overlap in too many ways. Simplify the pattern, or raise the limit with the option `max_states = ...`", max_states)]
+    TooManySearchStates { max_states: usize },
+    #[error(
+        "The branch '{}' can never be chosen for text like {:?}, because the earlier branch '{}' matches it as well. Reorder the branches or make them exclusive (at character {} of the pattern)",
+        branch,
+        text,
+        earlier,
+        index + 1
+    )]
+    OverlappingBranch {
+        branch: String,
+        earlier: String,
+        text: String,
+        index: usize,
+    },
 }
 
 impl ProcMacroError {
+    /// The errors of a macro that expands to an expression, wrapped in a block so that several errors still form one
     fn into_token_stream(self) -> proc_macro2::TokenStream {
+        let errors = self.into_syn_error().into_compile_error();
+        quote! { { #errors } }
+    }
+
+    fn into_syn_error(self) -> syn::Error {
         let msg = match self.kind {
             ProcMacroErrorKind::Parse(parse_error) => parse_error.to_string(),
             ProcMacroErrorKind::Nfa(nfa_error) => nfa_error.to_string(),
             ProcMacroErrorKind::Dfa(dfa_error) => dfa_error.to_string(),
+            kind @ (ProcMacroErrorKind::DeclaredPlace { .. }
+            | ProcMacroErrorKind::IterInLines { .. }
+            | ProcMacroErrorKind::FieldPlace { .. }
+            | ProcMacroErrorKind::MutableBinding { .. }
+            | ProcMacroErrorKind::CollidingVariable { .. }
+            | ProcMacroErrorKind::UnexpectedExampleChar { .. }
+            | ProcMacroErrorKind::ExampleEndsEarly
+            | ProcMacroErrorKind::ExampleMatches
+            | ProcMacroErrorKind::UncheckedComparison { .. }
+            | ProcMacroErrorKind::EmptyMatch { .. }
+            | ProcMacroErrorKind::BuiltInClass { .. }
+            | ProcMacroErrorKind::TooManySearchStates { .. }
+            | ProcMacroErrorKind::OverlappingBranch { .. }) => kind.to_string(),
         };
-        syn::Error::new(self.span, msg).into_compile_error()
+        let mut error = syn::Error::new(self.span, msg);
+        for related in self.related {
+            error.combine(related.into_syn_error());
+        }
+        error
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{re_parse_impl, ProcMacroErrorKind, ReParseInput};
+    use super::{
+        re_parse_impl, Options, ProcMacroErrorKind, ReForEachInput, ReParseAtInput,
+        ReParseFallbackInput, ReParseInput, ReParseIntoInput, ReParseLinesInput,
+    };
+    use crate::codegen::{Fallback, Mode};
     use crate::dfa::Dfa;
     use crate::nfa::Nfa;
     use crate::regex::Regex;
+    use proc_macro2::{Delimiter, TokenTree};
     use proptest::prelude::*;
     use quote::quote;
 
@@ -151,18 +1306,107 @@ mod tests {
         Ok(dfa)
     }
 
-    fn test_re_parse(input: proc_macro2::TokenStream) -> String {
-        let ReParseInput { regex, expression } = syn::parse2::<ReParseInput>(input).unwrap();
-        let stream = re_parse_impl(regex, expression).unwrap_or_else(|err| err.into_token_stream());
+    fn test_expansion(regex: syn::LitStr, expression: syn::Expr, mode: Mode) -> String {
+        test_expansion_with(regex, expression, mode, Options::default())
+    }
+
+    fn test_expansion_with(
+        regex: syn::LitStr,
+        expression: syn::Expr,
+        mode: Mode,
+        options: Options,
+    ) -> String {
+        let stream = re_parse_impl(regex, expression, mode, options)
+            .unwrap_or_else(|err| err.into_token_stream());
         let file_content = format!("fn main() {{ {stream} }}");
         let file = syn::parse_file(&file_content).unwrap();
         prettyplease::unparse(&file)
     }
 
+    fn test_re_parse(input: proc_macro2::TokenStream) -> String {
+        let ReParseFallbackInput {
+            regex,
+            expression,
+            fallback,
+        } = syn::parse2::<ReParseFallbackInput>(input).unwrap();
+        test_expansion(regex, expression, Mode::Parse { fallback })
+    }
+
+    fn test_re_parse_prefix(input: proc_macro2::TokenStream) -> String {
+        let ReParseInput { regex, expression } = syn::parse2::<ReParseInput>(input).unwrap();
+        test_expansion(regex, expression, Mode::Prefix)
+    }
+
+    fn test_re_find(input: proc_macro2::TokenStream) -> String {
+        let ReParseInput { regex, expression } = syn::parse2::<ReParseInput>(input).unwrap();
+        test_expansion(regex, expression, Mode::Find)
+    }
+
+    fn test_re_parse_lines(input: proc_macro2::TokenStream) -> String {
+        let ReParseLinesInput {
+            regex,
+            reader,
+            skip_mismatches,
+            ..
+        } = syn::parse2::<ReParseLinesInput>(input).unwrap();
+        test_expansion(regex, reader, Mode::Lines { skip_mismatches })
+    }
+
+    fn test_re_parse_into(input: proc_macro2::TokenStream) -> String {
+        let ReParseIntoInput {
+            regex,
+            expression,
+            target,
+        } = syn::parse2::<ReParseIntoInput>(input).unwrap();
+        test_expansion(regex, expression, Mode::Into { target })
+    }
+
+    fn test_re_for_each(input: proc_macro2::TokenStream) -> String {
+        let ReForEachInput {
+            regex,
+            expression,
+            body,
+        } = syn::parse2::<ReForEachInput>(input).unwrap();
+        test_expansion(regex, expression, Mode::ForEach { body })
+    }
+
+    fn test_re_parse_at(input: proc_macro2::TokenStream) -> String {
+        let ReParseAtInput {
+            regex,
+            expression,
+            cursor,
+        } = syn::parse2::<ReParseAtInput>(input).unwrap();
+        test_expansion(regex, expression, Mode::ParseAt { cursor })
+    }
+
     macro_rules! dbg_re_parse {
         ($($input:tt)*) => {test_re_parse(quote! {$($input)*})};
     }
 
+    macro_rules! dbg_re_parse_prefix {
+        ($($input:tt)*) => {test_re_parse_prefix(quote! {$($input)*})};
+    }
+
+    macro_rules! dbg_re_find {
+        ($($input:tt)*) => {test_re_find(quote! {$($input)*})};
+    }
+
+    macro_rules! dbg_re_parse_lines {
+        ($($input:tt)*) => {test_re_parse_lines(quote! {$($input)*})};
+    }
+
+    macro_rules! dbg_re_parse_into {
+        ($($input:tt)*) => {test_re_parse_into(quote! {$($input)*})};
+    }
+
+    macro_rules! dbg_re_for_each {
+        ($($input:tt)*) => {test_re_for_each(quote! {$($input)*})};
+    }
+
+    macro_rules! dbg_re_parse_at {
+        ($($input:tt)*) => {test_re_parse_at(quote! {$($input)*})};
+    }
+
     #[test]
     fn test_macro_expansion() {
         insta::assert_snapshot!(dbg_re_parse!("A", "A"));
@@ -170,11 +1414,237 @@ mod tests {
         insta::assert_snapshot!(dbg_re_parse!("({var*},)*", "1,2,3,4,"));
         insta::assert_snapshot!(dbg_re_parse!("([abc]\\s*)*", "A"));
         insta::assert_snapshot!(dbg_re_parse!("A.*B.*;", "AAABBB;"));
+        insta::assert_snapshot!(dbg_re_parse!("{a}( {b?})?", "1 2"));
+        insta::assert_snapshot!(dbg_re_parse!("({x},{y};)*=pairs", "1,2;"));
+        insta::assert_snapshot!(dbg_re_parse!("A{a}", "A1", else |err| { return Err(err) }));
+    }
+
+    #[test]
+    fn test_range_expansion() {
+        // The adjacent classes lead to the same state, so their ranges are merged
+        let expansion = dbg_re_parse!("([a-m]|[n-z])x", "bx");
+        assert!(expansion.contains("b'a'..=b'z' =>"), "{expansion}");
+        let expansion = dbg_re_parse!("{word:[一-龥]+}", "漢字");
+        assert!(expansion.contains("'一'..='龥' =>"), "{expansion}");
+    }
+
+    #[test]
+    fn test_byte_expansion() {
+        // A pattern that only tells ASCII characters apart matches the bytes of the input
+        let expansion = dbg_re_parse!("{a},{b}", "1,2");
+        assert!(
+            expansion.contains("as_bytes()") && expansion.contains("b','"),
+            "{expansion}"
+        );
+        let expansion = dbg_re_parse!("{a}→{b}", "1→2");
+        assert!(
+            expansion.contains("char_indices()") && expansion.contains("'→'"),
+            "{expansion}"
+        );
+    }
+
+    #[test]
+    fn test_literal_prefix_expansion() {
+        let expansion = dbg_re_parse!(r"Temperatures: \[{temps*}\]", "Temperatures: [1]");
+        assert!(
+            expansion.contains("__initial_input.starts_with(\"Temperatures: [\")")
+                && expansion.contains("__next_index = 15usize;"),
+            "{expansion}"
+        );
+        // A match may end within the literal or repeat it
+        for expansion in [
+            dbg_re_parse!("ab?", "a"),
+            dbg_re_parse!("A+", "AA"),
+            dbg_re_parse!("a{x}", "ab"),
+        ] {
+            assert!(!expansion.contains("starts_with"), "{expansion}");
+        }
+    }
+
+    #[test]
+    fn test_expected_table() {
+        // The messages are looked up by the state, so every state rejects the input with the same code
+        let expansion = dbg_re_parse!("{a},{b};", "1,2;");
+        assert!(
+            expansion.contains("#[repr(u8)]")
+                && expansion.contains("static __EXPECTED: [(&str, &str); 5usize]"),
+            "{expansion}"
+        );
+        assert_eq!(expansion.matches("Expected").count(), 5, "{expansion}");
+    }
+
+    #[test]
+    fn test_termination_tables() {
+        // Only the last state accepts the end of the input, which ends no variable
+        let expansion = dbg_re_parse!("{a},{b};", "1,2;");
+        assert!(
+            expansion.contains(
+                "static __ACCEPTING: [bool; 5usize] = [false, false, false, false, true];"
+            ) && !expansion.contains("__VARIABLES"),
+            "{expansion}"
+        );
+        // Only the state of `b` accepts the end of the input and ends its variable, the others hold the number of variables
+        let expansion = dbg_re_parse!("{a},{b}", "1,2");
+        assert!(
+            expansion.contains("static __VARIABLES: [u8; 4usize] = [2, 2, 2, 1];"),
+            "{expansion}"
+        );
+    }
+
+    #[test]
+    fn test_for_each_expansion() {
+        insta::assert_snapshot!(dbg_re_for_each!("{x},{y};", "1,2;3,4;", {
+            points.push((x, y));
+        }));
+    }
+
+    #[test]
+    fn test_parse_at_expansion() {
+        insta::assert_snapshot!(dbg_re_parse_at!("{a} ", "1 2", &mut cursor));
+    }
+
+    #[test]
+    fn test_into_expansion() {
+        insta::assert_snapshot!(dbg_re_parse_into!("{a}@{b}", "1@2", &mut target));
+    }
+
+    #[test]
+    fn test_prefix_expansion() {
+        insta::assert_snapshot!(dbg_re_parse_prefix!("{cmd} ", "echo hi"));
+    }
+
+    #[test]
+    fn test_find_expansion() {
+        insta::assert_snapshot!(dbg_re_find!("code {code};", "error code 42;"));
+    }
+
+    #[test]
+    fn test_lines_expansion() {
+        insta::assert_snapshot!(dbg_re_parse_lines!("{a} {b}", reader));
+        insta::assert_snapshot!(dbg_re_parse_lines!("{a}", reader, skip_mismatches = true));
+    }
+
+    #[test]
+    fn test_fallback_cannot_panic() {
+        fn assert_cannot_panic(tokens: proc_macro2::TokenStream) {
+            let mut previous = None;
+            let mut after_lifetime = false;
+            for tree in tokens {
+                if let TokenTree::Ident(ident) = &tree {
+                    assert!(
+                        ![
+                            "panic",
+                            "unwrap",
+                            "expect",
+                            "assert",
+                            "assert_eq",
+                            "unreachable",
+                            "__parse_capture",
+                        ]
+                        .contains(&ident.to_string().as_str()),
+                        "{ident} can panic"
+                    );
+                }
+                if let TokenTree::Group(group) = &tree {
+                    // Indexing the input or a static table panics if the index is out of bounds.
+                    // A slice type like `&'static [&str]` follows a lifetime instead.
+                    if let Some(TokenTree::Ident(ident)) =
+                        previous.as_ref().filter(|_| !after_lifetime)
+                    {
+                        assert!(
+                            group.delimiter() != Delimiter::Bracket,
+                            "{ident}{group} can panic"
+                        );
+                    }
+                    assert_cannot_panic(group.stream());
+                }
+                after_lifetime =
+                    matches!(&previous, Some(TokenTree::Punct(punct)) if punct.as_char() == '\'');
+                previous = Some(tree);
+            }
+        }
+
+        let patterns = [
+            r"{a}={b}",
+            r#"\[{numbers* sep=","}\];{port?=80};{n?}"#,
+            r"{flag:(on=>true|off=>false)};{small:x};{large|parse_hex};{name | trim}",
+            r"{initial:char};{quoted:quoted};{field:csv}\n{record*:csv}",
+            r"({pair;2},)#{2};({c}={v},)*=pairs;({k}:{w},)*=>map;(!{#count})*",
+            r"(({rows**},)*\n)*;({iter* : iter},?)*;({len* => buf},?)*;{span@range}",
+            r"{word} {word};{=prefix}",
+        ];
+        for (pattern, compact) in patterns
+            .into_iter()
+            .flat_map(|pattern| [(pattern, false), (pattern, true)])
+        {
+            let fallback = Some(Fallback {
+                error: None,
+                body: syn::parse_quote!({ return None }),
+            });
+            let options = Options {
+                compact,
+                ..Options::default()
+            };
+            let tokens = re_parse_impl(
+                syn::LitStr::new(pattern, proc_macro2::Span::call_site()),
+                syn::parse_quote!(input),
+                Mode::Parse { fallback },
+                options,
+            )
+            .unwrap();
+            assert_cannot_panic(tokens);
+        }
+    }
+
+    #[test]
+    fn test_debug_expansion() {
+        let options = Options {
+            debug: true,
+            ..Options::default()
+        };
+        insta::assert_snapshot!(test_expansion_with(
+            syn::parse_quote!("{a}={b}"),
+            syn::parse_quote!("x=1"),
+            Mode::Parse { fallback: None },
+            options
+        ));
+    }
+
+    #[test]
+    fn test_compact_expansion() {
+        let options = Options {
+            compact: true,
+            ..Options::default()
+        };
+        // The transitions are looked up in a table instead of matching on the characters of every state
+        let expansion = test_expansion_with(
+            syn::parse_quote!("{a}={b}"),
+            syn::parse_quote!("x=1"),
+            Mode::Parse { fallback: None },
+            options,
+        );
+        assert!(
+            expansion.contains("static __TRANSITIONS: [[(__State, u8); 3usize]; 4usize]")
+                && !expansion.contains("match __next_byte"),
+            "{expansion}"
+        );
+        let expansion = test_expansion_with(
+            syn::parse_quote!("{a}→{b}"),
+            syn::parse_quote!("1→2"),
+            Mode::Parse { fallback: None },
+            options,
+        );
+        assert!(
+            expansion
+                .contains("static __CHAR_CLASSES: [(char, char, u8); 1usize] = [('→', '→', 2)]"),
+            "{expansion}"
+        );
     }
 
     #[test]
     fn test_macro_errors() {
         insta::assert_snapshot!(dbg_re_parse!("A-", "A"));
+        insta::assert_snapshot!(dbg_re_parse!("A-{}", "A"));
     }
 
     proptest! {
@@ -0,0 +1,98 @@
+//! Maps ranges of the value of a string literal back to its source code, so that errors in a pattern can point at
+//! the part of the literal that caused them.
+
+use proc_macro2::Span;
+use std::ops::Range;
+use syn::LitStr;
+
+/// The span of the bytes `range` of the value of `literal`. Not every compiler supports spans inside of literals,
+/// so this falls back to the span of the whole literal.
+pub fn subspan(literal: &LitStr, range: Range<usize>) -> Span {
+    let token = literal.token();
+    source_range(&token.to_string(), &literal.value(), range)
+        .and_then(|range| token.subspan(range))
+        .unwrap_or_else(|| literal.span())
+}
+
+/// Converts a byte range of the `value` of a string literal to a byte range of its `source`, like `"a\tb"` or `r#"a"#`.
+/// An empty range points at the next character, which is the closing quote at the end of the value.
+fn source_range(source: &str, value: &str, range: Range<usize>) -> Option<Range<usize>> {
+    let offsets = value_offsets(source)?;
+    if offsets.len() != value.len() + 1 {
+        return None;
+    }
+    let start = *offsets.get(range.start)?;
+    let end = if range.is_empty() {
+        start + source[start..].chars().next()?.len_utf8()
+    } else {
+        *offsets.get(range.end)?
+    };
+    Some(start..end)
+}
+
+/// The offset in `source` of every byte of the value of the string literal, followed by the offset of the closing quote.
+/// Returns `None` if `source` is not a string literal.
+fn value_offsets(source: &str) -> Option<Vec<usize>> {
+    if let Some(raw) = source.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let start = 1 + hashes + 1;
+        let end = source.rfind('"')?;
+        return Some((start..=end).collect());
+    }
+
+    let mut offsets = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    chars.next_if(|(_, char)| *char == '"')?;
+    while let Some((offset, char)) = chars.next() {
+        let len = match char {
+            '"' => {
+                offsets.push(offset);
+                return Some(offsets);
+            }
+            '\\' => match chars.next()?.1 {
+                // A `\x` escape is always ascii
+                'x' => {
+                    chars.nth(1)?;
+                    1
+                }
+                'u' => {
+                    let mut digits = String::new();
+                    for (_, char) in chars.by_ref().skip(1) {
+                        match char {
+                            '}' => break,
+                            '_' => {}
+                            _ => digits.push(char),
+                        }
+                    }
+                    char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?.len_utf8()
+                }
+                // A backslash at the end of a line skips the line break and the indentation of the next line
+                '\n' => {
+                    while chars.next_if(|(_, char)| char.is_whitespace()).is_some() {}
+                    0
+                }
+                _ => 1,
+            },
+            _ => char.len_utf8(),
+        };
+        offsets.extend(std::iter::repeat_n(offset, len));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::literal::source_range;
+
+    #[test]
+    fn test_source_range() {
+        assert_eq!(source_range(r#""A-""#, "A-", 1..2), Some(2..3));
+        assert_eq!(source_range(r#""A-""#, "A-", 2..2), Some(3..4));
+        assert_eq!(source_range(r#""\t\\-""#, "\t\\-", 2..3), Some(5..6));
+        assert_eq!(source_range(r#""\x41\u{e9}-""#, "Aé-", 1..3), Some(5..11));
+        assert_eq!(source_range(r#""\u{e9}-""#, "é-", 2..3), Some(7..8));
+        assert_eq!(source_range("\"a\\\n    -\"", "a-", 1..2), Some(8..9));
+        assert_eq!(source_range(r##"r#"a"-"#"##, r#"a"-"#, 2..3), Some(5..6));
+        assert_eq!(source_range(r#"r"a-""#, "a-", 1..2), Some(3..4));
+    }
+}
@@ -1,57 +1,368 @@
 use crate::arena::{Arena, ArenaIndex};
-use crate::regex::{Regex, RegexArena, RegexNode, RegexNodeIndex, RegexPattern, RegexVariable};
+use crate::regex::{
+    Assertion, PatternError, PatternPart, Position, Regex, RegexArena, RegexNode, RegexNodeIndex,
+    RegexPattern, RegexVariable, VariableKind,
+};
 use crate::util::FloodFill;
-use crate::Set;
+use crate::Map;
+use std::ops::Range;
 use thiserror::Error;
 
 pub type NfaArena = Arena<NfaNode>;
 pub type NfaIndex = ArenaIndex<NfaNode>;
 
+pub type NfaError = PatternError<NfaErrorKind>;
+
+/// An error together with the node of the pattern that caused it
+type NodeError = (NfaErrorKind, RegexNodeIndex);
+
 #[derive(Error, Debug)]
-pub enum NfaError {
-    #[error("The variable {} is already declared. Capturing a variable twice is not supported right now.", name)]
+pub enum NfaErrorKind {
+    #[error("The variable {} is already declared. Only single variables outside of repetitions can be captured again, and the later captures must equal the first one.", name)]
     DuplicateVariable { name: String },
+    #[error("Lookaheads may only contain patterns of a bounded length. Variables, `*`, `+`, assertions and nested lookaheads are not supported inside of `(?=...)` and `(?!...)`.")]
+    UnsupportedLookahead,
+    #[error(
+        "The capture group {} contains another variable. Nested captures are not supported.",
+        name
+    )]
+    NestedCapture { name: String },
+    #[error("The char variable {} must match exactly one character", name)]
+    InvalidCharCapture { name: String },
+    #[error(
+        "The array variable {} must match exactly {} times in every match of the pattern",
+        name,
+        len
+    )]
+    ArrayLength { name: String, len: u32 },
 }
 
 #[derive(Debug)]
 pub struct Nfa {
     pub root: NfaIndex,
     pub nodes: NfaArena,
+    /// The position of the first occurrence of every variable in the pattern, for the errors of the dfa
+    pub positions: Map<String, Position>,
+    /// The position of the whole pattern
+    pub source: Position,
+    /// The values of the pattern together with the indices of the nodes that they were converted to,
+    /// for the errors about a part of the pattern
+    pub parts: Vec<(PatternPart, Range<usize>)>,
 }
 
 impl TryFrom<Regex> for Nfa {
     type Error = NfaError;
 
     fn try_from(value: Regex) -> Result<Self, NfaError> {
-        let Regex { arena, root } = value;
+        check_variables(&value.arena, value.root, &mut Map::default())
+            .and_then(|()| check_arrays(&value.arena, value.root))
+            .map_err(|(kind, node_idx)| PatternError {
+                kind,
+                position: value.position(node_idx),
+            })?;
+        let positions = value
+            .arena
+            .iter()
+            .filter_map(|node_idx| match &value.arena[node_idx] {
+                RegexNode::Variable(var, _)
+                | RegexNode::Capture(var, _)
+                | RegexNode::Counter(var) => {
+                    Some((var.name.clone(), value.variable_position(&var.name)))
+                }
+                _ => None,
+            })
+            .collect();
+        let Regex {
+            arena,
+            root,
+            source,
+            parts,
+            ..
+        } = value;
+
         let mut nodes = NfaArena::default();
+        let mut converted = Map::default();
         let root_node = nodes.add(NfaNode::EPSILON);
-        let target_node = convert_regex_node(&mut nodes, &arena, root, root_node);
+        let target_node = convert_regex_node(&mut nodes, &arena, root, root_node, &mut converted);
         nodes[target_node].is_accepting = true;
-
-        check_variables(&nodes)?;
+        let parts = parts
+            .into_iter()
+            .filter_map(|part| {
+                let range = converted.get(&part.node)?.clone();
+                Some((part, range))
+            })
+            .collect();
 
         Ok(Nfa {
             nodes,
+            positions,
+            source,
+            parts,
             root: root_node,
         })
     }
 }
 
-fn check_variables(nodes: &NfaArena) -> Result<(), NfaError> {
-    let mut visited_variables = Set::default();
-    for node in nodes.iter() {
-        if let NfaNodeKind::Variable(RegexVariable { name, .. }) = &nodes[node].kind {
-            if visited_variables.contains(name) {
-                return Err(NfaError::DuplicateVariable { name: name.clone() });
+impl Nfa {
+    pub fn has_assertions(&self) -> bool {
+        self.nodes
+            .iter()
+            .any(|node| self.nodes[node].edge_kind.is_assertion())
+    }
+
+    /// Returns the variables in the order in which they appear in the pattern
+    pub fn variables(&self) -> Vec<RegexVariable> {
+        let mut variables: Vec<RegexVariable> = Vec::new();
+        for node in self.nodes.iter() {
+            if let NfaNodeKind::Variable(var) | NfaNodeKind::Counter(var) = &self.nodes[node].kind {
+                if !variables.contains(var) {
+                    variables.push(var.clone());
+                }
+            }
+        }
+        variables
+    }
+}
+
+/// Makes sure that every variable is only declared once.
+/// Nodes can be referenced multiple times (e.g. by a repetition like `{a*},#{3}`), which is fine for
+/// multiple variables, but not for singular variables.
+fn check_variables(
+    arena: &RegexArena,
+    node_idx: RegexNodeIndex,
+    declared: &mut Map<String, RegexNodeIndex>,
+) -> Result<(), NodeError> {
+    match &arena[node_idx] {
+        RegexNode::And(nodes) => {
+            for node in nodes {
+                check_variables(arena, *node, declared)?;
+            }
+        }
+        // Only one branch can match, so every branch may declare the same variables
+        RegexNode::Or(nodes) => {
+            let before = declared.clone();
+            for node in nodes {
+                let mut branch = before.clone();
+                check_variables(arena, *node, &mut branch)?;
+                for (key, branch_idx) in branch {
+                    match declared.get(&key) {
+                        Some(other)
+                            if variable_at(arena, *other).kind
+                                != variable_at(arena, branch_idx).kind =>
+                        {
+                            return Err((
+                                NfaErrorKind::DuplicateVariable {
+                                    name: variable_at(arena, branch_idx).name.clone(),
+                                },
+                                branch_idx,
+                            ))
+                        }
+                        Some(_) => {}
+                        None => {
+                            declared.insert(key, branch_idx);
+                        }
+                    }
+                }
+            }
+        }
+        RegexNode::Literal(_) | RegexNode::Assertion(_) => {}
+        RegexNode::Variable(var, _) | RegexNode::Counter(var) => {
+            declare_variable(var, node_idx, declared)?
+        }
+        RegexNode::Capture(var, child) => {
+            declare_variable(var, node_idx, declared)?;
+            if contains_variable(arena, *child) {
+                return Err((
+                    NfaErrorKind::NestedCapture {
+                        name: var.name.clone(),
+                    },
+                    node_idx,
+                ));
+            }
+            if var.kind == VariableKind::Char && !is_single_char(arena, *child) {
+                return Err((
+                    NfaErrorKind::InvalidCharCapture {
+                        name: var.name.clone(),
+                    },
+                    node_idx,
+                ));
             }
-            visited_variables.insert(name.clone());
+        }
+        RegexNode::Lookahead(child) | RegexNode::NegativeLookahead(child) => {
+            check_lookahead(arena, *child).map_err(|kind| (kind, node_idx))?
+        }
+        RegexNode::ZeroOrOne(child) | RegexNode::Many(child) | RegexNode::OneOrMore(child) => {
+            check_variables(arena, *child, declared)?;
         }
     }
 
     Ok(())
 }
 
+fn variable_at(arena: &RegexArena, node_idx: RegexNodeIndex) -> &RegexVariable {
+    match &arena[node_idx] {
+        RegexNode::Variable(var, _) | RegexNode::Capture(var, _) | RegexNode::Counter(var) => var,
+        _ => unreachable!("Only variables are declared"),
+    }
+}
+
+fn declare_variable(
+    var: &RegexVariable,
+    node_idx: RegexNodeIndex,
+    declared: &mut Map<String, RegexNodeIndex>,
+) -> Result<(), NodeError> {
+    let key = var.key();
+    match declared.get(&key) {
+        Some(other)
+            if *other == node_idx
+                && matches!(
+                    var.kind,
+                    VariableKind::Multiple { .. }
+                        | VariableKind::Iter { .. }
+                        | VariableKind::Buffer { .. }
+                ) => {}
+        // The occurrences of an array variable are counted by `check_arrays`
+        Some(_) if matches!(var.kind, VariableKind::Array { .. }) => {}
+        // The parser inserts a counter with the name of a nested variable, which starts its lists
+        Some(_) if matches!(var.kind, VariableKind::Nested { .. }) => {}
+        Some(_) => {
+            return Err((
+                NfaErrorKind::DuplicateVariable {
+                    name: var.name.clone(),
+                },
+                node_idx,
+            ))
+        }
+        None => {
+            declared.insert(key, node_idx);
+        }
+    }
+    Ok(())
+}
+
+/// Makes sure that every array variable matches exactly as often as the length of its array
+fn check_arrays(arena: &RegexArena, root: RegexNodeIndex) -> Result<(), NodeError> {
+    for node_idx in arena.iter() {
+        let (RegexNode::Variable(var, _) | RegexNode::Capture(var, _)) = &arena[node_idx] else {
+            continue;
+        };
+        let VariableKind::Array { len } = var.kind else {
+            continue;
+        };
+        if count_occurrences(arena, root, &var.name) != (len, Some(len)) {
+            return Err((
+                NfaErrorKind::ArrayLength {
+                    name: var.name.clone(),
+                    len,
+                },
+                node_idx,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the minimum and maximum number of times that a variable can match, `None` if it is unbounded
+fn count_occurrences(
+    arena: &RegexArena,
+    node_idx: RegexNodeIndex,
+    name: &str,
+) -> (u32, Option<u32>) {
+    match &arena[node_idx] {
+        RegexNode::Variable(var, _) | RegexNode::Capture(var, _) if var.name == name => {
+            (1, Some(1))
+        }
+        RegexNode::Variable(..)
+        | RegexNode::Capture(..)
+        | RegexNode::Counter(_)
+        | RegexNode::Literal(_)
+        | RegexNode::Assertion(_)
+        | RegexNode::Lookahead(_)
+        | RegexNode::NegativeLookahead(_) => (0, Some(0)),
+        RegexNode::And(nodes) => nodes
+            .iter()
+            .map(|node| count_occurrences(arena, *node, name))
+            .fold((0, Some(0)), |(min, max), (node_min, node_max)| {
+                (
+                    min.saturating_add(node_min),
+                    max.zip(node_max)
+                        .map(|(max, node_max)| max.saturating_add(node_max)),
+                )
+            }),
+        RegexNode::Or(nodes) => nodes
+            .iter()
+            .map(|node| count_occurrences(arena, *node, name))
+            .reduce(|(min, max), (node_min, node_max)| {
+                (
+                    min.min(node_min),
+                    max.zip(node_max).map(|(max, node_max)| max.max(node_max)),
+                )
+            })
+            .unwrap_or((0, Some(0))),
+        RegexNode::ZeroOrOne(child) => (0, count_occurrences(arena, *child, name).1),
+        // A repetition is unbounded, unless its child never matches the variable
+        RegexNode::Many(child) => {
+            let (_, max) = count_occurrences(arena, *child, name);
+            (0, max.filter(|max| *max == 0))
+        }
+        RegexNode::OneOrMore(child) => {
+            let (min, max) = count_occurrences(arena, *child, name);
+            (min, max.filter(|max| *max == 0))
+        }
+    }
+}
+
+/// Returns whether the node always matches exactly one character
+fn is_single_char(arena: &RegexArena, node_idx: RegexNodeIndex) -> bool {
+    match &arena[node_idx] {
+        RegexNode::Literal(_) => true,
+        RegexNode::Or(nodes) => nodes.iter().all(|node| is_single_char(arena, *node)),
+        RegexNode::And(nodes) => match nodes.as_slice() {
+            [node] => is_single_char(arena, *node),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn contains_variable(arena: &RegexArena, node_idx: RegexNodeIndex) -> bool {
+    match &arena[node_idx] {
+        RegexNode::And(nodes) | RegexNode::Or(nodes) => {
+            nodes.iter().any(|node| contains_variable(arena, *node))
+        }
+        RegexNode::Literal(_) | RegexNode::Assertion(_) => false,
+        RegexNode::Variable(..) | RegexNode::Capture(..) | RegexNode::Counter(_) => true,
+        RegexNode::Lookahead(child)
+        | RegexNode::NegativeLookahead(child)
+        | RegexNode::ZeroOrOne(child)
+        | RegexNode::Many(child)
+        | RegexNode::OneOrMore(child) => contains_variable(arena, *child),
+    }
+}
+
+/// Makes sure that the content of a lookahead only matches a finite set of words,
+/// so that it does not have to be tracked for an unbounded amount of characters.
+fn check_lookahead(arena: &RegexArena, node_idx: RegexNodeIndex) -> Result<(), NfaErrorKind> {
+    match &arena[node_idx] {
+        RegexNode::And(nodes) | RegexNode::Or(nodes) => {
+            for node in nodes {
+                check_lookahead(arena, *node)?;
+            }
+            Ok(())
+        }
+        RegexNode::Literal(_) => Ok(()),
+        RegexNode::ZeroOrOne(child) => check_lookahead(arena, *child),
+        RegexNode::Variable(..)
+        | RegexNode::Capture(..)
+        | RegexNode::Counter(_)
+        | RegexNode::Assertion(_)
+        | RegexNode::Lookahead(_)
+        | RegexNode::NegativeLookahead(_)
+        | RegexNode::Many(_)
+        | RegexNode::OneOrMore(_) => Err(NfaErrorKind::UnsupportedLookahead),
+    }
+}
+
 #[derive(Debug)]
 pub struct NfaNode {
     pub edges: Vec<NfaIndex>,
@@ -73,32 +384,62 @@ impl NfaNode {
 pub enum NfaNodeKind {
     Simple,
     Variable(RegexVariable),
+    /// An epsilon node that counts how often it was passed, for the repetition count `{#n}`
+    Counter(RegexVariable),
+    /// The end of the nfa of a lookahead. It is reached once the lookahead matched.
+    LookaheadEnd,
 }
 
 #[derive(Debug)]
 pub enum NfaEdge {
     Epsilon,
     Pattern(RegexPattern),
+    /// Like [`NfaEdge::Epsilon`], but can only be taken if the assertion holds
+    Assertion(Assertion),
+    /// Like [`NfaEdge::Epsilon`], but the following input must also match the nfa that starts at the given node
+    Lookahead(NfaIndex),
+    /// Like [`NfaEdge::Epsilon`], but the following input must not match the nfa that starts at the given node
+    NegativeLookahead(NfaIndex),
 }
 
 impl NfaEdge {
     pub fn is_epsilon(&self) -> bool {
         matches!(self, NfaEdge::Epsilon)
     }
+
+    pub fn is_assertion(&self) -> bool {
+        matches!(self, NfaEdge::Assertion(_))
+    }
 }
 
+/// Converts a node of the pattern and its children. `converted` receives the indices of the nfa nodes
+/// that were added for every node of the pattern.
 fn convert_regex_node(
+    arena: &mut NfaArena,
+    regex_arena: &RegexArena,
+    node_idx: RegexNodeIndex,
+    predecessor: NfaIndex,
+    converted: &mut Map<RegexNodeIndex, Range<usize>>,
+) -> NfaIndex {
+    let first_node = arena.len();
+    let target_node = convert_regex_node_kind(arena, regex_arena, node_idx, predecessor, converted);
+    converted.insert(node_idx, first_node..arena.len());
+    target_node
+}
+
+fn convert_regex_node_kind(
     arena: &mut NfaArena,
     regex_arena: &RegexArena,
     node: RegexNodeIndex,
     predecessor: NfaIndex,
+    converted: &mut Map<RegexNodeIndex, Range<usize>>,
 ) -> NfaIndex {
     let node = &regex_arena[node];
     match node {
         RegexNode::And(nodes) => {
             let mut last_node = predecessor;
             for node in nodes {
-                let new_node = convert_regex_node(arena, regex_arena, *node, last_node);
+                let new_node = convert_regex_node(arena, regex_arena, *node, last_node, converted);
                 last_node = new_node;
             }
             last_node
@@ -106,7 +447,8 @@ fn convert_regex_node(
         RegexNode::Or(nodes) => {
             let target_node = arena.add(NfaNode::EPSILON);
             for node in nodes {
-                let new_node = convert_regex_node(arena, regex_arena, *node, predecessor);
+                let new_node =
+                    convert_regex_node(arena, regex_arena, *node, predecessor, converted);
                 arena.connect(new_node, target_node);
             }
             target_node
@@ -120,12 +462,28 @@ fn convert_regex_node(
                 is_accepting: false,
             },
         ),
-        RegexNode::Variable(var) => {
+        RegexNode::Assertion(assertion) => arena.add_after(
+            predecessor,
+            NfaNode {
+                edges: Vec::new(),
+                edge_kind: NfaEdge::Assertion(*assertion),
+                kind: NfaNodeKind::Simple,
+                is_accepting: false,
+            },
+        ),
+        RegexNode::Counter(var) => arena.add_after(
+            predecessor,
+            NfaNode {
+                kind: NfaNodeKind::Counter(var.clone()),
+                ..NfaNode::EPSILON
+            },
+        ),
+        RegexNode::Variable(var, pattern) => {
             let node = arena.add_after(
                 predecessor,
                 NfaNode {
                     edges: Vec::new(),
-                    edge_kind: NfaEdge::Pattern(RegexPattern::AnyCharLazy),
+                    edge_kind: NfaEdge::Pattern(*pattern),
                     kind: NfaNodeKind::Variable(var.clone()),
                     is_accepting: false,
                 },
@@ -133,10 +491,44 @@ fn convert_regex_node(
             arena.connect(node, node);
             node
         }
+        RegexNode::Capture(var, child) => {
+            let first_node = arena.iter().count();
+            let end_node = convert_regex_node(arena, regex_arena, *child, predecessor, converted);
+            // Only the nodes that consume a character belong to the variable,
+            // so that the capture does not start or end before its first or after its last character
+            for (_, node) in arena.iter_mut().skip(first_node) {
+                if let NfaEdge::Pattern(_) = node.edge_kind {
+                    node.kind = NfaNodeKind::Variable(var.clone());
+                }
+            }
+            end_node
+        }
+        RegexNode::Lookahead(child) | RegexNode::NegativeLookahead(child) => {
+            let start_node = arena.add(NfaNode::EPSILON);
+            let end_node = convert_regex_node(arena, regex_arena, *child, start_node, converted);
+            arena.add_after(
+                end_node,
+                NfaNode {
+                    kind: NfaNodeKind::LookaheadEnd,
+                    ..NfaNode::EPSILON
+                },
+            );
+            let edge_kind = match node {
+                RegexNode::NegativeLookahead(_) => NfaEdge::NegativeLookahead(start_node),
+                _ => NfaEdge::Lookahead(start_node),
+            };
+            arena.add_after(
+                predecessor,
+                NfaNode {
+                    edge_kind,
+                    ..NfaNode::EPSILON
+                },
+            )
+        }
         RegexNode::ZeroOrOne(child) => {
             let target_node = arena.add(NfaNode::EPSILON);
             arena.connect(predecessor, target_node);
-            let new_node = convert_regex_node(arena, regex_arena, *child, predecessor);
+            let new_node = convert_regex_node(arena, regex_arena, *child, predecessor, converted);
             arena.connect(new_node, target_node);
             target_node
         }
@@ -145,7 +537,8 @@ fn convert_regex_node(
             arena.connect(predecessor, iteration_node);
             let target_node = arena.add(NfaNode::EPSILON);
             arena.connect(predecessor, target_node);
-            let new_node = convert_regex_node(arena, regex_arena, *child, iteration_node);
+            let new_node =
+                convert_regex_node(arena, regex_arena, *child, iteration_node, converted);
             arena.connect(new_node, iteration_node);
             arena.connect(new_node, target_node);
             target_node
@@ -154,7 +547,8 @@ fn convert_regex_node(
             let iteration_node = arena.add(NfaNode::EPSILON);
             arena.connect(predecessor, iteration_node);
             let target_node = arena.add(NfaNode::EPSILON);
-            let new_node = convert_regex_node(arena, regex_arena, *child, iteration_node);
+            let new_node =
+                convert_regex_node(arena, regex_arena, *child, iteration_node, converted);
             arena.connect(new_node, iteration_node);
             arena.connect(new_node, target_node);
             target_node
@@ -184,7 +578,7 @@ impl FloodFill for Nfa {
 
 #[cfg(test)]
 mod tests {
-    use crate::nfa::Nfa;
+    use crate::nfa::{is_single_char, Nfa};
     use crate::regex::Regex;
     use crate::ProcMacroErrorKind;
 
@@ -203,8 +597,31 @@ mod tests {
         insta::assert_debug_snapshot!(parse(".+;"));
     }
 
+    #[test]
+    fn test_single_char() {
+        for (source, expected) in [
+            ("a", true),
+            ("[a-z]", true),
+            ("(?s:.)", true),
+            ("a|b", true),
+            ("ab", false),
+            ("a?", false),
+            ("a|bc", false),
+        ] {
+            let regex = Regex::from_str(source).unwrap();
+            assert_eq!(
+                is_single_char(&regex.arena, regex.root),
+                expected,
+                "{source}"
+            );
+        }
+    }
+
     #[test]
     fn test_duplicate_variable() {
-        insta::assert_debug_snapshot!(parse("{foo}bar{foo}"));
+        insta::assert_debug_snapshot!(parse("{foo}bar{foo*}"));
+        insta::assert_debug_snapshot!(parse("({foo},)#{2}"));
+        insta::assert_debug_snapshot!(parse("({foo} a|{foo*} b)").map(|_| ()));
+        assert!(parse("({foo} a|{foo} b)|{foo}").is_ok());
     }
 }
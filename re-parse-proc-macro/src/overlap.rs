@@ -0,0 +1,185 @@
+//! Finds branches of alternations that match a text that an earlier branch of the same alternation matches as well,
+//! like `a.c` in `(abc|a.c)`. The earlier branch is chosen for such a text, so the later branch never wins for it,
+//! which is usually a mistake in the pattern.
+//!
+//! Every branch is compiled into a [`Dfa`] on its own, and the states of two branches are explored together
+//! until both accept the same text.
+use crate::dfa::{Dfa, DfaIndex};
+use crate::nfa::Nfa;
+use crate::regex::{CharRange, Position, Regex, RegexNode, RegexNodeIndex, RegexPattern};
+use crate::Map;
+use std::collections::VecDeque;
+
+/// A state of both branches at once
+type StatePair = (DfaIndex, DfaIndex);
+
+/// A later branch of an alternation that matches the same text as an earlier branch
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overlap {
+    pub branch: Position,
+    pub earlier: Position,
+    /// The shortest text that both branches match
+    pub text: String,
+}
+
+/// Returns the first branch of an alternation in `regex` that matches the same text as an earlier branch.
+/// Branches that depend on the text around them or need more than `max_states` states are not checked.
+pub fn find_overlap(regex: &Regex, max_states: usize) -> Option<Overlap> {
+    for (node_idx, positions) in &regex.alternations {
+        let RegexNode::Or(branches) = &regex.arena[*node_idx] else {
+            continue;
+        };
+        if branches.len() != positions.len() {
+            continue;
+        }
+        let dfas = branches
+            .iter()
+            .map(|branch| branch_dfa(regex, *branch, max_states))
+            .collect::<Vec<_>>();
+        for (index, dfa) in dfas.iter().enumerate() {
+            let Some(dfa) = dfa else { continue };
+            for (earlier_index, earlier) in dfas[..index].iter().enumerate() {
+                let Some(earlier) = earlier else { continue };
+                if let Some(text) = common_text(earlier, dfa) {
+                    return Some(Overlap {
+                        branch: positions[index].clone(),
+                        earlier: positions[earlier_index].clone(),
+                        text,
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Whether the text that the node matches depends on more than the node itself: Untyped variables match
+/// everything that no other part of the pattern matches, and assertions and lookaheads depend on the text around them
+fn depends_on_context(regex: &Regex, node_idx: RegexNodeIndex) -> bool {
+    match &regex.arena[node_idx] {
+        RegexNode::And(nodes) | RegexNode::Or(nodes) => {
+            nodes.iter().any(|node| depends_on_context(regex, *node))
+        }
+        RegexNode::Literal(pattern) | RegexNode::Variable(_, pattern) => {
+            matches!(pattern, RegexPattern::AnyCharLazy { .. })
+        }
+        RegexNode::Capture(_, child)
+        | RegexNode::ZeroOrOne(child)
+        | RegexNode::Many(child)
+        | RegexNode::OneOrMore(child) => depends_on_context(regex, *child),
+        RegexNode::Assertion(_) | RegexNode::Lookahead(_) | RegexNode::NegativeLookahead(_) => true,
+        RegexNode::Counter(_) => false,
+    }
+}
+
+/// Compiles the branch on its own, or returns `None` if it cannot be compiled without the rest of the pattern
+fn branch_dfa(regex: &Regex, branch: RegexNodeIndex, max_states: usize) -> Option<Dfa> {
+    if depends_on_context(regex, branch) {
+        return None;
+    }
+    let regex = Regex {
+        arena: regex.arena.clone(),
+        root: branch,
+        positions: regex.positions.clone(),
+        source: regex.source.clone(),
+        parts: Vec::new(),
+        alternations: Vec::new(),
+    };
+    let nfa = Nfa::try_from(regex).ok()?;
+    Dfa::new(&nfa, max_states).ok()
+}
+
+/// Searches the pairs of states of both state machines breadth first, so the shortest text that both accept
+/// at the end of the input is found
+fn common_text(first: &Dfa, second: &Dfa) -> Option<String> {
+    let ranges = [first, second]
+        .iter()
+        .flat_map(|dfa| dfa.iter().map(move |idx| &dfa.nodes[idx]))
+        .flat_map(|node| {
+            node.edges
+                .explicit_ranges()
+                .chain(node.edges.rejected.iter().copied())
+                .collect::<Vec<_>>()
+        })
+        .map(|range| (range, ()))
+        .collect::<Vec<_>>();
+    // Every letter is represented by its first character, and the other characters by one that is in no letter
+    let letters = CharRange::partition(&ranges, &[])
+        .into_iter()
+        .map(|(range, _)| Some(range.start))
+        .chain(std::iter::once(None))
+        .collect::<Vec<_>>();
+    let other = ('a'..='z')
+        .chain('0'..='9')
+        .chain(['_', ' ', '-'])
+        .find(|char| ranges.iter().all(|(range, _)| !range.contains(*char)));
+
+    let root = (first.root, second.root);
+    let mut parents: Map<StatePair, Option<(StatePair, Option<char>)>> = Map::default();
+    parents.insert(root, None);
+    let mut queue = VecDeque::from([root]);
+    while let Some(state) = queue.pop_front() {
+        if first.nodes[state.0].is_accepting && second.nodes[state.1].is_accepting {
+            let mut text = Vec::new();
+            let mut current = state;
+            while let Some(Some((parent, char))) = parents.get(&current) {
+                text.push(char.or(other).unwrap_or('?'));
+                current = *parent;
+            }
+            return Some(text.into_iter().rev().collect());
+        }
+        for char in &letters {
+            let target = |dfa: &Dfa, idx: DfaIndex| {
+                let edges = &dfa.nodes[idx].edges;
+                char.map_or(edges.default, |char| edges.target(char))
+            };
+            let (Some(a), Some(b)) = (target(first, state.0), target(second, state.1)) else {
+                continue;
+            };
+            if !parents.contains_key(&(a, b)) {
+                parents.insert((a, b), Some((state, *char)));
+                queue.push_back((a, b));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dfa::DEFAULT_MAX_STATES;
+    use crate::overlap::find_overlap;
+    use crate::regex::Regex;
+
+    fn overlap(pattern: &str) -> Option<(String, String, String)> {
+        let regex = Regex::from_str(pattern).unwrap();
+        find_overlap(&regex, DEFAULT_MAX_STATES).map(|overlap| {
+            (
+                pattern[overlap.earlier.range].to_string(),
+                pattern[overlap.branch.range].to_string(),
+                overlap.text,
+            )
+        })
+    }
+
+    #[test]
+    fn test_overlapping_branches() {
+        let found = |earlier: &str, branch: &str, text: &str| {
+            Some((earlier.to_string(), branch.to_string(), text.to_string()))
+        };
+        assert_eq!(overlap("(abc|a.c)"), found("abc", "a.c", "abc"));
+        assert_eq!(overlap(r"x(\d+|1\d)"), found(r"\d+", r"1\d", "10"));
+        assert_eq!(overlap("(a|b|a)"), found("a", "a", "a"));
+        assert_eq!(overlap("(a|)b|ab"), found("(a|)b", "ab", "ab"));
+    }
+
+    #[test]
+    fn test_exclusive_branches() {
+        assert_eq!(overlap("(abc|abd)"), None);
+        assert_eq!(overlap("(cat {lives}|dog {name})"), None);
+        assert_eq!(overlap("({n} items|{n} item)"), None);
+        assert_eq!(overlap("({n} {unit}|{n})!"), None);
+        assert_eq!(overlap(r"(\bab|ab)"), None);
+        assert_eq!(overlap(r"(\d+|0x[0-9a-f]+)"), None);
+    }
+}
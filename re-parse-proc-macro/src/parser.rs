@@ -1,12 +1,23 @@
+use crate::classes;
 use crate::regex::{
-    Regex, RegexArena, RegexNode, RegexNodeIndex, RegexPattern, RegexVariable, VariableKind,
+    is_place_name, Assertion, PatternError, PatternPart, Position, Regex, RegexArena, RegexNode,
+    RegexNodeIndex, RegexPattern, RegexVariable, Transform, VariableKind,
 };
-use crate::tokenizer::{PostfixToken, Token};
+use crate::time_format;
+use crate::tokenizer::{
+    CaptureType, CharacterClass, Flag, Flags, PostfixToken, Spanned, Token, TokenError,
+};
+use crate::{Map, Set};
 use std::iter::Peekable;
+use std::ops::Range;
 use thiserror::Error;
 
+pub type ParseError = PatternError<ParseErrorKind>;
+
 #[derive(Debug, Error)]
-pub enum ParseError {
+pub enum ParseErrorKind {
+    #[error(transparent)]
+    Token(#[from] TokenError),
     #[error("Unexpected token '}}'. Did you forget a '{{'?")]
     UnexpectedRightBrace,
     #[error("Unexpected token ')'. Did you forget a '('?")]
@@ -29,57 +40,798 @@ pub enum ParseError {
     ExpectedPostfixOperator { got: Token },
     #[error("Expected end of input, got '{}'", got)]
     ExpectedEof { got: Token },
+    #[error(
+        "Invalid repetition '{}': The minimum must not be larger than the maximum",
+        token
+    )]
+    InvalidRepetition { token: Token },
+    #[error(
+        "Repetition count {} is too large. At most {} repetitions are supported",
+        count,
+        MAX_REPETITIONS
+    )]
+    RepetitionTooLarge { count: u32 },
+    #[error(
+        "The repetitions expand the pattern to {} parts, but at most {} are supported. Repeat fewer or smaller parts, or use `*` or `+` instead",
+        size,
+        MAX_EXPANDED_SIZE
+    )]
+    ExpansionTooLarge { size: usize },
+    #[error(
+        "Invalid length '{}': A variable spans at least one character and the minimum must not be larger than the maximum",
+        token
+    )]
+    InvalidLengthBound { token: Token },
+    #[error("Invalid minimum count '{}' for a repeated variable", count)]
+    InvalidMinimumCount { count: String },
+    #[error("Invalid length '{}' for an array variable", len)]
+    InvalidArrayLength { len: String },
+    #[error("The variable '{}' has a radix format like `:x`, which is only supported for single variables", name)]
+    InvalidRadixVariable { name: String },
+    #[error(
+        "The variable '{}' is `:quoted`, which is only supported for single variables",
+        name
+    )]
+    InvalidQuotedVariable { name: String },
+    #[error(
+        "The variable '{}' is `:csv`, which is only supported for single fields and for whole records like `{{{}*:csv}}`",
+        name,
+        name
+    )]
+    InvalidCsvVariable { name: String },
+    #[error(
+        "Unknown time format specifier '%{}'. Supported specifiers are: {}",
+        specifier,
+        time_format::specifiers()
+    )]
+    UnknownTimeFormat { specifier: char },
+    #[error(
+        "The variable '{}' has a time format, which is only supported for single variables",
+        name
+    )]
+    InvalidTimeVariable { name: String },
+    #[error(
+        "The variable '{}' captures a single value, which cannot be a `{}`. Use `{{{}*}}` to collect every match",
+        name,
+        ty,
+        name
+    )]
+    SingularCollection { name: String, ty: String },
+    #[error(
+        "The branch '{}' of the variable '{}' can never be chosen, because the earlier branch '{}' matches the same text",
+        branch,
+        name,
+        shadowing
+    )]
+    UnreachableBranch {
+        name: String,
+        branch: String,
+        shadowing: String,
+    },
+    #[error(
+        "The variable '{}' collects its matches into the type of the binding, so the type `{}` cannot be written in the pattern. Declare it at the binding instead",
+        name,
+        ty
+    )]
+    CollectionInPattern { name: String, ty: String },
+    #[error("Unknown class '\\k{{{}}}'. Classes have to be defined with `re_class!` before they are used", name)]
+    UnknownClass { name: String },
+    #[error("The class '{}' must not contain variables", name)]
+    VariableInClass { name: String },
+    #[error(
+        "Invalid default value '{}'. The default value of a variable must be a rust expression",
+        default
+    )]
+    InvalidDefault { default: String },
+    #[error(
+        "Invalid expected value '{}'. The value in `{{=value}}` must be a rust expression",
+        value
+    )]
+    InvalidExpectedValue { value: String },
+    #[error(
+        "The span of variable '{}' can only be captured at its first occurrence",
+        name
+    )]
+    SpannedBackreference { name: String },
+    #[error(
+        "Invalid function '{}'. The function of a variable like `{{name|function}}` must be a path",
+        function
+    )]
+    InvalidFunction { function: String },
+    #[error(
+        "The function of variable '{}' can only be given at its first occurrence",
+        name
+    )]
+    ConvertedBackreference { name: String },
+    #[error(
+        "The variable '{}' can only be marked as possibly empty like `{{{}??}}` at its first occurrence",
+        name,
+        name
+    )]
+    EmptyBackreference { name: String },
+    #[error("The function '{}' must be the last step of the pipeline. Only the transformations {} can come before it", function, Transform::ALL.map(Transform::name).join(", "))]
+    FunctionNotLast { function: String },
+    #[error(
+        "Invalid variable name '{}'. Names must be identifiers, and keywords have to be written as raw identifiers like `r#type`",
+        name
+    )]
+    InvalidIdent { name: String },
+    #[error(
+        "Invalid variable '{}'. A variable must be an identifier or a place like `self.x` or `points[0].y`",
+        place
+    )]
+    InvalidPlace { place: String },
+    #[error(
+        "The brackets or parentheses of the variable '{}' are not closed",
+        place
+    )]
+    UnbalancedPlace { place: String },
+    #[error(
+        "The variable '{}' only appears in some branches of an alternation, so it is optional and cannot be repeated elsewhere in the pattern",
+        name
+    )]
+    OptionalBackreference { name: String },
+    #[error(
+        "The variable '{}' has a separator, which is only supported for repeated variables like `{{{}* sep=\",\"}}`",
+        name,
+        name
+    )]
+    InvalidSeparator { name: String },
+    #[error(
+        "Only repeated variables like `{{{}* : iter}}` can be bound as an iterator",
+        name
+    )]
+    InvalidIter { name: String },
+    #[error(
+        "Only repeated variables like `{{{}* => buf}}` can be written into a buffer, and the buffer '{}' must be a rust expression",
+        name,
+        buffer
+    )]
+    InvalidBuffer { name: String, buffer: String },
+    #[error(
+        "The variable '{}' cannot be zipped into the list '{}'. Only single variables like `{{x}}` or `{{x:u32}}` can be zipped",
+        name,
+        list
+    )]
+    InvalidZippedVariable { name: String, list: String },
+    #[error(
+        "The variable '{}' is zipped into a list, so it cannot be repeated elsewhere in the pattern",
+        name
+    )]
+    ZippedBackreference { name: String },
+    #[error(
+        "The variable '{}' must match exactly once in every repetition of the group that is zipped into '{}'",
+        name,
+        list
+    )]
+    ZippedOccurrence { name: String, list: String },
+    #[error(
+        "The group that is collected into the map '{}' must contain exactly two variables, the key and the value, but it contains {}",
+        map,
+        count
+    )]
+    InvalidMapEntry { map: String, count: usize },
+    #[error(
+        "The nested variable '{}' must be inside of two repetitions, like `(({{{}**}},)*\\n)*`: One for the lists and one for the matches in every list",
+        name,
+        name
+    )]
+    NestedOutsideRepetition { name: String },
+    #[error(
+        "The repetition count '{}' must be inside of a repeated group like `(ab{{#{}}})*`, but not inside of an alternation, an optional part or a lookahead",
+        name,
+        name
+    )]
+    InvalidCounter { name: String },
+    #[error(
+        "The variable '{}' is greedy like `{{{}!}}`, which is only supported for single variables without a pattern that are matched exactly once",
+        name,
+        name
+    )]
+    InvalidGreedyVariable { name: String },
+    #[error(
+        "The variables '{}' and '{}' are both greedy, but only one variable of a pattern can be greedy",
+        first,
+        second
+    )]
+    MultipleGreedyVariables { first: String, second: String },
+    #[error("Every branch of the mapped variable '{}' has to be a literal text followed by `=>` and a rust expression, like `{{{}:(on=>true|off=>false)}}`", name, name)]
+    InvalidMapping { name: String },
+}
+
+impl ParseErrorKind {
+    /// The variable of an error that is found by checking the whole pattern, see [`RegexParser::check_pattern`]
+    fn variable(&self) -> Option<&str> {
+        match self {
+            Self::ZippedBackreference { name }
+            | Self::InvalidCounter { name }
+            | Self::NestedOutsideRepetition { name }
+            | Self::InvalidGreedyVariable { name }
+            | Self::OptionalBackreference { name } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that a variable name is `_` or a rust identifier. Keywords can be used as raw identifiers like `r#type`.
+fn validate_ident(name: String) -> Result<String> {
+    if name == "_" || syn::parse_str::<syn::Ident>(&name).is_ok() {
+        Ok(name)
+    } else {
+        Err(ParseErrorKind::InvalidIdent { name })
+    }
+}
+
+/// Whether an expression can be assigned to: an identifier, a dereference, or a field of or an index into a place.
+/// The place that is indexed may also be the result of a method call, like `grid.row_mut(y)[x]`.
+fn is_place_expression(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Path(path) => path.qself.is_none() && path.path.get_ident().is_some(),
+        syn::Expr::Unary(unary) => matches!(unary.op, syn::UnOp::Deref(_)),
+        syn::Expr::Field(syn::ExprField { base, .. })
+        | syn::Expr::Index(syn::ExprIndex { expr: base, .. }) => match &**base {
+            syn::Expr::MethodCall(call) => is_place_expression(&call.receiver),
+            base => is_place_expression(base),
+        },
+        _ => false,
+    }
 }
 
-type Result<T> = std::result::Result<T, ParseError>;
+/// The maximum count that is allowed in a bounded repetition like `a#{2,5}`.
+/// Every repetition is expanded into copies of its operand, so large counts quickly blow up the state machine.
+pub const MAX_REPETITIONS: u32 = 1000;
+
+/// The maximum number of parts that a pattern may expand to, where a part in a bounded repetition counts once for
+/// every repetition. The nfa creates new states whenever it visits a part, so nested repetitions like
+/// `((a#{1000})#{1000})#{100}` would otherwise run out of memory before the number of states is checked.
+pub const MAX_EXPANDED_SIZE: usize = 100_000;
+
+type Result<T> = std::result::Result<T, ParseErrorKind>;
+
+/// Converts a text like `north-east` to `NorthEast`
+fn upper_camel_case(text: &str) -> String {
+    text.split(|char: char| !char.is_alphanumeric())
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars))
+        })
+        .flatten()
+        .collect()
+}
 
-pub struct RegexParser<I: Iterator> {
+pub struct RegexParser<'a, I: Iterator> {
     source: Peekable<I>,
+    /// The text of the pattern
+    pattern: &'a str,
+    /// The range of the token that was peeked or consumed last. Errors are reported at this range.
+    range: Range<usize>,
+    /// The end of the token that was consumed last
+    consumed_end: usize,
+    /// The positions of the variables and lookaheads, see [`Regex::positions`]
+    positions: Map<RegexNodeIndex, Position>,
+    /// The values that were parsed so far, see [`Regex::parts`]
+    parts: Vec<PatternPart>,
     nodes: RegexArena,
     stack: Vec<Vec<RegexNodeIndex>>,
+    /// The flags that are active at the current position. Parentheses restore the flags when they are closed.
+    flags: Flags,
+    /// How often each singular variable occurred so far. Later occurrences are backreferences to the first one.
+    occurrences: Map<String, u32>,
+    /// The branches of alternations that were followed by a `=>value`, together with the value
+    mapped_branches: Vec<(RegexNodeIndex, String)>,
+    /// The branches of the alternations, see [`Regex::alternations`]
+    alternations: Vec<(RegexNodeIndex, Vec<Position>)>,
+    /// The variables that were declared as mutable like `{mut count}`. Their later occurrences are mutable as well.
+    mutable: Set<String>,
+    /// The greedy variable like `{path!}`, because a pattern can only contain one
+    greedy: Option<String>,
+    /// How many braces, parentheses and brackets are open after the consumed tokens.
+    /// After an error, the parser skips to the end of the variable or class where it was found.
+    open_braces: usize,
+    open_parentheses: usize,
+    open_brackets: usize,
 }
 
-impl<I> RegexParser<I>
+impl<'a, I> RegexParser<'a, I>
 where
-    I: Iterator<Item = Token>,
+    I: Iterator<Item = Spanned<Token>>,
 {
-    pub fn parse(source: I) -> Result<Regex> {
+    /// Parses the tokens of the pattern `source`. If the pattern is invalid, the parser continues after the errors,
+    /// so that all independent errors of the pattern are returned together.
+    pub fn parse(tokens: I, source: &'a str) -> std::result::Result<Regex, Vec<ParseError>> {
         let mut parser = RegexParser {
-            source: source.peekable(),
+            source: tokens.peekable(),
+            pattern: source,
+            range: 0..0,
+            consumed_end: 0,
+            positions: Map::default(),
+            parts: Vec::new(),
             nodes: RegexArena::default(),
             stack: vec![Vec::new()],
+            flags: Flags::default(),
+            occurrences: Map::default(),
+            mapped_branches: Vec::new(),
+            alternations: Vec::new(),
+            mutable: Set::default(),
+            greedy: None,
+            open_braces: 0,
+            open_parentheses: 0,
+            open_brackets: 0,
+        };
+
+        let errors = parser.parse_tokens();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let root = parser.root();
+        let checked = parser.check_pattern(root);
+        let regex = Regex {
+            arena: parser.nodes,
+            root,
+            positions: parser.positions,
+            source: Position::new(source, 0..source.len()),
+            parts: parser.parts,
+            alternations: parser.alternations,
         };
+        match checked {
+            Ok(()) => Ok(regex),
+            // These errors are about the whole pattern, so they are reported at the variable they are about
+            Err(kind) => Err(vec![PatternError {
+                position: kind
+                    .variable()
+                    .map_or(regex.source.clone(), |name| regex.variable_position(name)),
+                kind,
+            }]),
+        }
+    }
+
+    /// Parses the tokens into the nodes of the pattern and returns the errors that were found
+    fn parse_tokens(&mut self) -> Vec<ParseError> {
+        let mut errors = Vec::new();
+        loop {
+            let result = self.parse_regex().and_then(|()| match self.peek() {
+                Token::Eof => Ok(()),
+                got => Err(ParseErrorKind::ExpectedEof { got }),
+            });
+            match result {
+                Ok(()) => return errors,
+                // The end of a group that was left because of an error is not another error
+                Err(
+                    ParseErrorKind::ExpectedEof {
+                        got: Token::RightParenthesis,
+                    }
+                    | ParseErrorKind::UnexpectedRightParenthesis,
+                ) if self.open_parentheses > 0 => {
+                    self.consume();
+                    while let Token::Postfix(_) = self.peek() {
+                        self.consume();
+                    }
+                }
+                Err(kind) => {
+                    errors.push(PatternError {
+                        kind,
+                        position: Position::new(self.pattern, self.range.clone()),
+                    });
+                    if !self.recover() {
+                        return errors;
+                    }
+                }
+            }
+            self.stack = vec![Vec::new()];
+        }
+    }
 
-        parser.parse_regex()?;
-        if parser.peek() != Token::Eof {
-            return Err(ParseError::ExpectedEof { got: parser.peek() });
+    /// Skips the token of an error and the rest of the variable or class it is in, so that the rest of the pattern
+    /// can be checked for more errors. Returns `false` if the end of the pattern was reached.
+    fn recover(&mut self) -> bool {
+        // The token of the error was only peeked
+        if self.consumed_end < self.range.end {
+            self.consume();
         }
-        let root_node = *parser
+        while (self.open_braces > 0 || self.open_brackets > 0) && self.peek() != Token::Eof {
+            self.consume();
+        }
+        self.peek() != Token::Eof
+    }
+
+    /// The root node of the parsed pattern
+    fn root(&self) -> RegexNodeIndex {
+        let root_node = *self
             .stack
             .last()
             .expect("Stack should contain one row")
             .last()
             .expect("Stack should contain one element");
         assert!(
-            parser.stack.len() == 1 && parser.stack[0].len() == 1,
+            self.stack.len() == 1 && self.stack[0].len() == 1,
             "Stack should be empty now, but is: {:?}",
-            parser.stack
+            self.stack
         );
+        root_node
+    }
 
-        Ok(Regex {
-            arena: parser.nodes,
-            root: root_node,
-        })
+    /// Checks the parsed pattern and completes its variables
+    fn check_pattern(&mut self, root_node: RegexNodeIndex) -> Result<()> {
+        self.check_zipped_backreferences()?;
+        let counters = self.move_counters(root_node, false, &mut Set::default())?;
+        if let Some(counter) = counters.first() {
+            return Err(self.invalid_counter(*counter));
+        }
+        self.start_nested_rows(root_node, 0, &mut Set::default())?;
+        self.declare_branch_variables(root_node);
+        self.make_branch_variables_optional(root_node)
+    }
+
+    /// The captures of a zipped variable are collected into a list, so the variable cannot be repeated
+    fn check_zipped_backreferences(&self) -> Result<()> {
+        let zipped = self
+            .nodes
+            .iter()
+            .filter_map(|node_idx| match &self.nodes[node_idx] {
+                RegexNode::Variable(var, _) | RegexNode::Capture(var, _)
+                    if matches!(var.kind, VariableKind::Zipped { .. }) =>
+                {
+                    Some(var.name.clone())
+                }
+                _ => None,
+            })
+            .collect::<Set<_>>();
+        for node_idx in self.nodes.iter() {
+            if let RegexNode::Variable(var, _) | RegexNode::Capture(var, _) = &self.nodes[node_idx]
+            {
+                if matches!(var.kind, VariableKind::Backreference { .. })
+                    && zipped.contains(&var.name)
+                {
+                    return Err(ParseErrorKind::ZippedBackreference {
+                        name: var.name.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves every repetition count like `{#n}` to the start of the repeated group that directly contains it,
+    /// so that it counts the first character of every repetition. Returns the counters that are not inside of a repetition.
+    fn move_counters(
+        &mut self,
+        node_idx: RegexNodeIndex,
+        conditional: bool,
+        visited: &mut Set<RegexNodeIndex>,
+    ) -> Result<Vec<RegexNodeIndex>> {
+        if !visited.insert(node_idx) {
+            return Ok(Vec::new());
+        }
+        match &self.nodes[node_idx] {
+            RegexNode::And(children) => {
+                let mut counters = Vec::new();
+                for child in children.clone() {
+                    counters.extend(self.move_counters(child, conditional, visited)?);
+                }
+                Ok(counters)
+            }
+            RegexNode::Or(children) => {
+                for child in children.clone() {
+                    self.move_counters(child, true, visited)?;
+                }
+                Ok(Vec::new())
+            }
+            RegexNode::ZeroOrOne(child)
+            | RegexNode::Lookahead(child)
+            | RegexNode::NegativeLookahead(child) => {
+                self.move_counters(*child, true, visited)?;
+                Ok(Vec::new())
+            }
+            &RegexNode::Many(child) | &RegexNode::OneOrMore(child) => {
+                let counters = self.move_counters(child, false, visited)?;
+                if counters.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let mut body = Vec::new();
+                for counter in counters {
+                    let RegexNode::Counter(var) =
+                        std::mem::replace(&mut self.nodes[counter], RegexNode::And(Vec::new()))
+                    else {
+                        unreachable!("Only counters are collected");
+                    };
+                    let moved = self.nodes.add(RegexNode::Counter(var));
+                    if let Some(position) = self.positions.swap_remove(&counter) {
+                        self.positions.insert(moved, position);
+                    }
+                    body.push(moved);
+                }
+                body.push(child);
+                let body = self.nodes.add(RegexNode::And(body));
+                self.nodes[node_idx] = match self.nodes[node_idx] {
+                    RegexNode::Many(_) => RegexNode::Many(body),
+                    _ => RegexNode::OneOrMore(body),
+                };
+                Ok(Vec::new())
+            }
+            RegexNode::Counter(_) if conditional => Err(self.invalid_counter(node_idx)),
+            RegexNode::Counter(_) => Ok(vec![node_idx]),
+            RegexNode::Variable(..)
+            | RegexNode::Capture(..)
+            | RegexNode::Literal(_)
+            | RegexNode::Assertion(_) => Ok(Vec::new()),
+        }
+    }
+
+    fn invalid_counter(&self, node_idx: RegexNodeIndex) -> ParseErrorKind {
+        let RegexNode::Counter(var) = &self.nodes[node_idx] else {
+            unreachable!("Only counters are invalid counters");
+        };
+        ParseErrorKind::InvalidCounter {
+            name: var.name.clone(),
+        }
+    }
+
+    /// Starts a new list of every nested variable like `{n**}` in `(({n**},)*\n)*` at the start of every repetition
+    /// of its outer group, like a repetition count `{#n}` that is moved there, see [`Self::move_counters`].
+    /// A repetition of the outer group in which the variable does not match adds an empty list.
+    fn start_nested_rows(
+        &mut self,
+        node_idx: RegexNodeIndex,
+        repetitions: u32,
+        visited: &mut Set<RegexNodeIndex>,
+    ) -> Result<()> {
+        // Nodes can be referenced multiple times, but must only be split once
+        if !visited.insert(node_idx) {
+            return Ok(());
+        }
+        match &self.nodes[node_idx] {
+            RegexNode::And(children) | RegexNode::Or(children) => {
+                for child in children.clone() {
+                    self.start_nested_rows(child, repetitions, visited)?;
+                }
+            }
+            RegexNode::ZeroOrOne(child)
+            | RegexNode::Lookahead(child)
+            | RegexNode::NegativeLookahead(child) => {
+                self.start_nested_rows(*child, repetitions, visited)?
+            }
+            &RegexNode::Many(child) | &RegexNode::OneOrMore(child) => {
+                let many = matches!(self.nodes[node_idx], RegexNode::Many(_));
+                self.start_nested_rows(child, repetitions + 1, visited)?;
+                let mut rows = Vec::new();
+                self.collect_row_variables(child, 0, &mut rows);
+                if !rows.is_empty() {
+                    let mut iteration = rows
+                        .into_iter()
+                        .map(|var| self.nodes.add(RegexNode::Counter(var)))
+                        .collect::<Vec<_>>();
+                    iteration.push(child);
+                    let iteration = self.nodes.add(RegexNode::And(iteration));
+                    self.nodes[node_idx] = if many {
+                        RegexNode::Many(iteration)
+                    } else {
+                        RegexNode::OneOrMore(iteration)
+                    };
+                }
+            }
+            RegexNode::Variable(var, _) | RegexNode::Capture(var, _)
+                if matches!(var.kind, VariableKind::Nested { .. }) && repetitions < 2 =>
+            {
+                return Err(ParseErrorKind::NestedOutsideRepetition {
+                    name: var.name.clone(),
+                });
+            }
+            RegexNode::Variable(..)
+            | RegexNode::Capture(..)
+            | RegexNode::Counter(_)
+            | RegexNode::Literal(_)
+            | RegexNode::Assertion(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Collects the nested variables of the node that are inside of exactly one more repetition,
+    /// so that the node is the iteration of their outer group. They are returned as the counters that start a new list.
+    fn collect_row_variables(
+        &self,
+        node_idx: RegexNodeIndex,
+        repetitions: u32,
+        rows: &mut Vec<RegexVariable>,
+    ) {
+        match &self.nodes[node_idx] {
+            RegexNode::And(children) | RegexNode::Or(children) => {
+                for child in children {
+                    self.collect_row_variables(*child, repetitions, rows);
+                }
+            }
+            RegexNode::ZeroOrOne(child) => self.collect_row_variables(*child, repetitions, rows),
+            RegexNode::Many(child) | RegexNode::OneOrMore(child) if repetitions == 0 => {
+                self.collect_row_variables(*child, repetitions + 1, rows)
+            }
+            RegexNode::Variable(var, _) | RegexNode::Capture(var, _)
+                if repetitions == 1 && matches!(var.kind, VariableKind::Nested { .. }) =>
+            {
+                let row = RegexVariable {
+                    kind: VariableKind::Nested { row_start: true },
+                    ..var.clone()
+                };
+                if !rows.contains(&row) {
+                    rows.push(row);
+                }
+            }
+            RegexNode::Variable(..)
+            | RegexNode::Capture(..)
+            | RegexNode::Lookahead(_)
+            | RegexNode::NegativeLookahead(_)
+            | RegexNode::Many(_)
+            | RegexNode::OneOrMore(_)
+            | RegexNode::Counter(_)
+            | RegexNode::Literal(_)
+            | RegexNode::Assertion(_) => {}
+        }
+    }
+
+    /// A variable in one branch of an alternation like `({n} items|{n} item)` is not a backreference
+    /// to the same variable in another branch, because only one of them can match.
+    /// Those occurrences are turned back into single variables, so whichever branch matched sets the variable.
+    fn declare_branch_variables(&mut self, root: RegexNodeIndex) {
+        let mut declarations = Vec::new();
+        self.collect_branch_declarations(root, &mut Set::default(), &mut declarations);
+        for node_idx in declarations {
+            if let RegexNode::Variable(var, _) | RegexNode::Capture(var, _) =
+                &mut self.nodes[node_idx]
+            {
+                var.kind = VariableKind::Singular;
+            }
+        }
+    }
+
+    /// Collects the backreferences whose variable was not declared on the way to them.
+    /// `declared` contains the names of the variables that are declared before `node_idx`.
+    fn collect_branch_declarations(
+        &self,
+        node_idx: RegexNodeIndex,
+        declared: &mut Set<String>,
+        declarations: &mut Vec<RegexNodeIndex>,
+    ) {
+        match &self.nodes[node_idx] {
+            RegexNode::And(children) => {
+                for child in children {
+                    self.collect_branch_declarations(*child, declared, declarations);
+                }
+            }
+            RegexNode::Or(children) => {
+                let before = declared.clone();
+                for child in children {
+                    let mut branch = before.clone();
+                    self.collect_branch_declarations(*child, &mut branch, declarations);
+                    declared.extend(branch);
+                }
+            }
+            RegexNode::Variable(var, _) | RegexNode::Capture(var, _) => {
+                if !declared.insert(var.name.clone())
+                    || !matches!(var.kind, VariableKind::Backreference { .. })
+                {
+                    return;
+                }
+                declarations.push(node_idx);
+            }
+            RegexNode::Lookahead(child)
+            | RegexNode::NegativeLookahead(child)
+            | RegexNode::ZeroOrOne(child)
+            | RegexNode::Many(child)
+            | RegexNode::OneOrMore(child) => {
+                self.collect_branch_declarations(*child, declared, declarations)
+            }
+            RegexNode::Literal(_) | RegexNode::Assertion(_) | RegexNode::Counter(_) => {}
+        }
+    }
+
+    /// Variables that only appear in some branches of an alternation like `(cat {lives}|dog {name})`
+    /// are not set if another branch matches, so single variables among them become [`VariableKind::Optional`]
+    fn make_branch_variables_optional(&mut self, root: RegexNodeIndex) -> Result<()> {
+        let mut optional = Set::default();
+        self.collect_branch_variables(root, &mut optional);
+        for (_, node) in self.nodes.iter_mut() {
+            let (RegexNode::Variable(var, _) | RegexNode::Capture(var, _)) = node else {
+                continue;
+            };
+            if !optional.contains(&var.name) {
+                continue;
+            }
+            match var.kind {
+                VariableKind::Singular => var.kind = VariableKind::Optional { default: None },
+                VariableKind::Greedy => {
+                    return Err(ParseErrorKind::InvalidGreedyVariable {
+                        name: var.name.clone(),
+                    })
+                }
+                VariableKind::Backreference { .. } => {
+                    return Err(ParseErrorKind::OptionalBackreference {
+                        name: var.name.clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the variables below `node_idx` and adds the names of variables
+    /// that are missing in some branch of an alternation to `optional`
+    fn collect_branch_variables(
+        &self,
+        node_idx: RegexNodeIndex,
+        optional: &mut Set<String>,
+    ) -> Set<String> {
+        match &self.nodes[node_idx] {
+            RegexNode::And(children) => children
+                .iter()
+                .flat_map(|child| self.collect_branch_variables(*child, optional))
+                .collect(),
+            RegexNode::Or(children) => {
+                let branches = children
+                    .iter()
+                    .map(|child| self.collect_branch_variables(*child, optional))
+                    .collect::<Vec<_>>();
+                let variables = branches.iter().flatten().cloned().collect::<Set<_>>();
+                optional.extend(
+                    variables
+                        .iter()
+                        .filter(|name| !branches.iter().all(|branch| branch.contains(*name)))
+                        .cloned(),
+                );
+                variables
+            }
+            RegexNode::Variable(var, _) | RegexNode::Capture(var, _)
+                if matches!(var.kind, VariableKind::Backreference { .. }) =>
+            {
+                Set::default()
+            }
+            RegexNode::Variable(var, _) | RegexNode::Capture(var, _) => {
+                Set::from_iter([var.name.clone()])
+            }
+            RegexNode::Lookahead(child)
+            | RegexNode::NegativeLookahead(child)
+            | RegexNode::ZeroOrOne(child)
+            | RegexNode::Many(child)
+            | RegexNode::OneOrMore(child) => self.collect_branch_variables(*child, optional),
+            RegexNode::Literal(_) | RegexNode::Assertion(_) | RegexNode::Counter(_) => {
+                Set::default()
+            }
+        }
     }
 
     fn consume(&mut self) -> Token {
-        self.source.next().unwrap_or(Token::Eof)
+        self.peek();
+        self.consumed_end = self.range.end;
+        let token = self.source.next().map_or(Token::Eof, |(token, _)| token);
+        match token {
+            Token::LeftBrace | Token::ExpectedValue(_) => self.open_braces += 1,
+            Token::RightBrace => self.open_braces = self.open_braces.saturating_sub(1),
+            Token::LeftParenthesis
+            | Token::FlagGroup(_)
+            | Token::Lookahead
+            | Token::NegativeLookahead
+            | Token::NamedGroup => self.open_parentheses += 1,
+            Token::RightParenthesis => {
+                self.open_parentheses = self.open_parentheses.saturating_sub(1)
+            }
+            Token::LeftBracket => self.open_brackets += 1,
+            Token::RightBracket => self.open_brackets = self.open_brackets.saturating_sub(1),
+            _ => {}
+        }
+        token
+    }
+
+    /// Consumes the next token if it equals `token`
+    fn next_if_eq(&mut self, token: Token) -> bool {
+        let matches = self.peek() == token;
+        if matches {
+            self.consume();
+        }
+        matches
     }
 
     fn expect(&mut self, token: Token) -> Result<()> {
         let next = self.consume();
         if next != token {
-            Err(ParseError::UnexpectedToken {
+            Err(ParseErrorKind::UnexpectedToken {
                 got: next,
                 expected: token,
             })
@@ -89,25 +841,26 @@ where
     }
 
     fn peek(&mut self) -> Token {
-        self.source.peek().copied().unwrap_or(Token::Eof)
+        match self.source.peek() {
+            Some((token, range)) => {
+                self.range = range.clone();
+                token.clone()
+            }
+            None => {
+                self.range = self.range.end..self.range.end;
+                Token::Eof
+            }
+        }
     }
 
     /// Interprets the next token as a character in a `\[...\]` group
     fn consume_as_char(&mut self) -> Result<char> {
-        fn single_char(input: &str) -> char {
-            let mut chars = input.chars();
-            let char = chars.next().expect("Input should not be empty");
-            assert!(
-                chars.next().is_none(),
-                "Input should have a single character only"
-            );
-            char
+        match self.consume() {
+            Token::Char(char) => Ok(char),
+            Token::Minus => Ok('-'),
+            Token::Eof | Token::RightBracket => Err(ParseErrorKind::UnexpectedRightBracket),
+            got => Err(ParseErrorKind::ExpectedChar { got }),
         }
-        if matches!(self.peek(), Token::Eof | Token::RightBracket) {
-            return Err(ParseError::UnexpectedRightBracket);
-        };
-
-        Ok(single_char(&self.consume().to_string()))
     }
 
     fn push_node(&mut self, node: RegexNode) -> RegexNodeIndex {
@@ -136,6 +889,44 @@ where
         self.stack.push(Vec::new());
     }
 
+    /// Pushes a single node that matches any of the given nodes
+    fn push_or(&mut self, nodes: Vec<RegexNodeIndex>) {
+        match nodes.as_slice() {
+            [single] => self.push_node_idx(*single),
+            _ => {
+                self.push_node(RegexNode::Or(nodes));
+            }
+        }
+    }
+
+    /// Adds a literal node for every pattern, taking the current flags into account
+    /// Returns the patterns of `class`, which depend on the unicode flag
+    fn class_patterns<'c>(&self, class: &'c CharacterClass) -> &'c [RegexPattern] {
+        if self.flags.contains(Flag::Unicode) {
+            class.as_unicode_patterns()
+        } else {
+            class.as_patterns()
+        }
+    }
+
+    fn add_literals(&mut self, patterns: &[RegexPattern]) -> Vec<RegexNodeIndex> {
+        let case_insensitive = self.flags.contains(Flag::CaseInsensitive);
+        let mut nodes = Vec::new();
+        for pattern in patterns.iter().copied() {
+            let patterns = if case_insensitive {
+                pattern.case_folded()
+            } else {
+                vec![pattern]
+            };
+            nodes.extend(
+                patterns
+                    .into_iter()
+                    .map(|pattern| self.nodes.add(RegexNode::Literal(pattern))),
+            );
+        }
+        nodes
+    }
+
     fn parse_regex(&mut self) -> Result<()> {
         self.parse_or()
     }
@@ -143,10 +934,36 @@ where
     fn parse_or(&mut self) -> Result<()> {
         self.push_row();
 
+        let mut after_pipe = false;
+        let mut positions = Vec::new();
         loop {
-            self.parse_and()?;
+            self.peek();
+            let start = self.range.start;
+            // An empty branch, like in `(|a)` or `(a|)`, results in an empty and-node
+            if self.peek() == Token::Pipe || (after_pipe && self.peek() == Token::RightParenthesis)
+            {
+                self.push_node(RegexNode::And(Vec::new()));
+            } else {
+                self.parse_and()?;
+            }
+            positions.push(Position::new(
+                self.pattern,
+                start..self.consumed_end.max(start),
+            ));
+
+            if let Token::MappedValue(value) = self.peek() {
+                self.consume();
+                let branch = *self
+                    .stack
+                    .last()
+                    .and_then(|row| row.last())
+                    .expect("Stack not empty");
+                self.mapped_branches.push((branch, value));
+            }
+
             if self.peek() == Token::Pipe {
                 self.consume();
+                after_pipe = true;
             } else {
                 break;
             }
@@ -156,7 +973,8 @@ where
         match nodes.as_slice() {
             [single] => self.push_node_idx(*single),
             _ => {
-                self.push_node(RegexNode::Or(nodes));
+                let node_idx = self.push_node(RegexNode::Or(nodes));
+                self.alternations.push((node_idx, positions));
             }
         };
 
@@ -184,19 +1002,74 @@ where
         Ok(())
     }
 
+    /// Parses a value and records the positions of the variables and lookaheads in it.
+    /// Nested values are parsed first, so the innermost position of a node is kept.
     fn parse_value(&mut self) -> Result<()> {
+        self.peek();
+        let start = self.range.start;
+        let first_node = self.nodes.len();
+        self.parse_value_inner()?;
+
+        let range = start..self.consumed_end.max(start);
+        let position = Position::new(self.pattern, range.clone());
+        // Values like `(?i)` do not add a node, so the last node may belong to an earlier value
+        if let Some(node) = self.stack.last().and_then(|row| row.last()) {
+            if node.index() >= first_node {
+                self.parts.push(PatternPart {
+                    text: self.pattern[range].to_string(),
+                    position: position.clone(),
+                    node: *node,
+                });
+            }
+        }
+        for node_idx in self.nodes.iter().skip(first_node) {
+            if let RegexNode::Variable(..)
+            | RegexNode::Capture(..)
+            | RegexNode::Counter(_)
+            | RegexNode::Lookahead(_)
+            | RegexNode::NegativeLookahead(_) = self.nodes[node_idx]
+            {
+                self.positions
+                    .entry(node_idx)
+                    .or_insert_with(|| position.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_value_inner(&mut self) -> Result<()> {
         match self.peek() {
             Token::Eof => Ok(()),
             Token::Char(_) | Token::Dot | Token::CharacterClass(_) => self.parse_char(),
-            Token::RightBrace => Err(ParseError::UnexpectedRightBrace),
+            Token::RightBrace => Err(ParseErrorKind::UnexpectedRightBrace),
             Token::LeftBrace => self.parse_variable(),
-            Token::LeftParenthesis => self.parse_parenthesis(),
-            Token::RightParenthesis => Err(ParseError::UnexpectedRightParenthesis),
+            Token::ExpectedValue(value) => self.parse_expected_value(&value),
+            Token::Assertion(_) | Token::Caret | Token::Dollar => self.parse_assertion(),
+            Token::LeftParenthesis
+            | Token::FlagGroup(_)
+            | Token::Lookahead
+            | Token::NegativeLookahead
+            | Token::NamedGroup => self.parse_parenthesis(),
+            Token::RightParenthesis => Err(ParseErrorKind::UnexpectedRightParenthesis),
             Token::LeftBracket => self.parse_group(),
-            Token::RightBracket => Err(ParseError::UnexpectedRightBracket),
-            Token::Minus => Err(ParseError::UnexpectedMinus),
-            Token::Pipe => Err(ParseError::UnexpectedBar),
-            token @ Token::Postfix(_) => Err(ParseError::UnexpectedPostfixToken { got: token }),
+            Token::Reference | Token::Float => self.parse_reference(),
+            Token::RightBracket => Err(ParseErrorKind::UnexpectedRightBracket),
+            Token::Minus => Err(ParseErrorKind::UnexpectedMinus),
+            token @ (Token::CaptureType(_)
+            | Token::CollectionType(_)
+            | Token::LengthBound { .. }
+            | Token::DefaultValue(_)
+            | Token::MappedValue(_)
+            | Token::TimeFormat(_)
+            | Token::Separator(_)
+            | Token::Iter) => Err(ParseErrorKind::ExpectedChar { got: token }),
+            Token::Pipe => Err(ParseErrorKind::UnexpectedBar),
+            token @ Token::Postfix(_) => Err(ParseErrorKind::UnexpectedPostfixToken { got: token }),
+            Token::SetFlags(update) => {
+                self.consume();
+                self.flags = self.flags.apply(update);
+                Ok(())
+            }
         }
     }
 
@@ -214,51 +1087,282 @@ where
 
     fn parse_group_inner(&mut self) -> Result<()> {
         let mut chars = Vec::new();
-        while let Ok(char) = self.consume_as_char() {
+        loop {
+            if let Token::CharacterClass(class) = self.peek() {
+                self.consume();
+                chars.extend(self.add_literals(self.class_patterns(&class)));
+                continue;
+            }
+
+            if matches!(self.peek(), Token::Eof | Token::RightBracket) {
+                break;
+            }
+
+            let char = self.consume_as_char()?;
             if self.peek() == Token::Minus {
                 self.consume();
+                // A `-` at the end of the group is a literal
+                if self.peek() == Token::RightBracket {
+                    chars.extend(
+                        self.add_literals(&[RegexPattern::Char(char), RegexPattern::Char('-')]),
+                    );
+                    break;
+                }
                 let final_char = self.consume_as_char()?;
-                chars.push(
-                    self.nodes
-                        .add(RegexNode::Literal(RegexPattern::Range(char, final_char))),
-                )
+                chars.extend(self.add_literals(&[RegexPattern::Range(char, final_char)]));
             } else {
-                chars.push(self.nodes.add(RegexNode::Literal(RegexPattern::Char(char))));
+                chars.extend(self.add_literals(&[RegexPattern::Char(char)]));
             }
         }
 
-        match chars.as_slice() {
-            [single] => self.push_node_idx(*single),
-            _ => {
-                self.push_node(RegexNode::Or(chars));
-            }
+        self.push_or(chars);
+
+        Ok(())
+    }
+
+    /// Parses a `\k{name}` reference and inserts the nodes of the referenced class
+    fn parse_reference(&mut self) -> Result<()> {
+        let class = if self.next_if_eq(Token::Float) {
+            Regex::from_str(CaptureType::FLOAT).expect("The float pattern should be valid")
+        } else {
+            self.expect(Token::Reference)?;
+            self.expect(Token::LeftBrace)?;
+            let name = self.parse_ident()?;
+            self.expect(Token::RightBrace)?;
+
+            let source = classes::get(&name)
+                .ok_or_else(|| ParseErrorKind::UnknownClass { name: name.clone() })?;
+            // The error is reported at the reference, because the class is not part of this pattern
+            Regex::class(&name, &source).map_err(|err| err.kind)?
+        };
+        let node = self.insert_nodes(&class.arena, class.root);
+        self.push_node_idx(node);
+
+        if matches!(self.peek(), Token::Postfix(_)) {
+            self.parse_postfix()?;
         }
 
         Ok(())
     }
 
+    /// Copies `node` and its children from another arena into this parser's arena
+    fn insert_nodes(&mut self, arena: &RegexArena, node: RegexNodeIndex) -> RegexNodeIndex {
+        let node = match &arena[node] {
+            RegexNode::And(nodes) => RegexNode::And(
+                nodes
+                    .iter()
+                    .map(|node| self.insert_nodes(arena, *node))
+                    .collect(),
+            ),
+            RegexNode::Or(nodes) => RegexNode::Or(
+                nodes
+                    .iter()
+                    .map(|node| self.insert_nodes(arena, *node))
+                    .collect(),
+            ),
+            RegexNode::Literal(pattern) => RegexNode::Literal(*pattern),
+            RegexNode::Variable(var, pattern) => RegexNode::Variable(var.clone(), *pattern),
+            RegexNode::Capture(var, child) => {
+                RegexNode::Capture(var.clone(), self.insert_nodes(arena, *child))
+            }
+            RegexNode::Assertion(assertion) => RegexNode::Assertion(*assertion),
+            RegexNode::Counter(var) => RegexNode::Counter(var.clone()),
+            RegexNode::Lookahead(child) => RegexNode::Lookahead(self.insert_nodes(arena, *child)),
+            RegexNode::NegativeLookahead(child) => {
+                RegexNode::NegativeLookahead(self.insert_nodes(arena, *child))
+            }
+            RegexNode::ZeroOrOne(child) => RegexNode::ZeroOrOne(self.insert_nodes(arena, *child)),
+            RegexNode::Many(child) => RegexNode::Many(self.insert_nodes(arena, *child)),
+            RegexNode::OneOrMore(child) => RegexNode::OneOrMore(self.insert_nodes(arena, *child)),
+        };
+        self.nodes.add(node)
+    }
+
     fn parse_parenthesis(&mut self) -> Result<()> {
-        self.expect(Token::LeftParenthesis)?;
-        self.parse_regex()?;
+        let flags = self.flags;
+        let mut lookahead = None;
+        let mut capture = None;
+        match self.consume() {
+            Token::LeftParenthesis => {}
+            Token::FlagGroup(update) => self.flags = self.flags.apply(update),
+            token @ (Token::Lookahead | Token::NegativeLookahead) => lookahead = Some(token),
+            Token::NamedGroup => capture = Some(self.parse_group_name()?),
+            got => {
+                return Err(ParseErrorKind::UnexpectedToken {
+                    got,
+                    expected: Token::LeftParenthesis,
+                })
+            }
+        }
+        if self.peek() == Token::RightParenthesis {
+            self.push_node(RegexNode::And(Vec::new()));
+        } else {
+            self.parse_regex()?;
+        }
+        self.flags = flags;
         self.expect(Token::RightParenthesis)?;
 
+        if let Some(token) = lookahead {
+            let child = self.pop_single();
+            self.push_node(match token {
+                Token::NegativeLookahead => RegexNode::NegativeLookahead(child),
+                _ => RegexNode::Lookahead(child),
+            });
+        }
+
+        if let Some(name) = capture {
+            let child = self.pop_single();
+            self.occurrences.entry(name.clone()).or_default();
+            let var = RegexVariable {
+                name,
+                kind: VariableKind::Singular,
+                mutable: false,
+                ty: None,
+            };
+            self.push_node(RegexNode::Capture(var, child));
+        }
+
         if matches!(self.peek(), Token::Postfix(_)) {
             self.parse_postfix()?;
+            self.parse_zipped_list()?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses the `=name` part after a repeated group with variables like `({x},{y};)*=pairs`.
+    /// The variables of the group become [`VariableKind::Zipped`], so they are bound as a list of tuples
+    /// with one tuple per repetition. Without variables, or after other postfix operators, `=` stays a literal char.
+    /// With `=>name`, like `({key}: {value}\n)*=>headers`, the key/value pairs are collected into a map instead.
+    fn parse_zipped_list(&mut self) -> Result<()> {
+        if self.peek() != Token::Char('=') {
+            return Ok(());
+        }
+        let node_idx = *self
+            .stack
+            .last()
+            .and_then(|row| row.last())
+            .expect("The repetition should have been pushed");
+        let (RegexNode::Many(child) | RegexNode::OneOrMore(child)) = self.nodes[node_idx] else {
+            return Ok(());
+        };
+        let mut variables = Vec::new();
+        self.collect_variable_nodes(child, false, &mut variables);
+        if variables.is_empty() {
+            return Ok(());
+        }
+        self.consume();
+        let map = self.peek() == Token::Char('>');
+        if map {
+            self.consume();
+        }
+        // The name is followed by the rest of the pattern, so it ends at the first char that is not part of an identifier
+        let mut list = String::new();
+        while let Token::Char(char @ ('_' | '#' | '0'..='9' | 'a'..='z' | 'A'..='Z')) = self.peek()
+        {
+            list.push(char);
+            self.consume();
+        }
+        let list = validate_ident(list)?;
+        if map && variables.len() != 2 {
+            return Err(ParseErrorKind::InvalidMapEntry {
+                map: list.to_string(),
+                count: variables.len(),
+            });
         }
 
+        for (index, &(variable_idx, conditional)) in variables.iter().enumerate() {
+            let (RegexNode::Variable(var, _) | RegexNode::Capture(var, _)) =
+                &mut self.nodes[variable_idx]
+            else {
+                unreachable!("Only variable nodes are collected");
+            };
+            // A node can be visited twice by a bounded repetition like `({x},)#{2}`
+            let repeated = variables[..index]
+                .iter()
+                .any(|(other_idx, _)| *other_idx == variable_idx);
+            if conditional || repeated {
+                return Err(ParseErrorKind::ZippedOccurrence {
+                    name: var.name.clone(),
+                    list: list.to_string(),
+                });
+            }
+            if var.kind != VariableKind::Singular {
+                return Err(ParseErrorKind::InvalidZippedVariable {
+                    name: var.name.clone(),
+                    list: list.to_string(),
+                });
+            }
+            var.kind = VariableKind::Zipped {
+                list: list.clone(),
+                index: index as u32,
+                map,
+            };
+        }
         Ok(())
     }
 
+    /// Collects the variable nodes below `node_idx` in the order in which they appear in the pattern,
+    /// together with whether they are conditional, i.e. inside of an alternation or a repetition
+    fn collect_variable_nodes(
+        &self,
+        node_idx: RegexNodeIndex,
+        conditional: bool,
+        variables: &mut Vec<(RegexNodeIndex, bool)>,
+    ) {
+        match &self.nodes[node_idx] {
+            RegexNode::And(children) => {
+                for child in children {
+                    self.collect_variable_nodes(*child, conditional, variables);
+                }
+            }
+            RegexNode::Or(children) => {
+                for child in children {
+                    self.collect_variable_nodes(*child, true, variables);
+                }
+            }
+            RegexNode::Variable(..) | RegexNode::Capture(..) => {
+                variables.push((node_idx, conditional))
+            }
+            RegexNode::Lookahead(child)
+            | RegexNode::NegativeLookahead(child)
+            | RegexNode::ZeroOrOne(child)
+            | RegexNode::Many(child)
+            | RegexNode::OneOrMore(child) => self.collect_variable_nodes(*child, true, variables),
+            RegexNode::Literal(_) | RegexNode::Assertion(_) | RegexNode::Counter(_) => {}
+        }
+    }
+
     fn parse_postfix(&mut self) -> Result<()> {
         let token = self.consume();
         let Token::Postfix(postfix_token) = token else {
-            return Err(ParseError::ExpectedPostfixOperator { got: token });
+            return Err(ParseErrorKind::ExpectedPostfixOperator { got: token });
         };
 
         let node = match postfix_token {
             PostfixToken::QuestionMark => RegexNode::ZeroOrOne,
             PostfixToken::Star => RegexNode::Many,
             PostfixToken::Plus => RegexNode::OneOrMore,
+            PostfixToken::Repeat { min, max } => {
+                if let Some(count) = max.filter(|max| *max > MAX_REPETITIONS) {
+                    return Err(ParseErrorKind::RepetitionTooLarge { count });
+                }
+                if min > MAX_REPETITIONS {
+                    return Err(ParseErrorKind::RepetitionTooLarge { count: min });
+                }
+                if max.is_some_and(|max| max < min) {
+                    return Err(ParseErrorKind::InvalidRepetition { token });
+                }
+
+                let child = self.pop_single();
+                let node = self.repeat(child, min, max);
+                let size = self.expanded_size(node, &mut Map::default());
+                if size > MAX_EXPANDED_SIZE {
+                    return Err(ParseErrorKind::ExpansionTooLarge { size });
+                }
+                self.push_node_idx(node);
+                return Ok(());
+            }
         };
 
         let child = self.pop_single();
@@ -267,30 +1371,81 @@ where
         Ok(())
     }
 
+    /// Expands a bounded repetition into an equivalent tree.
+    /// The child node is not copied, instead all repetitions refer to the same node.
+    /// This also works for groups and classes, because the nfa creates new states every time it visits a node.
+    ///
+    /// For example, `a#{2,4}` becomes `aa(a(a)?)?` and `a#{2,}` becomes `aaa*`.
+    fn repeat(&mut self, child: RegexNodeIndex, min: u32, max: Option<u32>) -> RegexNodeIndex {
+        let mut parts = vec![child; min as usize];
+        match max {
+            None => parts.push(self.nodes.add(RegexNode::Many(child))),
+            Some(max) => {
+                let mut optional = None;
+                for _ in min..max {
+                    let inner = match optional {
+                        None => child,
+                        Some(rest) => self.nodes.add(RegexNode::And(vec![child, rest])),
+                    };
+                    optional = Some(self.nodes.add(RegexNode::ZeroOrOne(inner)));
+                }
+                parts.extend(optional);
+            }
+        }
+
+        match parts.as_slice() {
+            [single] => *single,
+            _ => self.nodes.add(RegexNode::And(parts)),
+        }
+    }
+
+    /// The number of parts that the nfa visits for `node_idx`, counting the parts that several repetitions refer to
+    /// once for every reference, see [`MAX_EXPANDED_SIZE`]. The sizes of the visited nodes are cached in `sizes`.
+    fn expanded_size(
+        &self,
+        node_idx: RegexNodeIndex,
+        sizes: &mut Map<RegexNodeIndex, usize>,
+    ) -> usize {
+        if let Some(size) = sizes.get(&node_idx) {
+            return *size;
+        }
+        let size = match &self.nodes[node_idx] {
+            RegexNode::And(children) | RegexNode::Or(children) => {
+                children.iter().fold(1_usize, |size, child| {
+                    size.saturating_add(self.expanded_size(*child, sizes))
+                })
+            }
+            RegexNode::Capture(_, child)
+            | RegexNode::Lookahead(child)
+            | RegexNode::NegativeLookahead(child)
+            | RegexNode::ZeroOrOne(child)
+            | RegexNode::Many(child)
+            | RegexNode::OneOrMore(child) => self.expanded_size(*child, sizes).saturating_add(1),
+            RegexNode::Literal(_)
+            | RegexNode::Variable(..)
+            | RegexNode::Assertion(_)
+            | RegexNode::Counter(_) => 1,
+        };
+        sizes.insert(node_idx, size);
+        size
+    }
+
     fn parse_char(&mut self) -> Result<()> {
         let token = self.consume();
         match token {
             Token::Char(char) => {
-                self.push_node(RegexNode::Literal(RegexPattern::Char(char)));
+                let nodes = self.add_literals(&[RegexPattern::Char(char)]);
+                self.push_or(nodes);
             }
             Token::Dot => {
-                self.push_node(RegexNode::Literal(RegexPattern::AnyChar));
+                let newline = self.flags.contains(Flag::DotAll);
+                self.push_node(RegexNode::Literal(RegexPattern::AnyChar { newline }));
             }
             Token::CharacterClass(class) => {
-                let patterns = class.as_patterns();
-                match patterns {
-                    [single] => self.push_node(RegexNode::Literal(*single)),
-                    _ => {
-                        let parts = patterns
-                            .iter()
-                            .copied()
-                            .map(|pat| self.nodes.add(RegexNode::Literal(pat)))
-                            .collect();
-                        self.push_node(RegexNode::Or(parts))
-                    }
-                };
+                let nodes = self.add_literals(self.class_patterns(&class));
+                self.push_or(nodes);
             }
-            _ => return Err(ParseError::ExpectedChar { got: token }),
+            _ => return Err(ParseErrorKind::ExpectedChar { got: token }),
         }
 
         if matches!(self.peek(), Token::Postfix(_)) {
@@ -300,31 +1455,703 @@ where
         Ok(())
     }
 
+    fn parse_assertion(&mut self) -> Result<()> {
+        let multiline = self.flags.contains(Flag::Multiline);
+        let assertion = match self.consume() {
+            Token::Assertion(assertion) => assertion,
+            Token::Caret if multiline => Assertion::StartLine,
+            Token::Caret => Assertion::StartText,
+            Token::Dollar if multiline => Assertion::EndLine,
+            Token::Dollar => Assertion::EndText,
+            token => return Err(ParseErrorKind::ExpectedChar { got: token }),
+        };
+        self.push_node(RegexNode::Assertion(assertion));
+        Ok(())
+    }
+
     fn parse_variable(&mut self) -> Result<()> {
         self.expect(Token::LeftBrace)?;
-        let ident = self.parse_ident()?;
+        // `{#name}` counts the repetitions of its group instead of capturing text
+        if self.peek() == Token::Char('#') {
+            self.consume();
+            let name = self.parse_place()?;
+            self.push_node(RegexNode::Counter(RegexVariable {
+                mutable: self.mutable.contains(&name),
+                name,
+                kind: VariableKind::Count,
+                ty: None,
+            }));
+            return self.expect(Token::RightBrace);
+        }
+        let ident = self.parse_place()?;
+        if ident == "_" {
+            return self.parse_discard();
+        }
+        self.skip_spaces();
+        let mut pattern_follows = false;
         let kind = if self.peek() == Token::Postfix(PostfixToken::Star) {
             self.consume();
-            VariableKind::Multiple
+            if self.peek() == Token::Postfix(PostfixToken::Star) {
+                self.consume();
+                VariableKind::Nested { row_start: false }
+            } else {
+                let min = self.parse_minimum_count()?;
+                VariableKind::Multiple { min }
+            }
+        } else if self.peek() == Token::Char(';') {
+            self.consume();
+            let len = self.parse_array_length()?;
+            VariableKind::Array { len }
+        } else if self.peek() == Token::Postfix(PostfixToken::QuestionMark) {
+            self.consume();
+            if self.peek() == Token::Postfix(PostfixToken::QuestionMark) {
+                self.consume();
+                if self.singular_kind(&ident) != VariableKind::Singular {
+                    return Err(ParseErrorKind::EmptyBackreference { name: ident });
+                }
+                VariableKind::Empty
+            } else {
+                VariableKind::Optional { default: None }
+            }
+        } else if let Token::DefaultValue(default) = self.peek() {
+            self.consume();
+            if syn::parse_str::<syn::Expr>(&default).is_err() {
+                return Err(ParseErrorKind::InvalidDefault { default });
+            }
+            VariableKind::Optional {
+                default: Some(default),
+            }
+        } else if self.peek() == Token::Pipe {
+            let (transforms, function);
+            (transforms, function, pattern_follows) = self.parse_pipeline()?;
+            if self.singular_kind(&ident) != VariableKind::Singular {
+                return Err(ParseErrorKind::ConvertedBackreference { name: ident });
+            }
+            VariableKind::Converted {
+                transforms,
+                function,
+            }
+        } else if self.peek() == Token::Char('@') {
+            self.consume();
+            let span = validate_ident(self.parse_ident()?)?;
+            if self.singular_kind(&ident) != VariableKind::Singular {
+                return Err(ParseErrorKind::SpannedBackreference { name: ident });
+            }
+            VariableKind::Spanned { span }
+        } else if self.peek() == Token::Char('!') {
+            self.parse_greedy(&ident)?
         } else {
-            VariableKind::Singular
+            self.singular_kind(&ident)
         };
-        self.push_node(RegexNode::Variable(RegexVariable { name: ident, kind }));
+        let separator = self.parse_separator(&ident, &kind)?;
+        let kind = self.parse_iter(&ident, kind)?;
+        let kind = self.parse_buffer(&ident, kind)?;
+        let var = RegexVariable {
+            mutable: self.mutable.contains(&ident),
+            name: ident,
+            kind: kind.clone(),
+            ty: None,
+        };
+        if kind == VariableKind::Greedy && self.peek() != Token::RightBrace {
+            return Err(ParseErrorKind::InvalidGreedyVariable { name: var.name });
+        }
+        if let Token::CollectionType(ty) = self.peek() {
+            let (name, ty) = (var.name, ty.to_string());
+            return Err(match var.kind {
+                VariableKind::Singular => ParseErrorKind::SingularCollection { name, ty },
+                _ => ParseErrorKind::CollectionInPattern { name, ty },
+            });
+        }
+        // `{name:type}` and `{name:pattern}` capture exactly the input that matches the type or the pattern
+        if let Token::CaptureType(ty) = self.peek() {
+            self.consume();
+            // A single char does not need to be parsed
+            let var = match (&var.kind, ty.radix()) {
+                (VariableKind::Singular, _) if ty.name == "char" => RegexVariable {
+                    kind: VariableKind::Char,
+                    ..var
+                },
+                (VariableKind::Singular, _) if ty.name == "quoted" => RegexVariable {
+                    kind: VariableKind::Quoted,
+                    ..var
+                },
+                (VariableKind::Singular, _) if ty.name == "csv" => RegexVariable {
+                    kind: VariableKind::Csv,
+                    ..var
+                },
+                (VariableKind::Multiple { min }, _) if ty.name == "csv" && separator.is_none() => {
+                    RegexVariable {
+                        kind: VariableKind::CsvRecord { min: *min },
+                        ..var
+                    }
+                }
+                // Backreferences only compare the captured text
+                (VariableKind::Backreference { .. }, None) => var,
+                (_, None) if ty.name == "quoted" => {
+                    return Err(ParseErrorKind::InvalidQuotedVariable { name: var.name })
+                }
+                (_, None) if ty.name == "csv" => {
+                    return Err(ParseErrorKind::InvalidCsvVariable { name: var.name })
+                }
+                (VariableKind::Singular, Some(radix)) => RegexVariable {
+                    kind: VariableKind::Radix { radix },
+                    ..var
+                },
+                (_, Some(_)) => {
+                    return Err(ParseErrorKind::InvalidRadixVariable { name: var.name })
+                }
+                (VariableKind::Singular, None) => RegexVariable {
+                    ty: Some(ty.name),
+                    ..var
+                },
+                _ => var,
+            };
+            // A record is captured as a whole, so that empty fields are kept
+            let regex = match var.kind {
+                VariableKind::CsvRecord { .. } => {
+                    Regex::from_str(&format!("({0})(,({0}))*", ty.pattern))
+                }
+                _ => Regex::from_str(ty.pattern),
+            }
+            .expect("Type patterns should be valid");
+            let child = self.insert_nodes(&regex.arena, regex.root);
+            self.push_node(RegexNode::Capture(var, child));
+        } else if let Token::LengthBound { .. } = self.peek() {
+            let child = self.length_bounded()?;
+            self.push_node(RegexNode::Capture(self.trimmed(var), child));
+        } else if let Token::TimeFormat(format) = self.peek() {
+            self.consume();
+            let (pattern, ty) = time_format::expand(&format)
+                .map_err(|specifier| ParseErrorKind::UnknownTimeFormat { specifier })?;
+            if var.kind != VariableKind::Singular {
+                return Err(ParseErrorKind::InvalidTimeVariable { name: var.name });
+            }
+            let regex = Regex::from_str(&pattern).expect("Time format patterns should be valid");
+            let child = self.insert_nodes(&regex.arena, regex.root);
+            let var = RegexVariable {
+                kind: VariableKind::Time { format, ty },
+                ..var
+            };
+            self.push_node(RegexNode::Capture(var, child));
+        } else if pattern_follows || self.next_if_eq(Token::Char(':')) {
+            let mapped_branches = self.mapped_branches.len();
+            self.parse_regex()?;
+            let child = self.pop_single();
+            let var = if self.mapped_branches.len() > mapped_branches {
+                let branches = self.mapped_branches.split_off(mapped_branches);
+                self.mapped_variable(var, child, branches)?
+            } else {
+                self.trimmed(var)
+            };
+            self.push_node(RegexNode::Capture(var, child));
+        } else {
+            let newline = self.flags.contains(Flag::DotAll);
+            let pattern = match var.kind {
+                VariableKind::Greedy => RegexPattern::AnyChar { newline },
+                _ => RegexPattern::AnyCharLazy { newline },
+            };
+            self.push_node(RegexNode::Variable(self.trimmed(var), pattern));
+        }
+        // An empty match is not captured, so the range of the variable stays empty
+        if kind == VariableKind::Empty {
+            let child = self.pop_single();
+            self.push_node(RegexNode::ZeroOrOne(child));
+        }
+        if let Some(separator) = separator {
+            self.separate_matches(&separator, kind);
+        }
         self.expect(Token::RightBrace)?;
         Ok(())
     }
 
-    fn parse_ident(&mut self) -> Result<String> {
-        let mut ident = String::new();
-        while let Token::Char(char) = self.peek() {
+    /// Parses the bounds of a length-bounded variable like `{code:3..=6}` into a counted repetition of any char.
+    /// Like a plain variable, the repetition is lazy, so it only spans more than `min` chars if it has to.
+    fn length_bounded(&mut self) -> Result<RegexNodeIndex> {
+        let token = self.consume();
+        let Token::LengthBound { min, max } = token else {
+            unreachable!("Only called for length bounds");
+        };
+        if let Some(count) = max.filter(|max| *max > MAX_REPETITIONS) {
+            return Err(ParseErrorKind::RepetitionTooLarge { count });
+        }
+        if min > MAX_REPETITIONS {
+            return Err(ParseErrorKind::RepetitionTooLarge { count: min });
+        }
+        if min == 0 || max.is_some_and(|max| max < min) {
+            return Err(ParseErrorKind::InvalidLengthBound { token });
+        }
+
+        let newline = self.flags.contains(Flag::DotAll);
+        let any = self
+            .nodes
+            .add(RegexNode::Literal(RegexPattern::AnyCharLazy { newline }));
+        Ok(self.repeat(any, min, max))
+    }
+
+    /// Applies the `t` flag, which trims the text of single variables before it is parsed.
+    /// This only adds a `trim` to the pipeline of the variable, so it matches the same text as before.
+    fn trimmed(&self, var: RegexVariable) -> RegexVariable {
+        if !self.flags.contains(Flag::Trim) {
+            return var;
+        }
+        let kind = match var.kind {
+            VariableKind::Singular => VariableKind::Converted {
+                transforms: vec![Transform::Trim],
+                function: None,
+            },
+            VariableKind::Converted {
+                transforms,
+                function,
+            } if !transforms.contains(&Transform::Trim) => VariableKind::Converted {
+                transforms: std::iter::once(Transform::Trim).chain(transforms).collect(),
+                function,
+            },
+            kind => kind,
+        };
+        RegexVariable { kind, ..var }
+    }
+
+    /// Parses the `!` of a greedy variable like `{path!}`
+    fn parse_greedy(&mut self, name: &str) -> Result<VariableKind> {
+        self.consume();
+        if self.singular_kind(name) != VariableKind::Singular {
+            return Err(ParseErrorKind::InvalidGreedyVariable {
+                name: name.to_string(),
+            });
+        }
+        if let Some(first) = self.greedy.replace(name.to_string()) {
+            return Err(ParseErrorKind::MultipleGreedyVariables {
+                first,
+                second: name.to_string(),
+            });
+        }
+        Ok(VariableKind::Greedy)
+    }
+
+    /// Parses a runtime value that the input has to equal, like `{=prefix}`.
+    /// It is matched like a singular variable, the generated code compares the match to the value.
+    fn parse_expected_value(&mut self, value: &str) -> Result<()> {
+        self.consume();
+        let value = value.trim();
+        if syn::parse_str::<syn::Expr>(value).is_err() {
+            return Err(ParseErrorKind::InvalidExpectedValue {
+                value: value.to_string(),
+            });
+        }
+        let name = format!("={value}");
+        let occurrence = *self
+            .occurrences
+            .entry(name.clone())
+            .and_modify(|occurrence| *occurrence += 1)
+            .or_default();
+        let newline = self.flags.contains(Flag::DotAll);
+        self.push_node(RegexNode::Variable(
+            RegexVariable {
+                name,
+                kind: VariableKind::Expected {
+                    value: value.to_string(),
+                    occurrence,
+                },
+                mutable: false,
+                ty: None,
+            },
+            RegexPattern::AnyCharLazy { newline },
+        ));
+        self.expect(Token::RightBrace)
+    }
+
+    /// Parses the optional separator of a repeated variable like `{items* sep=", "}`
+    fn parse_separator(&mut self, name: &str, kind: &VariableKind) -> Result<Option<String>> {
+        self.skip_spaces();
+        let Token::Separator(separator) = self.peek() else {
+            return Ok(None);
+        };
+        self.consume();
+        self.skip_spaces();
+        if !matches!(
+            kind,
+            VariableKind::Multiple { .. } | VariableKind::Array { .. }
+        ) {
+            return Err(ParseErrorKind::InvalidSeparator {
+                name: name.to_string(),
+            });
+        }
+        Ok(Some(separator))
+    }
+
+    /// Parses the optional `: iter` of a repeated variable like `{items* : iter}`, which binds an iterator instead of a `Vec`
+    fn parse_iter(&mut self, name: &str, kind: VariableKind) -> Result<VariableKind> {
+        if self.peek() != Token::Iter {
+            return Ok(kind);
+        }
+        self.consume();
+        match kind {
+            VariableKind::Multiple { min } => Ok(VariableKind::Iter { min }),
+            _ => Err(ParseErrorKind::InvalidIter {
+                name: name.to_string(),
+            }),
+        }
+    }
+
+    /// Parses the optional `=> buffer` of a repeated variable like `{len* => buf}`, which writes the matches into `buf`
+    fn parse_buffer(&mut self, name: &str, kind: VariableKind) -> Result<VariableKind> {
+        let Token::MappedValue(buffer) = self.peek() else {
+            return Ok(kind);
+        };
+        self.consume();
+        let buffer = buffer.trim();
+        match kind {
+            VariableKind::Multiple { min } if syn::parse_str::<syn::Expr>(buffer).is_ok() => {
+                Ok(VariableKind::Buffer {
+                    buffer: buffer.to_string(),
+                    min,
+                })
+            }
+            _ => Err(ParseErrorKind::InvalidBuffer {
+                name: name.to_string(),
+                buffer: buffer.to_string(),
+            }),
+        }
+    }
+
+    /// Replaces the variable node that was pushed last with a list of its matches with `separator` between them.
+    /// There is no separator after the last match, so `{items* sep=","}` is equivalent to `({items*}(,{items*})*)?`
+    /// and `{rgb;3 sep=","}` is equivalent to `{rgb;3}(,{rgb;3})#{2}`.
+    fn separate_matches(&mut self, separator: &str, kind: VariableKind) {
+        let item = self
+            .stack
+            .last_mut()
+            .expect("Stack not empty")
+            .pop()
+            .expect("The variable should have been pushed");
+        let mut separated = Vec::new();
+        for char in separator.chars() {
+            let nodes = self.add_literals(&[RegexPattern::Char(char)]);
+            separated.push(match nodes.as_slice() {
+                [single] => *single,
+                _ => self.nodes.add(RegexNode::Or(nodes)),
+            });
+        }
+        separated.push(item);
+        let separated = self.nodes.add(RegexNode::And(separated));
+        match kind {
+            VariableKind::Array { len } => {
+                let mut nodes = vec![item];
+                nodes.extend((1..len).map(|_| separated));
+                self.push_node(RegexNode::And(nodes));
+            }
+            VariableKind::Multiple { min }
+            | VariableKind::Iter { min }
+            | VariableKind::Buffer { min, .. } => {
+                let rest = self.nodes.add(RegexNode::Many(separated));
+                if min == 0 {
+                    let list = self.nodes.add(RegexNode::And(vec![item, rest]));
+                    self.push_node(RegexNode::ZeroOrOne(list));
+                } else {
+                    self.push_node(RegexNode::And(vec![item, rest]));
+                }
+            }
+            _ => unreachable!("Only repeated variables have a separator"),
+        }
+    }
+
+    /// Parses the rest of a discarded variable like `{_}` or `{_:\d+}`, which matches like a variable but is not captured
+    fn parse_discard(&mut self) -> Result<()> {
+        if let Token::CaptureType(ty) = self.peek() {
+            self.consume();
+            let regex = Regex::from_str(ty.pattern).expect("Type patterns should be valid");
+            let node = self.insert_nodes(&regex.arena, regex.root);
+            self.push_node_idx(node);
+        } else if let Token::LengthBound { .. } = self.peek() {
+            let node = self.length_bounded()?;
+            self.push_node_idx(node);
+        } else if self.peek() == Token::Char(':') {
+            self.consume();
+            self.parse_regex()?;
+        } else {
+            let newline = self.flags.contains(Flag::DotAll);
+            let any = self
+                .nodes
+                .add(RegexNode::Literal(RegexPattern::AnyCharLazy { newline }));
+            self.push_node(RegexNode::OneOrMore(any));
+        }
+        self.expect(Token::RightBrace)
+    }
+
+    /// Turns a capture whose branches are mapped to values into a [`VariableKind::Mapped`] variable.
+    /// Every branch of the pattern has to be a literal text with a value.
+    /// Alternatively, the whole alternation is mapped to an enum, like in `{dir:(north|south)=>Direction}`,
+    /// and every branch becomes the variant with the UpperCamelCase name of its text: `Direction::North`.
+    fn mapped_variable(
+        &self,
+        var: RegexVariable,
+        child: RegexNodeIndex,
+        branches: Vec<(RegexNodeIndex, String)>,
+    ) -> Result<RegexVariable> {
+        let invalid = || ParseErrorKind::InvalidMapping {
+            name: var.name.clone(),
+        };
+        let nodes = match &self.nodes[child] {
+            RegexNode::Or(nodes) => nodes.clone(),
+            _ => vec![child],
+        };
+        let branches = match branches.as_slice() {
+            [(branch, ty)] if *branch == child && nodes.len() > 1 => nodes
+                .iter()
+                .map(|node| {
+                    let text = self.literal_text(*node).ok_or_else(invalid)?;
+                    let variant = format!("{ty}::{}", upper_camel_case(&text));
+                    Ok((*node, variant))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => branches,
+        };
+        if var.kind != VariableKind::Singular
+            || nodes.len() != branches.len()
+            || nodes
+                .iter()
+                .zip(&branches)
+                .any(|(node, (branch, _))| node != branch)
+        {
+            return Err(invalid());
+        }
+        let mapping = branches
+            .into_iter()
+            .map(|(branch, value)| {
+                let text = self.literal_text(branch).ok_or_else(invalid)?;
+                if syn::parse_str::<syn::Expr>(&value).is_err() {
+                    return Err(invalid());
+                }
+                Ok((text, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // The first branch with the matched text is chosen, so a later branch with the same text is never used
+        for (index, (text, value)) in mapping.iter().enumerate() {
+            if let Some((_, shadowing)) = mapping[..index].iter().find(|(other, _)| other == text) {
+                return Err(ParseErrorKind::UnreachableBranch {
+                    name: var.name,
+                    branch: format!("{text}=>{value}"),
+                    shadowing: format!("{text}=>{shadowing}"),
+                });
+            }
+        }
+        Ok(RegexVariable {
+            kind: VariableKind::Mapped { mapping },
+            ..var
+        })
+    }
+
+    /// Returns the text that a node matches, if it only consists of literal characters
+    fn literal_text(&self, node_idx: RegexNodeIndex) -> Option<String> {
+        match &self.nodes[node_idx] {
+            RegexNode::Literal(RegexPattern::Char(char)) => Some(char.to_string()),
+            RegexNode::And(nodes) => nodes.iter().map(|node| self.literal_text(*node)).collect(),
+            _ => None,
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<String> {
+        let mut ident = String::new();
+        while let Token::Char(char) = self.peek() {
+            if matches!(char, ':' | '@' | ';' | '!') || char.is_whitespace() {
+                break;
+            }
             ident.push(char);
             self.consume();
         }
         if ident.is_empty() {
-            return Err(ParseError::ExpectedIdent { got: self.peek() });
+            return Err(ParseErrorKind::ExpectedIdent { got: self.peek() });
         }
         Ok(ident)
     }
+
+    /// Parses the name of a variable, which is an identifier or a place expression like `self.x`, `cells[i]` or `*slot`.
+    /// The tokenizer splits off dots, brackets and parentheses, so they are joined back together here.
+    fn parse_place(&mut self) -> Result<String> {
+        let mut place = String::new();
+        while self.peek() == Token::Postfix(PostfixToken::Star) {
+            self.consume();
+            place.push('*');
+        }
+        place.push_str(&self.parse_ident()?);
+        if place == "mut" && self.peek() == Token::Char(' ') {
+            return self.parse_mutable();
+        }
+        loop {
+            match self.peek() {
+                Token::Dot => {
+                    self.consume();
+                    place.push('.');
+                    place.push_str(&self.parse_ident()?);
+                }
+                Token::LeftBracket | Token::LeftParenthesis => self.parse_balanced(&mut place)?,
+                _ => break,
+            }
+        }
+        if !is_place_name(&place) {
+            return validate_ident(place);
+        }
+        if !syn::parse_str::<syn::Expr>(&place).is_ok_and(|expr| is_place_expression(&expr)) {
+            return Err(ParseErrorKind::InvalidPlace { place });
+        }
+        Ok(place)
+    }
+
+    /// Parses the name of a mutable binding like `{mut count}` after the `mut`.
+    /// The name is remembered, so that the variable and its later occurrences are marked as mutable.
+    fn parse_mutable(&mut self) -> Result<String> {
+        self.skip_spaces();
+        let name = validate_ident(self.parse_ident()?)?;
+        self.mutable.insert(name.clone());
+        Ok(name)
+    }
+
+    /// Appends the text of an index like `[i + 1]` or of the arguments of a method call like `(i)` to `place`.
+    /// Inside of brackets, the tokenizer returns parentheses and nested brackets as chars.
+    fn parse_balanced(&mut self, place: &mut String) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            let token = self.consume();
+            match token {
+                Token::Eof | Token::RightBrace => {
+                    return Err(ParseErrorKind::UnbalancedPlace {
+                        place: place.clone(),
+                    })
+                }
+                Token::LeftBracket
+                | Token::LeftParenthesis
+                | Token::Char('[')
+                | Token::Char('(') => depth += 1,
+                Token::RightBracket | Token::RightParenthesis | Token::Char(')') => depth -= 1,
+                _ => {}
+            }
+            place.push_str(&token.to_string());
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns [`VariableKind::Singular`] for the first occurrence of a variable and a backreference for every later occurrence
+    fn singular_kind(&mut self, name: &str) -> VariableKind {
+        match self.occurrences.get_mut(name) {
+            Some(occurrence) => {
+                *occurrence += 1;
+                VariableKind::Backreference {
+                    occurrence: *occurrence,
+                }
+            }
+            None => {
+                self.occurrences.insert(name.to_string(), 0);
+                VariableKind::Singular
+            }
+        }
+    }
+
+    /// Parses the optional `n,` part of a repeated variable like `{vals*2,}`
+    fn parse_minimum_count(&mut self) -> Result<u32> {
+        let mut digits = String::new();
+        while let Token::Char(digit @ '0'..='9') = self.peek() {
+            digits.push(digit);
+            self.consume();
+        }
+        if digits.is_empty() {
+            return Ok(0);
+        }
+        let min = digits
+            .parse()
+            .map_err(|_| ParseErrorKind::InvalidMinimumCount { count: digits })?;
+        self.expect(Token::Char(','))?;
+        Ok(min)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.next_if_eq(Token::Char(' ')) {}
+    }
+
+    /// Parses the pipeline of a variable like `{name | trim | lowercase}` or `{ts|parse_timestamp}`.
+    /// Returns the transformations, the function and whether a pattern follows, like in [`Self::parse_function`].
+    fn parse_pipeline(&mut self) -> Result<(Vec<Transform>, Option<String>, bool)> {
+        let mut transforms = Vec::new();
+        let mut function: Option<String> = None;
+        let mut pattern_follows = false;
+        while self.next_if_eq(Token::Pipe) {
+            if let Some(function) = function {
+                return Err(ParseErrorKind::FunctionNotLast { function });
+            }
+            self.skip_spaces();
+            let stage;
+            (stage, pattern_follows) = self.parse_function()?;
+            self.skip_spaces();
+            match Transform::from_name(&stage) {
+                Some(transform) => transforms.push(transform),
+                None => function = Some(stage),
+            }
+            if pattern_follows {
+                break;
+            }
+        }
+        Ok((transforms, function, pattern_follows))
+    }
+
+    /// Parses the path of the function in a variable like `{ts|parse_timestamp}`.
+    /// Also returns whether a pattern follows, in which case its `:` was already consumed: `{ts|parse_timestamp:\d+}`.
+    fn parse_function(&mut self) -> Result<(String, bool)> {
+        let mut function = String::new();
+        let mut pattern_follows = false;
+        loop {
+            match self.peek() {
+                Token::Char(':') => {
+                    self.consume();
+                    if !self.next_if_eq(Token::Char(':')) {
+                        pattern_follows = true;
+                        break;
+                    }
+                    function.push_str("::");
+                    continue;
+                }
+                Token::Char(' ') => break,
+                Token::Char(char) => function.push(char),
+                // The last segment of a path like `my::u8` is tokenized as a capture type
+                Token::CaptureType(ty) if function.ends_with("::") => function.push_str(ty.name),
+                _ => break,
+            }
+            self.consume();
+        }
+        if syn::parse_str::<syn::ExprPath>(&function).is_err() {
+            return Err(ParseErrorKind::InvalidFunction { function });
+        }
+        Ok((function, pattern_follows))
+    }
+
+    /// Parses the `n` part of an array variable like `{rgb;3}`
+    fn parse_array_length(&mut self) -> Result<u32> {
+        let mut digits = String::new();
+        while let Token::Char(digit @ '0'..='9') = self.peek() {
+            digits.push(digit);
+            self.consume();
+        }
+        digits
+            .parse()
+            .map_err(|_| ParseErrorKind::InvalidArrayLength { len: digits })
+    }
+
+    /// Parses the `name>` part of a named capture group
+    fn parse_group_name(&mut self) -> Result<String> {
+        let mut name = String::new();
+        while let Token::Char(char) = self.peek() {
+            if char == '>' {
+                break;
+            }
+            name.push(char);
+            self.consume();
+        }
+        if name.is_empty() {
+            return Err(ParseErrorKind::ExpectedIdent { got: self.peek() });
+        }
+        self.expect(Token::Char('>'))?;
+        Ok(name)
+    }
 }
 
 #[cfg(test)]
@@ -350,6 +2177,251 @@ mod tests {
         insta::assert_debug_snapshot!(parse("({a*},)*"));
     }
 
+    #[test]
+    fn test_variable_span() {
+        insta::assert_debug_snapshot!(parse(r"{key@key_span}={value@value_span:\d+}"));
+        insta::assert_debug_snapshot!(parse("{a} {a@span}"));
+        insta::assert_debug_snapshot!(parse("{a@}"));
+    }
+
+    #[test]
+    fn test_reference() {
+        crate::classes::define("test_hex".to_string(), "[0-9a-f]".to_string());
+        crate::classes::define("test_variable".to_string(), "{a}".to_string());
+        insta::assert_debug_snapshot!(parse("\\k{test_hex}+"));
+        insta::assert_debug_snapshot!(parse("\\k{test_unknown}"));
+        insta::assert_debug_snapshot!(parse("\\k{test_variable}"));
+    }
+
+    #[test]
+    fn test_variable_pattern() {
+        insta::assert_debug_snapshot!(parse("{a:\\d+}px"));
+        insta::assert_debug_snapshot!(parse("({a*:[ab]},)*"));
+        insta::assert_debug_snapshot!(parse("{id:[A-Z]{3}\\d+}"));
+        insta::assert_debug_snapshot!(parse("{a:b{2,}}}}"));
+    }
+
+    #[test]
+    fn test_capture_type() {
+        insta::assert_debug_snapshot!(parse("{a:u8}{b:bool}"));
+        insta::assert_debug_snapshot!(parse("{a:u33}"));
+        insta::assert_debug_snapshot!(parse("{a:char}{b?:char}"));
+        insta::assert_debug_snapshot!(parse("{a:x}{b:b}{c:o}"));
+        insta::assert_debug_snapshot!(parse("{a*:x}"));
+    }
+
+    #[test]
+    fn test_quoted_variable() {
+        insta::assert_debug_snapshot!(parse("{s:quoted}"));
+        insta::assert_debug_snapshot!(parse("{s*:quoted}"));
+    }
+
+    #[test]
+    fn test_greedy_variable() {
+        insta::assert_debug_snapshot!(parse("{path!} {line}"));
+        insta::assert_debug_snapshot!(parse("{a!} {b!}"));
+        insta::assert_debug_snapshot!(parse("{a!:u32}"));
+        insta::assert_debug_snapshot!(parse("{a} {a!}"));
+    }
+
+    #[test]
+    fn test_empty_variable() {
+        insta::assert_debug_snapshot!(parse("{a},{b??}"));
+        insta::assert_debug_snapshot!(parse("{a} {a??}"));
+    }
+
+    #[test]
+    fn test_length_bound() {
+        insta::assert_debug_snapshot!(parse("{a:2..=3}"));
+        insta::assert_debug_snapshot!(parse("{a:2..4}{b}"));
+        insta::assert_debug_snapshot!(parse("{a*:2..}"));
+        insta::assert_debug_snapshot!(parse("{_:1..=2}"));
+        insta::assert_debug_snapshot!(parse("{a:0..=2}"));
+        insta::assert_debug_snapshot!(parse("{a:3..3}"));
+        insta::assert_debug_snapshot!(parse("{a:3..=1001}"));
+    }
+
+    #[test]
+    fn test_csv_variable() {
+        insta::assert_debug_snapshot!(parse("{a:csv}"));
+        insta::assert_debug_snapshot!(parse("{b*2,:csv}"));
+        insta::assert_debug_snapshot!(parse("{a?:csv}"));
+        insta::assert_debug_snapshot!(parse(r#"{b* sep=";":csv}"#));
+    }
+
+    #[test]
+    fn test_mapped_variable() {
+        insta::assert_debug_snapshot!(parse("{on:(yes=>true|no=>false)}"));
+        insta::assert_debug_snapshot!(parse("{n:one=>Some(1)|none=>None}"));
+        insta::assert_debug_snapshot!(parse("{x:(a=>1|b)}"));
+        insta::assert_debug_snapshot!(parse("{x:[ab]=>1}"));
+        insta::assert_debug_snapshot!(parse("{x:a=>1 +}"));
+        insta::assert_debug_snapshot!(parse("{dir:(north|south_east)=>Direction}"));
+        insta::assert_debug_snapshot!(parse("{dir:(north|[sS])=>Direction}"));
+        insta::assert_debug_snapshot!(parse("{x:(on=>1|off=>0|on=>2)}"));
+        insta::assert_debug_snapshot!(parse("{dir:(north|north)=>Direction}"));
+    }
+
+    #[test]
+    fn test_backreference() {
+        insta::assert_debug_snapshot!(parse("{a} {a} {a}"));
+    }
+
+    #[test]
+    fn test_optional_variable() {
+        insta::assert_debug_snapshot!(parse("{name}( <{email?}>)?"));
+        insta::assert_debug_snapshot!(parse("{host}(:{port?=80})?"));
+        insta::assert_debug_snapshot!(parse("{a?=}"));
+    }
+
+    #[test]
+    fn test_discard() {
+        insta::assert_debug_snapshot!(parse("{_},{_},{a}"));
+        insta::assert_debug_snapshot!(parse(r"{_:\d+}{_:u8}"));
+        insta::assert_debug_snapshot!(parse("{_*}"));
+    }
+
+    #[test]
+    fn test_variable_minimum_count() {
+        insta::assert_debug_snapshot!(parse("({a*2,},)*"));
+        insta::assert_debug_snapshot!(parse("{a*99999999999,}"));
+        insta::assert_debug_snapshot!(parse("{a*2}"));
+    }
+
+    #[test]
+    fn test_converted_variable() {
+        insta::assert_debug_snapshot!(parse(r"{ts|parse_timestamp} {x|crate::hex::u8:\d+}"));
+        insta::assert_debug_snapshot!(parse("{a|}"));
+        insta::assert_debug_snapshot!(parse("{a}{a|f}"));
+        insta::assert_debug_snapshot!(parse("{name | trim | lowercase}"));
+        insta::assert_debug_snapshot!(parse("{a|uppercase|f:[A-Z]}"));
+        insta::assert_debug_snapshot!(parse("{a|f|trim}"));
+    }
+
+    #[test]
+    fn test_float_class() {
+        insta::assert_snapshot!(parse(r"{x:\f}m").unwrap());
+        insta::assert_debug_snapshot!(parse(r"[\f]"));
+    }
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn test_time_format() {
+        insta::assert_debug_snapshot!(parse("{date:%Y-%m-%d} {x}"));
+        insta::assert_debug_snapshot!(parse("{date:%Y-%q}"));
+        insta::assert_debug_snapshot!(parse("{date*:%Y}"));
+    }
+
+    #[test]
+    fn test_branch_variable() {
+        insta::assert_debug_snapshot!(parse("(cat {lives}|dog {name})"));
+        insta::assert_debug_snapshot!(parse("({a} ({b}|c)|{a})"));
+        insta::assert_debug_snapshot!(parse("({a}|b) {a}"));
+    }
+
+    #[test]
+    fn test_exclusive_branch_variable() {
+        insta::assert_debug_snapshot!(parse("({n} items|{n} item) {n}"));
+        insta::assert_debug_snapshot!(parse("({n} a|({n} b|{n} c))"));
+    }
+
+    #[test]
+    fn test_separator() {
+        insta::assert_debug_snapshot!(parse(r#"\[{items* sep=", "}\]"#));
+        insta::assert_debug_snapshot!(parse(r#"{rgb;3 sep=","}"#));
+        insta::assert_debug_snapshot!(parse(r#"{words*1, sep="\"" :\w+}"#));
+        insta::assert_debug_snapshot!(parse(r#"{item sep=","}"#));
+        insta::assert_debug_snapshot!(parse(r#"{items* sep=","#));
+    }
+
+    #[test]
+    fn test_collection_type() {
+        insta::assert_debug_snapshot!(parse("{xs: Vec<u32>}"));
+        insta::assert_debug_snapshot!(parse("{xs*:std::collections::HashSet<String>}"));
+        insta::assert_debug_snapshot!(parse("{x:Vec}"));
+    }
+
+    #[test]
+    fn test_zipped_list() {
+        insta::assert_debug_snapshot!(parse("({x},{y:u32};)*=pairs"));
+        insta::assert_debug_snapshot!(parse("(a)*=b"));
+        insta::assert_debug_snapshot!(parse("({x}(,{y})?;)*=pairs"));
+        insta::assert_debug_snapshot!(parse("({x*};)*=lists"));
+        insta::assert_debug_snapshot!(parse("({x};)*=xs {x}"));
+    }
+
+    #[test]
+    fn test_nested_variable() {
+        insta::assert_debug_snapshot!(parse(r"(({n**},)*\n)+"));
+        insta::assert_debug_snapshot!(parse("({n**},)*"));
+    }
+
+    #[test]
+    fn test_counter() {
+        insta::assert_debug_snapshot!(parse(r"(ab{#n})+c"));
+        insta::assert_debug_snapshot!(parse("{#n}a"));
+        insta::assert_debug_snapshot!(parse("(a|{#n}b)*"));
+    }
+
+    #[test]
+    fn test_iter_variable() {
+        insta::assert_debug_snapshot!(parse("{xs* : iter}"));
+        insta::assert_debug_snapshot!(parse("{x:iter}"));
+        insta::assert_debug_snapshot!(parse("{x? : iter}"));
+    }
+
+    #[test]
+    fn test_buffer_variable() {
+        insta::assert_debug_snapshot!(parse("{len* => buf}"));
+        insta::assert_debug_snapshot!(parse("{len*2, => self.buf}"));
+        insta::assert_debug_snapshot!(parse("{x => buf}"));
+        insta::assert_debug_snapshot!(parse("{xs* => buf[}"));
+    }
+
+    #[test]
+    fn test_zipped_map() {
+        insta::assert_debug_snapshot!(parse("({k}={v};)*=>map"));
+        insta::assert_debug_snapshot!(parse("({k};)*=>map"));
+    }
+
+    #[test]
+    fn test_raw_ident_variable() {
+        insta::assert_debug_snapshot!(parse("{r#type}@{r#match.r#in}"));
+        insta::assert_debug_snapshot!(parse("{type}"));
+        insta::assert_debug_snapshot!(parse("{value@r#loop}"));
+        insta::assert_debug_snapshot!(parse("{value@1span}"));
+    }
+
+    #[test]
+    fn test_expected_value() {
+        insta::assert_debug_snapshot!(parse("{=prefix}: {msg}"));
+        insta::assert_debug_snapshot!(parse("{= names[0] }/{=names[0]}"));
+        insta::assert_debug_snapshot!(parse("{=1 +}"));
+    }
+
+    #[test]
+    fn test_mutable_variable() {
+        insta::assert_debug_snapshot!(parse("{mut count:u32} {count}"));
+        insta::assert_debug_snapshot!(parse("{mut r#type}"));
+        insta::assert_debug_snapshot!(parse("{mut self.x}"));
+    }
+
+    #[test]
+    fn test_place_variable() {
+        insta::assert_debug_snapshot!(parse("{self.x},{points[i + 1].y*}"));
+        insta::assert_debug_snapshot!(parse("{grid[0][1]}"));
+        insta::assert_debug_snapshot!(parse("{point.}"));
+        insta::assert_debug_snapshot!(parse("{values[0}"));
+        insta::assert_debug_snapshot!(parse("{*slot} {cells[idx(x, y)]} {grid.row_mut(y)[x]}"));
+        insta::assert_debug_snapshot!(parse("{1.x}"));
+    }
+
+    #[test]
+    fn test_array_variable() {
+        insta::assert_debug_snapshot!(parse("({rgb;3},)#{3}"));
+        insta::assert_debug_snapshot!(parse("{rgb;}"));
+    }
+
     #[test]
     fn test_invalid_variable() {
         insta::assert_debug_snapshot!(parse("{a+test}"));
@@ -368,6 +2440,33 @@ mod tests {
         insta::assert_debug_snapshot!(parse("a**"));
     }
 
+    #[test]
+    fn test_error_range() {
+        insta::assert_debug_snapshot!(parse("A-"));
+        insta::assert_debug_snapshot!(parse("ä(b|c"));
+        insta::assert_debug_snapshot!(parse("(?x) a # comment\n  \\q"));
+        insta::assert_debug_snapshot!(parse("(a|b)-"));
+    }
+
+    #[test]
+    fn test_multiple_errors() {
+        fn errors(source: &str) -> Vec<String> {
+            Regex::parse(source)
+                .err()
+                .unwrap_or_default()
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        }
+
+        insta::assert_debug_snapshot!(errors("a)b{}c(d**)e"));
+        insta::assert_debug_snapshot!(errors("[\\f]x{a b}y+*"));
+        insta::assert_debug_snapshot!(errors("\\q(?z)"));
+        // The rest of a broken group or variable does not cause more errors
+        insta::assert_debug_snapshot!(errors("(a**)+b"));
+        insta::assert_debug_snapshot!(errors("{a b c}d"));
+    }
+
     #[test]
     fn test_or() {
         insta::assert_debug_snapshot!(parse("a|b"));
@@ -401,6 +2500,129 @@ mod tests {
         insta::assert_debug_snapshot!(parse("[,.{}()]"));
     }
 
+    #[test]
+    fn test_escape() {
+        insta::assert_debug_snapshot!(parse("a\\nb\\t\\r\\0"));
+        insta::assert_debug_snapshot!(parse("\\{\\.\\\\"));
+        insta::assert_debug_snapshot!(parse("\\q"));
+        insta::assert_debug_snapshot!(parse("a\\"));
+    }
+
+    #[test]
+    fn test_quote() {
+        insta::assert_debug_snapshot!(parse("\\Q{a}.(\\E{b}"));
+        insta::assert_debug_snapshot!(parse("(?x)\\Qa b\\E c"));
+        insta::assert_debug_snapshot!(parse("[\\Q]-\\E]"));
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        insta::assert_debug_snapshot!(parse("{{{a}}}"));
+        insta::assert_debug_snapshot!(parse("}}{{"));
+    }
+
+    #[test]
+    fn test_group_escape() {
+        insta::assert_debug_snapshot!(parse("[\\]\\-a-c]"));
+        insta::assert_debug_snapshot!(parse("[-a]"));
+        insta::assert_debug_snapshot!(parse("[a-]"));
+        insta::assert_debug_snapshot!(parse("[#{}]"));
+    }
+
+    #[test]
+    fn test_posix_class() {
+        insta::assert_debug_snapshot!(parse("[[:alpha:]]"));
+        insta::assert_debug_snapshot!(parse("[[:xdigit:]_]+"));
+        insta::assert_debug_snapshot!(parse("[[:foo:]]"));
+        insta::assert_debug_snapshot!(parse("[[a]"));
+    }
+
+    #[test]
+    fn test_unicode_class() {
+        insta::assert_debug_snapshot!(parse("\\p{Braille}"));
+        insta::assert_debug_snapshot!(parse("[a\\p{Braille}]"));
+        insta::assert_debug_snapshot!(parse("\\P{Braille}"));
+        insta::assert_debug_snapshot!(parse("\\p{Klingon}"));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        insta::assert_debug_snapshot!(parse("(?i)a1"));
+        insta::assert_debug_snapshot!(parse("(?i)[a-f_]"));
+        insta::assert_debug_snapshot!(parse("(a(?i)b)c"));
+        insta::assert_debug_snapshot!(parse("(?i)a(?-i)b"));
+        insta::assert_debug_snapshot!(parse("(?z)a"));
+    }
+
+    #[test]
+    fn test_case_insensitive_group() {
+        insta::assert_debug_snapshot!(parse("(?i:ab)c"));
+        insta::assert_debug_snapshot!(parse("(?i:a(?-i:b))+"));
+        insta::assert_debug_snapshot!(parse("(?i:a"));
+    }
+
+    #[test]
+    fn test_verbose() {
+        insta::assert_debug_snapshot!(parse("(?x) a b # comment\n c\\ [ ]"));
+        insta::assert_debug_snapshot!(parse("(?x: a b)c d"));
+        insta::assert_debug_snapshot!(parse("(?x)a #{2} { b }"));
+    }
+
+    #[test]
+    fn test_dot_all() {
+        insta::assert_debug_snapshot!(parse("(?s).{a}"));
+    }
+
+    #[test]
+    fn test_trim_flag() {
+        insta::assert_debug_snapshot!(parse("(?t){a},{b|lowercase}"));
+        insta::assert_debug_snapshot!(parse("(?t:{a}),{b:[a-z ]+}"));
+    }
+
+    #[test]
+    fn test_assertion() {
+        insta::assert_debug_snapshot!(parse("\\bab\\B"));
+        insta::assert_debug_snapshot!(parse("\\b*"));
+    }
+
+    #[test]
+    fn test_empty_branch() {
+        insta::assert_debug_snapshot!(parse("(a|)b"));
+        insta::assert_debug_snapshot!(parse("(|a)"));
+        insta::assert_debug_snapshot!(parse("a()b"));
+    }
+
+    #[test]
+    fn test_lookahead() {
+        insta::assert_debug_snapshot!(parse("{a}(?=px|em)"));
+    }
+
+    #[test]
+    fn test_negative_lookahead() {
+        insta::assert_debug_snapshot!(parse("<(?!/){tag}>"));
+    }
+
+    #[test]
+    fn test_named_capture() {
+        insta::assert_debug_snapshot!(parse("(?P<num>\\d+)px"));
+        insta::assert_debug_snapshot!(parse("(?<x>a|b)"));
+        insta::assert_debug_snapshot!(parse("(?P<>a)"));
+        insta::assert_debug_snapshot!(parse("(?P<x)"));
+    }
+
+    #[test]
+    fn test_anchors() {
+        insta::assert_debug_snapshot!(parse("^a$"));
+        insta::assert_debug_snapshot!(parse("(?m)^a$"));
+    }
+
+    #[test]
+    fn test_group_character_class() {
+        insta::assert_debug_snapshot!(parse("[\\d_]"));
+        insta::assert_debug_snapshot!(parse("[\\s,]+"));
+        insta::assert_debug_snapshot!(parse("[\\w]"));
+    }
+
     #[test]
     fn test_dot() {
         insta::assert_debug_snapshot!(parse("a.c"));
@@ -415,4 +2637,32 @@ mod tests {
         insta::assert_debug_snapshot!(parse("\\s"));
         insta::assert_debug_snapshot!(parse("\\w"));
     }
+
+    #[test]
+    fn test_repetition() {
+        insta::assert_debug_snapshot!(parse("a#{3}"));
+        insta::assert_debug_snapshot!(parse("a#{2,4}"));
+        insta::assert_debug_snapshot!(parse("(ab)#{1,}"));
+        insta::assert_debug_snapshot!(parse("a#{0}"));
+        insta::assert_debug_snapshot!(parse("a#b#{x}"));
+        insta::assert_debug_snapshot!(parse("[ab]#{2}"));
+        insta::assert_debug_snapshot!(parse("(a#{1,2}b)#{2}"));
+    }
+
+    #[test]
+    fn test_repetition_error() {
+        insta::assert_debug_snapshot!(parse("a#{3,2}"));
+        insta::assert_debug_snapshot!(parse("a#{1001}"));
+        insta::assert_debug_snapshot!(parse("#{2}"));
+        insta::assert_debug_snapshot!(parse("a#{,3}"));
+        insta::assert_debug_snapshot!(parse("a#{4294967296}"));
+        insta::assert_debug_snapshot!(parse("a#{2,x}"));
+        insta::assert_debug_snapshot!(parse("((a#{1000})#{1000})#{100}"));
+    }
+
+    #[test]
+    fn test_repetition_expansion_size() {
+        assert!(Regex::from_str("(a#{100})#{100}").is_ok());
+        assert!(Regex::from_str("(a#{1000})*").is_ok());
+    }
 }
@@ -1,20 +1,136 @@
 use crate::arena::{Arena, ArenaIndex};
-use crate::parser::{ParseError, RegexParser};
+use crate::parser::{ParseError, ParseErrorKind, RegexParser};
+use crate::time_format::TimeType;
 use crate::tokenizer::tokenize;
+use crate::Map;
 use std::fmt::{Debug, Display, Formatter, Write};
+use std::ops::Range;
+use thiserror::Error;
 
 pub type RegexArena = Arena<RegexNode>;
 
 pub type RegexNodeIndex = ArenaIndex<RegexNode>;
 
+/// A part of a pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// The byte range in the pattern, which is used for the span of compile errors
+    pub range: Range<usize>,
+    /// The index of the first character in the pattern, which is shown in error messages
+    pub index: usize,
+}
+
+impl Position {
+    pub fn new(source: &str, range: Range<usize>) -> Self {
+        let index = source[..range.start].chars().count();
+        Self { range, index }
+    }
+}
+
+/// A value that was written in the pattern, like `a+`, `(a|b){3}` or `{x}`, for errors about a part of the pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternPart {
+    /// The text of the value in the pattern
+    pub text: String,
+    pub position: Position,
+    /// The node that the value was parsed into
+    pub node: RegexNodeIndex,
+}
+
+/// An error in a pattern, together with the position in the pattern where it was detected
+#[derive(Debug, Error)]
+#[error("{kind} (at character {} of the pattern)", .position.index + 1)]
+pub struct PatternError<K> {
+    pub kind: K,
+    pub position: Position,
+}
+
 pub struct Regex {
     pub arena: RegexArena,
     pub root: RegexNodeIndex,
+    /// The positions of the variables and lookaheads that were written in the pattern, for the errors about them
+    pub positions: Map<RegexNodeIndex, Position>,
+    /// The position of the whole pattern, for errors about nodes that were not written in it
+    pub source: Position,
+    /// The values of the pattern, including the values inside of other values
+    pub parts: Vec<PatternPart>,
+    /// The alternations that were written in the pattern with the positions of their branches,
+    /// for the check that no branch matches the same text as an earlier one
+    pub alternations: Vec<(RegexNodeIndex, Vec<Position>)>,
 }
 
 impl Regex {
     pub fn from_str(input: &str) -> Result<Self, ParseError> {
-        RegexParser::parse(tokenize(input))
+        Self::parse(input).map_err(|errors| {
+            errors
+                .into_iter()
+                .next()
+                .expect("Parsing only fails with an error")
+        })
+    }
+
+    /// Like [`Self::from_str`], but returns all errors that were found in the pattern
+    pub fn parse(input: &str) -> Result<Self, Vec<ParseError>> {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for token in tokenize(input) {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err((err, range)) => errors.push(PatternError {
+                    kind: err.into(),
+                    position: Position::new(input, range),
+                }),
+            }
+        }
+        // Without the invalid tokens, the parser would only find follow-up errors
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        RegexParser::parse(tokens.into_iter(), input)
+    }
+
+    /// The position of the first occurrence of a variable, or of the whole pattern if it does not occur
+    pub fn variable_position(&self, name: &str) -> Position {
+        self.positions
+            .iter()
+            .filter(|(node_idx, _)| match &self.arena[**node_idx] {
+                RegexNode::Variable(var, _)
+                | RegexNode::Capture(var, _)
+                | RegexNode::Counter(var) => var.name == name,
+                _ => false,
+            })
+            .map(|(_, position)| position)
+            .min_by_key(|position| position.range.start)
+            .unwrap_or(&self.source)
+            .clone()
+    }
+
+    /// The position of a node, or of the whole pattern if the node was not written in it
+    pub fn position(&self, node_idx: RegexNodeIndex) -> Position {
+        self.positions
+            .get(&node_idx)
+            .unwrap_or(&self.source)
+            .clone()
+    }
+
+    /// Parses the source of a class that is defined with `re_class!`. Classes must not contain variables.
+    pub fn class(name: &str, source: &str) -> Result<Self, ParseError> {
+        let regex = Self::from_str(source)?;
+        let has_variables = regex.arena.iter().any(|node| {
+            matches!(
+                regex.arena[node],
+                RegexNode::Variable(..) | RegexNode::Capture(..)
+            )
+        });
+        if has_variables {
+            return Err(PatternError {
+                kind: ParseErrorKind::VariableInClass {
+                    name: name.to_string(),
+                },
+                position: regex.source,
+            });
+        }
+        Ok(regex)
     }
 }
 
@@ -42,22 +158,38 @@ impl Debug for Regex {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RegexNode {
     And(Vec<RegexNodeIndex>),
     Or(Vec<RegexNodeIndex>),
     Literal(RegexPattern),
-    Variable(RegexVariable),
+    /// A variable and the pattern that every character of the variable matches
+    Variable(RegexVariable, RegexPattern),
+    /// A variable that captures exactly the input matched by the child: `(?P<name>...)`
+    Capture(RegexVariable, RegexNodeIndex),
+    /// Matches the empty string, but only if the assertion holds at the current position
+    Assertion(Assertion),
+    /// Matches the empty string and counts how often it was passed: `{#n}`.
+    /// The parser moves it to the start of its repeated group, so it counts the repetitions of the group.
+    /// The counter of a nested variable starts a new list instead, see [`VariableKind::Nested`].
+    Counter(RegexVariable),
+    /// Matches the empty string, but only if the input at the current position starts with the child: `(?=...)`
+    Lookahead(RegexNodeIndex),
+    /// Matches the empty string, but only if the input at the current position does not start with the child: `(?!...)`
+    NegativeLookahead(RegexNodeIndex),
     ZeroOrOne(RegexNodeIndex),
     Many(RegexNodeIndex),
     OneOrMore(RegexNodeIndex),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum RegexPattern {
     Char(char),
     Range(char, char),
-    AnyChar,
+    /// Matches every character. A newline is only matched if `newline` is true (e.g. because of the `(?s)` flag).
+    AnyChar {
+        newline: bool,
+    },
     /// Matches every character, except those that were explicitly specified.
     /// For example `(ABC|.)` (where `.` is [AnyChar]) matches the input `A`, because the `.`
     /// matched. If the `.` would be [AnyCharLazy], the regex would not match the input `A`, because
@@ -65,19 +197,457 @@ pub enum RegexPattern {
     ///
     /// This is used for variables: `{var}` gets transformed into `.+`, where the `.` is lazy.
     /// The reason this is done is to make it possible to match anything at all.
-    AnyCharLazy,
+    AnyCharLazy {
+        newline: bool,
+    },
+}
+
+impl RegexPattern {
+    /// Returns this pattern together with patterns that match the other case of its letters.
+    /// Single characters are folded using unicode case mapping, ranges only fold ascii letters.
+    pub fn case_folded(self) -> Vec<RegexPattern> {
+        fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+            let char = chars.next()?;
+            chars.next().is_none().then_some(char)
+        }
+
+        let mut patterns = vec![self];
+        match self {
+            RegexPattern::Char(char) => {
+                let cases = [single(char.to_lowercase()), single(char.to_uppercase())];
+                for other in cases.into_iter().flatten() {
+                    if other != char && !patterns.contains(&RegexPattern::Char(other)) {
+                        patterns.push(RegexPattern::Char(other));
+                    }
+                }
+            }
+            RegexPattern::Range(start, end) => {
+                for (from, to) in [('a', 'A'), ('A', 'a')] {
+                    let from_end = (from as u8 + 25) as char;
+                    let (overlap_start, overlap_end) = (start.max(from), end.min(from_end));
+                    if overlap_start <= overlap_end {
+                        let shift = |char: char| (char as u8 - from as u8 + to as u8) as char;
+                        patterns.push(RegexPattern::Range(
+                            shift(overlap_start),
+                            shift(overlap_end),
+                        ));
+                    }
+                }
+            }
+            RegexPattern::AnyChar { .. } | RegexPattern::AnyCharLazy { .. } => {}
+        }
+        patterns
+    }
+}
+
+/// A zero-width assertion about the characters around the current position
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Assertion {
+    /// `\b`
+    WordBoundary,
+    /// `\B`
+    NotWordBoundary,
+    /// `^`
+    StartText,
+    /// `$`
+    EndText,
+    /// `^` in multiline mode
+    StartLine,
+    /// `$` in multiline mode
+    EndLine,
+}
+
+impl Assertion {
+    pub fn holds(self, previous: CharKind, next: CharKind) -> bool {
+        let is_boundary = (previous == CharKind::Word) != (next == CharKind::Word);
+        match self {
+            Assertion::WordBoundary => is_boundary,
+            Assertion::NotWordBoundary => !is_boundary,
+            Assertion::StartText => previous == CharKind::Boundary,
+            Assertion::EndText => next == CharKind::Boundary,
+            Assertion::StartLine => matches!(previous, CharKind::Boundary | CharKind::Newline),
+            Assertion::EndLine => matches!(next, CharKind::Boundary | CharKind::Newline),
+        }
+    }
+}
+
+/// Classifies the characters before and after a position, which is all that assertions depend on
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum CharKind {
+    /// The start or the end of the input
+    Boundary,
+    Newline,
+    /// An ascii word character, like `\w`
+    Word,
+    Other,
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assertion::WordBoundary => f.write_str("\\b"),
+            Assertion::NotWordBoundary => f.write_str("\\B"),
+            Assertion::StartText => f.write_char('^'),
+            Assertion::EndText => f.write_char('$'),
+            Assertion::StartLine => f.write_str("(?m:^)"),
+            Assertion::EndLine => f.write_str("(?m:$)"),
+        }
+    }
+}
+
+impl CharKind {
+    pub fn of(char: char) -> Self {
+        match char {
+            '\n' => CharKind::Newline,
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => CharKind::Word,
+            _ => CharKind::Other,
+        }
+    }
+
+    /// Returns ranges that cover all characters that are not [`CharKind::Other`]
+    pub fn special_ranges() -> [CharRange; 5] {
+        [
+            CharRange::single('\n'),
+            CharRange::new('0', '9'),
+            CharRange::new('A', 'Z'),
+            CharRange::single('_'),
+            CharRange::new('a', 'z'),
+        ]
+    }
+}
+
+/// An inclusive range of characters
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct CharRange {
+    pub start: char,
+    pub end: char,
+}
+
+impl CharRange {
+    pub fn new(start: char, end: char) -> Self {
+        Self { start, end }
+    }
+
+    pub fn single(char: char) -> Self {
+        Self::new(char, char)
+    }
+
+    pub fn contains(self, char: char) -> bool {
+        self.start <= char && char <= self.end
+    }
+
+    /// The kind of the characters in this range. Only meaningful if all characters are of the same kind.
+    pub fn kind(self) -> CharKind {
+        CharKind::of(self.start)
+    }
+
+    /// Sorts disjoint `ranges` and joins the ones that follow each other without a gap, like `'a'..='f'` and `'g'..='z'`
+    pub fn merge_adjacent(ranges: &mut Vec<CharRange>) {
+        ranges.sort_unstable();
+        ranges.dedup_by(|next, previous| {
+            // The surrogates are skipped, because they are not valid characters
+            let follows = match previous.end {
+                '\u{D7FF}' => next.start == '\u{E000}',
+                end => end as u32 + 1 == next.start as u32,
+            };
+            if follows {
+                previous.end = next.end;
+            }
+            follows
+        });
+    }
+
+    /// Splits the union of `ranges` into disjoint ranges, so that every range of `ranges` consists of whole ranges
+    /// of the result. Each resulting range is returned with the values of the ranges that contain it.
+    /// The characters in `splits` always get a range of their own.
+    pub fn partition<T: Copy + Ord>(
+        ranges: &[(CharRange, T)],
+        splits: &[char],
+    ) -> Vec<(CharRange, Vec<T>)> {
+        let mut events = Vec::new();
+        for (index, (range, _)) in ranges.iter().enumerate() {
+            events.push((range.start as u32, Some(index)));
+            events.push((range.end as u32 + 1, Some(index)));
+        }
+        for split in splits {
+            events.push((*split as u32, None));
+            events.push((*split as u32 + 1, None));
+        }
+        events.sort_unstable();
+
+        let mut result = Vec::new();
+        let mut active: Vec<usize> = Vec::new();
+        let mut events = events.into_iter().peekable();
+        while let Some((point, index)) = events.next() {
+            if let Some(index) = index {
+                match active.iter().position(|other| *other == index) {
+                    Some(position) => {
+                        active.swap_remove(position);
+                    }
+                    None => active.push(index),
+                }
+            }
+            let Some((next_point, _)) = events.peek() else {
+                break;
+            };
+            if *next_point == point || active.is_empty() {
+                continue;
+            }
+
+            // Surrogates are not valid characters, so ranges can not start or end inside of them
+            let start = char::from_u32(point).unwrap_or('\u{E000}');
+            let end = char::from_u32(next_point - 1).unwrap_or('\u{D7FF}');
+            if start <= end {
+                let mut values = active
+                    .iter()
+                    .map(|index| ranges[*index].1)
+                    .collect::<Vec<_>>();
+                values.sort_unstable();
+                values.dedup();
+                result.push((CharRange::new(start, end), values));
+            }
+        }
+        result
+    }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+impl Display for CharRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            f.write_char(self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl Debug for CharRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            Debug::fmt(&self.start, f)
+        } else {
+            write!(f, "{:?}..={:?}", self.start, self.end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct RegexVariable {
     pub name: String,
     pub kind: VariableKind,
+    /// Whether the variable is declared as a mutable binding like `{mut count}`
+    pub mutable: bool,
+    /// The primitive type of a typed capture like `{count:u32}`, which annotates the binding that the macro declares
+    pub ty: Option<&'static str>,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+/// Whether a variable name is a place expression, see [`RegexVariable::is_place`]
+pub fn is_place_name(name: &str) -> bool {
+    let ident = name.strip_prefix("r#").unwrap_or(name);
+    !ident
+        .chars()
+        .all(|char| char == '_' || char.is_alphanumeric())
+}
+
+impl RegexVariable {
+    /// A name that is unique for every capture, because all occurrences of a backreference share the same name
+    pub fn key(&self) -> String {
+        match self.kind {
+            VariableKind::Backreference { occurrence }
+            | VariableKind::Expected { occurrence, .. } => {
+                format!("{}#{occurrence}", self.name)
+            }
+            _ => self.name.clone(),
+        }
+    }
+
+    /// Whether the variable is a place expression like `self.x`, `cells[i]` or `*slot` instead of an identifier
+    pub fn is_place(&self) -> bool {
+        !matches!(self.kind, VariableKind::Expected { .. }) && is_place_name(&self.name)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum VariableKind {
     Singular,
-    Multiple,
+    /// A variable that collects every match. The generated code panics if there are less than `min` matches.
+    Multiple {
+        min: u32,
+    },
+    /// A variable that collects exactly `len` matches into an array: `{rgb;3}`.
+    /// The pattern must guarantee that the variable matches exactly `len` times.
+    Array {
+        len: u32,
+    },
+    /// A variable that collects its matches into one list per repetition of an outer group: `{n**}` in `(({n**},)*\n)*`.
+    /// The parser inserts a repetition count with `row_start` set at the start of the outer group,
+    /// which starts a new list in every repetition, see [`RegexNode::Counter`].
+    Nested {
+        row_start: bool,
+    },
+    /// A multiple variable that is bound as an iterator over its parsed matches instead of a `Vec`: `{items* : iter}`.
+    /// The matches are parsed lazily, while the iterator is consumed.
+    Iter {
+        min: u32,
+    },
+    /// A multiple variable whose matches are parsed right away and written into a caller-provided buffer,
+    /// like a `&mut [T]` or an array: `{len* => buf}`. The variable is bound to the number of matches.
+    /// No spans are collected, so nothing is allocated.
+    Buffer {
+        buffer: String,
+        min: u32,
+    },
+    /// The number of repetitions of a group, as a `usize`: `{#n}` in `(\#{#n})*`.
+    /// It does not capture any text, it is incremented whenever the first character of a repetition is consumed.
+    Count,
+    /// A variable that is `None` if the part of the pattern that contains it was not matched: `{name?}`.
+    /// With a default expression like `{name?=80}`, the variable is assigned the default instead of `None`.
+    Optional {
+        default: Option<String>,
+    },
+    /// A singular variable of type `char`: `{name:char}`.
+    /// Its pattern matches exactly one character, which is bound directly instead of being parsed.
+    Char,
+    /// A singular variable that matches a double-quoted string with backslash escapes: `{name:quoted}`.
+    /// The quotes are removed and every escaped character is unescaped before the contents are parsed.
+    Quoted,
+    /// A singular variable that matches a field of a CSV record, as described in RFC 4180: `{name:csv}`.
+    /// A quoted field may contain commas, line breaks and doubled quotes. It is unquoted before it is parsed.
+    Csv,
+    /// A variable that matches a whole CSV record and binds its fields, like `{fields*:csv}`.
+    /// The record is captured as one range, which is split into the unquoted fields before they are parsed.
+    CsvRecord {
+        min: u32,
+    },
+    /// A singular integer variable in another radix, like `{addr:x}`, which is parsed with `from_str_radix`
+    Radix {
+        radix: u32,
+    },
+    /// A singular variable with a time format like `{ts:%Y-%m-%d}`, which is parsed into the `chrono` type `ty`
+    Time {
+        format: String,
+        ty: TimeType,
+    },
+    /// A singular variable whose pattern is an alternation of literal texts, which are mapped to rust expressions:
+    /// `{enabled:(on=>true|off=>false)}`.
+    Mapped {
+        mapping: Vec<(String, String)>,
+    },
+    /// A singular variable whose text is transformed before it is parsed, like `{name|trim|lowercase}`,
+    /// or that is converted by calling a function instead of `FromStr`, like `{ts|parse_timestamp}`.
+    /// The function takes the captured `&str` and returns a `Result`.
+    Converted {
+        transforms: Vec<Transform>,
+        function: Option<String>,
+    },
+    /// A singular variable whose byte range in the input is also bound, to the variable `span`: `{name@span}`.
+    Spanned {
+        span: String,
+    },
+    /// A variable in a repeated group whose captures are zipped into a list of tuples, like `x` in `({x},{y};)*=pairs`.
+    /// It matches exactly once per repetition and is element `index` of the tuples in `list`.
+    /// With `map`, the group was written like `({k}={v};)*=>map` and the key/value tuples are collected into a map.
+    Zipped {
+        list: String,
+        index: u32,
+        map: bool,
+    },
+    /// A runtime value that the input has to equal at this position, like `{=prefix}`. The name is `=` and the value.
+    /// It matches like a singular variable, and whenever it ends, the generated code compares the match to the value.
+    /// The value is a rust expression that evaluates to something like `&str` or `String`.
+    Expected {
+        value: String,
+        occurrence: u32,
+    },
+    /// A singular variable that matches as much as possible while the rest of the pattern still matches: `{path!}`.
+    /// It ends at the last position where the rest of the pattern can start, instead of the first one.
+    Greedy,
+    /// A singular variable that may also match zero characters: `{name??}`.
+    /// An empty match is bound to `Default::default()` instead of being parsed.
+    Empty,
+    /// A later occurrence of a singular variable, like the second `{word}` in `{word} and {word}`.
+    /// It is captured separately and the generated code checks that it matched the same text as the first occurrence.
+    Backreference {
+        occurrence: u32,
+    },
+}
+
+impl VariableKind {
+    /// Whether a match of the variable is assigned to it, so that a later match replaces it.
+    /// Other variables collect their matches or compare them to something else.
+    pub fn is_assigned(&self) -> bool {
+        !matches!(
+            self,
+            VariableKind::Multiple { .. }
+                | VariableKind::Iter { .. }
+                | VariableKind::Array { .. }
+                | VariableKind::Nested { .. }
+                | VariableKind::Zipped { .. }
+                | VariableKind::Buffer { .. }
+                | VariableKind::Expected { .. }
+                | VariableKind::Count
+        )
+    }
+
+    /// Whether the variable is meant to be left without a match, so that matching the empty string is no mistake.
+    /// Backreferences, expected values and counters do not capture anything on their own.
+    pub fn is_optional(&self) -> bool {
+        matches!(
+            self,
+            VariableKind::Optional { .. }
+                | VariableKind::Empty
+                | VariableKind::Count
+                | VariableKind::Backreference { .. }
+                | VariableKind::Expected { .. }
+        )
+    }
+}
+
+/// A built-in transformation of the captured text in the pipeline of a variable: `{name|trim|lowercase}`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Transform {
+    Trim,
+    TrimStart,
+    TrimEnd,
+    Lowercase,
+    Uppercase,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 5] = [
+        Transform::Trim,
+        Transform::TrimStart,
+        Transform::TrimEnd,
+        Transform::Lowercase,
+        Transform::Uppercase,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transform::Trim => "trim",
+            Transform::TrimStart => "trim_start",
+            Transform::TrimEnd => "trim_end",
+            Transform::Lowercase => "lowercase",
+            Transform::Uppercase => "uppercase",
+        }
+    }
+
+    /// The method of `str` that performs the transformation
+    pub fn method(self) -> &'static str {
+        match self {
+            Transform::Trim => "trim",
+            Transform::TrimStart => "trim_start",
+            Transform::TrimEnd => "trim_end",
+            Transform::Lowercase => "to_lowercase",
+            Transform::Uppercase => "to_uppercase",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|transform| transform.name() == name)
+    }
 }
 
 pub struct RegexDisplay<'arena> {
@@ -115,12 +685,83 @@ impl Display for RegexDisplay<'_> {
             RegexNode::Literal(pat) => match pat {
                 RegexPattern::Char(char) => f.write_char(*char)?,
                 RegexPattern::Range(start, end) => write!(f, "{}-{}", start, end)?,
-                RegexPattern::AnyChar | RegexPattern::AnyCharLazy => f.write_char('.')?,
+                RegexPattern::AnyChar { .. } | RegexPattern::AnyCharLazy { .. } => {
+                    f.write_char('.')?
+                }
             },
-            RegexNode::Variable(RegexVariable { name, kind }) => match kind {
-                VariableKind::Singular => write!(f, "{{{name}}}")?,
-                VariableKind::Multiple => write!(f, "{{{name}*}}")?,
+            RegexNode::Variable(
+                RegexVariable {
+                    name,
+                    kind,
+                    mutable,
+                    ..
+                },
+                _,
+            ) => match kind {
+                VariableKind::Singular
+                | VariableKind::Char
+                | VariableKind::Quoted
+                | VariableKind::Csv
+                | VariableKind::Radix { .. }
+                | VariableKind::Time { .. }
+                | VariableKind::Mapped { .. }
+                | VariableKind::Zipped { .. }
+                | VariableKind::Expected { .. }
+                | VariableKind::Backreference { .. } => match mutable {
+                    true => write!(f, "{{mut {name}}}")?,
+                    false => write!(f, "{{{name}}}")?,
+                },
+                VariableKind::Spanned { span } => write!(f, "{{{name}@{span}}}")?,
+                VariableKind::Greedy => write!(f, "{{{name}!}}")?,
+                VariableKind::Empty => write!(f, "{{{name}??}}")?,
+                VariableKind::Converted {
+                    transforms,
+                    function,
+                } => {
+                    write!(f, "{{{name}")?;
+                    for transform in transforms.iter() {
+                        write!(f, "|{}", transform.name())?;
+                    }
+                    if let Some(function) = function {
+                        write!(f, "|{function}")?;
+                    }
+                    f.write_char('}')?;
+                }
+                VariableKind::Optional { default: None } => write!(f, "{{{name}?}}")?,
+                VariableKind::Optional {
+                    default: Some(default),
+                } => write!(f, "{{{name}?={default}}}")?,
+                VariableKind::Multiple { min: 0 } | VariableKind::CsvRecord { min: 0 } => {
+                    write!(f, "{{{name}*}}")?
+                }
+                VariableKind::Multiple { min } | VariableKind::CsvRecord { min } => {
+                    write!(f, "{{{name}*{min},}}")?
+                }
+                VariableKind::Iter { min: 0 } => write!(f, "{{{name}* : iter}}")?,
+                VariableKind::Iter { min } => write!(f, "{{{name}*{min}, : iter}}")?,
+                VariableKind::Buffer { buffer, min: 0 } => write!(f, "{{{name}* => {buffer}}}")?,
+                VariableKind::Buffer { buffer, min } => write!(f, "{{{name}*{min}, => {buffer}}}")?,
+                VariableKind::Array { len } => write!(f, "{{{name};{len}}}")?,
+                VariableKind::Nested { .. } => write!(f, "{{{name}**}}")?,
+                VariableKind::Count => write!(f, "{{#{name}}}")?,
             },
+            RegexNode::Counter(RegexVariable { name, .. }) => write!(f, "{{#{name}}}")?,
+            RegexNode::Capture(RegexVariable { name, .. }, node) => {
+                write!(f, "(?P<{name}>")?;
+                Display::fmt(&self.node(*node), f)?;
+                f.write_char(')')?;
+            }
+            RegexNode::Assertion(assertion) => write!(f, "{assertion}")?,
+            RegexNode::Lookahead(node) => {
+                f.write_str("(?=")?;
+                Display::fmt(&self.node(*node), f)?;
+                f.write_char(')')?;
+            }
+            RegexNode::NegativeLookahead(node) => {
+                f.write_str("(?!")?;
+                Display::fmt(&self.node(*node), f)?;
+                f.write_char(')')?;
+            }
             RegexNode::ZeroOrOne(node) => {
                 Display::fmt(&self.node(*node), f)?;
                 f.write_char('?')?;
@@ -158,11 +799,32 @@ impl Debug for RegexDisplay<'_> {
                 tuple.finish()?;
             }
             RegexNode::Literal(literal) => f.debug_tuple("Literal").field(literal).finish()?,
-            RegexNode::Variable(var) => f.debug_tuple("Variable").field(var).finish()?,
+            RegexNode::Variable(var, pattern) => f
+                .debug_tuple("Variable")
+                .field(var)
+                .field(pattern)
+                .finish()?,
+            RegexNode::Capture(var, child) => f
+                .debug_tuple("Capture")
+                .field(var)
+                .field(&self.node(*child))
+                .finish()?,
             RegexNode::ZeroOrOne(child) => f
                 .debug_tuple("ZeroOrOne")
                 .field(&self.node(*child))
                 .finish()?,
+            RegexNode::Assertion(assertion) => {
+                f.debug_tuple("Assertion").field(assertion).finish()?
+            }
+            RegexNode::Counter(var) => f.debug_tuple("Counter").field(var).finish()?,
+            RegexNode::Lookahead(child) => f
+                .debug_tuple("Lookahead")
+                .field(&self.node(*child))
+                .finish()?,
+            RegexNode::NegativeLookahead(child) => f
+                .debug_tuple("NegativeLookahead")
+                .field(&self.node(*child))
+                .finish()?,
             RegexNode::Many(child) => f.debug_tuple("Many").field(&self.node(*child)).finish()?,
             RegexNode::OneOrMore(child) => f
                 .debug_tuple("OneOrMore")
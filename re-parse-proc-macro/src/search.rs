@@ -0,0 +1,276 @@
+//! Finds where the leftmost match of a pattern starts for `re_find!` and `re_for_each!`, reading the input only once.
+//!
+//! Running the state machine of the pattern from every position of the input would read the input again for every
+//! position. Instead, the search follows the matches from all positions at the same time. A state of the search is
+//! the list of the [`Dfa`] states that these matches are in, ordered by where they started, and the generated code
+//! keeps the start of every match in an array of slots in the same order. Matches that reach the same [`Dfa`] state
+//! continue alike, so only the one that started first is kept. Once a match could end, the matches that started
+//! after it cannot be the leftmost one anymore, and the search stops when this happens to the first slot.
+//! The state machine of the pattern then runs once from the start that was found, to find the longest match there.
+use crate::dfa::{DeferredMatch, Dfa, DfaIndex, DfaNode};
+use crate::regex::{CharKind, CharRange};
+use crate::Map;
+
+#[derive(Debug)]
+pub struct Search {
+    /// Disjoint ranges of characters that every state of the search handles alike.
+    /// The characters in no letter are handled alike as well.
+    pub letters: Vec<CharRange>,
+    pub nodes: Vec<SearchNode>,
+    /// The initial state if the search starts at the beginning of the input
+    pub root: usize,
+    /// The initial states if the search starts after a character, by the kind of that character.
+    /// This is empty if the kind does not matter, like for [`Dfa::inner_roots`].
+    pub inner_roots: Vec<(CharKind, usize)>,
+    /// The state in which the match of the first slot could end, so that the search is over
+    pub found: Option<usize>,
+    /// The number of slots that a state uses at most
+    pub slot_count: usize,
+}
+
+#[derive(Debug)]
+pub struct SearchNode {
+    /// The transition for the characters in no letter, followed by the transition for every letter
+    pub transitions: Vec<SearchTransition>,
+    /// The first slot whose match could end at the end of the input
+    pub end: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SearchTransition {
+    pub target: usize,
+    /// For every slot of the target, the slot of the current state that its start is moved from,
+    /// or `None` for a match that starts after the character
+    pub sources: Vec<Option<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Slot {
+    /// A match that is in this state of the [`Dfa`]
+    Running(DfaIndex),
+    /// A match that could have ended already. It is always the last slot, because the matches that started after it
+    /// are dropped.
+    Ended,
+}
+
+impl Search {
+    /// Builds the search for the matches of `dfa`, or returns `None` if it needs more than `max_states` states
+    pub fn new(dfa: &Dfa, max_states: usize) -> Option<Self> {
+        let mut ranges = dfa
+            .iter()
+            .flat_map(|idx| {
+                let node = &dfa.nodes[idx];
+                node.edges
+                    .explicit_ranges()
+                    .chain(node.accepting_overrides.keys().copied())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // A match that starts after a character starts in the inner root for the kind of the character
+        if !dfa.inner_roots.is_empty() {
+            ranges.extend(CharKind::special_ranges());
+        }
+        let ranges = ranges
+            .into_iter()
+            .map(|range| (range, ()))
+            .collect::<Vec<_>>();
+        let letters = CharRange::partition(&ranges, &[])
+            .into_iter()
+            .map(|(range, _)| range)
+            .collect::<Vec<_>>();
+
+        let mut keys: Map<Vec<Slot>, ()> = Map::default();
+        let root = keys.insert_full(vec![Slot::Running(dfa.root)], ()).0;
+        let inner_roots = dfa
+            .inner_roots
+            .iter()
+            .map(|(kind, idx)| (*kind, keys.insert_full(vec![Slot::Running(*idx)], ()).0))
+            .collect();
+
+        let mut nodes = Vec::new();
+        while let Some((slots, _)) = keys.get_index(nodes.len()) {
+            let slots = slots.clone();
+            let transitions = std::iter::once(None)
+                .chain(letters.iter().map(|letter| Some(letter.start)))
+                .map(|char| {
+                    let (target, sources) = step(dfa, &slots, char);
+                    SearchTransition {
+                        target: keys.insert_full(target, ()).0,
+                        sources,
+                    }
+                })
+                .collect();
+            let end = slots.iter().position(|slot| match slot {
+                Slot::Running(idx) => accepts_at_end(&dfa.nodes[*idx]),
+                Slot::Ended => true,
+            });
+            nodes.push(SearchNode { transitions, end });
+            if keys.len() > max_states {
+                return None;
+            }
+        }
+
+        Some(Search {
+            letters,
+            nodes,
+            root,
+            inner_roots,
+            found: keys.get_index_of(&vec![Slot::Ended]),
+            slot_count: keys.keys().map(Vec::len).max().unwrap_or_default(),
+        })
+    }
+}
+
+/// The slots after `char`, or after any character in no letter if it is `None`,
+/// together with the slot that the start of each of them is moved from
+fn step(dfa: &Dfa, slots: &[Slot], char: Option<char>) -> (Vec<Slot>, Vec<Option<usize>>) {
+    let mut slots = slots.iter().copied().zip(0..).collect::<Vec<_>>();
+    let ended = slots.iter().position(|(slot, _)| match slot {
+        Slot::Running(idx) => accepts_before(&dfa.nodes[*idx], char),
+        Slot::Ended => false,
+    });
+    if let Some(ended) = ended {
+        slots.truncate(ended);
+        slots.push((Slot::Ended, ended));
+    }
+
+    let mut next: Vec<(Slot, Option<usize>)> = Vec::new();
+    for (slot, source) in slots {
+        let slot = match slot {
+            Slot::Running(idx) => {
+                let edges = &dfa.nodes[idx].edges;
+                match char.map_or(edges.default, |char| edges.target(char)) {
+                    Some(target) => Slot::Running(target),
+                    None => continue,
+                }
+            }
+            Slot::Ended => Slot::Ended,
+        };
+        if !next.iter().any(|(other, _)| *other == slot) {
+            next.push((slot, Some(source)));
+        }
+    }
+    // A new match starts after the character, unless an earlier match could end already
+    if !next.iter().any(|(slot, _)| *slot == Slot::Ended) {
+        let kind = char.map_or(CharKind::Other, CharKind::of);
+        let root = dfa
+            .inner_roots
+            .iter()
+            .find(|(other, _)| *other == kind)
+            .map_or(dfa.root, |(_, idx)| *idx);
+        let root = Slot::Running(root);
+        if !next.iter().any(|(other, _)| *other == root) {
+            next.push((root, None));
+        }
+    }
+    next.into_iter().unzip()
+}
+
+/// Whether a match may end in `node` before `char`, like the state machine decides it
+fn accepts_before(node: &DfaNode, char: Option<char>) -> bool {
+    node.deferred_match == DeferredMatch::Accepted
+        || node.accepts_before(char.and_then(|char| node.edges.range_of(char)))
+}
+
+/// Whether a match may end in `node` at the end of the input, like the state machine decides it
+fn accepts_at_end(node: &DfaNode) -> bool {
+    node.is_accepting
+        || matches!(
+            node.deferred_match,
+            DeferredMatch::Accepted | DeferredMatch::AcceptedAtEnd
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dfa::{Dfa, DEFAULT_MAX_STATES};
+    use crate::nfa::Nfa;
+    use crate::regex::{CharKind, Regex};
+    use crate::search::Search;
+
+    fn build(pattern: &str) -> Option<Search> {
+        let nfa = Nfa::try_from(Regex::from_str(pattern).unwrap()).unwrap();
+        Search::new(&Dfa::try_from(nfa).unwrap(), DEFAULT_MAX_STATES)
+    }
+
+    /// Runs the search like the generated code does and returns where the leftmost match starts
+    fn match_start(search: &Search, haystack: &str, from: usize) -> Option<usize> {
+        let input = haystack.get(from..)?;
+        let mut state = match haystack[..from].chars().next_back() {
+            Some(char) if !search.inner_roots.is_empty() => {
+                let kind = CharKind::of(char);
+                search
+                    .inner_roots
+                    .iter()
+                    .find(|(other, _)| *other == kind)?
+                    .1
+            }
+            _ => search.root,
+        };
+        let mut starts = vec![from; search.slot_count];
+        for (index, char) in input.char_indices() {
+            let letter = search
+                .letters
+                .iter()
+                .position(|letter| letter.contains(char))
+                .map_or(0, |letter| letter + 1);
+            let transition = &search.nodes[state].transitions[letter];
+            for (slot, source) in transition.sources.iter().enumerate() {
+                starts[slot] = match source {
+                    Some(source) => starts[*source],
+                    None => from + index + char.len_utf8(),
+                };
+            }
+            state = transition.target;
+            if Some(state) == search.found {
+                return Some(starts[0]);
+            }
+        }
+        search.nodes[state].end.map(|slot| starts[slot])
+    }
+
+    #[test]
+    fn test_leftmost_start() {
+        let search = build("ab|bc").unwrap();
+        assert_eq!(match_start(&search, "xxabc", 0), Some(2));
+        assert_eq!(match_start(&search, "xxbc", 0), Some(2));
+        assert_eq!(match_start(&search, "xxbc", 3), None);
+        assert_eq!(match_start(&search, "xxb", 0), None);
+
+        // The match at 0 only ends after the one at 1, but it starts first
+        let search = build("abcd|bc").unwrap();
+        assert_eq!(match_start(&search, "abcd", 0), Some(0));
+        assert_eq!(match_start(&search, "abcx", 0), Some(1));
+    }
+
+    #[test]
+    fn test_start_after_character_kind() {
+        let search = build(r"\bab").unwrap();
+        assert_eq!(match_start(&search, "cab ab", 0), Some(4));
+        assert_eq!(match_start(&search, "cab ab", 1), Some(4));
+        assert_eq!(match_start(&search, " ab", 1), Some(1));
+    }
+
+    #[test]
+    fn test_empty_match() {
+        let search = build("a*").unwrap();
+        assert_eq!(match_start(&search, "bbb", 0), Some(0));
+        assert_eq!(match_start(&search, "bbb", 3), Some(3));
+        assert_eq!(match_start(&search, "bbb", 4), None);
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let search = build("a(?=b)").unwrap();
+        assert_eq!(match_start(&search, "aacab", 0), Some(3));
+        assert_eq!(match_start(&search, "aa", 0), None);
+    }
+
+    #[test]
+    fn test_states_stay_few() {
+        // The matches from every position end up in the same states, so the search does not grow with the input
+        let search = build("{x};").unwrap();
+        assert!(search.nodes.len() <= 4, "{search:?}");
+        assert_eq!(match_start(&search, &"x".repeat(1000), 0), None);
+    }
+}
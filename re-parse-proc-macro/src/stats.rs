@@ -0,0 +1,105 @@
+//! Statistics about the state machine of every pattern, which are printed while the macros are expanded if the
+//! environment variable `RE_PARSE_STATS` is set. They help to find the patterns that make builds slow or binaries large.
+//!
+//! Proc macros cannot emit notes on stable, so the statistics are printed to stderr in the format of a note.
+use crate::dfa::Dfa;
+use proc_macro2::{TokenStream, TokenTree};
+use std::fmt::{Display, Formatter};
+use syn::LitStr;
+
+const STATS_VARIABLE: &str = "RE_PARSE_STATS";
+
+/// Whether `RE_PARSE_STATS` is set to a value other than `0`
+pub fn enabled() -> bool {
+    std::env::var_os(STATS_VARIABLE).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Stats {
+    pub states: usize,
+    /// The explicit edges and default edges of all states
+    pub edges: usize,
+    /// The number of tokens of the generated code, which estimates its size
+    pub tokens: usize,
+}
+
+impl Stats {
+    pub fn new(dfa: &Dfa, code: &TokenStream) -> Self {
+        let states = dfa.iter().count();
+        let edges = dfa
+            .iter()
+            .map(|idx| {
+                let edges = &dfa.nodes[idx].edges;
+                edges.edges.len() + usize::from(edges.default.is_some())
+            })
+            .sum();
+        Self {
+            states,
+            edges,
+            tokens: count_tokens(code.clone()),
+        }
+    }
+
+    /// Prints the statistics as a note that points at the pattern. Only works while a macro is expanded.
+    pub fn report(&self, pattern: &LitStr) {
+        let span = pattern.span().unwrap();
+        eprintln!(
+            "note: re-parse: {self} for the pattern {:?}\n  --> {}:{}:{}",
+            pattern.value(),
+            span.file(),
+            span.line(),
+            span.column()
+        );
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} states, {} edges and {} tokens of generated code",
+            self.states, self.edges, self.tokens
+        )
+    }
+}
+
+/// Counts the tokens including the tokens inside of groups. A group counts as one token for its delimiters.
+fn count_tokens(tokens: TokenStream) -> usize {
+    tokens
+        .into_iter()
+        .map(|tree| match tree {
+            TokenTree::Group(group) => 1 + count_tokens(group.stream()),
+            _ => 1,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dfa::Dfa;
+    use crate::nfa::Nfa;
+    use crate::regex::Regex;
+    use crate::stats::{count_tokens, Stats};
+    use quote::quote;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(count_tokens(quote! { a(b, c) }), 5);
+
+        let nfa = Nfa::try_from(Regex::from_str("a(b|c)*").unwrap()).unwrap();
+        let dfa = Dfa::try_from(nfa).unwrap();
+        let stats = Stats::new(&dfa, &quote! { a });
+        assert_eq!(
+            stats,
+            Stats {
+                states: 2,
+                edges: 3,
+                tokens: 1
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "2 states, 3 edges and 1 tokens of generated code"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Time formats like `%Y-%m-%d %H:%M:%S` in variables like `{ts:%Y-%m-%d}`.
+//!
+//! Each specifier is expanded to a pattern that matches it, and the captured text is parsed with `chrono`.
+//! Time formats are only recognized with the `chrono` feature, because the generated code refers to the `chrono` crate.
+
+/// The `chrono` type that a time format is parsed into, depending on the fields that the format contains
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum TimeType {
+    Date,
+    Time,
+    DateTime,
+    DateTimeWithOffset,
+}
+
+impl TimeType {
+    /// The path of the type, which has a `parse_from_str(&str, &str)` function
+    pub fn path(self) -> &'static str {
+        match self {
+            TimeType::Date => "::chrono::NaiveDate",
+            TimeType::Time => "::chrono::NaiveTime",
+            TimeType::DateTime => "::chrono::NaiveDateTime",
+            TimeType::DateTimeWithOffset => "::chrono::DateTime::<::chrono::FixedOffset>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Field {
+    Date,
+    Time,
+    Offset,
+}
+
+/// The supported specifiers, together with the field they describe and the pattern that matches them
+const SPECIFIERS: &[(char, Field, &str)] = &[
+    ('Y', Field::Date, r"\d#{4}"),
+    ('y', Field::Date, r"\d#{2}"),
+    ('m', Field::Date, r"(0[1-9]|1[0-2])"),
+    ('d', Field::Date, r"(0[1-9]|[12]\d|3[01])"),
+    ('j', Field::Date, r"\d#{3}"),
+    (
+        'b',
+        Field::Date,
+        "(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)",
+    ),
+    (
+        'F',
+        Field::Date,
+        r"\d#{4}\-(0[1-9]|1[0-2])\-(0[1-9]|[12]\d|3[01])",
+    ),
+    ('H', Field::Time, r"([01]\d|2[0-3])"),
+    ('M', Field::Time, r"[0-5]\d"),
+    ('S', Field::Time, r"([0-5]\d|60)"),
+    ('T', Field::Time, r"([01]\d|2[0-3]):[0-5]\d:([0-5]\d|60)"),
+    ('z', Field::Offset, r"[+\-]\d#{4}"),
+];
+
+/// The names of the supported specifiers, for error messages
+pub fn specifiers() -> String {
+    SPECIFIERS
+        .iter()
+        .map(|(specifier, _, _)| format!("%{specifier}"))
+        .chain(["%%".to_string()])
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Expands a time format to a pattern and determines the type that it is parsed into.
+/// Returns the first unknown specifier as an error.
+pub fn expand(format: &str) -> Result<(String, TimeType), char> {
+    let mut pattern = String::new();
+    let mut fields = Vec::new();
+    let mut chars = format.chars();
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            if !char.is_alphanumeric() {
+                pattern.push('\\');
+            }
+            pattern.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => pattern.push_str(r"\%"),
+            Some(specifier) => {
+                let (_, field, specifier_pattern) = SPECIFIERS
+                    .iter()
+                    .find(|(name, _, _)| *name == specifier)
+                    .ok_or(specifier)?;
+                fields.push(*field);
+                pattern.push_str(specifier_pattern);
+            }
+            None => return Err('%'),
+        }
+    }
+
+    let has_date = fields.contains(&Field::Date);
+    let has_time = fields.contains(&Field::Time);
+    let ty = match (has_date, has_time) {
+        _ if fields.contains(&Field::Offset) => TimeType::DateTimeWithOffset,
+        (true, true) => TimeType::DateTime,
+        (false, true) => TimeType::Time,
+        _ => TimeType::Date,
+    };
+    Ok((pattern, ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::regex::Regex;
+    use crate::time_format::{expand, TimeType};
+
+    #[test]
+    fn test_expand() {
+        insta::assert_debug_snapshot!(expand("%Y-%m-%d %H:%M:%S"));
+        insta::assert_debug_snapshot!(expand("%T"));
+        insta::assert_debug_snapshot!(expand("%d/%b/%Y:%T %z"));
+        insta::assert_debug_snapshot!(expand("100%% %Q"));
+    }
+
+    #[test]
+    fn test_patterns_are_valid() {
+        let (pattern, ty) = expand("%Y %y %m %d %j %b %F %H %M %S %T %z %%").unwrap();
+        assert_eq!(ty, TimeType::DateTimeWithOffset);
+        Regex::from_str(&pattern).unwrap();
+    }
+}
@@ -1,12 +1,51 @@
-use crate::regex::RegexPattern;
+use crate::regex::{Assertion, RegexPattern};
+use regex_syntax::hir::{Class, HirKind};
 use std::fmt::{Display, Write};
-use std::iter::Peekable;
+use std::ops::Range;
+use std::sync::OnceLock;
+use thiserror::Error;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("Unknown escape sequence '\\{}'. Supported escapes are \\n, \\t, \\r, \\0, \\s, \\d, \\w, \\b, \\B, \\p{{..}}, \\P{{..}}, \\k{{..}}, \\f, \\Q...\\E and escaped punctuation", char)]
+    UnknownEscape { char: char },
+    #[error("Unknown character class '[:{}:]'. Supported classes are: {}", name, PosixClass::ALL.map(|class| class.name()).join(", "))]
+    UnknownPosixClass { name: String },
+    #[error("Unknown flag '{}'. Supported flags are: {}", char, Flag::ALL.map(|flag| flag.as_char().to_string()).join(", "))]
+    UnknownFlag { char: char },
+    #[error("Expected ')' or ':' after the flags")]
+    UnclosedFlags,
+    #[error("Unknown unicode class '{}'. Use a general category like 'L' or 'Nd', or a script like 'Greek'", name)]
+    UnknownUnicodeClass { name: String },
+    #[error("Expected '}}' after the name of the unicode class")]
+    UnclosedUnicodeClass,
+    #[error("Expected '\"' at the end of the separator")]
+    UnclosedSeparator,
+    #[error("The pattern ends with an unfinished escape. Use '\\\\' to match a backslash")]
+    UnfinishedEscape,
+    #[error(
+        "Invalid repetition '#{{{}}}'. Use #{{n}}, #{{n,}} or #{{n,m}} with whole numbers",
+        bounds
+    )]
+    InvalidRepetition { bounds: String },
+    #[error(
+        "The repetition count {} is too large. The maximum is {}",
+        count,
+        u32::MAX
+    )]
+    RepetitionOverflow { count: String },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Token {
     Char(char),
     Dot,
     CharacterClass(CharacterClass),
+    Assertion(Assertion),
+    /// `^`, which is resolved to an [`Assertion`] by the parser, depending on the multiline flag
+    Caret,
+    /// `$`, which is resolved to an [`Assertion`] by the parser, depending on the multiline flag
+    Dollar,
     LeftBrace,
     RightBrace,
     LeftParenthesis,
@@ -16,12 +55,52 @@ pub enum Token {
     Minus,
     Postfix(PostfixToken),
     Pipe,
+    /// Inline flags like `(?i)`, which apply until the end of the surrounding group
+    SetFlags(FlagUpdate),
+    /// The start of a group with flags, like `(?i:`. The flags only apply inside the group.
+    FlagGroup(FlagUpdate),
+    /// The start of a lookahead group: `(?=`
+    Lookahead,
+    /// The start of a negative lookahead group: `(?!`
+    NegativeLookahead,
+    /// The start of a named capture group: `(?P<` or `(?<`. The name follows as [`Token::Char`]s up to a `>`.
+    NamedGroup,
+    /// `\k`, which is followed by the name of a class in braces: `\k{hex}`
+    Reference,
+    /// `\f`, which matches a number with an optional sign, decimals and exponent, like `-1.5e-3`
+    Float,
+    /// The `:type` part of a typed capture like `{var:u32}`
+    CaptureType(CaptureType),
+    /// The `: Vec<T>` part of a variable like `{var: Vec<u32>}`, which looks like a rust collection type.
+    /// Types are not written in patterns, so the parser reports it. The type is kept as raw text.
+    CollectionType(String),
+    /// The `?=expr` part of an optional variable with a default value like `{port?=80}`.
+    /// The expression is kept as raw text, so it can be parsed as rust code later.
+    DefaultValue(String),
+    /// The `=>expr` part of a branch in a mapped capture like `{enabled:(on=>true|off=>false)}`.
+    /// Like [`Token::DefaultValue`], the expression is kept as raw text.
+    MappedValue(String),
+    /// The `:min..=max` part of a length-bounded capture like `{code:3..=6}`.
+    /// An exclusive end like in `{code:3..7}` is stored as the inclusive maximum, `{code:3..}` has no maximum.
+    LengthBound {
+        min: u32,
+        max: Option<u32>,
+    },
+    /// The `:format` part of a variable with a time format like `{ts:%Y-%m-%d}`, which requires the `chrono` feature
+    TimeFormat(String),
+    /// The `sep="..."` part of a repeated variable like `{items* sep=", "}`. The separator is the unescaped text.
+    Separator(String),
+    /// The `: iter` part of a repeated variable like `{items* : iter}`, which binds an iterator instead of a `Vec`
+    Iter,
+    /// The `{=expr` part of a value that the input has to equal, like `{=prefix}`. The closing `}` follows.
+    /// Like [`Token::DefaultValue`], the expression is kept as raw text.
+    ExpectedValue(String),
     Eof,
 }
 
 impl Token {
     /// Indicates whether this token may follow after a value to combine into an and-node
-    pub fn is_valid_after_value(self) -> bool {
+    pub fn is_valid_after_value(&self) -> bool {
         match self {
             Token::RightBrace
             | Token::RightParenthesis
@@ -29,28 +108,155 @@ impl Token {
             | Token::Postfix(_)
             | Token::Pipe
             | Token::Minus
+            | Token::CaptureType(_)
+            | Token::CollectionType(_)
+            | Token::LengthBound { .. }
+            | Token::DefaultValue(_)
+            | Token::MappedValue(_)
+            | Token::TimeFormat(_)
+            | Token::Separator(_)
+            | Token::Iter
             | Token::Eof => false,
             Token::Char(_)
             | Token::Dot
             | Token::CharacterClass(_)
+            | Token::Assertion(_)
+            | Token::Caret
+            | Token::Dollar
             | Token::LeftBrace
+            | Token::ExpectedValue(_)
             | Token::LeftParenthesis
-            | Token::LeftBracket => true,
+            | Token::LeftBracket
+            | Token::SetFlags(_)
+            | Token::FlagGroup(_)
+            | Token::Lookahead
+            | Token::NegativeLookahead
+            | Token::NamedGroup
+            | Token::Reference
+            | Token::Float => true,
         }
     }
 }
 
 /// Perl character classes (e.g. `\d`, `\w`)
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum CharacterClass {
     Whitespace,
     Digit,
     Word,
+    Posix(PosixClass),
+    /// A unicode general category or script (e.g. `\p{L}`, `\P{Greek}`)
+    Unicode(UnicodeClass),
 }
 
-impl CharacterClass {
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnicodeClass {
+    name: Box<str>,
+    negated: bool,
+    patterns: Box<[RegexPattern]>,
+}
+
+/// POSIX character classes (e.g. `[:alpha:]`), which are only valid inside a group: `[[:alpha:]]`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PosixClass {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Word,
+    XDigit,
+}
+
+impl PosixClass {
+    pub const ALL: [PosixClass; 13] = [
+        PosixClass::Alnum,
+        PosixClass::Alpha,
+        PosixClass::Blank,
+        PosixClass::Cntrl,
+        PosixClass::Digit,
+        PosixClass::Graph,
+        PosixClass::Lower,
+        PosixClass::Print,
+        PosixClass::Punct,
+        PosixClass::Space,
+        PosixClass::Upper,
+        PosixClass::Word,
+        PosixClass::XDigit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PosixClass::Alnum => "alnum",
+            PosixClass::Alpha => "alpha",
+            PosixClass::Blank => "blank",
+            PosixClass::Cntrl => "cntrl",
+            PosixClass::Digit => "digit",
+            PosixClass::Graph => "graph",
+            PosixClass::Lower => "lower",
+            PosixClass::Print => "print",
+            PosixClass::Punct => "punct",
+            PosixClass::Space => "space",
+            PosixClass::Upper => "upper",
+            PosixClass::Word => "word",
+            PosixClass::XDigit => "xdigit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|class| class.name() == name)
+    }
+
     /// Returns a list of patterns that correspond to this character class if or-ed together
     pub fn as_patterns(self) -> &'static [RegexPattern] {
+        match self {
+            PosixClass::Alnum => &[
+                RegexPattern::Range('a', 'z'),
+                RegexPattern::Range('A', 'Z'),
+                RegexPattern::Range('0', '9'),
+            ],
+            PosixClass::Alpha => &[RegexPattern::Range('a', 'z'), RegexPattern::Range('A', 'Z')],
+            PosixClass::Blank => &[RegexPattern::Char(' '), RegexPattern::Char('\t')],
+            PosixClass::Cntrl => &[
+                RegexPattern::Range('\0', '\x1F'),
+                RegexPattern::Char('\x7F'),
+            ],
+            PosixClass::Digit => &[RegexPattern::Range('0', '9')],
+            PosixClass::Graph => &[RegexPattern::Range('!', '~')],
+            PosixClass::Lower => &[RegexPattern::Range('a', 'z')],
+            PosixClass::Print => &[RegexPattern::Range(' ', '~')],
+            PosixClass::Punct => &[
+                RegexPattern::Range('!', '/'),
+                RegexPattern::Range(':', '@'),
+                RegexPattern::Range('[', '`'),
+                RegexPattern::Range('{', '~'),
+            ],
+            PosixClass::Space => &[RegexPattern::Char(' '), RegexPattern::Range('\t', '\r')],
+            PosixClass::Upper => &[RegexPattern::Range('A', 'Z')],
+            PosixClass::Word => &[
+                RegexPattern::Range('a', 'z'),
+                RegexPattern::Range('A', 'Z'),
+                RegexPattern::Range('0', '9'),
+                RegexPattern::Char('_'),
+            ],
+            PosixClass::XDigit => &[
+                RegexPattern::Range('0', '9'),
+                RegexPattern::Range('a', 'f'),
+                RegexPattern::Range('A', 'F'),
+            ],
+        }
+    }
+}
+
+impl CharacterClass {
+    /// Returns a list of patterns that correspond to this character class if or-ed together
+    pub fn as_patterns(&self) -> &[RegexPattern] {
         match self {
             CharacterClass::Whitespace => &[
                 RegexPattern::Char('\r'),
@@ -65,6 +271,214 @@ impl CharacterClass {
                 RegexPattern::Range('0', '9'),
                 RegexPattern::Char('_'),
             ],
+            CharacterClass::Posix(class) => class.as_patterns(),
+            CharacterClass::Unicode(class) => &class.patterns,
+        }
+    }
+
+    /// Looks up the ranges of a unicode class by its name, using the tables of `regex-syntax`
+    fn unicode(name: &str, negated: bool) -> Result<Self, TokenError> {
+        let escape = if negated { 'P' } else { 'p' };
+        let patterns = unicode_patterns(&format!("\\{escape}{{{name}}}")).ok_or_else(|| {
+            TokenError::UnknownUnicodeClass {
+                name: name.to_string(),
+            }
+        })?;
+        Ok(CharacterClass::Unicode(UnicodeClass {
+            name: name.into(),
+            negated,
+            patterns: patterns.into(),
+        }))
+    }
+
+    /// Like [`CharacterClass::as_patterns`], but `\d` and `\w` match all unicode digits and word characters,
+    /// like with the `(?u)` flag
+    pub fn as_unicode_patterns(&self) -> &[RegexPattern] {
+        static DIGIT: OnceLock<Vec<RegexPattern>> = OnceLock::new();
+        static WORD: OnceLock<Vec<RegexPattern>> = OnceLock::new();
+
+        let (lock, class) = match self {
+            CharacterClass::Digit => (&DIGIT, "\\d"),
+            CharacterClass::Word => (&WORD, "\\w"),
+            _ => return self.as_patterns(),
+        };
+        lock.get_or_init(|| unicode_patterns(class).expect("Unicode classes should be supported"))
+    }
+}
+
+/// Returns the ranges of the unicode class `class` (e.g. `\p{L}`), using the tables of `regex-syntax`
+fn unicode_patterns(class: &str) -> Option<Vec<RegexPattern>> {
+    let hir = regex_syntax::parse(class).ok()?;
+    let HirKind::Class(Class::Unicode(class)) = hir.kind() else {
+        return None;
+    };
+
+    let patterns = class
+        .ranges()
+        .iter()
+        .map(|range| match (range.start(), range.end()) {
+            (start, end) if start == end => RegexPattern::Char(start),
+            (start, end) => RegexPattern::Range(start, end),
+        })
+        .collect();
+    Some(patterns)
+}
+
+/// A flag that changes how a part of the pattern is interpreted
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Flag {
+    /// `i`: Letters match both their uppercase and lowercase variant
+    CaseInsensitive,
+    /// `x`: Whitespace is ignored and `#` starts a comment until the end of the line.
+    /// This flag is handled entirely by the tokenizer.
+    Verbose,
+    /// `s`: `.` and variables also match `\n`
+    DotAll,
+    /// `m`: `^` and `$` also match at the start and end of each line
+    Multiline,
+    /// `u`: `\d` and `\w` match all unicode digits and word characters instead of only ascii ones
+    Unicode,
+    /// `t`: The text of single variables is trimmed before it is parsed, like with `{var | trim}`
+    Trim,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 6] = [
+        Flag::CaseInsensitive,
+        Flag::Verbose,
+        Flag::DotAll,
+        Flag::Multiline,
+        Flag::Unicode,
+        Flag::Trim,
+    ];
+
+    pub fn as_char(self) -> char {
+        match self {
+            Flag::CaseInsensitive => 'i',
+            Flag::Verbose => 'x',
+            Flag::DotAll => 's',
+            Flag::Multiline => 'm',
+            Flag::Unicode => 'u',
+            Flag::Trim => 't',
+        }
+    }
+
+    pub fn from_char(char: char) -> Option<Self> {
+        Self::ALL.into_iter().find(|flag| flag.as_char() == char)
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A set of [`Flag`]s
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Flags {
+    bits: u8,
+}
+
+impl Flags {
+    pub fn contains(self, flag: Flag) -> bool {
+        self.bits & flag.bit() != 0
+    }
+
+    pub fn insert(&mut self, flag: Flag) {
+        self.bits |= flag.bit();
+    }
+
+    pub fn apply(self, update: FlagUpdate) -> Self {
+        Self {
+            bits: (self.bits | update.enable.bits) & !update.disable.bits,
+        }
+    }
+
+    fn iter(self) -> impl Iterator<Item = Flag> {
+        Flag::ALL
+            .into_iter()
+            .filter(move |flag| self.contains(*flag))
+    }
+}
+
+/// The flags that are enabled and disabled by `(?flags-flags)`
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct FlagUpdate {
+    pub enable: Flags,
+    pub disable: Flags,
+}
+
+impl Display for FlagUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for flag in self.enable.iter() {
+            f.write_char(flag.as_char())?;
+        }
+        if self.disable != Flags::default() {
+            f.write_char('-')?;
+            for flag in self.disable.iter() {
+                f.write_char(flag.as_char())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A primitive type that restricts the characters of a capture to the ones that can be parsed as that type
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CaptureType {
+    pub name: &'static str,
+    pub pattern: &'static str,
+}
+
+impl CaptureType {
+    const UNSIGNED: &'static str = r"\+?\d+";
+    const SIGNED: &'static str = r"[+\-]?\d+";
+    pub const FLOAT: &'static str = r"[+\-]?(\d+(\.\d*)?|\.\d+)([eE][+\-]?\d+)?";
+
+    pub const ALL: [CaptureType; 21] = [
+        Self::new("u8", Self::UNSIGNED),
+        Self::new("u16", Self::UNSIGNED),
+        Self::new("u32", Self::UNSIGNED),
+        Self::new("u64", Self::UNSIGNED),
+        Self::new("u128", Self::UNSIGNED),
+        Self::new("usize", Self::UNSIGNED),
+        Self::new("i8", Self::SIGNED),
+        Self::new("i16", Self::SIGNED),
+        Self::new("i32", Self::SIGNED),
+        Self::new("i64", Self::SIGNED),
+        Self::new("i128", Self::SIGNED),
+        Self::new("isize", Self::SIGNED),
+        Self::new("f32", Self::FLOAT),
+        Self::new("f64", Self::FLOAT),
+        Self::new("char", r"(?s:.)"),
+        Self::new("bool", "true|false"),
+        // Integers in another radix, which are parsed with `from_str_radix`
+        Self::new("x", "[0-9a-fA-F]+"),
+        Self::new("b", "[01]+"),
+        Self::new("o", "[0-7]+"),
+        // A double-quoted string, where a backslash escapes the next character, like `"say \"hi\""`
+        // A CSV field, which is either quoted with doubled quotes inside or contains no comma, quote or line break
+        Self::new(
+            "csv",
+            "\"([\u{0}-!#-\u{10FFFF}]|\"\")*\"|[\u{0}-\t\u{b}\u{c}\u{e}-!#-+\\--\u{10FFFF}]*",
+        ),
+        Self::new("quoted", "\"([\u{0}-!#-\\[\\]-\u{10FFFF}]|\\\\(?s:.))*\""),
+    ];
+
+    const fn new(name: &'static str, pattern: &'static str) -> Self {
+        Self { name, pattern }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name == name)
+    }
+
+    /// The radix of the integer format specifiers `x`, `b` and `o`
+    pub fn radix(self) -> Option<u32> {
+        match self.name {
+            "x" => Some(16),
+            "b" => Some(2),
+            "o" => Some(8),
+            _ => None,
         }
     }
 }
@@ -74,18 +488,32 @@ pub enum PostfixToken {
     QuestionMark,
     Star,
     Plus,
+    /// Bounded repetition: `#{n}`, `#{n,}` or `#{n,m}`.
+    /// The regular `{n,m}` syntax can not be used, because braces denote variables.
+    Repeat {
+        min: u32,
+        max: Option<u32>,
+    },
 }
 
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            Token::Char(c) => f.write_char(c),
+        match self {
+            Token::Char(c) => f.write_char(*c),
             Token::Dot => f.write_str("."),
             Token::CharacterClass(class) => match class {
                 CharacterClass::Whitespace => f.write_str("\\s"),
                 CharacterClass::Digit => f.write_str("\\d"),
                 CharacterClass::Word => f.write_str("\\w"),
+                CharacterClass::Posix(class) => write!(f, "[:{}:]", class.name()),
+                CharacterClass::Unicode(UnicodeClass { name, negated, .. }) => {
+                    let escape = if *negated { 'P' } else { 'p' };
+                    write!(f, "\\{escape}{{{name}}}")
+                }
             },
+            Token::Assertion(assertion) => write!(f, "{assertion}"),
+            Token::Caret => f.write_char('^'),
+            Token::Dollar => f.write_char('$'),
             Token::LeftBrace => f.write_char('{'),
             Token::RightBrace => f.write_char('}'),
             Token::LeftParenthesis => f.write_char('('),
@@ -93,63 +521,588 @@ impl Display for Token {
             Token::LeftBracket => f.write_char('['),
             Token::RightBracket => f.write_char(']'),
             Token::Minus => f.write_char('-'),
-            Token::Postfix(postfix_token) => match postfix_token {
+            Token::Postfix(postfix_token) => match *postfix_token {
                 PostfixToken::QuestionMark => f.write_char('?'),
                 PostfixToken::Star => f.write_char('*'),
                 PostfixToken::Plus => f.write_char('+'),
+                PostfixToken::Repeat { min, max } => match max {
+                    Some(max) if max == min => write!(f, "#{{{min}}}"),
+                    Some(max) => write!(f, "#{{{min},{max}}}"),
+                    None => write!(f, "#{{{min},}}"),
+                },
             },
             Token::Pipe => f.write_char('|'),
+            Token::SetFlags(update) => write!(f, "(?{update})"),
+            Token::FlagGroup(update) => write!(f, "(?{update}:"),
+            Token::Lookahead => f.write_str("(?="),
+            Token::NegativeLookahead => f.write_str("(?!"),
+            Token::NamedGroup => f.write_str("(?P<"),
+            Token::Reference => f.write_str("\\k"),
+            Token::Float => f.write_str("\\f"),
+            Token::CaptureType(ty) => write!(f, ":{}", ty.name),
+            Token::CollectionType(ty) => write!(f, ": {ty}"),
+            Token::LengthBound { min, max } => match *max {
+                Some(max) => write!(f, ":{min}..={max}"),
+                None => write!(f, ":{min}.."),
+            },
+            Token::DefaultValue(default) => write!(f, "?={default}"),
+            Token::MappedValue(value) => write!(f, "=>{value}"),
+            Token::TimeFormat(format) => write!(f, ":{format}"),
+            Token::Separator(separator) => write!(f, "sep=\"{separator}\""),
+            Token::Iter => f.write_str(": iter"),
+            Token::ExpectedValue(value) => write!(f, "{{={value}"),
             Token::Eof => f.write_str("<EOF>"),
         }
     }
 }
 
-pub fn tokenize(input: &str) -> impl Iterator<Item = Token> + use<'_> {
+/// A token or an error together with the byte range of the pattern that it was read from
+pub type Spanned<T> = (T, Range<usize>);
+
+pub fn tokenize(
+    input: &str,
+) -> impl Iterator<Item = Result<Spanned<Token>, Spanned<TokenError>>> + use<'_> {
     Tokenizer {
-        chars: input.chars().peekable(),
+        chars: Chars { input, offset: 0 },
+        token_start: 0,
+        variable_depth: 0,
+        in_group: false,
+        in_quote: false,
+        flags: Flags::default(),
+        scopes: Vec::new(),
     }
 }
 
-struct Tokenizer<I: Iterator> {
-    chars: Peekable<I>,
+/// The characters of a pattern. This works like a peekable iterator, but it also knows the byte offset
+/// of the next character, so that the tokens can point at the part of the pattern they were read from.
+#[derive(Clone)]
+struct Chars<'a> {
+    input: &'a str,
+    offset: usize,
 }
 
-impl<I> Iterator for Tokenizer<I>
-where
-    I: Iterator<Item = char>,
-{
-    type Item = Token;
+impl Chars<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.offset..].chars().next()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let char = self.chars.next()?;
+    fn next_if(&mut self, func: impl FnOnce(&char) -> bool) -> Option<char> {
+        let char = self.peek().filter(func)?;
+        self.offset += char.len_utf8();
+        Some(char)
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|char| char == expected)
+    }
+}
+
+impl Iterator for Chars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.next_if(|_| true)
+    }
+}
+
+struct Tokenizer<'a> {
+    chars: Chars<'a>,
+    /// The byte offset where the token that is currently read starts
+    token_start: usize,
+    /// How many braces of variables (and of `\k{..}` inside of variable patterns) are open.
+    /// Outside of variables, `{{` and `}}` are escaped braces, inside they are not, so `{a}}}` works.
+    /// Inside of variables, `{n,m}` is a repetition, because variables can not be nested: `{id:[A-Z]{3}}`.
+    variable_depth: usize,
+    /// Whether the tokenizer is inside a `[...]` group.
+    /// Inside groups, only `]`, `-` and escapes have a special meaning.
+    in_group: bool,
+    /// Whether the tokenizer is between `\Q` and `\E`, where every character is taken literally.
+    in_quote: bool,
+    /// The currently active flags. The tokenizer only needs them for [`Flag::Verbose`].
+    flags: Flags,
+    /// The flags that are restored at the end of each currently open parenthesis
+    scopes: Vec<Flags>,
+}
+
+impl Tokenizer<'_> {
+    /// Tries to read the `{n}`, `{n,}` or `{n,m}` part of a repetition after a `#`.
+    /// Returns `None` without consuming anything if the `{` is not followed by a digit or a comma,
+    /// like in `#{id}`, where the `#` is a char in front of a variable.
+    /// Otherwise the braces have to contain valid bounds.
+    fn try_repeat(&mut self) -> Option<Result<PostfixToken, TokenError>> {
+        let mut chars = self.chars.clone();
+        chars.next_if_eq(&'{')?;
+        if !chars
+            .peek()
+            .is_some_and(|char| char.is_ascii_digit() || char == ',')
+        {
+            return None;
+        }
+        self.chars = chars;
+        Some(Self::repeat_bounds(&mut self.chars))
+    }
+
+    /// Like [`Self::try_repeat`], but the `{` was already consumed.
+    /// If the input is not a valid repetition, nothing is consumed.
+    fn try_repeat_bounds(&mut self) -> Option<PostfixToken> {
+        let mut chars = self.chars.clone();
+        let repeat = Self::repeat_bounds(&mut chars).ok()?;
+
+        self.chars = chars;
+        Some(repeat)
+    }
+
+    /// Reads the `n}`, `n,}` or `n,m}` part of a repetition, up to the closing `}`
+    fn repeat_bounds(chars: &mut Chars) -> Result<PostfixToken, TokenError> {
+        let mut bounds = String::new();
+        while let Some(char) = chars.next_if(|char| *char != '}') {
+            bounds.push(char);
+        }
+        let invalid = || TokenError::InvalidRepetition {
+            bounds: bounds.clone(),
+        };
+        chars.next_if_eq(&'}').ok_or_else(invalid)?;
+        let number = |digits: &str| {
+            if digits.is_empty() || !digits.chars().all(|char| char.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            digits
+                .parse::<u32>()
+                .map_err(|_| TokenError::RepetitionOverflow {
+                    count: digits.to_string(),
+                })
+        };
+
+        let (min, max) = match bounds.split_once(',') {
+            None => {
+                let count = number(&bounds)?;
+                (count, Some(count))
+            }
+            Some((min, "")) => (number(min)?, None),
+            Some((min, max)) => (number(min)?, Some(number(max)?)),
+        };
+        Ok(PostfixToken::Repeat { min, max })
+    }
+
+    /// Tries to read the `min..=max`, `min..max` or `min..` part of a length-bounded capture like `{code:3..=6}` after the `:`.
+    /// If the input is not such a range followed by `}`, nothing is consumed.
+    fn try_length_bound(&mut self) -> Option<Token> {
+        fn number(chars: &mut Chars) -> Option<u32> {
+            let mut digits = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                digits.push(digit);
+            }
+            digits.parse().ok()
+        }
+
+        let mut chars = self.chars.clone();
+        let min = number(&mut chars)?;
+        chars.next_if_eq(&'.')?;
+        chars.next_if_eq(&'.')?;
+        let max = if chars.next_if_eq(&'=').is_some() {
+            Some(number(&mut chars)?)
+        } else if chars.peek() == Some('}') {
+            None
+        } else {
+            Some(number(&mut chars)?.saturating_sub(1))
+        };
+        if chars.peek() != Some('}') {
+            return None;
+        }
+
+        self.chars = chars;
+        Some(Token::LengthBound { min, max })
+    }
+
+    /// Tries to read the `type` part of a typed capture like `{var:u32}` or `{var: u32}` after the `:`.
+    /// If the input is not a known type followed by `}`, nothing is consumed.
+    fn try_capture_type(&mut self) -> Option<CaptureType> {
+        let mut chars = self.chars.clone();
+        while chars.next_if_eq(&' ').is_some() {}
+        let mut name = String::new();
+        while let Some(char) = chars.next_if(char::is_ascii_alphanumeric) {
+            name.push(char);
+        }
+        if chars.peek() != Some('}') {
+            return None;
+        }
+        let ty = CaptureType::from_name(&name)?;
+
+        self.chars = chars;
+        Some(ty)
+    }
+
+    /// Tries to read a rust collection type like ` Vec<u32>` after the `:` of a variable, up to the closing `}`.
+    /// If the input is not a known collection with generic arguments, nothing is consumed.
+    fn try_collection_type(&mut self) -> Option<String> {
+        const COLLECTIONS: [&str; 10] = [
+            "Vec",
+            "VecDeque",
+            "LinkedList",
+            "HashSet",
+            "BTreeSet",
+            "BinaryHeap",
+            "HashMap",
+            "BTreeMap",
+            "SmallVec",
+            "ArrayVec",
+        ];
+
+        let mut chars = self.chars.clone();
+        let mut ty = String::new();
+        while let Some(char) = chars.next_if(|char| *char != '}') {
+            ty.push(char);
+        }
+        if chars.peek() != Some('}') {
+            return None;
+        }
+        let ty = ty.trim();
+        let (path, arguments) = ty.split_once('<')?;
+        let name = path.rsplit("::").next()?;
+        if !COLLECTIONS.contains(&name) || !arguments.ends_with('>') {
+            return None;
+        }
+
+        self.chars = chars;
+        Some(ty.to_string())
+    }
+
+    /// Reads a rust expression up to one of the `terminators` outside of any brackets. The terminator is not consumed.
+    fn rust_expression(&mut self, terminators: &[char]) -> String {
+        let mut expression = String::new();
+        let mut depth = 0_usize;
+        while let Some(char) = self
+            .chars
+            .next_if(|char| depth > 0 || !terminators.contains(char))
+        {
+            match char {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            expression.push(char);
+        }
+        expression
+    }
+
+    /// Reads a time format like `%Y-%m-%d` up to the `}` that closes the variable, which is not consumed.
+    fn time_format(&mut self) -> String {
+        let mut format = String::new();
+        while let Some(char) = self.chars.next_if(|char| *char != '}') {
+            format.push(char);
+        }
+        format
+    }
+
+    /// Tries to read the `ep="..."` part of a separator like `sep=", "` after an `s` inside a variable.
+    /// Inside the quotes, a backslash escapes the next character, like in `sep="\""`.
+    /// If the input does not have this form, nothing is consumed.
+    fn try_separator(&mut self) -> Option<Result<String, TokenError>> {
+        let mut chars = self.chars.clone();
+        for expected in ['e', 'p', '=', '"'] {
+            chars.next_if_eq(&expected)?;
+        }
+        let mut separator = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => match chars.next() {
+                    Some(char) => separator.push(char),
+                    None => return Some(Err(TokenError::UnclosedSeparator)),
+                },
+                Some(char) => separator.push(char),
+                None => return Some(Err(TokenError::UnclosedSeparator)),
+            }
+        }
+
+        self.chars = chars;
+        Some(Ok(separator))
+    }
+
+    /// Tries to read the ` iter` part of `{items* : iter}` after the `:`, up to the closing `}`.
+    /// If the input does not have this form, nothing is consumed.
+    fn try_iter(&mut self) -> bool {
+        let mut chars = self.chars.clone();
+        while chars.next_if(|char| *char == ' ').is_some() {}
+        for expected in "iter".chars() {
+            if chars.next_if_eq(&expected).is_none() {
+                return false;
+            }
+        }
+        while chars.next_if(|char| *char == ' ').is_some() {}
+        if chars.peek() != Some('}') {
+            return false;
+        }
+
+        self.chars = chars;
+        true
+    }
+
+    /// Tries to read the `:name:]` part of a POSIX character class after a `[` inside a group.
+    /// If the input does not have this form, nothing is consumed.
+    fn try_posix_class(&mut self) -> Option<Result<CharacterClass, TokenError>> {
+        let mut chars = self.chars.clone();
+        chars.next_if_eq(&':')?;
+        let mut name = String::new();
+        while let Some(char) = chars.next_if(char::is_ascii_alphabetic) {
+            name.push(char);
+        }
+        chars.next_if_eq(&':')?;
+        chars.next_if_eq(&']')?;
+
+        self.chars = chars;
+        Some(
+            PosixClass::from_name(&name)
+                .map(CharacterClass::Posix)
+                .ok_or(TokenError::UnknownPosixClass { name }),
+        )
+    }
+
+    /// Reads the `{name}` or single letter name of a unicode class after a `\p` or `\P`
+    fn unicode_class(&mut self, negated: bool) -> Result<CharacterClass, TokenError> {
+        let name = if self.chars.next_if_eq(&'{').is_some() {
+            let mut name = String::new();
+            loop {
+                match self.chars.next() {
+                    Some('}') => break name,
+                    Some(char) => name.push(char),
+                    None => return Err(TokenError::UnclosedUnicodeClass),
+                }
+            }
+        } else {
+            self.chars
+                .next()
+                .ok_or(TokenError::UnclosedUnicodeClass)?
+                .to_string()
+        };
+        CharacterClass::unicode(&name, negated)
+    }
+
+    /// Reads the `flags-flags)` or `flags-flags:` part of an inline flag group after a `(?`.
+    /// A `(?=` or `(?!` starts a lookahead group and a `(?P<` or `(?<` starts a named capture group instead.
+    fn flags(&mut self) -> Result<Token, TokenError> {
+        let is_named_group = match self.chars.peek() {
+            Some('<') => true,
+            Some('P') => {
+                let mut chars = self.chars.clone();
+                chars.next();
+                chars.next() == Some('<')
+            }
+            _ => false,
+        };
+        if is_named_group {
+            self.chars.next_if_eq(&'P');
+            self.chars.next();
+            self.scopes.push(self.flags);
+            return Ok(Token::NamedGroup);
+        }
+        if self.chars.next_if_eq(&'=').is_some() {
+            self.scopes.push(self.flags);
+            return Ok(Token::Lookahead);
+        }
+        if self.chars.next_if_eq(&'!').is_some() {
+            self.scopes.push(self.flags);
+            return Ok(Token::NegativeLookahead);
+        }
+
+        let mut update = FlagUpdate::default();
+        let mut negated = false;
+        loop {
+            match self.chars.next() {
+                Some(')') => {
+                    self.flags = self.flags.apply(update);
+                    return Ok(Token::SetFlags(update));
+                }
+                Some(':') => {
+                    self.scopes.push(self.flags);
+                    self.flags = self.flags.apply(update);
+                    return Ok(Token::FlagGroup(update));
+                }
+                Some('-') if !negated => negated = true,
+                Some(char) => {
+                    let flag = Flag::from_char(char).ok_or(TokenError::UnknownFlag { char })?;
+                    if negated {
+                        update.disable.insert(flag);
+                    } else {
+                        update.enable.insert(flag);
+                    }
+                }
+                None => return Err(TokenError::UnclosedFlags),
+            }
+        }
+    }
+
+    /// Returns the next char that is not skipped because of verbose mode
+    fn next_char(&mut self) -> Option<Result<char, Token>> {
+        loop {
+            self.token_start = self.chars.offset;
+            let char = self.chars.next()?;
+            if !self.flags.contains(Flag::Verbose) || self.in_group {
+                return Some(Ok(char));
+            }
+
+            match char {
+                _ if char.is_whitespace() => {}
+                '#' => {
+                    if let Some(Ok(repeat)) = self.try_repeat() {
+                        return Some(Err(Token::Postfix(repeat)));
+                    }
+                    while self.chars.next_if(|char| *char != '\n').is_some() {}
+                }
+                _ => return Some(Ok(char)),
+            }
+        }
+    }
+
+    /// Reads the next token and sets [`Self::token_start`] to where it starts
+    fn next_token(&mut self) -> Option<Result<Token, TokenError>> {
+        if self.in_quote {
+            self.token_start = self.chars.offset;
+            let char = self.chars.next()?;
+            if char == '\\' && self.chars.next_if_eq(&'E').is_some() {
+                self.in_quote = false;
+                return self.next_token();
+            }
+            return Some(Ok(Token::Char(char)));
+        }
+
+        let char = match self.next_char()? {
+            Ok(char) => char,
+            Err(token) => return Some(Ok(token)),
+        };
 
         match char {
             '\\' => {
                 let Some(next) = self.chars.next() else {
-                    // TODO: This should probably return an error
-                    return None;
+                    return Some(Err(TokenError::UnfinishedEscape));
                 };
                 let token = match next {
                     's' => Token::CharacterClass(CharacterClass::Whitespace),
                     'd' => Token::CharacterClass(CharacterClass::Digit),
                     'w' => Token::CharacterClass(CharacterClass::Word),
+                    'p' | 'P' => match self.unicode_class(next == 'P') {
+                        Ok(class) => Token::CharacterClass(class),
+                        Err(err) => return Some(Err(err)),
+                    },
+                    'b' => Token::Assertion(Assertion::WordBoundary),
+                    'B' => Token::Assertion(Assertion::NotWordBoundary),
+                    'k' => Token::Reference,
+                    'f' => Token::Float,
+                    'n' => Token::Char('\n'),
+                    't' => Token::Char('\t'),
+                    'r' => Token::Char('\r'),
+                    '0' => Token::Char('\0'),
+                    'Q' => {
+                        self.in_quote = true;
+                        return self.next_token();
+                    }
+                    _ if next.is_alphanumeric() => {
+                        return Some(Err(TokenError::UnknownEscape { char: next }))
+                    }
                     _ => Token::Char(next),
                 };
-                Some(token)
-            }
-            '{' => Some(Token::LeftBrace),
-            '}' => Some(Token::RightBrace),
-            '(' => Some(Token::LeftParenthesis),
-            ')' => Some(Token::RightParenthesis),
-            '[' => Some(Token::LeftBracket),
-            ']' => Some(Token::RightBracket),
-            '-' => Some(Token::Minus),
-            '?' => Some(Token::Postfix(PostfixToken::QuestionMark)),
-            '*' => Some(Token::Postfix(PostfixToken::Star)),
-            '+' => Some(Token::Postfix(PostfixToken::Plus)),
-            '|' => Some(Token::Pipe),
-            '.' => Some(Token::Dot),
-            _ => Some(Token::Char(char)),
+                Some(Ok(token))
+            }
+            _ if self.in_group => match char {
+                ']' => {
+                    self.in_group = false;
+                    Some(Ok(Token::RightBracket))
+                }
+                '-' => Some(Ok(Token::Minus)),
+                '[' => match self.try_posix_class() {
+                    Some(class) => Some(class.map(Token::CharacterClass)),
+                    None => Some(Ok(Token::Char('['))),
+                },
+                _ => Some(Ok(Token::Char(char))),
+            },
+            '{' if self.variable_depth == 0 && self.chars.next_if_eq(&'{').is_some() => {
+                Some(Ok(Token::Char('{')))
+            }
+            '}' if self.variable_depth == 0 && self.chars.next_if_eq(&'}').is_some() => {
+                Some(Ok(Token::Char('}')))
+            }
+            '{' => {
+                if self.variable_depth > 0 {
+                    if let Some(repeat) = self.try_repeat_bounds() {
+                        return Some(Ok(Token::Postfix(repeat)));
+                    }
+                }
+                self.variable_depth += 1;
+                if self.chars.next_if_eq(&'=').is_some() {
+                    return Some(Ok(Token::ExpectedValue(self.rust_expression(&['}']))));
+                }
+                Some(Ok(Token::LeftBrace))
+            }
+            '}' => {
+                self.variable_depth = self.variable_depth.saturating_sub(1);
+                Some(Ok(Token::RightBrace))
+            }
+            ':' if self.variable_depth > 0
+                && cfg!(feature = "chrono")
+                && self.chars.peek() == Some('%') =>
+            {
+                Some(Ok(Token::TimeFormat(self.time_format())))
+            }
+            's' if self.variable_depth > 0 => Some(
+                self.try_separator()
+                    .map_or(Ok(Token::Char('s')), |separator| {
+                        separator.map(Token::Separator)
+                    }),
+            ),
+            ':' if self.variable_depth > 0 && self.try_iter() => Some(Ok(Token::Iter)),
+            ':' if self.variable_depth > 0 => Some(Ok(self
+                .try_length_bound()
+                .or_else(|| self.try_capture_type().map(Token::CaptureType))
+                .or_else(|| self.try_collection_type().map(Token::CollectionType))
+                .unwrap_or(Token::Char(':')))),
+            '(' if self.chars.next_if_eq(&'?').is_some() => Some(self.flags()),
+            '(' => {
+                self.scopes.push(self.flags);
+                Some(Ok(Token::LeftParenthesis))
+            }
+            ')' => {
+                if let Some(flags) = self.scopes.pop() {
+                    self.flags = flags;
+                }
+                Some(Ok(Token::RightParenthesis))
+            }
+            '[' => {
+                self.in_group = true;
+                Some(Ok(Token::LeftBracket))
+            }
+            ']' => Some(Ok(Token::RightBracket)),
+            '-' => Some(Ok(Token::Minus)),
+            '?' if self.variable_depth > 0 && self.chars.next_if_eq(&'=').is_some() => {
+                Some(Ok(Token::DefaultValue(self.rust_expression(&['}']))))
+            }
+            '=' if self.variable_depth > 0 && self.chars.next_if_eq(&'>').is_some() => Some(Ok(
+                Token::MappedValue(self.rust_expression(&['|', ')', '}'])),
+            )),
+            '?' => Some(Ok(Token::Postfix(PostfixToken::QuestionMark))),
+            '*' => Some(Ok(Token::Postfix(PostfixToken::Star))),
+            '+' => Some(Ok(Token::Postfix(PostfixToken::Plus))),
+            '#' => Some(
+                self.try_repeat()
+                    .map_or(Ok(Token::Char('#')), |repeat| repeat.map(Token::Postfix)),
+            ),
+            '|' => Some(Ok(Token::Pipe)),
+            '.' => Some(Ok(Token::Dot)),
+            '^' => Some(Ok(Token::Caret)),
+            '$' => Some(Ok(Token::Dollar)),
+            _ => Some(Ok(Token::Char(char))),
         }
     }
 }
+
+impl Iterator for Tokenizer<'_> {
+    type Item = Result<Spanned<Token>, Spanned<TokenError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token()?;
+        let range = self.token_start..self.chars.offset;
+        Some(match token {
+            Ok(token) => Ok((token, range)),
+            Err(err) => Err((err, range)),
+        })
+    }
+}
@@ -1,4 +1,5 @@
 use crate::Set;
+use std::collections::VecDeque;
 use std::hash::Hash;
 
 pub trait FloodFill {
@@ -6,24 +7,26 @@ pub trait FloodFill {
 
     fn get_neighbors(&self, item: &Self::Item) -> impl Iterator<Item = Self::Item>;
 
+    /// Visits every item that is reachable from `start` in breadth-first order, so the order only depends on the
+    /// order of the neighbors
     fn iter(&self, start: Self::Item) -> impl Iterator<Item = Self::Item>
     where
         Self: Sized,
         Self::Item: Eq + Hash + Clone,
     {
-        let mut pending_nodes = Set::default();
-        pending_nodes.insert(start);
+        let mut visited_nodes = Set::default();
+        visited_nodes.insert(start.clone());
         FloodFillIter {
             flood_fill: self,
-            pending_nodes,
-            visited_nodes: Set::default(),
+            pending_nodes: VecDeque::from([start]),
+            visited_nodes,
         }
     }
 }
 
 struct FloodFillIter<'a, T: FloodFill> {
     flood_fill: &'a T,
-    pending_nodes: Set<T::Item>,
+    pending_nodes: VecDeque<T::Item>,
     visited_nodes: Set<T::Item>,
 }
 
@@ -35,17 +38,13 @@ where
     type Item = T::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next = self.pending_nodes.iter().next()?;
+        let next = self.pending_nodes.pop_front()?;
 
-        let next = next.clone();
-
-        self.visited_nodes.insert(next.clone());
-        self.pending_nodes.remove(&next);
-        self.pending_nodes.extend(
-            self.flood_fill
-                .get_neighbors(&next)
-                .filter(|neighbor| !self.visited_nodes.contains(neighbor)),
-        );
+        for neighbor in self.flood_fill.get_neighbors(&next) {
+            if self.visited_nodes.insert(neighbor.clone()) {
+                self.pending_nodes.push_back(neighbor);
+            }
+        }
 
         Some(next)
     }
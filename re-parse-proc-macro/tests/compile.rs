@@ -1,4 +1,12 @@
-use re_parse_proc_macro::re_parse;
+use re_parse_proc_macro::{
+    re_assert_matches, re_assert_no_match, re_class, re_debug_dot, re_explain, re_find,
+    re_for_each, re_parse, re_parse_at, re_parse_into, re_parse_lines, re_parse_prefix,
+    re_validate,
+};
+
+re_class!(hex = "[0-9a-fA-F]");
+re_class!(octet = r"\d#{1,3}");
+re_class!(address = r"\k{octet}\.\k{octet}\.\k{octet}\.\k{octet}");
 
 #[test]
 fn test_compile_fails() {
@@ -24,12 +32,98 @@ fn test_empty_fail() {
     re_parse!("", "1");
 }
 
+#[test]
+#[should_panic(expected = "\nname: Alice, age: 4x\n                   ^")]
+fn test_failure_context() {
+    let (_name, _age): (String, u32);
+    re_parse!("name: {_name}, age: {_age:u32}", "name: Alice, age: 4x");
+}
+
+#[test]
+#[should_panic(
+    expected = "\n...123456789012345678901234567890xabcdefghijabcdefghijabcdefghi...\n                                 ^"
+)]
+fn test_failure_context_long_line() {
+    let input = format!(
+        "first\n{}x{}\n",
+        "1234567890".repeat(5),
+        "abcdefghij".repeat(4)
+    );
+    re_parse!(r"first\n\d+\n", input);
+}
+
+#[test]
+#[should_panic(expected = "\nab\n  ^")]
+fn test_failure_context_end_of_input() {
+    re_parse!("abc", "ab");
+}
+
 #[test]
 #[should_panic(expected = "Unexpected character: D. Expected one of: 'A', 'B', 'C'")]
 fn test_unexpected_character() {
     re_parse!("[ABC]*", "ABCD");
 }
 
+#[test]
+#[should_panic(expected = "Unexpected end of input after 4 bytes. Expected one of: ',', ']'")]
+fn test_unexpected_end_of_input() {
+    re_parse!(r"\[a(,a)*\]", "[a,a");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected end of input after 2 bytes. Expected '}'")]
+fn test_unexpected_end_of_input_escaped() {
+    re_parse!("{{a}}", "{a");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected end of input after 1 byte. Expected 'b'")]
+fn test_unexpected_end_of_input_after_one_byte() {
+    re_parse!("ab", "a");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected end of input after 1 byte. No further input can match")]
+fn test_unexpected_end_of_input_no_match_possible() {
+    re_parse!(r"a(?=b)", "a");
+}
+
+#[test]
+fn test_no_match_possible_error() {
+    let mut error = None;
+    re_parse!(r"a(?=b)", "ac", else |err| {
+        error = Some((err.message, err.unexpected, err.expected));
+    });
+    assert_eq!(
+        error,
+        Some((
+            "No further input can match",
+            Some(regex_parse::Unexpected::Char('c')),
+            &[][..]
+        ))
+    );
+}
+
+#[test]
+fn test_ascii_pattern_with_unicode_input() {
+    let key: String;
+    let value: String;
+    let letter: char;
+    re_parse!(r"{key}=\({value}\).{letter:char};", "schlüssel=(wört🦀)é€;");
+    assert_eq!(
+        (key.as_str(), value.as_str(), letter),
+        ("schlüssel", "wört🦀", '€')
+    );
+
+    assert_eq!(re_find!("[0-9]+", "größe: 42"), 9..11);
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character ü. Expected ';'")]
+fn test_ascii_pattern_unexpected_unicode() {
+    re_parse!("a;", "aü");
+}
+
 #[test]
 fn test_parse_text() {
     let var: u32;
@@ -118,3 +212,2209 @@ fn test_character_class() {
     re_parse!("\\w+ {a}\\s?", "Hello World ");
     assert_eq!(a, "World");
 }
+
+#[test]
+fn test_escape() {
+    let a: u32;
+    let b: String;
+    re_parse!(r"{a}\n{b}\t\.", "1\nfoo\t.");
+    assert_eq!(a, 1);
+    assert_eq!(b, "foo");
+}
+
+#[test]
+fn test_escaped_braces() {
+    let value: u32;
+    re_parse!("{{ {value} }}", "{ 42 }");
+    assert_eq!(value, 42);
+
+    let inner: String;
+    re_parse!("{{{inner}}}", "{abc}");
+    assert_eq!(inner, "abc");
+}
+
+#[test]
+fn test_quote() {
+    let file: String;
+    re_parse!(r"\Q/tmp/{dir}.(1)\E/{file}", "/tmp/{dir}.(1)/a.txt");
+    assert_eq!(file, "a.txt");
+
+    let rest: String;
+    re_parse!(r"\Qa+b{rest}", "a+b{rest}");
+    re_parse!(r"\Q*\E{rest}", "*abc");
+    assert_eq!(rest, "abc");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character ]. Expected '}'")]
+fn test_escaped_braces_fail() {
+    re_parse!("{{a}}", "{a]");
+}
+
+#[test]
+fn test_character_class_in_group() {
+    let numbers: Vec<u32>;
+    re_parse!(r"({numbers*}[\s,]+)*", "1, 2,3 4 ");
+    assert_eq!(numbers, vec![1, 2, 3, 4]);
+
+    re_parse!(r"[\w]+ = [\d_]+;", "foo_1 = 1_000;");
+}
+
+#[test]
+fn test_group_escape() {
+    for input in ["a]", "b-", "-", "]"] {
+        re_parse!(r"[\]\-a-z]+", input);
+    }
+
+    re_parse!("[+-]?[0-9]+", "-42");
+    re_parse!("[-+]?[0-9]+", "+42");
+}
+
+#[test]
+fn test_posix_class() {
+    let color: String;
+    re_parse!("#{color}", "#1f2A3b");
+    re_parse!("#[[:xdigit:]]#{6}", "#1f2A3b");
+    assert_eq!(color, "1f2A3b");
+
+    let word: String;
+    re_parse!("[[:punct:][:space:]]*{word}[[:punct:]]", "  ...Hello!");
+    assert_eq!(word, "Hello");
+}
+
+#[test]
+fn test_unicode_class() {
+    let size: u32;
+    re_parse!(r"\p{L}+ {size}", "Größe 42");
+    assert_eq!(size, 42);
+
+    assert_eq!(re_find!(r"\p{Greek}+", "abc αβγ def"), 4..10);
+    assert_eq!(re_find!(r"\P{L}+", "abc, def"), 3..5);
+
+    let words: Vec<String>;
+    re_parse!(r"({words*}[\pZ\p{Po}]+)*", "Grüße, 世界! ");
+    assert_eq!(words, vec!["Grüße", "世界"]);
+}
+
+#[test]
+#[should_panic]
+fn test_unicode_class_fail() {
+    re_parse!(r"\p{Lu}+", "ABc");
+}
+
+#[test]
+fn test_unicode_flag() {
+    let name: String;
+    let amount: String;
+    re_parse!(r"(?u)\w+ {name}: {amount}", "Straße Jürgen: ٤٢");
+    assert_eq!(name, "Jürgen");
+    assert_eq!(amount, "٤٢");
+
+    assert_eq!(re_find!(r"(?u)\d+", "x ٤٢ 1"), 2..6);
+    assert_eq!(re_find!(r"(?u:\w+) \w+", "été été ete"), 6..15);
+}
+
+#[test]
+fn test_trim_flag() {
+    let (name, age): (String, u32);
+    re_parse!("(?t){name},{age}", "  Alice ,  42 ");
+    assert_eq!((name.as_str(), age), ("Alice", 42));
+
+    let (key, value): (String, String);
+    re_parse!("(?t:{key})={value}", " key = value ");
+    assert_eq!((key.as_str(), value.as_str()), ("key", " value "));
+
+    let mut entries: Vec<(String, u32)> = Vec::new();
+    re_for_each!("(?t){name | uppercase}:{count};", " a : 1; b:2 ;", {
+        entries.push((name, count));
+    });
+    assert_eq!(entries, vec![("A".to_string(), 1), ("B".to_string(), 2)]);
+}
+
+#[test]
+#[should_panic]
+fn test_unicode_flag_fail() {
+    re_parse!(r"\w+", "Straße");
+}
+
+#[test]
+fn test_case_insensitive() {
+    let level: String;
+    let message: String;
+    re_parse!(
+        r"(?i)[a-z]+ \({level}\): {message}",
+        "LOG (warn): Disk full"
+    );
+    assert_eq!(level, "warn");
+    assert_eq!(message, "Disk full");
+
+    for input in ["hello world", "HELLO world", "HeLlO world"] {
+        re_parse!("(?i)hello (?-i)world", input);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character W. Expected 'w'")]
+fn test_case_insensitive_scoped_fail() {
+    re_parse!("(?i)hello (?-i)world", "hello World");
+}
+
+#[test]
+fn test_case_insensitive_group() {
+    let msg: String;
+    re_parse!("(?i:error|warn): {msg}", "WARN: Disk full");
+    assert_eq!(msg, "Disk full");
+
+    re_parse!("(?i:error|warn): ok", "Error: ok");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character E. Expected 'e'")]
+fn test_case_insensitive_group_fail() {
+    re_parse!("(?i:a)e", "AE");
+}
+
+#[test]
+fn test_verbose() {
+    let year: u32;
+    let month: u32;
+    let day: u32;
+    re_parse!(
+        r"(?x)
+        {year}  \-    # the year can have any number of digits
+        {month} \-    # the month
+        {day}         # the day
+        ",
+        "2024-12-15"
+    );
+    assert_eq!((year, month, day), (2024, 12, 15));
+
+    re_parse!(r"(?x) a\ b [ ] \# (?-x)c d", "a b #c d");
+}
+
+#[test]
+fn test_dot_newline() {
+    let a: u32;
+    let b: u32;
+    re_parse!("{a}\n{b}", "1\n2");
+    assert_eq!((a, b), (1, 2));
+
+    re_parse!("(?s)a.*b", "a\n\nb");
+    let text: String;
+    re_parse!("(?s:a{text}b)", "a1\n2b");
+    assert_eq!(text, "1\n2");
+}
+
+#[test]
+#[should_panic(expected = "Expected 'Any character except \\n'")]
+fn test_dot_newline_fail() {
+    re_parse!("a.*b", "a\nb");
+}
+
+#[test]
+#[should_panic(expected = "Expected 'Any character except \\n'")]
+fn test_variable_newline_fail() {
+    let a: String;
+    re_parse!("{a}", "1\n2");
+    assert_eq!(a, "1");
+}
+
+#[test]
+fn test_repetition() {
+    let year: u32;
+    let rest: String;
+    re_parse!(r"{year}\-\d#{2}\-\d#{2}{rest}", "2024-12-15T12:00");
+    assert_eq!(year, 2024);
+    assert_eq!(rest, "T12:00");
+}
+
+#[test]
+fn test_repetition_range() {
+    for input in ["ab", "abb", "abbb", "abbbbbb"] {
+        re_parse!("ab#{1,3}(b#{3,})?", input);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character c. Expected 'b'")]
+fn test_repetition_range_fail() {
+    re_parse!("ab#{2,3}c", "abc");
+}
+
+#[test]
+fn test_repetition_group() {
+    let (ip, rest): (String, String);
+    re_parse!(r"{ip}: {rest}", "192.168.0.1: ok");
+    re_parse!(r"([0-9]#{1,3}\.)#{3}[0-9]#{1,3}", &ip);
+    assert_eq!(rest, "ok");
+
+    assert_eq!(
+        re_find!(
+            r"([[:xdigit:]]#{2}:)#{5}[[:xdigit:]]#{2}",
+            "mac 00:1A:2b:3C:4d:5E"
+        ),
+        4..21
+    );
+
+    let (group, check): (String, char);
+    re_parse!(
+        r"978\-(?P<group>\d#{1,5})\-(\d+\-)#{2}(?P<check>[\dX])",
+        "978-3-16-148410-0"
+    );
+    assert_eq!(group, "3");
+    assert_eq!(check, '0');
+}
+
+#[test]
+#[should_panic]
+fn test_repetition_group_fail() {
+    re_parse!(r"([0-9]#{1,3}\.)#{3}[0-9]#{1,3}", "192.168.1");
+}
+
+#[test]
+fn test_repetition_multiple_variable() {
+    let numbers: Vec<u32>;
+    re_parse!(r"({numbers*},)#{3}", "1,2,3,");
+    assert_eq!(numbers, vec![1, 2, 3]);
+}
+
+#[test]
+fn test_repetition_minimum_count() {
+    let numbers: Vec<u32>;
+    re_parse!(r"({numbers*2,},)*", "1,2,3,");
+    assert_eq!(numbers, vec![1, 2, 3]);
+
+    let mut lists: Vec<Vec<String>> = Vec::new();
+    re_for_each!(r"\[({items*1,};)*\]", "[a;] [b;c;]", {
+        lists.push(items);
+    });
+    assert_eq!(
+        lists,
+        vec![
+            vec!["a".to_string()],
+            vec!["b".to_string(), "c".to_string()]
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "Expected at least 2 matches for _numbers, got 1")]
+fn test_repetition_minimum_count_fail() {
+    let _numbers: Vec<u32>;
+    re_parse!(r"({_numbers*2,},)*", "1,");
+}
+
+#[test]
+fn test_variable_pattern() {
+    let (id, code, number): (String, String, u32);
+    re_parse!(r"{id:[A-Z]{3}\d+}", "ABC123");
+    assert_eq!(id, "ABC123");
+    re_parse!(r"{code:[A-Z]{2,3}}{number:\d+}", "AB42");
+    assert_eq!((code.as_str(), number), ("AB", 42));
+
+    let (red, green, blue): (String, String, String);
+    re_parse!(
+        r"#{red:\k{hex}{2}}{green:\k{hex}{2}}{blue:\k{hex}{2}}",
+        "#1f2A3b"
+    );
+    assert_eq!(
+        (red.as_str(), green.as_str(), blue.as_str()),
+        ("1f", "2A", "3b")
+    );
+
+    let words: Vec<String>;
+    re_parse!(r"({words*:\w+}\s*)*", "a bc  def");
+    assert_eq!(words, vec!["a", "bc", "def"]);
+}
+
+#[test]
+#[should_panic]
+fn test_variable_pattern_fail() {
+    let _id: String;
+    re_parse!(r"{_id:[A-Z]{3}\d+}", "AB123");
+}
+
+fn parse_hex(text: &str) -> Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(text, 16)
+}
+
+mod units {
+    pub fn kilobytes(text: &str) -> Result<u64, std::num::ParseIntError> {
+        text.trim_end_matches("kB")
+            .parse::<u64>()
+            .map(|kb| kb * 1000)
+    }
+}
+
+#[test]
+fn test_converted_variable() {
+    let (address, size): (u32, u64);
+    re_parse!("{address|parse_hex}: {size|units::kilobytes}", "ff00: 12kB");
+    assert_eq!(address, 0xff00);
+    assert_eq!(size, 12000);
+
+    let mut values = Vec::new();
+    re_for_each!(r"0x{value|parse_hex:[0-9a-f]+}", "0x1f 0xa0", {
+        values.push(value);
+    });
+    assert_eq!(values, [0x1f, 0xa0]);
+}
+
+#[test]
+fn test_variable_pipeline() {
+    let (name, count): (String, u32);
+    re_parse!("{name | trim | lowercase},{count|trim}", "  Alice ,  7");
+    assert_eq!(name, "alice");
+    assert_eq!(count, 7);
+
+    let address: u32;
+    re_parse!("0x{address|trim_end|lowercase|parse_hex}", "0xFF00  ");
+    assert_eq!(address, 0xff00);
+
+    let mut codes = Vec::<String>::new();
+    re_for_each!("<{code|uppercase:[a-z]+}>", "<ab> <cd>", {
+        codes.push(code);
+    });
+    assert_eq!(codes, ["AB", "CD"]);
+}
+
+#[test]
+#[should_panic(expected = "Failed to parse capture `_score` (f32) from \"55.x\"")]
+fn test_parse_failure() {
+    let (_name, _score): (String, f32);
+    re_parse!("{_name}: {_score}", "alice: 55.x");
+}
+
+#[test]
+#[should_panic(expected = "Failed to parse capture `values` (u8) from \"300\"")]
+fn test_parse_failure_in_list() {
+    re_for_each!("{values* sep=\",\"};", "1,2,300;", {
+        let _: Vec<u8> = values;
+    });
+}
+
+#[test]
+#[should_panic(expected = "Failed to parse capture `_address` (u32) from \"xyz\"")]
+fn test_converted_variable_fail() {
+    let _address: u32;
+    re_parse!("{_address|parse_hex}", "xyz");
+}
+
+#[test]
+fn test_array_variable() {
+    let rgb: [u8; 3];
+    re_parse!(r"rgb\({rgb;3},{rgb;3},{rgb;3}\)", "rgb(255,128,0)");
+    assert_eq!(rgb, [255, 128, 0]);
+
+    let mut rows = Vec::new();
+    re_for_each!(r"({row;2} )#{2}\|", "1 2 |3 4 |", {
+        let row: [u32; 2] = row;
+        rows.push(row);
+    });
+    assert_eq!(rows, [[1, 2], [3, 4]]);
+}
+
+#[test]
+fn test_optional_variable() {
+    let name: String;
+    let email: Option<String>;
+    re_parse!("{name}( <{email?}>)?", "Jane <jane@example.com>");
+    assert_eq!(name, "Jane");
+    assert_eq!(email.as_deref(), Some("jane@example.com"));
+
+    let name: String;
+    let email: Option<String>;
+    re_parse!("{name}( <{email?}>)?", "Jane");
+    assert_eq!(name, "Jane");
+    assert_eq!(email, None);
+
+    let mut ports = Vec::new();
+    re_for_each!(r"{host:\w+}(:{port?:u16})?;", "a:80;b;c:8080;", {
+        ports.push((host, port));
+    });
+    assert_eq!(
+        ports,
+        vec![
+            ("a".to_string(), Some(80)),
+            ("b".to_string(), None),
+            ("c".to_string(), Some(8080))
+        ]
+    );
+}
+
+#[test]
+fn test_variable_span() {
+    let (key, value): (String, u32);
+    let (key_span, value_span);
+    re_parse!(r"{key@key_span} = {value@value_span:\d+}", "port = 8080");
+    assert_eq!((key, value), ("port".to_string(), 8080));
+    assert_eq!(key_span, 0..4);
+    assert_eq!(value_span, 7..11);
+
+    let mut words = Vec::<(String, _)>::new();
+    re_for_each!(r"<{word@span}>", "a <b> c <dd>", {
+        words.push((word, span));
+    });
+    assert_eq!(words, [("b".to_string(), 3..4), ("dd".to_string(), 9..11)]);
+
+    let number: u32;
+    let number_span;
+    let match_span = re_find!(r"#{number@number_span:\d+}", "issue #42");
+    assert_eq!(number, 42);
+    assert_eq!(number_span, 7..9);
+    assert_eq!(match_span, 6..9);
+
+    let lines: Vec<(String, _)> = re_parse_lines!(" {name@span}", " a\n bc".as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(lines, [("a".to_string(), 1..2), ("bc".to_string(), 1..3)]);
+}
+
+#[test]
+fn test_discard() {
+    let name: String;
+    re_parse!("{_},{_},{name}", "id,ignored,Jane");
+    assert_eq!(name, "Jane");
+
+    let value: u32;
+    re_parse!(r"{_:\d+}{_:u8}={value}", "1234=56");
+    assert_eq!(value, 56);
+
+    let mut names = Vec::<String>::new();
+    re_for_each!("{_}:{name};", "1:a;2:b;", {
+        names.push(name);
+    });
+    assert_eq!(names, ["a", "b"]);
+}
+
+#[test]
+fn test_optional_default() {
+    let host: String;
+    let port: u16;
+    re_parse!("{host}(:{port?=80})?", "example.com");
+    assert_eq!(host, "example.com");
+    assert_eq!(port, 80);
+
+    let host: String;
+    let port: u16;
+    re_parse!("{host}(:{port?=80})?", "example.com:8080");
+    assert_eq!(host, "example.com");
+    assert_eq!(port, 8080);
+
+    let mut names = Vec::new();
+    re_for_each!(
+        "{first}( {last?=String::from(\"-\")})?;",
+        "Jane Doe;John;",
+        {
+            names.push((first, last));
+        }
+    );
+    assert_eq!(
+        names,
+        vec![
+            ("Jane".to_string(), "Doe".to_string()),
+            ("John".to_string(), "-".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_mapped_capture() {
+    let (name, enabled): (String, bool);
+    re_parse!("{name}={enabled:(on=>true|off=>false)}", "logging=on");
+    assert_eq!(name, "logging");
+    assert!(enabled);
+
+    let mut levels = Vec::new();
+    re_for_each!(
+        r"\[{level:(low=>1|mid=>5|high=>10|none=>0u8)}\]",
+        "[high] [none] [low] [x]",
+        {
+            levels.push(level);
+        }
+    );
+    assert_eq!(levels, [10, 0, 1]);
+}
+
+#[derive(Debug, PartialEq)]
+enum Direction {
+    North,
+    South,
+    NorthEast,
+}
+
+#[test]
+fn test_enum_capture() {
+    let (dir, steps): (Direction, u32);
+    re_parse!(
+        r"{dir:(north|south|north\-east)=>Direction} {steps}",
+        "north-east 3"
+    );
+    assert_eq!(dir, Direction::NorthEast);
+    assert_eq!(steps, 3);
+
+    let mut moves = Vec::new();
+    re_for_each!("{dir:(n=>Direction::North|s=>Direction::South)}", "nxs", {
+        moves.push(dir);
+    });
+    assert_eq!(moves, [Direction::North, Direction::South]);
+}
+
+#[test]
+#[should_panic]
+fn test_mapped_capture_fail() {
+    let _enabled: bool;
+    re_parse!("{_enabled:(on=>true|off=>false)}", "true");
+}
+
+#[test]
+fn test_float_class() {
+    let (x, y, unit): (f64, f64, String);
+    re_parse!(r"\({x:\f},{y:\f}\){unit}", "(-1.5e-3,+.5)mm");
+    assert_eq!((x, y), (-1.5e-3, 0.5));
+    assert_eq!(unit, "mm");
+
+    let mut values = Vec::<f32>::new();
+    re_for_each!(r"{value:\f}", "a=1.25, b=-3e2; c=.5", {
+        values.push(value);
+    });
+    assert_eq!(values, [1.25, -300.0, 0.5]);
+}
+
+#[test]
+#[cfg(feature = "chrono")]
+fn test_time_format() {
+    use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+
+    let (time, level): (NaiveDateTime, String);
+    re_parse!(
+        r"\[{time:%Y-%m-%d %H:%M:%S}\] {level}",
+        "[2024-03-01 12:30:05] warn"
+    );
+    assert_eq!(
+        time,
+        NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(12, 30, 5)
+            .unwrap()
+    );
+    assert_eq!(level, "warn");
+
+    let mut dates = Vec::<NaiveDate>::new();
+    re_for_each!("{date:%F}", "from 2024-01-31 to 2024-02-29", {
+        dates.push(date);
+    });
+    assert_eq!(
+        dates,
+        [
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+        ]
+    );
+
+    let (start, stamp): (NaiveTime, DateTime<FixedOffset>);
+    re_parse!(
+        "{start:%T} {stamp:%d/%b/%Y:%T %z}",
+        "08:15:00 10/Oct/2000:13:55:36 -0700"
+    );
+    assert_eq!(start, NaiveTime::from_hms_opt(8, 15, 0).unwrap());
+    let offset = FixedOffset::west_opt(7 * 3600).unwrap();
+    assert_eq!(
+        stamp,
+        offset.with_ymd_and_hms(2000, 10, 10, 13, 55, 36).unwrap()
+    );
+}
+
+#[test]
+#[cfg(feature = "chrono")]
+#[should_panic(
+    expected = "Failed to parse capture `_date` (chrono::naive::date::NaiveDate) from \"2024-02-30\""
+)]
+fn test_time_format_fail() {
+    let _date: chrono::NaiveDate;
+    re_parse!("{_date:%F}", "2024-02-30");
+}
+
+#[test]
+fn test_radix_capture() {
+    let (start, end, mode, mask): (u64, u64, u32, u8);
+    re_parse!(
+        r"{start:x}\-{end:x} {mode:o} {mask:b}",
+        "7fA0-7fff 755 1010"
+    );
+    assert_eq!((start, end), (0x7fa0, 0x7fff));
+    assert_eq!(mode, 0o755);
+    assert_eq!(mask, 0b1010);
+
+    let mut bytes = Vec::<u8>::new();
+    re_for_each!("{byte:x}", "de ad be ef", {
+        bytes.push(byte);
+    });
+    assert_eq!(bytes, [0xde, 0xad, 0xbe, 0xef]);
+}
+
+#[test]
+#[should_panic(expected = "Failed to parse capture `_byte` (u8) from \"1ff\"")]
+fn test_radix_capture_fail() {
+    let _byte: u8;
+    re_parse!("{_byte:x}", "1ff");
+}
+
+#[test]
+fn test_char_capture() {
+    let (first, rest): (char, String);
+    re_parse!("{first:char}{rest}", "éclair");
+    assert_eq!(first, 'é');
+    assert_eq!(rest, "clair");
+
+    let mut pairs = Vec::new();
+    re_for_each!("{key:char}={value:char}", "a=1 b=2 ü=3", {
+        pairs.push((key, value));
+    });
+    assert_eq!(pairs, [('a', '1'), ('b', '2'), ('ü', '3')]);
+
+    let c: char;
+    re_parse!("{c:char}{c:char}", "xx");
+    assert_eq!(c, 'x');
+}
+
+#[test]
+fn test_quoted_capture() {
+    let (key, value): (String, String);
+    re_parse!(
+        "{key:quoted}: {value:quoted}",
+        r#""name": "say \"hi\" \\o/""#
+    );
+    assert_eq!(key, "name");
+    assert_eq!(value, r#"say "hi" \o/"#);
+
+    let mut words: Vec<String> = Vec::new();
+    re_for_each!("{word:quoted}", r#"["a,b", "", "c\"d"]"#, {
+        words.push(word);
+    });
+    assert_eq!(words, vec!["a,b", "", "c\"d"]);
+
+    let (n, rest): (u32, String);
+    re_parse!("{n:quoted} {rest}", "\"4\\2\" \"tail\"");
+    assert_eq!(n, 42);
+    assert_eq!(rest, "\"tail\"");
+}
+
+#[test]
+fn test_csv_capture() {
+    let (name, note, age): (String, String, u32);
+    re_parse!(
+        "{name:csv},{note:csv},{age:csv}",
+        r#"Smith,"says ""hi"", then leaves","42""#
+    );
+    assert_eq!(name, "Smith");
+    assert_eq!(note, r#"says "hi", then leaves"#);
+    assert_eq!(age, 42);
+
+    let (a, b, c): (String, String, String);
+    re_parse!("{a:csv},{b:csv},{c:csv}", "x,,z");
+    assert_eq!((a.as_str(), b.as_str(), c.as_str()), ("x", "", "z"));
+
+    let fields: Vec<String>;
+    re_parse!("{fields*:csv}", "a,,\"b,\nc\",d,");
+    assert_eq!(fields, vec!["a", "", "b,\nc", "d", ""]);
+
+    let mut records = Vec::new();
+    re_for_each!(r"{row*:csv}\r?\n", "1,2\r\n3,\"4\"\n", {
+        records.push(row);
+    });
+    let records: Vec<Vec<u32>> = records;
+    assert_eq!(records, vec![vec![1, 2], vec![3, 4]]);
+}
+
+#[test]
+fn test_capture_type() {
+    let (amount, unit): (u32, String);
+    re_parse!("{amount:u32}{unit:[a-z]+}", "+12kg");
+    assert_eq!(amount, 12);
+    assert_eq!(unit, "kg");
+
+    let (x, y, flag, c): (i64, f32, bool, char);
+    re_parse!("{x:i64} {y:f64}{flag:bool}{c:char}", "-3 .5e1true!");
+    assert_eq!((x, y, flag, c), (-3, 0.5e1, true, '!'));
+
+    let mut sizes: Vec<usize> = Vec::new();
+    re_for_each!("{size:usize}px", "a1px, 22px, -3px", {
+        sizes.push(size);
+    });
+    assert_eq!(sizes, vec![1, 22, 3]);
+}
+
+#[test]
+#[should_panic]
+fn test_capture_type_fail() {
+    let _amount: u32;
+    re_parse!("{_amount:u32}", "-12");
+}
+
+#[test]
+fn test_backreference() {
+    let word: String;
+    re_parse!("{word} and {word}", "cats and cats");
+    assert_eq!(word, "cats");
+
+    let tag: String;
+    let content: String;
+    re_parse!("<{tag}>{content}</{tag}>", "<b>bold</b>");
+    assert_eq!(tag, "b");
+    assert_eq!(content, "bold");
+
+    let mut pairs: Vec<u32> = Vec::new();
+    re_for_each!(r"{n}={n};", "1=1;2=3;4=4;", {
+        pairs.push(n);
+    });
+    assert_eq!(pairs, vec![1, 4]);
+
+    let c: char;
+    assert_eq!(re_find!(r"(?P<c>\w)\-{c}\.", "a-b. c-c."), 5..9);
+    assert_eq!(c, 'c');
+}
+
+#[test]
+#[should_panic(expected = "The repeated variables of the pattern did not match the same text")]
+fn test_backreference_fail() {
+    let _word: String;
+    re_parse!("{_word} and {_word}", "cats and dogs");
+}
+
+#[test]
+fn test_class_reference() {
+    let color: String;
+    re_parse!(r"color: {color}", "color: #1f2A3b");
+    re_parse!(r"#\k{hex}#{6}", &color);
+
+    let (ip, port): (String, u16);
+    re_parse!(r"(?P<ip>\k{address}):{port}", "127.0.0.1:8080");
+    assert_eq!(ip, "127.0.0.1");
+    assert_eq!(port, 8080);
+}
+
+#[macro_use]
+mod classes {
+    use re_parse_proc_macro::{re_class, re_find};
+
+    re_class!(word = "[a-z]+");
+
+    pub fn first_word(text: &str) -> std::ops::Range<usize> {
+        // A class that is defined in a function is only in scope there
+        re_class!(numbered = r"\k{word}[0-9]");
+        re_find!(r"\k{numbered}", text)
+    }
+}
+
+#[test]
+fn test_class_scope() {
+    // The class of a module declared with `#[macro_use]` is in scope after the module
+    assert_eq!(re_find!(r"\k{word}", "12 ab3 cd"), 3..5);
+    assert_eq!(classes::first_word("12 ab3 cd"), 3..6);
+}
+
+#[test]
+fn test_presets() {
+    let (addr, port): (std::net::Ipv4Addr, u16);
+    re_parse!(r"{addr:\k{ipv4}}:{port}", "192.168.0.1:80");
+    assert_eq!(addr, std::net::Ipv4Addr::new(192, 168, 0, 1));
+    assert_eq!(port, 80);
+
+    let addr: std::net::Ipv6Addr;
+    re_parse!(r"\[{addr:\k{ipv6}}\]", "[fe80::1:2]");
+    assert_eq!(addr, "fe80::1:2".parse::<std::net::Ipv6Addr>().unwrap());
+    re_parse!(r"\k{ipv6}", "2001:db8:0:0:0:ff00:42:8329");
+    re_parse!(r"\k{ipv6}", "::");
+
+    let (user, domain): (String, String);
+    re_parse!(r"\k{email}", "jane.doe+news@mail.example.com");
+    re_parse!(r"{user}@{domain}", "jane@example.com");
+    assert_eq!((user.as_str(), domain.as_str()), ("jane", "example.com"));
+
+    assert_eq!(re_find!(r"\k{iso_date}", "due 2024-02-29!"), 4..14);
+    re_parse!(r"\k{uuid}", "123e4567-e89b-12d3-a456-426614174000");
+
+    let mut values: Vec<f64> = Vec::new();
+    re_for_each!(r"{value:\k{float}}", "x=1.5 y=-2 z=.5e3", {
+        values.push(value);
+    });
+    assert_eq!(values, vec![1.5, -2.0, 500.0]);
+}
+
+#[test]
+#[should_panic]
+fn test_presets_fail() {
+    re_parse!(r"\k{ipv4}", "256.0.0.1");
+}
+
+#[test]
+#[should_panic]
+fn test_class_reference_fail() {
+    re_parse!(r"\k{hex}+", "12g");
+}
+
+#[test]
+fn test_for_each() {
+    let mut points: Vec<(i32, i32)> = Vec::new();
+    re_for_each!("{x},{y};", "1,2;3,4;-5,6;", {
+        points.push((x, y));
+    });
+    assert_eq!(points, vec![(1, 2), (3, 4), (-5, 6)]);
+}
+
+#[test]
+fn test_for_each_long_mismatch() {
+    // The matches from all positions fail together at the end of the line, so the input is only read once
+    let input = "x".repeat(1_000_000) + "\nab;";
+    let mut words: Vec<String> = Vec::new();
+    re_for_each!("{word};", &input, {
+        words.push(word);
+    });
+    assert_eq!(words, vec!["ab"]);
+}
+
+#[test]
+fn test_for_each_skips_unmatched_input() {
+    let mut users: Vec<String> = Vec::new();
+    re_for_each!("@{user} ", "Hi @alice and @bob, how are you? ", {
+        users.push(user);
+    });
+    assert_eq!(users, vec!["alice", "bob,"]);
+}
+
+#[test]
+fn test_for_each_multiple_variable() {
+    let mut lists: Vec<Vec<u32>> = Vec::new();
+    re_for_each!(r"\[({values*},?)*\]", "[1,2] and [3,4,5]", {
+        lists.push(values);
+    });
+    assert_eq!(lists, vec![vec![1, 2], vec![3, 4, 5]]);
+}
+
+#[test]
+fn test_parse_at() {
+    let input = "3 apples, 5 pears";
+    let mut cursor = 0;
+    let mut fruits: Vec<(u32, String)> = Vec::new();
+    while cursor < input.len() {
+        let count: u32;
+        let fruit: String;
+        re_parse_at!("{count} {fruit}(, )?", input, &mut cursor);
+        fruits.push((count, fruit));
+    }
+    assert_eq!(
+        fruits,
+        vec![(3, "apples".to_string()), (5, "pears".to_string())]
+    );
+}
+
+#[test]
+fn test_expected_value() {
+    let prefix = "WARN";
+    let msg: String;
+    re_parse!(r"\[{=prefix}\] {msg}", "[WARN] disk full");
+    assert_eq!(msg, "disk full");
+
+    let level = String::from("ERROR");
+    let mut errors = Vec::new();
+    re_for_each!(
+        r"\[{=level}\] {line:[\w ]+}\n",
+        "[INFO] a\n[ERROR] b c\n[ERROR] d\n",
+        {
+            let line: String = line;
+            errors.push(line);
+        }
+    );
+    assert_eq!(errors, vec!["b c", "d"]);
+
+    let (key, value): (String, u32);
+    re_parse!(
+        "{=format!(\"{}-{}\", \"a\", 1)}={key}:{value}",
+        "a-1=size:3"
+    );
+    assert_eq!((key.as_str(), value), ("size", 3));
+
+    let sep = "--";
+    let parts: Vec<u32>;
+    re_parse!(r"({parts*:\d+}{=sep})*\.", "1--2--3--.");
+    assert_eq!(parts, vec![1, 2, 3]);
+}
+
+#[test]
+#[should_panic(expected = "The input did not match the expected value `prefix`")]
+fn test_expected_value_mismatch() {
+    let prefix = "WARN";
+    let _msg: String;
+    re_parse!(r"\[{=prefix}\] {_msg}", "[INFO] disk full");
+}
+
+#[test]
+fn test_parse_into() {
+    #[derive(Debug, Default, PartialEq)]
+    struct Config {
+        host: String,
+        port: u16,
+        tags: Vec<String>,
+        user: Option<String>,
+        port_span: std::ops::Range<usize>,
+    }
+
+    let mut config = Config {
+        host: "localhost".to_string(),
+        port: 80,
+        ..Config::default()
+    };
+    re_parse_into!("port={port@port_span}", "port=8080", &mut config);
+    assert_eq!(config.host, "localhost");
+    assert_eq!((config.port, config.port_span.clone()), (8080, 5..9));
+
+    let target = &mut config;
+    re_parse_into!(
+        r#"(user={user?:\w+} )?host={host}( tags={tags* sep=","})?"#,
+        "host=example.com tags=a,b",
+        target
+    );
+    assert_eq!(config.host, "example.com");
+    assert_eq!(config.user, None);
+    assert_eq!(config.tags, vec!["a", "b"]);
+    assert_eq!(config.port, 8080);
+}
+
+#[test]
+fn test_parse_at_stops_at_accepting_state() {
+    let input = "abc 123 456";
+    let number: u32;
+    let mut cursor = 4;
+    re_parse_at!(r"\d+", input, &mut cursor);
+    assert_eq!(cursor, 7);
+    re_parse_at!(" {number}", input, &mut cursor);
+    assert_eq!(number, 456);
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character b. Expected 'a'")]
+fn test_parse_at_fail() {
+    let mut cursor = 1;
+    re_parse_at!("a", "ab", &mut cursor);
+}
+
+#[test]
+fn test_parse_at_last_accept() {
+    let mut cursor = 0;
+    re_parse_at!("ab|abcd", "abce", &mut cursor);
+    assert_eq!(cursor, 2);
+
+    let name: String;
+    let mut cursor = 0;
+    re_parse_at!("{name:[a-z]+}(12)?", "ab13", &mut cursor);
+    assert_eq!((name.as_str(), cursor), ("ab", 2));
+
+    // The variables end like in the match that ended at the last accepting position
+    let numbers: Vec<u32>;
+    let mut cursor = 0;
+    re_parse_at!("({numbers*},)*", "1,2,3", &mut cursor);
+    assert_eq!((numbers, cursor), (vec![1, 2], 4));
+
+    let numbers: Vec<u32>;
+    let mut cursor = 0;
+    re_parse_at!("({numbers*},)*", "1,2,3", &mut cursor, compact = true);
+    assert_eq!((numbers, cursor), (vec![1, 2], 4));
+}
+
+#[test]
+fn test_parse_prefix() {
+    let command: String;
+    let rest = re_parse_prefix!("{command} ", "cd /home/user");
+    assert_eq!(command, "cd");
+    assert_eq!(rest, "/home/user");
+}
+
+#[test]
+fn test_parse_prefix_string() {
+    let input = String::from("42abc");
+    let rest = re_parse_prefix!(r"\d+", input);
+    assert_eq!(rest, "abc");
+    let rest = re_parse_prefix!("abc", rest);
+    assert_eq!(rest, "");
+}
+
+#[test]
+fn test_parse_prefix_line() {
+    let line: String;
+    let rest = re_parse_prefix!("{line}", "abc\ndef");
+    assert_eq!(line, "abc");
+    assert_eq!(rest, "\ndef");
+}
+
+#[test]
+fn test_parse_prefix_last_accept() {
+    let rest = re_parse_prefix!("a(bc)?", "abd");
+    assert_eq!(rest, "bd");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected end of input")]
+fn test_parse_prefix_fail() {
+    let command: String;
+    let _ = re_parse_prefix!("{command} ", "ls");
+    let _ = command;
+}
+
+#[test]
+fn test_find() {
+    let code: u32;
+    let span = re_find!("error code {code};", "[12:00:01] error code 404; not found");
+    assert_eq!(code, 404);
+    assert_eq!(span, 11..26);
+}
+
+#[test]
+fn test_find_trailing_variable() {
+    let code: u32;
+    let log_line = "[x] error code 404";
+    let span = re_find!("error code {code}", log_line);
+    assert_eq!(code, 404);
+    assert_eq!(span, 4..18);
+
+    let rest: String;
+    re_find!("error code {rest}", "[x] error code 404 not found");
+    assert_eq!(rest, "404 not found");
+
+    let code: u32;
+    re_find!("error code {code:u32}", "[x] error code 404 not found");
+    assert_eq!(code, 404);
+}
+
+#[test]
+fn test_find_overlapping_prefix() {
+    let span = re_find!("aab", "xaaab");
+    assert_eq!(span, 2..5);
+}
+
+#[test]
+fn test_find_leftmost() {
+    let word: String;
+    re_find!(r"\s{word}\s", "first second third ");
+    assert_eq!(word, "second");
+}
+
+#[test]
+fn test_find_last_accept() {
+    assert_eq!(re_find!("ab|abcd", "abce"), 0..2);
+    assert_eq!(re_find!("a(bc)?", "xabd"), 1..2);
+}
+
+#[test]
+fn test_find_earlier_start_ends_later() {
+    assert_eq!(re_find!("abcd|bc", "xabcd"), 1..5);
+    assert_eq!(re_find!("abcd|bc", "xabce"), 2..4);
+}
+
+#[test]
+#[should_panic(expected = "No match found")]
+fn test_find_fail() {
+    let _ = re_find!("abc", "ababab");
+}
+
+#[test]
+#[should_panic(expected = "No match found")]
+fn test_find_long_mismatch() {
+    // Every position starts a match that only fails at the end of the line, so a search that ran the state machine
+    // from every position would read the input once per position
+    let input = "x".repeat(1_000_000) + "\n;";
+    let x: String;
+    let _ = re_find!("{x};", &input);
+    let _ = x;
+}
+
+#[test]
+fn test_parse_lines() {
+    let input = "Alice: 30\nBob: 25\r\nCarol: 41";
+    let people: Vec<(String, u32)> = re_parse_lines!("{name}: {age}", input.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        people,
+        vec![
+            ("Alice".to_string(), 30),
+            ("Bob".to_string(), 25),
+            ("Carol".to_string(), 41)
+        ]
+    );
+}
+
+#[test]
+fn test_parse_lines_skip_mismatches() {
+    let input = "n=1\n\n# comment\nn=2";
+    let sum: u32 = re_parse_lines!(r"n={n}", input.as_bytes(), skip_mismatches = true,)
+        .map(|line| -> u32 { line.unwrap().0 })
+        .sum();
+    assert_eq!(sum, 3);
+}
+
+#[test]
+fn test_max_states_option() {
+    let n: u32;
+    re_parse!("n={n}", "n=5", max_states = 20);
+    assert_eq!(n, 5);
+
+    let input = "n=1\nx\nn=2";
+    let sum: u32 = re_parse_lines!(
+        "n={n}",
+        input.as_bytes(),
+        skip_mismatches = true,
+        max_states = 20
+    )
+    .map(|line| -> u32 { line.unwrap().0 })
+    .sum();
+    assert_eq!(sum, 3);
+}
+
+#[test]
+fn test_strict_option() {
+    let x: Vec<u32>;
+    re_parse!("({x*},)+", "1,2,", strict = true);
+    assert_eq!(x, vec![1, 2]);
+
+    let name: Option<String>;
+    let count: usize;
+    re_parse!("({#count};)*({name?})?", ";;", strict = true);
+    assert_eq!((name, count), (None, 2));
+}
+
+#[test]
+fn test_parse_lines_mismatch() {
+    let input = "1,2\n3;4";
+    let lines = re_parse_lines!("{a},{b}", input.as_bytes())
+        .map(|line| line.map(|(a, b): (u32, u32)| a + b))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(*lines[0].as_ref().unwrap(), 3);
+    match &lines[1] {
+        Err(regex_parse::LineError::Mismatch { line: 2, text }) => assert_eq!(text, "3;4"),
+        other => panic!("Expected a mismatch of line 2, got {other:?}"),
+    }
+    assert_eq!(
+        lines[1].as_ref().unwrap_err().to_string(),
+        r#"Line 2 does not match the pattern: "3;4""#
+    );
+}
+
+#[test]
+fn test_parse_lines_io_error() {
+    // The second line is not valid UTF-8, so it cannot be read
+    let input: &[u8] = b"1\n\xff\n3\n";
+    let lines = re_parse_lines!("{n}", input)
+        .map(|line| line.map(|(n,): (u32,)| n))
+        .collect::<Vec<_>>();
+    assert_eq!(*lines[0].as_ref().unwrap(), 1);
+    match &lines[1] {
+        Err(err @ regex_parse::LineError::Io { line: 2, error }) => {
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+            assert_eq!(err.line(), 2);
+            assert!(std::error::Error::source(err).is_some());
+        }
+        other => panic!("Expected an I/O error in line 2, got {other:?}"),
+    }
+    // Skipping mismatches still reports errors of the reader
+    let mut lines = re_parse_lines!("{n}", input, skip_mismatches = true);
+    let _: u32 = lines.next().unwrap().unwrap().0;
+    assert!(matches!(
+        lines.next(),
+        Some(Err(regex_parse::LineError::Io { line: 2, .. }))
+    ));
+}
+
+#[test]
+fn test_word_boundary() {
+    let code: u32;
+    re_parse!(r"\bERROR\b {code}", "ERROR 42");
+    assert_eq!(code, 42);
+
+    assert_eq!(re_find!(r"\bcat\b", "concatenate cat"), 12..15);
+    assert_eq!(re_find!(r"\Bcat", "cat concat"), 7..10);
+
+    let mut count = 0;
+    re_for_each!(r"\b\d\b", "1 23 4", {
+        count += 1;
+    });
+    assert_eq!(count, 2);
+}
+
+#[test]
+#[should_panic]
+fn test_word_boundary_fail() {
+    re_parse!(r"a\bb", "ab");
+}
+
+#[test]
+#[should_panic]
+fn test_word_boundary_at_cursor_fail() {
+    let mut cursor = 3;
+    re_parse_at!(r"\bfoo", "barfoo", &mut cursor);
+}
+
+#[test]
+fn test_anchors() {
+    let value: u32;
+    re_parse!("^value: {value}$", "value: 7");
+    assert_eq!(value, 7);
+
+    assert_eq!(re_find!("^foo", "foo foo"), 0..3);
+    assert_eq!(re_find!("foo$", "foo foo"), 4..7);
+    assert_eq!(re_find!("(?m)^b", "ab\nb"), 3..4);
+
+    let mut lines: Vec<String> = Vec::new();
+    re_for_each!("(?m)^{line}$", "a\nbb\nccc", {
+        lines.push(line);
+    });
+    assert_eq!(lines, vec!["a", "bb", "ccc"]);
+}
+
+#[test]
+#[should_panic]
+fn test_anchors_fail() {
+    let _ = re_find!("^foo", "xfoo");
+}
+
+#[test]
+#[should_panic]
+fn test_anchors_multiline_fail() {
+    let _ = re_find!("^b", "ab\nb");
+}
+
+#[test]
+fn test_empty_branch() {
+    re_parse!("colo(u|)r", "colour");
+    re_parse!("colo(u|)r", "color");
+    re_parse!(r"(|\+)a()b", "ab");
+    re_parse!(r"(|\+)a()b", "+ab");
+    re_parse!("(a|)*b", "aab");
+    re_parse!("a||b", "b");
+}
+
+#[test]
+#[should_panic]
+fn test_empty_branch_fail() {
+    re_parse!("colo(u|)r", "colouur");
+}
+
+#[test]
+fn test_lookahead() {
+    let num: u32;
+    re_parse!("{num}(?=px)px", "12px");
+    assert_eq!(num, 12);
+
+    assert_eq!(re_find!(r"\d+(?=px)", "3em 12px"), 4..6);
+
+    let mut words: Vec<String> = Vec::new();
+    re_for_each!(r"(?=\d){word} ", "ab 1x 2y ", {
+        words.push(word);
+    });
+    assert_eq!(words, vec!["1x", "2y"]);
+
+    let key: String;
+    let rest = re_parse_prefix!("{key}(?=: )", "name: value");
+    assert_eq!(key, "name");
+    assert_eq!(rest, ": value");
+}
+
+#[test]
+#[should_panic]
+fn test_lookahead_fail() {
+    let _ = re_find!(r"\d+(?=px)", "3em 12pt");
+}
+
+#[test]
+fn test_negative_lookahead() {
+    let mut tags: Vec<String> = Vec::new();
+    re_for_each!("<(?!/){tag}>", "<a></a><b>", {
+        tags.push(tag);
+    });
+    assert_eq!(tags, vec!["a", "b"]);
+
+    assert_eq!(re_find!(r"\d+(?!\.)", "1.5 and 42"), 2..3);
+    assert_eq!(re_find!("ab(?!cd)", "abcd abce"), 5..7);
+    assert_eq!(re_find!("ab(?!cd)", "abc"), 0..2);
+
+    re_parse!("a(?!b).", "ac");
+}
+
+#[test]
+#[should_panic]
+fn test_negative_lookahead_fail() {
+    re_parse!("a(?!b).", "ab");
+}
+
+#[test]
+fn test_named_capture() {
+    let num: u32;
+    re_parse!(r"(?P<num>\d+)px", "12px");
+    assert_eq!(num, 12);
+
+    let (a, b): (String, u32);
+    re_parse!(r"(?<a>[a-z]+)(?P<b>\d+)", "abc123");
+    assert_eq!(a, "abc");
+    assert_eq!(b, 123);
+
+    let mut sizes: Vec<(u32, String)> = Vec::new();
+    re_for_each!(r"(?P<size>\d+)(?<unit>px|em)", "1px 2.5em 30em", {
+        sizes.push((size, unit));
+    });
+    assert_eq!(
+        sizes,
+        vec![
+            (1, "px".to_string()),
+            (5, "em".to_string()),
+            (30, "em".to_string())
+        ]
+    );
+}
+
+#[test]
+#[should_panic]
+fn test_named_capture_fail() {
+    let _num: u32;
+    re_parse!(r"(?P<_num>\d+)px", "12.5px");
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    fn parse(&mut self, input: &str) {
+        re_parse!(r"\({self.x},{self.y}\)", input);
+    }
+}
+
+#[test]
+fn test_place_variable() {
+    let mut point = Point::default();
+    point.parse("(3,-4)");
+    assert_eq!(point, Point { x: 3, y: -4 });
+
+    let mut points = [Point::default(), Point::default()];
+    re_parse!(
+        r"{points[0].x} {points[0].y}; {points[1].x} {points[1].y}",
+        "1 2; 3 4"
+    );
+    assert_eq!(points, [Point { x: 1, y: 2 }, Point { x: 3, y: 4 }]);
+
+    let mut grid = [[0_u8; 2]; 2];
+    let mut words = (String::new(), Vec::<String>::new());
+    re_parse!(
+        r"{grid[0][1]}:{grid[1][0]} {words.0}( {words.1*})*",
+        "5:7 a b c"
+    );
+    assert_eq!(grid, [[0, 5], [7, 0]]);
+    assert_eq!(
+        words,
+        ("a".to_string(), vec!["b".to_string(), "c".to_string()])
+    );
+}
+
+struct Grid {
+    width: usize,
+    cells: Vec<u8>,
+}
+
+impl Grid {
+    fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        &mut self.cells[y * self.width..(y + 1) * self.width]
+    }
+}
+
+#[test]
+fn test_indexed_place_variable() {
+    let input = "1 2 3\n4 5 6";
+    let mut cells = [0_u8; 6];
+    for (y, line) in input.lines().enumerate() {
+        let width = 3;
+        re_parse!(
+            "{cells[y * width]} {cells[y * width + 1]} {cells[(y + 1) * width - 1]}",
+            line
+        );
+    }
+    assert_eq!(cells, [1, 2, 3, 4, 5, 6]);
+
+    let mut grid = Grid {
+        width: 2,
+        cells: vec![0; 4],
+    };
+    for y in 0..2 {
+        re_parse!("{grid.row_mut(y)[0]},{grid.row_mut(y)[1]}", "7,8");
+    }
+    assert_eq!(grid.cells, [7, 8, 7, 8]);
+
+    let mut total = 0_u32;
+    let slot = &mut total;
+    re_parse!("total: {*slot}", "total: 42");
+    assert_eq!(total, 42);
+}
+
+#[test]
+fn test_raw_ident_variable() {
+    let r#type: String;
+    let r#match: u32;
+    re_parse!("{r#type}={r#match}", "kind=3");
+    assert_eq!(r#type, "kind");
+    assert_eq!(r#match, 3);
+
+    struct Token {
+        r#type: String,
+    }
+    let mut token = Token {
+        r#type: String::new(),
+    };
+    re_parse!("<{token.r#type}>", "<ident>");
+    assert_eq!(token.r#type, "ident");
+
+    let mut types = Vec::new();
+    re_for_each!("{r#type}@{r#in}:{_};", "a@1:x;b@2:y;", {
+        types.push((r#type, r#in));
+    });
+    assert_eq!(types, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+
+    let lines = re_parse_lines!("{r#fn@r#loop}", "main".as_bytes())
+        .map(|line| -> (String, _) { line.unwrap() })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, vec![("main".to_string(), 0..4)]);
+}
+
+#[test]
+fn test_branch_variable() {
+    let (lives, name): (Option<u32>, Option<String>);
+    re_parse!("(cat {lives}|dog {name})", "cat 9");
+    assert_eq!((lives, name), (Some(9), None));
+
+    let (lives, name): (Option<u32>, Option<String>);
+    re_parse!("(cat {lives}|dog {name})", "dog Rex");
+    assert_eq!((lives, name), (None, Some("Rex".to_string())));
+
+    let mut pets = Vec::new();
+    re_for_each!(r"(cat {lives:u32}|dog {name:\w+}),", "cat 7,dog Bo,", {
+        pets.push((lives, name));
+    });
+    assert_eq!(
+        pets,
+        vec![(Some(7_u32), None), (None, Some("Bo".to_string()))]
+    );
+}
+
+#[test]
+fn test_exclusive_branch_variable() {
+    let n: u32;
+    re_parse!("({n} items|{n} item)", "1 item");
+    assert_eq!(n, 1);
+
+    let mut counts = Vec::<u32>::new();
+    re_for_each!(r"(\d+/{n:u32}|{n:u32}x)(, )?", "3/4, 5x, 6/7", {
+        counts.push(n);
+    });
+    assert_eq!(counts, vec![4, 5, 7]);
+
+    let (n, unit): (u32, Option<String>);
+    re_parse!("({n} {unit}|{n})!", "42!");
+    assert_eq!((n, unit), (42, None));
+}
+
+#[test]
+fn test_separator() {
+    let items: Vec<u32>;
+    re_parse!(r#"\[{items* sep=", "}\]"#, "[1, 2, 3]");
+    assert_eq!(items, vec![1, 2, 3]);
+
+    let items: Vec<u32>;
+    re_parse!(r#"\[{items* sep=", "}\]"#, "[]");
+    assert_eq!(items, Vec::<u32>::new());
+
+    let rgb: [u8; 3];
+    re_parse!(r#"rgb\({rgb;3 sep=","}\)"#, "rgb(255,128,0)");
+    assert_eq!(rgb, [255, 128, 0]);
+
+    let words: Vec<String>;
+    re_parse!(r#"{words*1, sep=" | ":\w+}!"#, "a | bc | d!");
+    assert_eq!(words, vec!["a", "bc", "d"]);
+}
+
+#[test]
+#[should_panic]
+fn test_separator_fail() {
+    let _items: Vec<u32>;
+    re_parse!(r#"\[{_items* sep=", "}\]"#, "[1, 2, ]");
+}
+
+#[test]
+fn test_zipped_list() {
+    let pairs: Vec<(i32, i32)>;
+    re_parse!("({x},{y};)*=pairs", "1,2;3,-4;");
+    assert_eq!(pairs, vec![(1, 2), (3, -4)]);
+
+    let entries: Vec<(String, u8)>;
+    re_parse!(r"\[({key:\w+}={value:u8};)+=entries\]", "[a=1;b=2;]");
+    assert_eq!(entries, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+
+    let empty: Vec<(u32,)>;
+    re_parse!(r"\[({x},)*=empty\]", "[]");
+    assert_eq!(empty, vec![]);
+
+    let mut all = Vec::new();
+    re_for_each!(r"\{({k}:{v};)*=fields\}", "{a:1;} {b:2;c:3;}", {
+        all.push(fields);
+    });
+    let all: Vec<Vec<(String, u32)>> = all;
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[1], vec![("b".to_string(), 2), ("c".to_string(), 3)]);
+
+    let total: u32 = re_parse_lines!("({v},)*=values", "1,2,\n3,".as_bytes())
+        .map(|line| -> u32 {
+            let (values,): (Vec<(u32,)>,) = line.unwrap();
+            values.iter().map(|(v,)| v).sum()
+        })
+        .sum();
+    assert_eq!(total, 6);
+}
+
+#[test]
+fn test_nested_variable() {
+    let rows: Vec<Vec<u32>>;
+    re_parse!(r"(({rows**},)* \n)*", "1,2,3, \n4, \n5,6, \n");
+    assert_eq!(rows, vec![vec![1, 2, 3], vec![4], vec![5, 6]]);
+
+    let words: Vec<Vec<String>>;
+    re_parse!(r"(\[({words**:\w+};)+\])+", "[a;b;][c;][d;e;f;]");
+    assert_eq!(words, vec![vec!["a", "b"], vec!["c"], vec!["d", "e", "f"]]);
+
+    let mut totals = Vec::new();
+    re_for_each!(r"<(({n**} )+;)*>", "<1 2 ;3 ;> <4 5 6 ;>", {
+        totals.push(
+            n.iter()
+                .map(|row: &Vec<u32>| row.iter().sum::<u32>())
+                .collect::<Vec<_>>(),
+        );
+    });
+    assert_eq!(totals, vec![vec![3, 3], vec![15]]);
+
+    // A line without numbers is an empty row
+    let n: Vec<Vec<u32>>;
+    re_parse!(r"(({n**},)*\n)*", "1,2,\n\n3,\n");
+    assert_eq!(n, vec![vec![1, 2], vec![], vec![3]]);
+}
+
+#[test]
+fn test_counter() {
+    let level: usize;
+    let title: String;
+    re_parse!(r"(\#{#level})+ {title}", "### Installation");
+    assert_eq!((level, title.as_str()), (3, "Installation"));
+
+    let words: usize;
+    let last: String;
+    re_parse!(r"({#words}\w+ )*: {last}", "a bb ccc : end");
+    assert_eq!((words, last.as_str()), (3, "end"));
+
+    let count: usize;
+    re_parse!(r"\[({_},{#count})*\]", "[]");
+    assert_eq!(count, 0);
+
+    let (xs, rows, cells): (Vec<u32>, usize, usize);
+    re_parse!(r"(({xs*}{#cells};)+\n{#rows})*", "1;2;\n3;\n4;5;6;\n");
+    assert_eq!(xs, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!((rows, cells), (3, 6));
+
+    let mut runs = Vec::new();
+    re_for_each!(r"(!{#n})+", "a!!b!c!!!", {
+        runs.push(n);
+    });
+    assert_eq!(runs, vec![2, 1, 3]);
+}
+
+#[test]
+fn test_mutable_variable() {
+    let mut totals: Vec<u32> = Vec::new();
+    re_for_each!("{mut total:u32}: {xs* sep=\",\":u32};", "1: 2,3; 10: 4;", {
+        let xs: Vec<u32> = xs;
+        total += xs.iter().sum::<u32>();
+        totals.push(total);
+    });
+    assert_eq!(totals, vec![6, 14]);
+
+    let mut values: Vec<i64> = Vec::new();
+    re_for_each!("<{mut n}>", "<-1> <2>", {
+        n -= 1;
+        values.push(n);
+    });
+    assert_eq!(values, vec![-2, 1]);
+
+    // The type of a typed capture annotates the binding, so the body does not need to constrain it
+    let mut doubled = Vec::new();
+    re_for_each!("{mut count: u32};", "1;2;", {
+        count *= 2;
+        doubled.push(count.to_string());
+    });
+    assert_eq!(doubled, ["2", "4"]);
+
+    let mut shifted = Vec::new();
+    re_for_each!("{mut x:u8},", "3,4,", {
+        x <<= 1;
+        shifted.push(x.count_ones());
+    });
+    assert_eq!(shifted, [2, 1]);
+}
+
+#[test]
+fn test_iter_variable() {
+    let numbers;
+    re_parse!(r"({numbers* : iter},?)*", "1,2,3,4");
+    assert_eq!(numbers.filter(|n: &u64| *n > 2).sum::<u64>(), 7);
+
+    let words;
+    re_parse!(r#"\[{words* sep=" ":iter}\]"#, "[a bb ccc]");
+    let words: Vec<String> = words.collect();
+    assert_eq!(words, vec!["a", "bb", "ccc"]);
+
+    let mut maxima = Vec::new();
+    re_for_each!(r#"<{xs* sep="," : iter}>"#, "<1,5,2> <7>", {
+        maxima.push(xs.max().unwrap_or(0_u32));
+    });
+    assert_eq!(maxima, vec![5, 7]);
+}
+
+#[test]
+fn test_buffer_variable() {
+    let mut buf = [0u32; 4];
+    let len: usize;
+    re_parse!("({len* => buf},?)*", "1,2,3");
+    assert_eq!(len, 3);
+    assert_eq!(buf, [1, 2, 3, 0]);
+
+    let mut storage = [0i8; 8];
+    let slice: &mut [i8] = &mut storage;
+    let count;
+    re_parse!(r#"\[{count*1, sep=" " => slice}\]"#, "[-1 2]");
+    assert_eq!(count, 2);
+    assert_eq!(&storage[..count], &[-1, 2]);
+
+    let mut sums = Vec::new();
+    let mut row = [0u16; 3];
+    re_for_each!(r"<({n* => row},?)*>", "<1,2> <3,4,5>", {
+        sums.push(row[..n].iter().sum::<u16>());
+    });
+    assert_eq!(sums, vec![3, 12]);
+}
+
+#[test]
+#[should_panic(expected = "Expected at most 2 matches for the buffer buf")]
+fn test_buffer_variable_overflow() {
+    let mut buf = [0u32; 2];
+    let _len: usize;
+    re_parse!("({_len* => buf},?)*", "1,2,3");
+}
+
+#[test]
+fn test_stack_collections() {
+    let numbers: smallvec::SmallVec<[u32; 4]>;
+    re_parse!("({numbers*},?)*", "1,2,3");
+    assert_eq!(numbers.as_slice(), &[1, 2, 3]);
+    assert!(!numbers.spilled());
+
+    let numbers: arrayvec::ArrayVec<u32, 4>;
+    re_parse!("({numbers*},?)*", "4,5");
+    assert_eq!(numbers.as_slice(), &[4, 5]);
+}
+
+#[test]
+fn test_zipped_map() {
+    let headers: std::collections::HashMap<String, String>;
+    re_parse!(
+        r"({key:[\w\-]+}: {value:[\w.]+}\n)*=>headers",
+        "Host: example.com\nContent-Length: 12\n"
+    );
+    assert_eq!(headers.len(), 2);
+    assert_eq!(headers["Host"], "example.com");
+    assert_eq!(headers["Content-Length"], "12");
+
+    let query: std::collections::BTreeMap<String, u32>;
+    re_parse!(r"\?({k:\w+}={v}&?)+=>query", "?a=1&b=2&a=3");
+    assert_eq!(
+        query.into_iter().collect::<Vec<_>>(),
+        vec![("a".to_string(), 3), ("b".to_string(), 2)]
+    );
+
+    let mut configs = Vec::new();
+    re_for_each!(r"\[({k:\w+}={v:u32};)*=>config\]", "[a=1;b=2;] [c=3;]", {
+        configs.push(config);
+    });
+    let configs: Vec<std::collections::HashMap<String, u32>> = configs;
+    assert_eq!(configs.len(), 2);
+    assert_eq!(configs[0]["b"], 2);
+    assert_eq!(configs[1]["c"], 3);
+
+    let map: std::collections::HashMap<String, u32>;
+    re_parse!("({key} => {value}; )*=>map", "a => 1; b => 2; ");
+    assert_eq!((map["a"], map["b"]), (1, 2));
+}
+
+#[test]
+fn test_greedy_variable() {
+    let (path, line): (String, u32);
+    re_parse!("{path!} {line}", "my documents/main file.rs 10");
+    assert_eq!((path.as_str(), line), ("my documents/main file.rs", 10));
+
+    let (path, line): (String, u32);
+    re_parse!("{path!}:{line}", "C:/dir:a/file:42");
+    assert_eq!((path.as_str(), line), ("C:/dir:a/file", 42));
+
+    let (name, rest): (String, String);
+    re_parse!("{name} {rest!}", "a b c");
+    assert_eq!((name.as_str(), rest.as_str()), ("a", "b c"));
+
+    let text: String;
+    re_parse!("<{text!}>", "<a> <b>");
+    assert_eq!(text, "a> <b");
+
+    // The separator may start again inside of itself
+    let (key, value): (String, String);
+    re_parse!("{key!} = {value}", "x = y = z");
+    assert_eq!((key.as_str(), value.as_str()), ("x = y", "z"));
+
+    let (a, b): (String, String);
+    re_parse!("{a!} end {b}", "x end y end z");
+    assert_eq!((a.as_str(), b.as_str()), ("x end y", "z"));
+
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    re_for_each!("{key!}={value}\n", "a=b=c\nd=e\n", {
+        pairs.push((key, value));
+    });
+    assert_eq!(
+        pairs,
+        vec![
+            ("a=b".to_string(), "c".to_string()),
+            ("d".to_string(), "e".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_length_bounded_variable() {
+    let (year, month, day): (u32, u32, u32);
+    re_parse!("{year:4..=4}{month:2..=2}{day:2..=2}", "20240315");
+    assert_eq!((year, month, day), (2024, 3, 15));
+
+    let (code, rest): (String, String);
+    re_parse!(r"{code:3..=6}\-{rest}", "ABCD-x-y");
+    assert_eq!((code.as_str(), rest.as_str()), ("ABCD", "x-y"));
+
+    let (id, name): (u32, String);
+    re_parse!("{id:2..3}{name:1..}", "42alice");
+    assert_eq!((id, name.as_str()), (42, "alice"));
+
+    let mut records: Vec<(String, u32)> = Vec::new();
+    re_for_each!("{kind:1..=1}{value:3..=3}", "A001B042C100", {
+        records.push((kind, value));
+    });
+    assert_eq!(
+        records,
+        vec![
+            ("A".to_string(), 1),
+            ("B".to_string(), 42),
+            ("C".to_string(), 100)
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character G. Expected '-'")]
+fn test_length_bounded_variable_too_long() {
+    re_parse!(r"{_:3..=6}\-", "ABCDEFG-");
+}
+
+#[test]
+fn test_empty_variable() {
+    let (name, age, city): (String, u32, String);
+    re_parse!("{name},{age??},{city??}", "alice,,");
+    assert_eq!((name.as_str(), age, city.as_str()), ("alice", 0, ""));
+
+    let (name, age, city): (String, u32, String);
+    re_parse!("{name},{age??},{city??}", "bob,42,paris");
+    assert_eq!((name.as_str(), age, city.as_str()), ("bob", 42, "paris"));
+
+    let code: u32;
+    re_parse!("<{code??:u32}>", "<>");
+    assert_eq!(code, 0);
+
+    let mut fields: Vec<(String, u32)> = Vec::new();
+    re_for_each!("{key}={value??};", "a=1;b=;c=3;", {
+        fields.push((key, value));
+    });
+    assert_eq!(
+        fields,
+        vec![
+            ("a".to_string(), 1),
+            ("b".to_string(), 0),
+            ("c".to_string(), 3)
+        ]
+    );
+}
+
+#[test]
+fn test_variables_with_common_prefix() {
+    let (num, word): (Option<u32>, Option<String>);
+    re_parse!(r"#({num:x\d+}|{word:x[a-z]+})", "#xabc");
+    assert_eq!((num, word.as_deref()), (None, Some("xabc")));
+
+    let (code, name): (Option<String>, Option<String>);
+    re_parse!(r"({code:ID\d+}|{name:ID[A-Z_]+}) = 1", "ID42 = 1");
+    assert_eq!((code.as_deref(), name), (Some("ID42"), None));
+
+    let mut tokens: Vec<(Option<String>, Option<String>)> = Vec::new();
+    re_for_each!(r"({hex:0x[0-9a-f]+}|{oct:0o[0-7]+});", "0x1f;0o17;", {
+        tokens.push((hex, oct));
+    });
+    assert_eq!(
+        tokens,
+        vec![
+            (Some("0x1f".to_string()), None),
+            (None, Some("0o17".to_string()))
+        ]
+    );
+}
+
+#[test]
+fn test_else_fallback() {
+    fn parse_point(input: &str) -> Option<(i32, i32)> {
+        let x: i32;
+        let y: i32;
+        re_parse!(r"\({x},{y}\)", input, else { return None });
+        Some((x, y))
+    }
+    assert_eq!(parse_point("(1,-2)"), Some((1, -2)));
+    assert_eq!(parse_point("(1,-2"), None);
+    assert_eq!(parse_point("(1;-2)"), None);
+
+    let (mut position, mut message) = (0, "");
+    let _name: String;
+    re_parse!("name: {_name}!", "name: Alice?", else |err| {
+        position = err.position;
+        message = err.message;
+    });
+    assert_eq!((position, message), (12, "Unexpected end of input"));
+
+    let result: Result<(), String> = (|| {
+        let word: String;
+        re_parse!("{word} and {word}", "ab and ac", else |err| { return Err(err.to_string()) });
+        assert_eq!(word, "ab");
+        Ok(())
+    })();
+    assert_eq!(
+        result,
+        Err("The repeated variables of the pattern did not match the same text (at byte 9 of the input)".to_string())
+    );
+
+    let n: u32;
+    re_parse!("n={n}", "n=5", else { unreachable!() }, max_states = 20);
+    assert_eq!(n, 5);
+}
+
+#[test]
+fn test_else_unexpected() {
+    let mut error = None;
+    let _id: u32;
+    re_parse!(r"id: {_id:\d+};", "id: x1;", else |err| {
+        error = Some((err.position, err.unexpected == Some(regex_parse::Unexpected::Char('x')), err.expected, err.to_string()));
+    });
+    assert_eq!(
+        error,
+        Some((
+            4,
+            true,
+            &["0-9"][..],
+            "Unexpected character 'x'. Expected '0-9' (at byte 4 of the input)".to_string()
+        ))
+    );
+
+    let mut error = None;
+    re_parse!("(ab|ac)é", "a", else |err| {
+        error = Some((err.unexpected == Some(regex_parse::Unexpected::EndOfInput), err.expected));
+    });
+    assert_eq!(error, Some((true, &["b", "c"][..])));
+
+    // A match that failed a check afterwards was not rejected at a character
+    let mut unexpected = None;
+    let _word: String;
+    re_parse!("{_word} and {_word}", "ab and ac", else |err| { unexpected = Some(err.unexpected.is_none()) });
+    assert_eq!(unexpected, Some(true));
+}
+
+#[test]
+fn test_else_returns_error() {
+    fn parse_id(input: &str) -> Result<u32, regex_parse::Error<'_>> {
+        let id: u32;
+        re_parse!("id={id:u32}", input, else |err| { return Err(err) });
+        Ok(id)
+    }
+    assert_eq!(parse_id("id=7"), Ok(7));
+    let err = parse_id("id=x").unwrap_err();
+    assert_eq!(err.unexpected, Some(regex_parse::Unexpected::Char('x')));
+    let err = parse_id("id=99999999999").unwrap_err();
+    assert_eq!(err.capture.map(|capture| capture.text), Some("99999999999"));
+}
+
+#[test]
+fn test_else_parse_failure() {
+    fn parse_point(input: &str) -> Result<(i32, i32), (usize, &'static str)> {
+        let x: i32;
+        let y: i32;
+        re_parse!(r"\({x},{y}\)", input, else |err| { return Err((err.position, err.message)) });
+        Ok((x, y))
+    }
+    assert_eq!(parse_point("(1,-2)"), Ok((1, -2)));
+    assert_eq!(
+        parse_point("(1,two)"),
+        Err((3, "Failed to parse capture `y`"))
+    );
+
+    // The error contains the type and the text of the capture that could not be parsed
+    let result: Result<(), String> = (|| {
+        let _byte: u8;
+        re_parse!("{_byte:x}", "1ff", else |err| { return Err(err.to_string()) });
+        Ok(())
+    })();
+    assert_eq!(
+        result,
+        Err(
+            "Failed to parse capture `_byte` (u8) from \"1ff\" (at byte 0 of the input)"
+                .to_string()
+        )
+    );
+
+    // Every capture that cannot be parsed runs the `else` block instead of panicking
+    fn numbers(input: &str) -> Option<Vec<u8>> {
+        let numbers: Vec<u8>;
+        re_parse!(r#"\[{numbers* sep=","}\]"#, input, else { return None });
+        Some(numbers)
+    }
+    assert_eq!(numbers("[1,2]"), Some(vec![1, 2]));
+    assert_eq!(numbers("[1,256]"), None);
+
+    fn address(input: &str) -> Option<(String, u16)> {
+        let host: String;
+        let port: u16;
+        re_parse!("{host}(:{port?=80})?", input, else { return None });
+        Some((host, port))
+    }
+    assert_eq!(address("a:8080"), Some(("a".to_string(), 8080)));
+    assert_eq!(address("a"), Some(("a".to_string(), 80)));
+    assert_eq!(address("a:99999"), None);
+
+    fn radix(input: &str) -> Option<(u8, u32)> {
+        let small: u8;
+        let large: u32;
+        re_parse!("{small:x} {large|parse_hex}", input, else { return None });
+        Some((small, large))
+    }
+    assert_eq!(radix("ff ff00"), Some((255, 0xff00)));
+    assert_eq!(radix("fff ff00"), None);
+    assert_eq!(radix("ff zz"), None);
+
+    fn quoted(input: &str) -> Option<(char, u8, u8)> {
+        let initial: char;
+        let quoted: u8;
+        let field: u8;
+        re_parse!("{initial:char};{quoted:quoted};{field:csv}", input, else { return None });
+        Some((initial, quoted, field))
+    }
+    assert_eq!(quoted(r#"x;"1";"2""#), Some(('x', 1, 2)));
+    assert_eq!(quoted(r#"x;"a";"2""#), None);
+    assert_eq!(quoted(r#"x;"1";"b""#), None);
+
+    type Groups = ([u8; 2], Vec<(char, u8)>, usize);
+    fn groups(input: &str) -> Option<Groups> {
+        let pair: [u8; 2];
+        let pairs: Vec<(char, u8)>;
+        let count: usize;
+        re_parse!(r"({pair;2},)#{2};({c}={v},)*=pairs;(!{#count})*", input, else { return None });
+        Some((pair, pairs, count))
+    }
+    assert_eq!(
+        groups("3,4,;a=1,b=2,;!!"),
+        Some(([3, 4], vec![('a', 1), ('b', 2)], 2))
+    );
+    assert_eq!(groups("3,999,;;"), None);
+    assert_eq!(groups("3,4,;a=999,;"), None);
+
+    fn rows(input: &str) -> Option<(Vec<Vec<u8>>, Vec<u8>)> {
+        let rows: Vec<Vec<u8>>;
+        let iter;
+        re_parse!(r"(({rows**},)*\n)*;({iter* : iter},?)*", input, else { return None });
+        Some((rows, iter.collect()))
+    }
+    assert_eq!(
+        rows("1,2,\n3,\n;4,5"),
+        Some((vec![vec![1, 2], vec![3]], vec![4, 5]))
+    );
+    assert_eq!(rows("1,200,\n3,\n;4,500"), None);
+    assert_eq!(rows("1,2,\n300,\n;4,5"), None);
+
+    let mut buf = [0u8; 2];
+    let mut len: usize;
+    let mut message = "";
+    re_parse!("({len* => buf},?)*", "1,2,3", else |err| { message = err.message });
+    assert_eq!(message, "Too many matches for the buffer buf");
+    re_parse!("({len* => buf},?)*", "1,300", else |err| { message = err.message });
+    assert_eq!(message, "Failed to parse capture `len`");
+    re_parse!("({len* => buf},?)*", "1,2", else { unreachable!() });
+    assert_eq!(&buf[..len], &[1, 2]);
+}
+
+re_assert_matches!(r"\k{address}:{port}", "192.168.0.1:8080");
+re_assert_no_match!(r"\k{address}:{port}", "192.168.0:8080");
+
+#[test]
+fn test_assert_matches() {
+    re_assert_matches!("{a} {b}", "1 2");
+    re_assert_matches!(r"(?i)color: #\k{hex}+", "COLOR: #1f2A3b");
+    re_assert_matches!(r"({x},{y};)*=pairs", "");
+    re_assert_matches!("é{name}", "éAlice");
+    re_assert_no_match!("{a} {b}", "1 ");
+    re_assert_no_match!(r"\d+(?=px)", "12");
+    re_assert_no_match!(".", "\n");
+    re_assert_matches!("(a|b)*a(a|b)#{3}", "bbabab", max_states = 100);
+}
+
+re_validate!(r"\k{address}:{port}");
+
+#[test]
+fn test_validate() {
+    re_validate!("{a} {b}");
+    re_validate!("(a|b)*a(a|b)#{13}", max_states = 20_000);
+}
+
+#[test]
+fn test_debug_option() {
+    let key: String;
+    let value: u32;
+    re_parse!("{key}={value}", "x=1", debug = true);
+    assert_eq!((key, value), ("x".to_string(), 1));
+
+    let mut values: Vec<u32> = Vec::new();
+    re_for_each!("{n};", "1;2;", { values.push(n) }, debug = true);
+    assert_eq!(values, vec![1, 2]);
+}
+
+#[test]
+fn test_literal_prefix() {
+    let temps: Vec<i32>;
+    re_parse!(
+        r#"Temperatures: \[{temps* sep=", "}\]"#,
+        "Temperatures: [3, -1, 12]"
+    );
+    assert_eq!(temps, vec![3, -1, 12]);
+
+    // The characters of the prefix are skipped one by one if it is not ASCII
+    let size: u32;
+    re_parse!("Größe: {size}", "Größe: 42");
+    assert_eq!(size, 42);
+
+    let code: u32;
+    assert_eq!(
+        re_find!("error code {code};", "error: error code 7;"),
+        7..20
+    );
+    assert_eq!(code, 7);
+
+    let input = "id=1 id=2";
+    let mut cursor = 5;
+    let id: u32;
+    re_parse_at!("id={id}", input, &mut cursor);
+    assert_eq!((id, cursor), (2, 9));
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character z. Expected 's'")]
+fn test_literal_prefix_mismatch() {
+    let _temps: Vec<i32>;
+    re_parse!(r"Temperatures: \[{_temps*}\]", "Temperaturez: [1]");
+}
+
+#[test]
+#[should_panic(expected = "Unexpected end of input after 5 bytes. Expected 'r'")]
+fn test_literal_prefix_end_of_input() {
+    let _temps: Vec<i32>;
+    re_parse!(r"Temperatures: \[{_temps*}\]", "Tempe");
+}
+
+#[test]
+fn test_compact_option() {
+    let key: String;
+    let value: u32;
+    re_parse!("{key}={value}", "x=1", compact = true);
+    assert_eq!((key, value), ("x".to_string(), 1));
+
+    // Characters outside of Latin-1 are looked up in the table of character ranges
+    let word: String;
+    let count: usize;
+    re_parse!("({#count}→)*{word:[a-zα-ω]+}€", "→→λογοςx€", compact = true);
+    assert_eq!((word, count), ("λογοςx".to_string(), 2));
+
+    let path: String;
+    let file: String;
+    re_parse!("{path!}/{file}", "a/b/c.txt", compact = true, debug = true);
+    assert_eq!((path, file), ("a/b".to_string(), "c.txt".to_string()));
+
+    let mut values: Vec<u32> = Vec::new();
+    re_for_each!("{n};", "1;2;", { values.push(n) }, compact = true);
+    assert_eq!(values, vec![1, 2]);
+
+    let code: u32;
+    assert_eq!(
+        re_find!("error {code};", "ok; error 404;", compact = true),
+        4..14
+    );
+    assert_eq!(code, 404);
+    assert_eq!(
+        re_find!(r"\bcat\b", "concatenate cat", compact = true),
+        12..15
+    );
+
+    let result = (|| {
+        let number: u32;
+        re_parse!(r"{number:\d+}!", "12?", else |err| { return Err(err.position) }, compact = true);
+        Ok(number)
+    })();
+    assert_eq!(result, Err(2));
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character ü. Expected ';'")]
+fn test_compact_option_unexpected_character() {
+    re_parse!("a;", "aü", compact = true);
+}
+
+#[test]
+fn test_debug_dot() {
+    const DOT: &str = re_debug_dot!("{key}={value}");
+    assert!(DOT.starts_with("digraph dfa {"));
+    assert!(DOT.contains("State_1 [label=\"State_1\\ncapture key\"];"));
+    assert!(DOT.contains("State_1 -> State_2 [label=\"'='\"];"));
+}
+
+#[test]
+fn test_explain() {
+    const TEXT: &str = re_explain!("{key}={value}");
+    assert!(TEXT.starts_with("start: State_0\n"));
+    assert!(TEXT.contains("State_1: capture key\n    '=' -> State_2\n"));
+    assert!(TEXT
+        .ends_with("State_3: accepting, capture value\n    any other except '\\n' -> State_3\n"));
+}
+
+#[test]
+#[should_panic(expected = "Unexpected character c. Expected 'a'")]
+fn test_dead_state_expected_characters() {
+    // The assertion can never hold, so every character is rejected, but the error still names the `a`
+    re_parse!(r"a\bb", "c");
+}
@@ -0,0 +1,6 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let (a, b, c): (String, String, String);
+    re_parse!("{a!} {b} {c}", "x y z");
+}
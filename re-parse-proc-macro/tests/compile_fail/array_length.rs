@@ -0,0 +1,6 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let values: [u32; 3];
+    re_parse!("{values;3}(,{values;3})*", "1,2,3");
+}
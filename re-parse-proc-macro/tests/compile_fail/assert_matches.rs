@@ -0,0 +1,8 @@
+use re_parse_proc_macro::{re_assert_matches, re_assert_no_match};
+
+re_assert_matches!(r"{year}/{month}/{day}", "2024/01");
+re_assert_matches!(r"\d+ apples", "12 pears");
+re_assert_no_match!(r"\d+ apples", "12 apples");
+re_assert_matches!(r"<{tag}>{content}</{tag}>", "<b>bold</b>");
+
+fn main() {}
@@ -0,0 +1,6 @@
+use re_parse_proc_macro::re_for_each;
+
+fn main() {
+    let mut point = (0, 0);
+    re_for_each!("{point.0}", "1 2", {});
+}
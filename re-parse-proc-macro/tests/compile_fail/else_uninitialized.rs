@@ -0,0 +1,9 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let n: u32;
+    re_parse!("n={n}", "n=x", else |err| {
+        println!("{err}");
+    });
+    println!("{n}");
+}
@@ -0,0 +1,5 @@
+use re_parse_proc_macro::re_validate;
+
+fn main() {
+    re_validate!("((a#{1000})#{1000})#{100}");
+}
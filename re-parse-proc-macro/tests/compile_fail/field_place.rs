@@ -0,0 +1,12 @@
+use re_parse_proc_macro::re_parse_into;
+
+struct Point {
+    x: i32,
+}
+
+fn main() {
+    let mut point = Point { x: 0 };
+    let mut values = [0];
+    re_parse_into!("{values[0]}", "1", &mut point);
+    assert_eq!(point.x, 0);
+}
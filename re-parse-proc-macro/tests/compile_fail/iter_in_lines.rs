@@ -0,0 +1,7 @@
+use re_parse_proc_macro::re_parse_lines;
+
+fn main() {
+    for (xs,) in re_parse_lines!("{xs* : iter}", "1 2".as_bytes()) {
+        let _: u32 = xs.sum();
+    }
+}
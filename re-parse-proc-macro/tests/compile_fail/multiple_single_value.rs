@@ -0,0 +1,8 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let name: String;
+    let score: u32;
+    re_parse!("{name}: ({score*},)*", "a: 1,2,");
+    println!("{name} {score}");
+}
@@ -0,0 +1,7 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let mut count: u32;
+    re_parse!("{mut count}", "3");
+    count += 1;
+}
@@ -0,0 +1,6 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let (key, value): (String, String);
+    re_parse!("(?P<key>{value}:)", "a:");
+}
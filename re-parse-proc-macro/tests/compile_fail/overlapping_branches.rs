@@ -0,0 +1,5 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    re_parse!("(abc|a.c)", "abc");
+}
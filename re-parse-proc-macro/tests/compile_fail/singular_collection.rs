@@ -0,0 +1,9 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let numbers: Vec<u32>;
+    re_parse!("{numbers}", "1");
+    println!("{numbers:?}");
+
+    re_parse!("{numbers: Vec<u32>}", "1");
+}
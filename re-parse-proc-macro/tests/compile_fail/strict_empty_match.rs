@@ -0,0 +1,6 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let x: Vec<u32>;
+    re_parse!("({x*},)*", "1,2,", strict = true);
+}
@@ -0,0 +1,5 @@
+use re_parse_proc_macro::re_find;
+
+fn main() {
+    let _ = re_find!("a(?=b+)", "aab");
+}
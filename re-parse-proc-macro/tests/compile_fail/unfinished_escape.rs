@@ -1,5 +1,5 @@
 use re_parse_proc_macro::re_parse;
 
 fn main() {
-    re_parse!("a||B", "111B222");
-}
\ No newline at end of file
+    re_parse!(r"{a}\", "x");
+}
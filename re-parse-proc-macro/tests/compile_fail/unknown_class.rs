@@ -0,0 +1,10 @@
+use re_parse_proc_macro::{re_class, re_parse};
+
+re_class!(hex = "[0-9a-fA-F]");
+re_class!(name = "{name}");
+
+fn main() {
+    re_parse!(r"\k{hexadecimal}", "a");
+}
+
+re_class!(float = r"\d+\.\d+");
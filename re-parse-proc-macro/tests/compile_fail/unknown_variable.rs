@@ -0,0 +1,7 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let score: u32;
+    re_parse!("Score: {scroe}", "Score: 5");
+    println!("{score}");
+}
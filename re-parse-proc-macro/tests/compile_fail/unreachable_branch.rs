@@ -0,0 +1,6 @@
+use re_parse_proc_macro::re_parse;
+
+fn main() {
+    let enabled: bool;
+    re_parse!("{enabled:(on=>true|off=>false|on=>false)}", "on");
+}
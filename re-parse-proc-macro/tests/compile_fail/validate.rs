@@ -0,0 +1,7 @@
+use re_parse_proc_macro::re_validate;
+
+re_validate!("Foo{variable}B*{other_variable}C");
+re_validate!("(a|b)*a(a|b)#{20}");
+re_validate!("A-");
+
+fn main() {}
@@ -0,0 +1,131 @@
+use std::fmt::{Display, Formatter};
+
+/// Describes why the input did not match, for the `else` block of [`re_parse!`](crate::re_parse) like
+/// `else |err| { ... }`. The error does not allocate, so it borrows the text of a capture that could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error<'a> {
+    /// The byte index where the input stopped matching, or the start of the capture that could not be parsed
+    pub position: usize,
+    pub message: &'static str,
+    /// The character or the end of the input that the pattern rejected, if the input did not match the pattern
+    pub unexpected: Option<Unexpected>,
+    /// The characters that the pattern expected instead of `unexpected`, like `["0-9"]`
+    pub expected: &'static [&'static str],
+    /// The capture that could not be parsed
+    pub capture: Option<CaptureError<'a>>,
+}
+
+/// What the pattern rejected, see [`Error::unexpected`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unexpected {
+    Char(char),
+    EndOfInput,
+}
+
+/// A capture that could not be parsed, see [`Error::capture`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureError<'a> {
+    /// The byte index where the capture starts
+    pub position: usize,
+    /// The name of the type that the capture was parsed as
+    pub ty: &'static str,
+    pub text: &'a str,
+}
+
+// The generated code rejects the input with these functions
+impl<'a> Error<'a> {
+    #[doc(hidden)]
+    #[cold]
+    pub fn reject<T>(position: usize, message: &'static str) -> Result<T, Self> {
+        Err(Self {
+            position,
+            message,
+            unexpected: None,
+            expected: &[],
+            capture: None,
+        })
+    }
+
+    #[doc(hidden)]
+    #[cold]
+    pub fn reject_input<T>(
+        position: usize,
+        message: &'static str,
+        unexpected: Unexpected,
+        expected: &'static [&'static str],
+    ) -> Result<T, Self> {
+        Err(Self {
+            position,
+            message,
+            unexpected: Some(unexpected),
+            expected,
+            capture: None,
+        })
+    }
+
+    #[doc(hidden)]
+    #[cold]
+    pub fn reject_capture<T>(capture: CaptureError<'a>, message: &'static str) -> Result<T, Self> {
+        Err(Self {
+            position: capture.position,
+            message,
+            unexpected: None,
+            expected: &[],
+            capture: Some(capture),
+        })
+    }
+}
+
+impl Display for Error<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(Unexpected::Char(char)) = self.unexpected {
+            write!(f, "Unexpected character {char:?}. ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(capture) = &self.capture {
+            write!(f, " ({}) from {:?}", capture.ty, capture.text)?;
+        }
+        write!(f, " (at byte {} of the input)", self.position)
+    }
+}
+
+impl std::error::Error for Error<'_> {}
+
+/// Why [`re_parse_lines!`](crate::re_parse_lines) could not yield the captures of a line.
+/// Line numbers start at 1.
+#[derive(Debug)]
+pub enum LineError {
+    /// The line could not be read from the reader
+    Io { line: usize, error: std::io::Error },
+    /// The line does not match the pattern
+    Mismatch { line: usize, text: String },
+}
+
+impl LineError {
+    /// The number of the line, starting at 1
+    pub fn line(&self) -> usize {
+        match self {
+            LineError::Io { line, .. } | LineError::Mismatch { line, .. } => *line,
+        }
+    }
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LineError::Io { line, error } => write!(f, "Could not read line {line}: {error}"),
+            LineError::Mismatch { line, text } => {
+                write!(f, "Line {line} does not match the pattern: {text:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LineError::Io { error, .. } => Some(error),
+            LineError::Mismatch { .. } => None,
+        }
+    }
+}
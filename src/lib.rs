@@ -1,7 +1,14 @@
 //! For detailed documentation, look at [re_parse]
 #![doc=include_str!("../README.md")]
 
-pub use re_parse_proc_macro::re_parse;
+mod error;
+
+pub use error::{CaptureError, Error, LineError, Unexpected};
+pub use re_parse_proc_macro::{
+    re_assert_matches, re_assert_no_match, re_class, re_debug_dot, re_explain, re_find,
+    re_for_each, re_parse, re_parse_at, re_parse_into, re_parse_lines, re_parse_prefix,
+    re_validate,
+};
 
 #[cfg(test)]
 mod tests {